@@ -0,0 +1,213 @@
+//! Benchmarks of the loaders, to evaluate changes to their internals.
+//!
+//! `cargo bench` runs them on the futures runtime, `cargo bench --features runtime-tokio` on
+//! Tokio. Compare against a baseline with `--save-baseline` and `--baseline`.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use dataloader::{cached, non_cached, BatchFn, LoadError, NoopObserver, Observer};
+use futures::future::join_all;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const RUNTIME: &str = if cfg!(feature = "runtime-tokio") {
+    "tokio"
+} else {
+    "futures"
+};
+
+#[derive(Clone)]
+struct IdentityLoadFn;
+
+impl BatchFn<u64, u64> for IdentityLoadFn {
+    async fn load(&mut self, keys: &[u64]) -> HashMap<u64, u64> {
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+/// Runs futures on the runtime of the enabled cargo features.
+struct Executor {
+    #[cfg(feature = "runtime-tokio")]
+    runtime: tokio::runtime::Runtime,
+}
+
+impl Executor {
+    fn new() -> Self {
+        Executor {
+            #[cfg(feature = "runtime-tokio")]
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("tokio runtime"),
+        }
+    }
+
+    fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        #[cfg(feature = "runtime-tokio")]
+        return self.runtime.block_on(future);
+        #[cfg(not(feature = "runtime-tokio"))]
+        return futures::executor::block_on(future);
+    }
+}
+
+/// Many concurrent loads of distinct keys, batched together.
+fn distinct_keys(c: &mut Criterion) {
+    let executor = Executor::new();
+    let mut group = c.benchmark_group(format!("distinct_keys/{}", RUNTIME));
+    for callers in [100, 1_000, 10_000] {
+        group.throughput(Throughput::Elements(callers));
+        group.bench_with_input(BenchmarkId::new("non_cached", callers), &callers, |b, n| {
+            let loader = non_cached::Loader::new(IdentityLoadFn);
+            b.iter(|| executor.run(join_all((0..*n).map(|k| loader.load(k)))))
+        });
+        group.bench_with_input(BenchmarkId::new("cached", callers), &callers, |b, n| {
+            b.iter(|| {
+                // a new loader per iteration, so that every key misses
+                let loader = cached::Loader::new(IdentityLoadFn);
+                executor.run(join_all((0..*n).map(|k| loader.load(k))))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Many concurrent loads of a few keys.
+fn duplicate_keys(c: &mut Criterion) {
+    let executor = Executor::new();
+    let mut group = c.benchmark_group(format!("duplicate_keys/{}", RUNTIME));
+    let callers = 10_000;
+    group.throughput(Throughput::Elements(callers));
+    for distinct in [1, 10, 100] {
+        group.bench_with_input(
+            BenchmarkId::new("non_cached", distinct),
+            &distinct,
+            |b, d| {
+                let loader = non_cached::Loader::new(IdentityLoadFn);
+                b.iter(|| executor.run(join_all((0..callers).map(|k| loader.load(k % d)))))
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("cached", distinct), &distinct, |b, d| {
+            b.iter(|| {
+                let loader = cached::Loader::new(IdentityLoadFn);
+                executor.run(join_all((0..callers).map(|k| loader.load(k % d))))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Loads of cached keys, which never reach the batch function.
+fn cached_hits(c: &mut Criterion) {
+    let executor = Executor::new();
+    let mut group = c.benchmark_group(format!("cached_hits/{}", RUNTIME));
+    let loader = cached::Loader::new(IdentityLoadFn);
+    executor.run(loader.prime_many((0..1_000).map(|k| (k, k))));
+    group.bench_function("load", |b| b.iter(|| executor.run(loader.load(42))));
+    group.throughput(Throughput::Elements(1_000));
+    group.bench_function("load_many", |b| {
+        b.iter(|| executor.run(loader.load_many(0..1_000)))
+    });
+    group.finish();
+}
+
+/// A single `load_many` of a large vector of keys, split into many batches.
+fn load_many_large(c: &mut Criterion) {
+    let executor = Executor::new();
+    let mut group = c.benchmark_group(format!("load_many_large/{}", RUNTIME));
+    for keys in [10_000, 100_000] {
+        group.throughput(Throughput::Elements(keys));
+        group.bench_with_input(BenchmarkId::new("non_cached", keys), &keys, |b, n| {
+            let loader = non_cached::Loader::new(IdentityLoadFn);
+            b.iter(|| executor.run(loader.load_many(0..*n)))
+        });
+        group.bench_with_input(BenchmarkId::new("cached", keys), &keys, |b, n| {
+            b.iter(|| {
+                let loader = cached::Loader::new(IdentityLoadFn);
+                executor.run(loader.load_many(0..*n))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Counts every event, the cheapest observer which is not a no-op.
+#[derive(Clone, Default)]
+struct CountingObserver(Arc<AtomicU64>);
+
+impl Observer for CountingObserver {
+    fn batch_dispatched(&self, _keys: usize) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn batch_completed(&self, _keys: usize, _elapsed: Duration, _error: Option<&LoadError>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cache_hit(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cache_miss(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A loader of `keys` keys, the first half of them cached.
+fn half_cached<O: Observer>(
+    executor: &Executor,
+    loader: cached::Loader<u64, u64, IdentityLoadFn, HashMap<u64, u64>, O>,
+    keys: u64,
+) -> cached::Loader<u64, u64, IdentityLoadFn, HashMap<u64, u64>, O> {
+    executor.run(loader.prime_many((0..keys / 2).map(|k| (k, k))));
+    loader
+}
+
+/// Loads without an observer, with the [`NoopObserver`] passed explicitly and with an observer
+/// counting the events. The first two must not differ: a disabled observer compiles down to the
+/// loader without one.
+fn observer(c: &mut Criterion) {
+    let executor = Executor::new();
+    let mut group = c.benchmark_group(format!("observer/{}", RUNTIME));
+    let keys = 1_000;
+    group.throughput(Throughput::Elements(keys));
+    group.bench_function("none", |b| {
+        b.iter_batched(
+            || half_cached(&executor, cached::Loader::new(IdentityLoadFn), keys),
+            |loader| executor.run(loader.load_many(0..keys)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("noop", |b| {
+        b.iter_batched(
+            || {
+                let loader = cached::Loader::new(IdentityLoadFn).with_observer(NoopObserver);
+                half_cached(&executor, loader, keys)
+            },
+            |loader| executor.run(loader.load_many(0..keys)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("counting", |b| {
+        let observer = CountingObserver::default();
+        b.iter_batched(
+            || {
+                let loader = cached::Loader::new(IdentityLoadFn).with_observer(observer.clone());
+                half_cached(&executor, loader, keys)
+            },
+            |loader| executor.run(loader.load_many(0..keys)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    distinct_keys,
+    duplicate_keys,
+    cached_hits,
+    load_many_large,
+    observer
+);
+criterion_main!(benches);
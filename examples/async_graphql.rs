@@ -1,3 +1,5 @@
+#![allow(clippy::clone_on_copy, clippy::new_without_default)]
+
 use async_graphql::{Context, EmptyMutation, EmptySubscription, Schema};
 use dataloader::cached::Loader;
 use dataloader::BatchFn;
@@ -1,3 +1,5 @@
+#![allow(clippy::clone_on_copy, clippy::new_without_default)]
+
 use dataloader::cached::Loader;
 use dataloader::BatchFn;
 use fake::faker::company::en::CompanyName;
@@ -0,0 +1,39 @@
+use dataloader::{cached, non_cached, BatchFn, MissingKeyPolicy};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+/// Returns values for even keys only.
+struct EvenLoadFn;
+
+impl BatchFn<usize, usize> for EvenLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let ret = keys.iter().filter(|k| *k % 2 == 0).map(|k| (*k, *k));
+        ready(ret.collect()).await
+    }
+}
+
+fn main() {
+    let policies = vec![
+        MissingKeyPolicy::Error,
+        MissingKeyPolicy::Default(0),
+        MissingKeyPolicy::Skip,
+    ];
+    for policy in policies {
+        let loader = cached::Loader::new(EvenLoadFn).with_missing_key_policy(policy.clone());
+        println!(
+            "cached {:?}: try_load(1) = {:?}, load_results([1, 2]) = {:?}",
+            policy,
+            block_on(loader.try_load(1)),
+            block_on(loader.load_results(vec![1, 2]))
+        );
+
+        let loader = non_cached::Loader::new(EvenLoadFn).with_missing_key_policy(policy.clone());
+        println!(
+            "non_cached {:?}: try_load(1) = {:?}, load_results([1, 2]) = {:?}",
+            policy,
+            block_on(loader.try_load(1)),
+            block_on(loader.load_results(vec![1, 2]))
+        );
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::clone_on_copy)]
+
 use dataloader::non_cached::Loader;
 use dataloader::BatchFn;
 use futures::executor::block_on;
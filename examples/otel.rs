@@ -0,0 +1,48 @@
+//! Records the metrics of two named loaders with an OpenTelemetry meter and prints them.
+//!
+//! `cargo run --example otel --features otel`; in a service, pass the meter of a provider
+//! exporting to your collector instead.
+use dataloader::{cached, non_cached, BatchFn};
+use futures::executor::block_on;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use std::collections::HashMap;
+use std::future::ready;
+
+struct UserLoadFn;
+
+impl BatchFn<u64, String> for UserLoadFn {
+    async fn load(&mut self, keys: &[u64]) -> HashMap<u64, String> {
+        let ret = keys.iter().map(|id| (*id, format!("user {}", id)));
+        ready(ret.collect()).await
+    }
+}
+
+fn main() {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let meter = provider.meter("dataloader");
+
+    let users = cached::Loader::new(UserLoadFn)
+        .with_name("user_loader")
+        .with_otel_metrics(&meter);
+    let authors = non_cached::Loader::new(UserLoadFn)
+        .with_name("author_loader")
+        .with_otel_metrics(&meter);
+    block_on(async {
+        users.load_many(vec![1, 2, 3]).await;
+        users.load(1).await;
+        authors.load_many(vec![4, 5]).await;
+    });
+
+    provider.force_flush().unwrap();
+    for resource_metrics in exporter.get_finished_metrics().unwrap() {
+        for scope in resource_metrics.scope_metrics() {
+            for metric in scope.metrics() {
+                println!("{}: {:?}", metric.name(), metric.data());
+            }
+        }
+    }
+}
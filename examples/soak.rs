@@ -0,0 +1,197 @@
+//! Soak test for release validation: runs a mixed workload of loads, primes, clears, failing
+//! batches and cancelled loads against a shared loader, checking every result and printing
+//! stats periodically, then checks that no value leaked once the loader is dropped.
+//!
+//! `cargo run --release --example soak -- [seconds] [workers]`, defaulting to 10 seconds on 8
+//! workers; run it for hours before a release.
+use dataloader::cached::Loader;
+use dataloader::{LoadError, Observer, TryBatchFn};
+use futures::executor::block_on;
+use futures::FutureExt;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEYS: usize = 10_000;
+/// Keys divisible by this are missing from the backing store.
+const MISSING: usize = 97;
+/// Every this many batches fails as a whole.
+const FAILING: usize = 50;
+
+static LIVE_VALUES: AtomicUsize = AtomicUsize::new(0);
+
+/// A value counting its live instances, so that leaks show up once the loader is dropped.
+#[derive(Debug, PartialEq)]
+struct Value(usize);
+
+impl Value {
+    fn of(key: usize) -> Self {
+        LIVE_VALUES.fetch_add(1, Ordering::SeqCst);
+        Value(key * 2)
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        LIVE_VALUES.fetch_add(1, Ordering::SeqCst);
+        Value(self.0)
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        LIVE_VALUES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct FlakyLoadFn {
+    batches: usize,
+}
+
+impl TryBatchFn<usize, Value> for FlakyLoadFn {
+    type Error = String;
+
+    async fn try_load(&mut self, keys: &[usize]) -> Result<HashMap<usize, Value>, String> {
+        self.batches += 1;
+        if self.batches.is_multiple_of(FAILING) {
+            return Err(format!("batch {} failed", self.batches));
+        }
+        let found = keys.iter().filter(|k| !k.is_multiple_of(MISSING));
+        Ok(found.map(|k| (*k, Value::of(*k))).collect())
+    }
+}
+
+#[derive(Clone, Default)]
+struct Stats {
+    ops: Arc<AtomicUsize>,
+    batches: Arc<AtomicUsize>,
+    failed_batches: Arc<AtomicUsize>,
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicUsize>,
+}
+
+impl Observer for Stats {
+    fn batch_completed(&self, _keys: usize, _elapsed: Duration, error: Option<&LoadError>) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        if error.is_some() {
+            self.failed_batches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn cache_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cache_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Stats {
+    fn print(&self, elapsed: Duration) {
+        println!(
+            "{:>6}s ops={} batches={} failed_batches={} hits={} misses={} cancelled={} live_values={}",
+            elapsed.as_secs(),
+            self.ops.load(Ordering::Relaxed),
+            self.batches.load(Ordering::Relaxed),
+            self.failed_batches.load(Ordering::Relaxed),
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+            self.cancelled.load(Ordering::Relaxed),
+            LIVE_VALUES.load(Ordering::SeqCst),
+        );
+    }
+}
+
+/// Checks the result of loading `key`: the value derived from the key, `NotFound` for missing
+/// keys, or the error of a failed batch.
+fn check(key: usize, r: &Result<Value, LoadError>) {
+    match r {
+        Ok(v) => assert_eq!(v.0, key * 2, "wrong value for key {}", key),
+        Err(LoadError::NotFound(_)) => {
+            assert!(key.is_multiple_of(MISSING), "key {} not found", key)
+        }
+        Err(LoadError::Batch(_)) => {}
+        Err(e) => panic!("unexpected error for key {}: {}", key, e),
+    }
+}
+
+type SoakLoader = Loader<usize, Value, FlakyLoadFn, HashMap<usize, Value>, Stats>;
+
+fn work(loader: &SoakLoader, stats: &Stats) {
+    let mut rng = rand::thread_rng();
+    let key = rng.gen_range(0..KEYS);
+    match rng.gen_range(0..100) {
+        0..=59 => check(key, &block_on(loader.try_load(key))),
+        60..=79 => {
+            let keys = (0..rng.gen_range(1..50))
+                .map(|_| rng.gen_range(0..KEYS))
+                .collect::<Vec<_>>();
+            for (key, r) in block_on(loader.load_results(keys)).iter() {
+                check(*key, r);
+            }
+        }
+        80..=87 => {
+            if !key.is_multiple_of(MISSING) {
+                block_on(loader.prime(key, Value::of(key)));
+            }
+        }
+        88..=93 => block_on(loader.clear(key)),
+        94..=98 => {
+            // polls the load once and drops it, withdrawing the key unless it was cached
+            if let Some(r) = loader.try_load(key).now_or_never() {
+                check(key, &r);
+            } else {
+                stats.cancelled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        _ => block_on(loader.clear_all()),
+    }
+    stats.ops.fetch_add(1, Ordering::Relaxed);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let seconds = args.next().map_or(10, |s| s.parse().expect("seconds"));
+    let workers = args.next().map_or(8, |s| s.parse().expect("workers"));
+    let duration = Duration::from_secs(seconds);
+
+    let stats = Stats::default();
+    let loader = Loader::new(FlakyLoadFn { batches: 0 })
+        .with_max_batch_size(100)
+        .with_observer(stats.clone());
+    let done = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+
+    let handles = (0..workers)
+        .map(|_| {
+            let (loader, stats, done) = (loader.clone(), stats.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    work(&loader, &stats);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    while started.elapsed() < duration {
+        thread::sleep(Duration::from_secs(1).min(duration));
+        if started.elapsed().as_secs().is_multiple_of(10) {
+            stats.print(started.elapsed());
+        }
+    }
+    done.store(true, Ordering::Relaxed);
+    for handle in handles.into_iter() {
+        handle.join().expect("worker panicked");
+    }
+    stats.print(started.elapsed());
+
+    drop(loader);
+    let leaked = LIVE_VALUES.load(Ordering::SeqCst);
+    assert_eq!(leaked, 0, "{} values leaked", leaked);
+    println!("ok");
+}
@@ -0,0 +1,138 @@
+use crate::cached::Cache;
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+/// A cache accessed asynchronously, e.g. Redis or memcached shared by several processes.
+///
+/// Attached to a cached loader with `with_async_cache`, it sits between the loader's own cache
+/// and the batch function: keys requested without a principal are looked up in it before they
+/// are loaded, and the values loaded for them are written to it. Fresh loads skip the lookup,
+/// and `clear` and `clear_all` without a principal clear it too. Implementations should treat
+/// errors of the backing store as misses.
+pub trait AsyncCache: Send + Sync + 'static {
+    type Key: Eq + Hash + Clone + Send + Sync;
+    type Val: Send;
+
+    fn get(&self, key: &Self::Key) -> impl Future<Output = Option<Self::Val>> + Send;
+    fn insert(&self, key: Self::Key, val: Self::Val) -> impl Future<Output = ()> + Send;
+    fn remove(&self, key: &Self::Key) -> impl Future<Output = ()> + Send;
+    fn clear(&self) -> impl Future<Output = ()> + Send;
+
+    /// Looks up all of `keys`, e.g. with a single `MGET`. Defaults to one `get` after another.
+    fn get_many(
+        &self,
+        keys: &[Self::Key],
+    ) -> impl Future<Output = HashMap<Self::Key, Self::Val>> + Send {
+        async move {
+            let mut ret = HashMap::new();
+            for key in keys.iter() {
+                if let Some(val) = self.get(key).await {
+                    ret.insert(key.clone(), val);
+                }
+            }
+            ret
+        }
+    }
+
+    /// Writes all of `values`. Defaults to one `insert` after another.
+    fn insert_many(&self, values: Vec<(Self::Key, Self::Val)>) -> impl Future<Output = ()> + Send {
+        async move {
+            for (key, val) in values.into_iter() {
+                self.insert(key, val).await;
+            }
+        }
+    }
+}
+
+pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The start of a batch whose values are inserted into a cache which versions its writes, see
+/// [`DynAsyncCache::begin`].
+pub(crate) struct BatchStart {
+    // the version of the last write to the cache when the batch started
+    pub(crate) version: u64,
+    // keeps the cache versioning its writes until the batch is over
+    pub(crate) _versioning: Box<dyn Send + Sync>,
+}
+
+// The object safe counterpart of `AsyncCache`.
+pub(crate) trait DynAsyncCache<K, V>: Send + Sync {
+    /// Marks the start of a batch, so that `insert_many` leaves out the values of keys written
+    /// since. Caches which don't version their writes return `None`, the default.
+    fn begin(&self) -> BoxFuture<'_, Option<BatchStart>> {
+        Box::pin(ready(None))
+    }
+
+    fn get_many<'a>(&'a self, keys: &'a [K]) -> BoxFuture<'a, HashMap<K, V>>;
+    fn insert_many(&self, values: Vec<(K, V)>, start: Option<BatchStart>) -> BoxFuture<'_, ()>;
+    fn remove<'a>(&'a self, key: &'a K) -> BoxFuture<'a, ()>;
+    fn clear(&self) -> BoxFuture<'_, ()>;
+}
+
+impl<A: AsyncCache> DynAsyncCache<A::Key, A::Val> for A {
+    fn get_many<'a>(&'a self, keys: &'a [A::Key]) -> BoxFuture<'a, HashMap<A::Key, A::Val>> {
+        Box::pin(AsyncCache::get_many(self, keys))
+    }
+
+    fn insert_many(
+        &self,
+        values: Vec<(A::Key, A::Val)>,
+        _start: Option<BatchStart>,
+    ) -> BoxFuture<'_, ()> {
+        Box::pin(AsyncCache::insert_many(self, values))
+    }
+
+    fn remove<'a>(&'a self, key: &'a A::Key) -> BoxFuture<'a, ()> {
+        Box::pin(AsyncCache::remove(self, key))
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(AsyncCache::clear(self))
+    }
+}
+
+/// Adapts a synchronous [`Cache`] to [`AsyncCache`], e.g. to share an in-process cache between
+/// several loaders, or to test a loader set up for a remote cache.
+pub struct SyncCache<C>(Mutex<C>);
+
+impl<C> SyncCache<C> {
+    pub fn new(cache: C) -> Self {
+        SyncCache(Mutex::new(cache))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, C> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<C> AsyncCache for SyncCache<C>
+where
+    C: Cache + Send + 'static,
+    C::Key: Eq + Hash + Clone + Send + Sync,
+    C::Val: Clone + Send,
+{
+    type Key = C::Key;
+    type Val = C::Val;
+
+    fn get(&self, key: &C::Key) -> impl Future<Output = Option<C::Val>> + Send {
+        ready(self.lock().get(key).cloned())
+    }
+
+    fn insert(&self, key: C::Key, val: C::Val) -> impl Future<Output = ()> + Send {
+        self.lock().insert(key, val);
+        ready(())
+    }
+
+    fn remove(&self, key: &C::Key) -> impl Future<Output = ()> + Send {
+        self.lock().remove(key);
+        ready(())
+    }
+
+    fn clear(&self) -> impl Future<Output = ()> + Send {
+        self.lock().clear();
+        ready(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+/// The barriers held on a loader and the callers waiting for them to be released.
+#[derive(Default)]
+pub(crate) struct Barriers(Mutex<(usize, Vec<Waker>)>);
+
+impl Barriers {
+    fn lock(&self) -> MutexGuard<'_, (usize, Vec<Waker>)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn hold(self: &Arc<Self>) -> Barrier {
+        self.lock().0 += 1;
+        Barrier(self.clone())
+    }
+
+    pub(crate) fn is_held(&self) -> bool {
+        self.lock().0 > 0
+    }
+
+    /// Waits until no barrier is held anymore.
+    pub(crate) async fn released(&self) {
+        poll_fn(|cx| {
+            let mut barriers = self.lock();
+            if barriers.0 == 0 {
+                return Poll::Ready(());
+            }
+            barriers.1.push(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Holds back the batches of a loader while alive, returned by `barrier` of the loaders.
+///
+/// Callers which are done waiting for work keep waiting while any barrier of the loader is
+/// held, and dispatch once the last one is dropped, so that a framework can keep a batch open
+/// until it has polled every resolver of one level of a query. Pending keys still go out right
+/// away once a full batch is pending. Awaiting a load of the loader while holding one of its
+/// barriers never completes, unless the batch fills up.
+#[must_use = "the batches are dispatched once the barrier is dropped"]
+pub struct Barrier(Arc<Barriers>);
+
+impl Drop for Barrier {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut barriers = self.0.lock();
+            barriers.0 -= 1;
+            if barriers.0 > 0 {
+                return;
+            }
+            std::mem::take(&mut barriers.1)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
@@ -1,5 +1,510 @@
-use std::collections::HashMap;
+use crate::runtime::{self, Arc, Runtime};
+use crate::{BatchError, LoadError, RetryPolicy};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::{poll_fn, Future};
+use std::hash::Hash;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::task::Poll;
+use std::time::Duration;
 
 pub trait BatchFn<K, V> {
+    /// Whether the loaders call [`BatchFn::load_with_counts`] rather than [`BatchFn::load`],
+    /// counting the callers of every key. Set it when overriding `load_with_counts`.
+    const COUNTS: bool = false;
+
     fn load(&mut self, keys: &[K]) -> impl std::future::Future<Output = HashMap<K, V>>;
+
+    /// Loads `keys` along with the number of callers waiting for each of them, e.g. to
+    /// prioritize popular keys within the batch. Prefetched keys nobody waits for yet count 0.
+    /// Only called if [`BatchFn::COUNTS`] is set, forwards to [`BatchFn::load`] by default.
+    fn load_with_counts(
+        &mut self,
+        keys: &[(K, usize)],
+    ) -> impl std::future::Future<Output = HashMap<K, V>>
+    where
+        K: Clone,
+    {
+        let keys = keys.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+        async move { self.load(&keys).await }
+    }
+
+    /// Takes the values a call of [`BatchFn::load`] delivered before it was dropped because it
+    /// timed out, which resolve their keys while the other keys of the batch fail with
+    /// [`LoadError::Timeout`], see [`Streaming`]. None by default.
+    fn salvage(&mut self) -> HashMap<K, V> {
+        HashMap::new()
+    }
+}
+
+/// A batch function which writes values, used by a [`Writer`](crate::writer::Writer) to
+/// coalesce writes the same way loads are coalesced. `values` are in the order they were
+/// stored and may contain a key more than once, e.g. for counter increments.
+pub trait BatchStoreFn<K, V> {
+    fn store(&mut self, values: Vec<(K, V)>) -> impl std::future::Future<Output = ()>;
+}
+
+/// A batch function which can fail as a whole, failing the keys of the batch with
+/// [`LoadError::Batch`](crate::LoadError::Batch) unless a retry succeeds, see
+/// [`RetryPolicy`](crate::RetryPolicy). The error is kept as the
+/// [`BatchError`](crate::BatchError), so any error type, `String` or `anyhow::Error` will do.
+///
+/// Every [`BatchFn`] is a `TryBatchFn` which never fails, so both can be used with the loaders.
+pub trait TryBatchFn<K, V> {
+    type Error: Into<Box<dyn Error + Send + Sync>>;
+
+    /// Whether the loaders call [`TryBatchFn::try_load_with_counts`], see [`BatchFn::COUNTS`].
+    const COUNTS: bool = false;
+
+    fn try_load(
+        &mut self,
+        keys: &[K],
+    ) -> impl std::future::Future<Output = Result<HashMap<K, V>, Self::Error>>;
+
+    /// Loads `keys` along with the number of callers waiting for each of them, see
+    /// [`BatchFn::load_with_counts`]. Forwards to [`TryBatchFn::try_load`] by default.
+    fn try_load_with_counts(
+        &mut self,
+        keys: &[(K, usize)],
+    ) -> impl std::future::Future<Output = Result<HashMap<K, V>, Self::Error>>
+    where
+        K: Clone,
+    {
+        let keys = keys.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+        async move { self.try_load(&keys).await }
+    }
+
+    /// Takes the values a call delivered before it timed out, see [`BatchFn::salvage`].
+    fn salvage(&mut self) -> HashMap<K, V> {
+        HashMap::new()
+    }
+}
+
+impl<K, V, F> TryBatchFn<K, V> for F
+where
+    F: BatchFn<K, V>,
+{
+    type Error = Infallible;
+
+    const COUNTS: bool = F::COUNTS;
+
+    async fn try_load(&mut self, keys: &[K]) -> Result<HashMap<K, V>, Infallible> {
+        Ok(self.load(keys).await)
+    }
+
+    async fn try_load_with_counts(
+        &mut self,
+        keys: &[(K, usize)],
+    ) -> Result<HashMap<K, V>, Infallible>
+    where
+        K: Clone,
+    {
+        Ok(self.load_with_counts(keys).await)
+    }
+
+    fn salvage(&mut self) -> HashMap<K, V> {
+        BatchFn::salvage(self)
+    }
+}
+
+/// A batch function which splits or reorders the keys of a batch before it is loaded, e.g. by
+/// shard or by the parameter limit of a query, enabled with `with_batch_planner` of the
+/// loaders. The loaders call the batch function once per group of the plan.
+pub trait BatchPlanner<K> {
+    /// Splits the tentative batch `keys` into the groups to load, all of them at once by
+    /// default. Keys left out of the plan are loaded in a group of their own after the planned
+    /// groups, and keys which were not in `keys` are ignored.
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        vec![keys]
+    }
+}
+
+/// Plans the batches of a loader with the batch function `F`, see [`BatchPlanner`].
+pub(crate) type PlanFn<F, K> = fn(&mut F, Vec<K>) -> Vec<Vec<K>>;
+
+/// The clones of the batch function of a loader, one per batch of a flush loading at once,
+/// cloned from the first one as a flush needs them, see `with_max_concurrent_batches`.
+pub(crate) struct LoadFns<F>(std::sync::Mutex<Vec<Arc<runtime::Mutex<F>>>>);
+
+impl<F> LoadFns<F> {
+    pub(crate) fn new(load_fn: F) -> Self {
+        LoadFns(std::sync::Mutex::new(vec![Arc::new(runtime::Mutex::new(
+            load_fn,
+        ))]))
+    }
+
+    /// Returns `n` clones of the batch function, cloning the missing ones with `clone`, or the
+    /// first one only without it.
+    pub(crate) async fn take(
+        &self,
+        n: usize,
+        clone: Option<fn(&F) -> F>,
+    ) -> Vec<Arc<runtime::Mutex<F>>> {
+        let (first, clone) = {
+            let load_fns = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            match clone {
+                Some(clone) if load_fns.len() < n => (load_fns[0].clone(), clone),
+                _ => return load_fns[..n.clamp(1, load_fns.len())].to_vec(),
+            }
+        };
+        let load_fn = first.lock().await;
+        let mut load_fns = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        while load_fns.len() < n {
+            load_fns.push(Arc::new(runtime::Mutex::new(clone(&load_fn))));
+        }
+        load_fns[..n].to_vec()
+    }
+}
+
+/// The groups `plan` splits `keys` into, keeping every key of `keys` in exactly one group, see
+/// [`BatchPlanner::plan`].
+pub(crate) fn planned<K>(keys: Vec<K>, plan: impl FnOnce(Vec<K>) -> Vec<Vec<K>>) -> Vec<Vec<K>>
+where
+    K: Eq + Hash + Clone,
+{
+    let mut unplanned = keys.iter().cloned().collect::<HashSet<_>>();
+    let mut groups = plan(keys.clone());
+    for group in groups.iter_mut() {
+        group.retain(|k| unplanned.remove(k));
+    }
+    groups.retain(|group| !group.is_empty());
+    if !unplanned.is_empty() {
+        groups.push(keys.into_iter().filter(|k| unplanned.contains(k)).collect());
+    }
+    groups
+}
+
+/// A batch function returning its values positionally: `load` returns a value or `None` for
+/// every key, in the order of `keys`. Use it with the loaders by wrapping it in a
+/// [`Positional`], which associates the values with their keys.
+pub trait PositionalBatchFn<K, V> {
+    fn load(&mut self, keys: &[K]) -> impl std::future::Future<Output = Vec<Option<V>>>;
+}
+
+/// Adapts a [`PositionalBatchFn`] to the loaders, which load `Result<V, LoadError>` values.
+/// Keys whose value is `None` are not found, and every key of a batch whose values are not
+/// aligned with its keys resolves to [`LoadError::UnequalKeyValueSize`].
+#[derive(Debug, Clone, Default)]
+pub struct Positional<F>(pub F);
+
+impl<K, F: BatchPlanner<K>> BatchPlanner<K> for Positional<F> {
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        self.0.plan(keys)
+    }
+}
+
+impl<K, V, F> BatchFn<K, Result<V, LoadError>> for Positional<F>
+where
+    K: Eq + Hash + Clone,
+    F: PositionalBatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Result<V, LoadError>> {
+        let values = self.0.load(keys).await;
+        if values.len() != keys.len() {
+            let e = LoadError::UnequalKeyValueSize {
+                key_count: keys.len(),
+                value_count: values.len(),
+            };
+            return keys.iter().map(|k| (k.clone(), Err(e.clone()))).collect();
+        }
+        keys.iter()
+            .zip(values)
+            .filter_map(|(k, v)| v.map(|v| (k.clone(), Ok(v))))
+            .collect()
+    }
+}
+
+/// A batch function returning any number of values per key, e.g. the posts of every author id
+/// looked up by a secondary index. Use it with the loaders by wrapping it in a [`Many`].
+pub trait BatchFnMany<K, V> {
+    fn load(&mut self, keys: &[K]) -> impl std::future::Future<Output = HashMap<K, Vec<V>>>;
+}
+
+/// Adapts a [`BatchFnMany`] to the loaders, which load `Vec<V>` values. Keys without values
+/// resolve to an empty `Vec` rather than to [`LoadError::NotFound`], see `load_values` of the
+/// loaders.
+#[derive(Debug, Clone, Default)]
+pub struct Many<F>(pub F);
+
+impl<K, F: BatchPlanner<K>> BatchPlanner<K> for Many<F> {
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        self.0.plan(keys)
+    }
+}
+
+impl<K, V, F> BatchFn<K, Vec<V>> for Many<F>
+where
+    K: Eq + Hash + Clone,
+    F: BatchFnMany<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Vec<V>> {
+        let mut values = self.0.load(keys).await;
+        for key in keys.iter() {
+            if !values.contains_key(key) {
+                values.insert(key.clone(), Vec::new());
+            }
+        }
+        values
+    }
+}
+
+/// Adapts a batch function of `V` values to loaders of `Arc<V>` values, so that cache hits
+/// hand out refcounted pointers rather than deep clones of the values, see
+/// [`ArcLoader`](crate::cached::ArcLoader).
+#[derive(Debug, Clone, Default)]
+pub struct ArcBatchFn<F>(pub F);
+
+impl<K, F: BatchPlanner<K>> BatchPlanner<K> for ArcBatchFn<F> {
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        self.0.plan(keys)
+    }
+}
+
+impl<K, V, F> BatchFn<K, Arc<V>> for ArcBatchFn<F>
+where
+    K: Eq + Hash,
+    F: BatchFn<K, V>,
+{
+    const COUNTS: bool = F::COUNTS;
+
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Arc<V>> {
+        let values = self.0.load(keys).await;
+        values.into_iter().map(|(k, v)| (k, Arc::new(v))).collect()
+    }
+
+    async fn load_with_counts(&mut self, keys: &[(K, usize)]) -> HashMap<K, Arc<V>>
+    where
+        K: Clone,
+    {
+        let values = self.0.load_with_counts(keys).await;
+        values.into_iter().map(|(k, v)| (k, Arc::new(v))).collect()
+    }
+
+    fn salvage(&mut self) -> HashMap<K, Arc<V>> {
+        let values = self.0.salvage();
+        values.into_iter().map(|(k, v)| (k, Arc::new(v))).collect()
+    }
+}
+
+/// A batch function delivering the values of a batch as they arrive, e.g. the rows of a
+/// streamed query, so that a batch which times out still resolves the keys whose values arrived
+/// before, see `with_load_timeout` of the loaders. Use it with the loaders by wrapping it in a
+/// [`Streaming`].
+pub trait StreamingBatchFn<K, V> {
+    fn load(&mut self, keys: &[K], values: Arrived<'_, K, V>) -> impl Future<Output = ()>;
+}
+
+/// The values of a batch of a [`StreamingBatchFn`] which arrived so far.
+pub struct Arrived<'a, K, V>(&'a mut HashMap<K, V>);
+
+impl<K: Eq + Hash, V> Arrived<'_, K, V> {
+    pub fn push(&mut self, key: K, value: V) {
+        self.0.insert(key, value);
+    }
+}
+
+/// Adapts a [`StreamingBatchFn`] to the loaders, keeping the values which arrived before a batch
+/// timed out, see [`BatchFn::salvage`].
+#[derive(Debug, Default)]
+pub struct Streaming<F, K, V> {
+    load_fn: F,
+    arrived: HashMap<K, V>,
+}
+
+impl<F, K, V> Streaming<F, K, V> {
+    pub fn new(load_fn: F) -> Self {
+        Streaming {
+            load_fn,
+            arrived: HashMap::new(),
+        }
+    }
+}
+
+impl<F: Clone, K, V> Clone for Streaming<F, K, V> {
+    fn clone(&self) -> Self {
+        Streaming::new(self.load_fn.clone())
+    }
+}
+
+impl<K, V, F: BatchPlanner<K>> BatchPlanner<K> for Streaming<F, K, V> {
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        self.load_fn.plan(keys)
+    }
+}
+
+impl<K, V, F> BatchFn<K, V> for Streaming<F, K, V>
+where
+    K: Eq + Hash,
+    F: StreamingBatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        self.arrived.clear();
+        self.load_fn.load(keys, Arrived(&mut self.arrived)).await;
+        std::mem::take(&mut self.arrived)
+    }
+
+    fn salvage(&mut self) -> HashMap<K, V> {
+        std::mem::take(&mut self.arrived)
+    }
+}
+
+/// A batch function whose futures are `Send`, so that a dispatcher task can call it, see
+/// [`Loader::spawn`](crate::non_cached::Loader::spawn). Use it with the loaders by wrapping it
+/// in a [`Sendable`].
+pub trait SendBatchFn<K, V>: Send + 'static {
+    fn load(&mut self, keys: &[K]) -> impl std::future::Future<Output = HashMap<K, V>> + Send;
+}
+
+/// Adapts a [`SendBatchFn`] to the loaders, which can then be spawned on a dispatcher task.
+#[derive(Debug, Clone, Default)]
+pub struct Sendable<F>(pub F);
+
+impl<K, F: BatchPlanner<K>> BatchPlanner<K> for Sendable<F> {
+    fn plan(&mut self, keys: Vec<K>) -> Vec<Vec<K>> {
+        self.0.plan(keys)
+    }
+}
+
+impl<K, V, F> BatchFn<K, V> for Sendable<F>
+where
+    F: SendBatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        self.0.load(keys).await
+    }
+}
+
+/// Resolves to the output of `future`, or to [`LoadError::Panicked`] if polling it panics.
+async fn catch_panic<T>(future: impl Future<Output = T>) -> Result<T, LoadError> {
+    let mut future = Box::pin(future);
+    poll_fn(
+        |cx| match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(LoadError::Panicked(panic_message(&*payload)))),
+        },
+    )
+    .await
+}
+
+/// Resolves to the output of `future`, failing after `timeout` or if polling it panics.
+async fn call<T>(
+    runtime: &dyn Runtime,
+    timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Result<T, LoadError> {
+    let call = catch_panic(future);
+    match timeout {
+        Some(timeout) => runtime::timeout(runtime, timeout, call)
+            .await
+            .and_then(|r| r),
+        None => call.await,
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => (*message).to_owned(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<dyn Any>".to_owned(),
+        },
+    }
+}
+
+/// Calls `load_fn` with `keys` and the number of callers waiting for each of them as told by
+/// `counts`, failing the call after `timeout` and retrying failed calls as long as `retry`
+/// allows. Before each retry `retain` drops the keys nobody waits for anymore,
+/// the batch fails without retrying once no keys are left. A panicking call fails the batch
+/// with [`LoadError::Panicked`] and is not retried.
+///
+/// Returns the outcome of the keys left in `keys` along with the values `load_fn` salvaged from
+/// calls which timed out, whose keys are taken out of `keys` and not retried.
+pub(crate) async fn load_batch<K, V, F, CF, RF>(
+    runtime: &dyn Runtime,
+    load_fn: &mut F,
+    keys: &mut Vec<K>,
+    timeout: Option<Duration>,
+    retry: Option<&dyn RetryPolicy>,
+    counts: impl Fn(&[K]) -> CF,
+    retain: impl Fn(Vec<K>) -> RF,
+) -> (Result<HashMap<K, V>, LoadError>, HashMap<K, V>)
+where
+    K: Eq + Hash + Clone,
+    F: TryBatchFn<K, V>,
+    CF: Future<Output = Vec<usize>>,
+    RF: Future<Output = Vec<K>>,
+{
+    let mut attempt = 0;
+    let mut salvaged = HashMap::new();
+    loop {
+        let ret = if F::COUNTS {
+            let counted = counts(keys)
+                .await
+                .into_iter()
+                .zip(keys.drain(..))
+                .map(|(n, k)| (k, n))
+                .collect::<Vec<_>>();
+            let ret = call(runtime, timeout, load_fn.try_load_with_counts(&counted)).await;
+            keys.extend(counted.into_iter().map(|(k, _)| k));
+            ret
+        } else {
+            call(runtime, timeout, load_fn.try_load(keys)).await
+        };
+        let e = match ret {
+            Ok(Ok(values)) => return (Ok(values), salvaged),
+            Ok(Err(e)) => LoadError::Batch(BatchError::new(e)),
+            Err(e @ LoadError::Panicked(_)) => return (Err(e), salvaged),
+            Err(e) => e,
+        };
+        if e == LoadError::Timeout {
+            let mut arrived = load_fn.salvage();
+            if !arrived.is_empty() {
+                keys.retain(|key| match arrived.remove_entry(key) {
+                    Some((key, value)) => {
+                        salvaged.insert(key, value);
+                        false
+                    }
+                    None => true,
+                });
+            }
+        }
+        attempt += 1;
+        match retry.and_then(|retry| retry.retry_after(attempt, &e)) {
+            Some(delay) => {
+                if let Some(sleep) = runtime.sleep(delay) {
+                    sleep.await;
+                }
+            }
+            None => return (Err(e), salvaged),
+        }
+        *keys = retain(std::mem::take(keys)).await;
+        if keys.is_empty() {
+            return (Err(e), salvaged);
+        }
+    }
+}
+
+/// Splits the keys of a batch into the groups loaded by separate calls of the batch function.
+pub(crate) type GroupFn<K> = dyn Fn(Vec<K>) -> Vec<Vec<K>> + Send + Sync;
+
+/// Returns a [`GroupFn`] grouping keys by `group_of`, keeping the order in which groups and
+/// their keys first appear.
+pub(crate) fn group_by<K, G>(group_of: impl Fn(&K) -> G + Send + Sync + 'static) -> Arc<GroupFn<K>>
+where
+    G: Eq + Hash,
+{
+    Arc::new(move |keys| {
+        let mut index = HashMap::new();
+        let mut groups: Vec<Vec<K>> = Vec::new();
+        for key in keys.into_iter() {
+            let i = *index.entry(group_of(&key)).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[i].push(key);
+        }
+        groups
+    })
 }
@@ -0,0 +1,306 @@
+//! The batching core of the loaders, which only needs `core` and `alloc`.
+//!
+//! The cached and non-cached loaders are built on this module: a queue of distinct pending
+//! keys, the wait for other loads to join the pending batch, and the splitting and running of
+//! batches. Whatever depends on a runtime or on `std` is injected: the loaders lock their state
+//! with the async mutex of their runtime through [`Lock`], and wait for work with the yields,
+//! ticks or custom waits of their settings through [`Yield`].
+//!
+//! Without the `std` feature, which the runtime features enable, this module is all the crate
+//! provides, so that an executor without `std`, e.g. on WASM or embedded targets, can batch
+//! loads with its own mutex and yield.
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::hash::{BuildHasher, Hash};
+use core::ops::DerefMut;
+use core::pin::Pin;
+use core::task::Poll;
+use hashbrown::HashMap;
+
+/// Locks the state of a loader, e.g. with an async mutex.
+pub trait Lock {
+    type State;
+    type Guard: DerefMut<Target = Self::State>;
+
+    fn lock(&self) -> impl Future<Output = Self::Guard>;
+}
+
+/// Lets other loads run and queue their keys before a batch is dispatched, e.g. by yielding to
+/// the executor once or by sleeping.
+pub trait Yield {
+    fn yield_now(&self) -> impl Future<Output = ()>;
+}
+
+/// When a caller stops waiting for work, see [`wait_for_work`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounds {
+    /// The number of rounds in a row without new keys after which the caller stops waiting.
+    pub idle: usize,
+    /// The number of rounds after which the caller stops waiting anyway.
+    pub max: usize,
+}
+
+/// Waits for other loads to queue their keys, yielding once per round and locking the state
+/// after each round, until `done` returns true given the state and whether the `rounds` are
+/// over. `enqueued` returns the number of keys queued so far, which tells whether keys are
+/// still arriving. Returns the state locked for the caller to dispatch.
+pub async fn wait_for_work<L: Lock>(
+    lock: &L,
+    yielder: &impl Yield,
+    state: L::Guard,
+    rounds: Rounds,
+    enqueued: impl Fn(&L::State) -> usize,
+    mut done: impl FnMut(&mut L::State, bool) -> bool,
+) -> L::Guard {
+    let mut seen = enqueued(&state);
+    drop(state);
+    let (mut round, mut idle) = (0, 0);
+    loop {
+        yielder.yield_now().await;
+        round += 1;
+        let mut state = lock.lock().await;
+        let now = enqueued(&state);
+        idle = if now == seen { idle + 1 } else { 0 };
+        let over = round >= rounds.max || idle >= rounds.idle;
+        if done(&mut state, over) {
+            return state;
+        }
+        seen = now;
+    }
+}
+
+/// The pending keys in the order they were first queued, so that batches are filled with the
+/// oldest keys first. Keys are shared with the other maps of the loader state rather than
+/// cloned into each of them. A key stays pending until its batch completes, but leaves the
+/// queue while the batch is in flight, so that it is not dispatched twice.
+pub struct Pending<K, S> {
+    // the number each key was queued as and its cost
+    seqs: HashMap<Arc<K>, (usize, usize), S>,
+    // the keys waiting for a batch and their summed cost
+    queue: BTreeMap<usize, Arc<K>>,
+    cost: usize,
+}
+
+impl<K: Eq + Hash, S: BuildHasher> Pending<K, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Pending {
+            seqs: HashMap::with_hasher(hasher),
+            queue: BTreeMap::new(),
+            cost: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seqs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seqs.is_empty()
+    }
+
+    /// The number of pending keys waiting for a batch, i.e. not in flight.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.seqs.contains_key(key)
+    }
+
+    /// The shared pending `key`, if it is pending.
+    pub fn get(&self, key: &K) -> Option<&Arc<K>> {
+        self.seqs.get_key_value(key).map(|(key, _)| key)
+    }
+
+    /// The summed cost of the queued keys.
+    pub fn cost(&self) -> usize {
+        self.cost
+    }
+
+    /// Queues `key` as the `seq`th key.
+    pub fn insert(&mut self, key: Arc<K>, seq: usize, cost: usize) {
+        self.queue.insert(seq, key.clone());
+        self.seqs.insert(key, (seq, cost));
+        self.cost = self.cost.saturating_add(cost);
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((seq, cost)) = self.seqs.remove(key) {
+            if self.queue.remove(&seq).is_some() {
+                self.cost = self.cost.saturating_sub(cost);
+            }
+        }
+    }
+
+    /// Takes `key` out of the queue as its batch is dispatched, returning the shared key unless
+    /// it was not queued.
+    pub fn start(&mut self, key: &K) -> Option<Arc<K>> {
+        let (key, (seq, cost)) = self.seqs.get_key_value(key)?;
+        self.queue.remove(seq)?;
+        self.cost = self.cost.saturating_sub(*cost);
+        Some(key.clone())
+    }
+
+    /// Queues `key` again if it is still pending once its batch is over, i.e. if the batch left
+    /// it out to be retried or was dropped before completing.
+    pub fn requeue(&mut self, key: &K) {
+        if let Some((key, (seq, cost))) = self.seqs.get_key_value(key) {
+            if !self.queue.contains_key(seq) {
+                self.queue.insert(*seq, key.clone());
+                self.cost = self.cost.saturating_add(*cost);
+            }
+        }
+    }
+
+    /// The `n` oldest queued keys, oldest first.
+    pub fn oldest(&self, n: usize) -> impl Iterator<Item = &K> {
+        self.queue.values().take(n).map(|key| &**key)
+    }
+
+    /// The queued keys with the numbers they were queued as, oldest first.
+    pub fn numbered(&self) -> impl Iterator<Item = (usize, &K)> {
+        self.queue.iter().map(|(seq, key)| (*seq, &**key))
+    }
+}
+
+/// Splits every batch of `batches` into chunks of up to `chunk_size` keys, in order.
+pub fn chunk<K>(batches: Vec<Vec<K>>, chunk_size: usize) -> Vec<Vec<K>> {
+    let mut chunks = Vec::new();
+    for mut keys in batches.into_iter() {
+        while keys.len() > chunk_size {
+            let rest = keys.split_off(chunk_size);
+            chunks.push(keys);
+            keys = rest;
+        }
+        chunks.push(keys);
+    }
+    chunks
+}
+
+/// Splits `items`, in order, into up to `max_batches` batches whose items cost up to `budget`
+/// in total. An item costing more than the budget makes a batch of its own, and the items left
+/// over once `max_batches` batches are full are dropped.
+pub fn split_by_cost<T>(
+    items: Vec<T>,
+    cost: impl Fn(&T) -> usize,
+    budget: usize,
+    max_batches: usize,
+) -> Vec<Vec<T>> {
+    let mut batches: Vec<Vec<T>> = Vec::new();
+    let mut spent = 0usize;
+    for item in items.into_iter() {
+        let c = cost(&item);
+        match batches.last_mut() {
+            Some(batch) if spent.saturating_add(c) <= budget => {
+                spent = spent.saturating_add(c);
+                batch.push(item);
+                continue;
+            }
+            _ => {}
+        }
+        if batches.len() == max_batches.max(1) {
+            break;
+        }
+        spent = c;
+        batches.push(vec![item]);
+    }
+    batches
+}
+
+/// The turns in which pending items were queued, a turn being the items queued by one caller
+/// while it held the loader state. Items are numbered in the order they were queued, and a turn
+/// is known by the number of its first item. Batches taking the items of every turn in turn
+/// keep a caller queuing many items from holding back the others.
+#[derive(Default)]
+pub struct Turns {
+    starts: BTreeSet<usize>,
+    open: bool,
+}
+
+impl Turns {
+    /// Ends the current turn, the next item queued starts another one.
+    pub fn next(&mut self) {
+        self.open = false;
+    }
+
+    /// Records the `seq`th item queued.
+    pub fn queued(&mut self, seq: usize) {
+        if !self.open {
+            self.starts.insert(seq);
+            self.open = true;
+        }
+    }
+
+    /// Forgets the turns before the one of the `oldest` item still pending.
+    pub fn prune(&mut self, oldest: usize) {
+        if let Some(start) = self.starts.range(..=oldest).next_back().copied() {
+            self.starts = self.starts.split_off(&start);
+        }
+    }
+
+    /// Takes up to `n` of the numbered `items`, given oldest first: the first item of every
+    /// turn, oldest turn first, then the second item of every turn and so on.
+    pub fn round_robin<T>(&self, items: impl Iterator<Item = (usize, T)>, n: usize) -> Vec<T> {
+        let mut turns: Vec<(Option<usize>, VecDeque<T>)> = Vec::new();
+        for (seq, item) in items {
+            let start = self.starts.range(..=seq).next_back().copied();
+            match turns.last_mut() {
+                Some((last, queue)) if *last == start => queue.push_back(item),
+                _ => turns.push((start, VecDeque::from(vec![item]))),
+            }
+        }
+        let mut picked = Vec::new();
+        while picked.len() < n && !turns.is_empty() {
+            for (_, queue) in turns.iter_mut() {
+                if picked.len() == n {
+                    break;
+                }
+                picked.extend(queue.pop_front());
+            }
+            turns.retain(|(_, queue)| !queue.is_empty());
+        }
+        picked
+    }
+}
+
+/// Runs `run(slot, item)` for every item in order, at most `limit` of them at once. `slot` is
+/// an index below `limit` which no other running item has, e.g. to pick a batch function.
+pub async fn run_concurrently<T, Fut>(
+    items: Vec<T>,
+    limit: usize,
+    mut run: impl FnMut(usize, T) -> Fut,
+) where
+    Fut: Future<Output = ()>,
+{
+    let mut items = items.into_iter();
+    let mut slots: Vec<Option<Pin<Box<Fut>>>> = (0..limit.max(1)).map(|_| None).collect();
+    poll_fn(|cx| {
+        loop {
+            let mut completed = false;
+            for (slot, running) in slots.iter_mut().enumerate() {
+                if running.is_none() {
+                    *running = items.next().map(|item| Box::pin(run(slot, item)));
+                }
+                if let Some(fut) = running {
+                    if fut.as_mut().poll(cx).is_ready() {
+                        *running = None;
+                        completed = true;
+                    }
+                }
+            }
+            if !completed {
+                break;
+            }
+        }
+        if slots.iter().all(Option::is_none) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
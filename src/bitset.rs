@@ -0,0 +1,114 @@
+use crate::cached::Cache;
+use std::marker::PhantomData;
+
+/// A key which maps onto a dense integer index, so that it can be stored in a [`BitsetCache`].
+pub trait DenseKey: Copy {
+    fn index(&self) -> usize;
+}
+
+macro_rules! impl_dense_key {
+    ($($t:ty),*) => {
+        $(
+            impl DenseKey for $t {
+                #[inline]
+                fn index(&self) -> usize {
+                    *self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_dense_key!(u8, u16, u32, u64, usize);
+
+const BITS: usize = u64::BITS as usize;
+
+/// A cache for boolean values (existence or permission checks) which uses two bits per key
+/// instead of a `HashMap` entry, indexed by [`DenseKey::index`].
+///
+/// Memory is proportional to the largest index stored, so keys should be dense integer ids.
+pub struct BitsetCache<K> {
+    known: Vec<u64>,
+    values: Vec<u64>,
+    _key: PhantomData<fn(K)>,
+}
+
+impl<K: DenseKey> BitsetCache<K> {
+    pub fn new() -> Self {
+        BitsetCache {
+            known: Vec::new(),
+            values: Vec::new(),
+            _key: PhantomData,
+        }
+    }
+
+    /// Number of keys with a cached value.
+    pub fn len(&self) -> usize {
+        self.known.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known.iter().all(|w| *w == 0)
+    }
+
+    #[inline]
+    fn bit(&self, words: &[u64], index: usize) -> bool {
+        words
+            .get(index / BITS)
+            .is_some_and(|w| w & (1 << (index % BITS)) != 0)
+    }
+}
+
+impl<K: DenseKey> Default for BitsetCache<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: DenseKey> Cache for BitsetCache<K> {
+    type Key = K;
+    type Val = bool;
+
+    fn get(&mut self, key: &K) -> Option<&bool> {
+        let index = key.index();
+        if !self.bit(&self.known, index) {
+            return None;
+        }
+        if self.bit(&self.values, index) {
+            Some(&true)
+        } else {
+            Some(&false)
+        }
+    }
+
+    fn insert(&mut self, key: K, val: bool) {
+        let index = key.index();
+        let (word, mask) = (index / BITS, 1 << (index % BITS));
+        if word >= self.known.len() {
+            self.known.resize(word + 1, 0);
+            self.values.resize(word + 1, 0);
+        }
+        self.known[word] |= mask;
+        if val {
+            self.values[word] |= mask;
+        } else {
+            self.values[word] &= !mask;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<bool> {
+        let index = key.index();
+        let val = self.get(key).copied()?;
+        self.known[index / BITS] &= !(1 << (index % BITS));
+        Some(val)
+    }
+
+    fn clear(&mut self) {
+        self.known.clear();
+        self.values.clear();
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
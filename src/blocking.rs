@@ -0,0 +1,174 @@
+//! A facade loading from blocking code, e.g. rayon workers or a thread pool serving requests,
+//! see [`BlockingLoader`].
+//!
+//! Every call blocks the calling thread on the [`Executor`] the loader was created with, e.g. a
+//! [`TokioExecutor`] on the handle of a tokio runtime. Loads of threads blocked at the same
+//! time share batches like concurrent async loads do. A call from an async task would stall the
+//! other tasks of its thread, so async code makes blocking calls in the `spawn_blocking` of its
+//! runtime. The executors which can tell fail such calls with [`BlockingError::InAsyncTask`].
+
+use crate::{cached, non_cached, BlockingError, LoadError, Observer, TryBatchFn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+
+/// Runs the loads of a [`BlockingLoader`] to completion on the calling thread.
+pub trait Executor {
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> Result<T, BlockingError>;
+}
+
+/// Blocks with `futures::executor::block_on`.
+#[cfg(feature = "runtime-futures")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuturesExecutor;
+
+#[cfg(feature = "runtime-futures")]
+impl Executor for FuturesExecutor {
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> Result<T, BlockingError> {
+        // `block_on` panics on a thread which is running another of its executors
+        drop(futures::executor::enter().map_err(|_| BlockingError::InAsyncTask)?);
+        Ok(futures::executor::block_on(future))
+    }
+}
+
+/// Blocks with `async_std::task::block_on`.
+#[cfg(feature = "runtime-async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdExecutor;
+
+#[cfg(feature = "runtime-async-std")]
+impl Executor for AsyncStdExecutor {
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> Result<T, BlockingError> {
+        if async_std::task::try_current().is_some() {
+            return Err(BlockingError::InAsyncTask);
+        }
+        Ok(async_std::task::block_on(future))
+    }
+}
+
+/// Blocks on the handle of a tokio runtime.
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Clone)]
+pub struct TokioExecutor(tokio::runtime::Handle);
+
+#[cfg(feature = "runtime-tokio")]
+impl TokioExecutor {
+    /// Blocks on the runtime of `handle`, which must be multi-threaded, as only the thread
+    /// running `Runtime::block_on` of a current-thread runtime drives its timers.
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        TokioExecutor(handle)
+    }
+
+    /// Blocks on the runtime the calling thread belongs to, failing with
+    /// [`BlockingError::NoRuntime`] on other threads, e.g. rayon workers, which need
+    /// [`TokioExecutor::new`] with the handle of the runtime instead.
+    pub fn current() -> Result<Self, BlockingError> {
+        tokio::runtime::Handle::try_current()
+            .map(TokioExecutor)
+            .map_err(|_| BlockingError::NoRuntime)
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Executor for TokioExecutor {
+    /// Like `Handle::block_on`, panics when called from an async task of a runtime, which
+    /// tokio doesn't tell apart from a `spawn_blocking` closure.
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> Result<T, BlockingError> {
+        Ok(self.0.block_on(future))
+    }
+}
+
+/// A loader whose loads block the calling thread until the values are loaded.
+#[derive(Clone)]
+pub struct BlockingLoader<L, E> {
+    loader: L,
+    executor: E,
+}
+
+impl<L, E: Executor> BlockingLoader<L, E> {
+    /// Wraps `loader`, blocking on `executor`.
+    pub fn new(loader: L, executor: E) -> Self {
+        BlockingLoader { loader, executor }
+    }
+
+    /// The wrapped loader, e.g. to load from async code as well.
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+
+    pub fn into_inner(self) -> L {
+        self.loader
+    }
+
+    fn block_on<T>(
+        &self,
+        future: impl Future<Output = Result<T, LoadError>>,
+    ) -> Result<T, LoadError> {
+        self.executor
+            .block_on(future)
+            .unwrap_or_else(|e| Err(e.into()))
+    }
+}
+
+impl<K, V, F, C, O, S, E> BlockingLoader<cached::Loader<K, V, F, C, O, S>, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: cached::Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+    E: Executor,
+{
+    pub fn try_load_blocking(&self, key: K) -> Result<V, LoadError> {
+        self.block_on(self.loader.try_load(key))
+    }
+
+    pub fn load_blocking(&self, key: K) -> V {
+        self.try_load_blocking(key)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_load_many_blocking(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.block_on(self.loader.try_load_many(keys))
+    }
+
+    pub fn load_many_blocking(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_many_blocking(keys)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<K, V, F, O, S, E> BlockingLoader<non_cached::Loader<K, V, F, O, S>, E>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+    E: Executor,
+{
+    pub fn try_load_blocking(&self, key: K) -> Result<V, LoadError> {
+        self.block_on(self.loader.try_load(key))
+    }
+
+    pub fn load_blocking(&self, key: K) -> V {
+        self.try_load_blocking(key)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_load_many_blocking(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.block_on(self.loader.try_load_many(keys))
+    }
+
+    pub fn load_many_blocking(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_many_blocking(keys)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
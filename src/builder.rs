@@ -0,0 +1,230 @@
+//! A builder configuring either loader with the settings both have in common, validating that
+//! they make sense together, see [`LoaderBuilder`].
+
+use crate::{cached, non_cached, BuildError, TryBatchFn};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Settings for common workloads, see [`LoaderBuilder::preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Preset {
+    /// Small batches dispatched after yielding once, for loads on the path of a request.
+    LowLatency,
+    /// Large batches dispatched after yielding longer and waiting for more keys, for
+    /// background jobs loading many keys.
+    Throughput,
+}
+
+/// The settings of a builder, applied to the loader once it is built.
+#[derive(Clone, Copy, Default)]
+struct Settings {
+    max_batch_size: Option<usize>,
+    yield_count: Option<usize>,
+    delay: Option<Duration>,
+    max_wait_rounds: Option<usize>,
+    min_batch_size: Option<(usize, Duration)>,
+    chunk_size: Option<usize>,
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    load_timeout: Option<Duration>,
+}
+
+impl Settings {
+    fn validate(&self, default_max_batch_size: usize) -> Result<(), BuildError> {
+        if self.max_batch_size == Some(0) {
+            return Err(BuildError::ZeroMaxBatchSize);
+        }
+        if self.chunk_size == Some(0) {
+            return Err(BuildError::ZeroChunkSize);
+        }
+        if self.yield_count.is_some() && self.delay.is_some() {
+            return Err(BuildError::ConflictingWait);
+        }
+        let max_batch_size = self.max_batch_size.unwrap_or(default_max_batch_size);
+        match self.min_batch_size {
+            Some((min_batch_size, _)) if min_batch_size > max_batch_size => {
+                Err(BuildError::MinBatchSizeAboveMax {
+                    min_batch_size,
+                    max_batch_size,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Applies `settings` to a loader of either module, whose builder methods are the same.
+macro_rules! configure {
+    ($loader:expr, $settings:expr) => {{
+        let (mut loader, settings) = ($loader, $settings);
+        if let Some(max_batch_size) = settings.max_batch_size {
+            loader = loader.with_max_batch_size(max_batch_size);
+        }
+        if let Some(yield_count) = settings.yield_count {
+            loader = loader.with_yield_count(yield_count);
+        }
+        #[cfg(any(
+            feature = "runtime-async-std",
+            feature = "runtime-tokio",
+            feature = "runtime-wasm"
+        ))]
+        if let Some(delay) = settings.delay {
+            loader = loader.with_custom_wait_for_work(crate::delay_fn(delay, Default::default()));
+        }
+        if let Some(max_wait_rounds) = settings.max_wait_rounds {
+            loader = loader.with_max_wait_rounds(max_wait_rounds);
+        }
+        if let Some((min_batch_size, max_delay)) = settings.min_batch_size {
+            loader = loader.with_min_batch_size(min_batch_size, max_delay);
+        }
+        if let Some(chunk_size) = settings.chunk_size {
+            loader = loader.with_chunk_size(chunk_size);
+        }
+        #[cfg(any(
+            feature = "runtime-async-std",
+            feature = "runtime-tokio",
+            feature = "runtime-wasm"
+        ))]
+        if let Some(timeout) = settings.load_timeout {
+            loader = loader.with_load_timeout(timeout);
+        }
+        loader
+    }};
+}
+
+/// Builds a [`cached::Loader`] or a [`non_cached::Loader`] with the settings both have in
+/// common, e.g. `LoaderBuilder::new(load_fn).max_batch_size(100).cache(cache).build_cached()`,
+/// failing with a [`BuildError`] if they don't make sense together. Settings which are not set
+/// keep the defaults of the loader; other settings are set on the built loader.
+///
+/// `C` is the cache set with [`LoaderBuilder::cache`], or `()` if none was set, in which case
+/// [`LoaderBuilder::build_cached`] caches values in a `HashMap`.
+pub struct LoaderBuilder<K, V, F, C = ()> {
+    load_fn: F,
+    cache: C,
+    settings: Settings,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, F> LoaderBuilder<K, V, F> {
+    pub fn new(load_fn: F) -> Self {
+        LoaderBuilder {
+            load_fn,
+            cache: (),
+            settings: Settings::default(),
+            _types: PhantomData,
+        }
+    }
+
+    /// Caches values in `cache`, see [`cached::Loader::with_cache`]. A loader with a cache can
+    /// only be built with [`LoaderBuilder::build_cached`].
+    pub fn cache<C>(self, cache: C) -> LoaderBuilder<K, V, F, C> {
+        LoaderBuilder {
+            load_fn: self.load_fn,
+            cache,
+            settings: self.settings,
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<K, V, F, C> LoaderBuilder<K, V, F, C> {
+    /// Applies the settings of `preset`. They include a yield count, so presets don't combine
+    /// with a delay; settings made afterwards override those of the preset.
+    pub fn preset(self, preset: Preset) -> Self {
+        match preset {
+            Preset::LowLatency => self.max_batch_size(100).yield_count(1).max_wait_rounds(1),
+            Preset::Throughput => self.max_batch_size(1000).yield_count(50).max_wait_rounds(3),
+        }
+    }
+
+    /// See [`cached::Loader::with_max_batch_size`]; must not be 0.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.settings.max_batch_size = Some(max_batch_size);
+        self
+    }
+
+    /// See [`cached::Loader::with_yield_count`]; conflicts with a delay.
+    pub fn yield_count(mut self, yield_count: usize) -> Self {
+        self.settings.yield_count = Some(yield_count);
+        self
+    }
+
+    /// Waits for `delay` before dispatching, see [`delay_fn`](crate::delay_fn); conflicts with
+    /// a yield count.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.settings.delay = Some(delay);
+        self
+    }
+
+    /// See [`cached::Loader::with_max_wait_rounds`].
+    pub fn max_wait_rounds(mut self, max_wait_rounds: usize) -> Self {
+        self.settings.max_wait_rounds = Some(max_wait_rounds);
+        self
+    }
+
+    /// See [`cached::Loader::with_min_batch_size`]; must not exceed the max batch size.
+    pub fn min_batch_size(mut self, min_batch_size: usize, max_delay: Duration) -> Self {
+        self.settings.min_batch_size = Some((min_batch_size, max_delay));
+        self
+    }
+
+    /// See [`cached::Loader::with_chunk_size`]; must not be 0.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.settings.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// See [`cached::Loader::with_load_timeout`].
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn load_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.load_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<K, V, F> LoaderBuilder<K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    pub fn build(self) -> Result<non_cached::Loader<K, V, F>, BuildError> {
+        let loader = non_cached::Loader::new(self.load_fn);
+        self.settings.validate(loader.max_batch_size())?;
+        Ok(configure!(loader, self.settings))
+    }
+
+    pub fn build_cached(self) -> Result<cached::Loader<K, V, F, HashMap<K, V>>, BuildError> {
+        self.cache(HashMap::new()).build_cached()
+    }
+}
+
+impl<K, V, F, C> LoaderBuilder<K, V, F, C>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: cached::Cache<Key = K, Val = V>,
+{
+    pub fn build_cached(self) -> Result<cached::Loader<K, V, F, C>, BuildError> {
+        let loader = cached::Loader::with_cache(self.load_fn, self.cache);
+        self.settings.validate(loader.max_batch_size())?;
+        Ok(configure!(loader, self.settings))
+    }
+}
@@ -1,10 +1,51 @@
-use crate::runtime::{Arc, Mutex};
-use crate::{yield_fn, BatchFn, WaitForWorkFn};
+use crate::async_cache::{BatchStart, BoxFuture, DynAsyncCache};
+use crate::batch_fn::{group_by, load_batch, planned, GroupFn, LoadFns, PlanFn};
+use crate::batching::{
+    chunk, run_concurrently, split_by_cost, wait_for_work, Lock, Pending, Turns,
+};
+use crate::builder::LoaderBuilder;
+#[cfg(feature = "debug-diagnostics")]
+use crate::diagnostics::LockHolds;
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+use crate::idle::IdleTicker;
+use crate::journal::Journal;
+#[cfg(feature = "otel")]
+use crate::otel::OtelObserver;
+use crate::redact::{describe, DebugKeys, KeyRedactor};
+use crate::runtime::{
+    try_lock, Arc, DefaultRuntime, Instant, Mutex, MutexGuard, Runtime, SystemTime,
+};
+use crate::shadow::{Shadow, ShadowHook};
+use crate::watch::Watchers;
+use crate::{
+    Abandoned, ArcBatchFn, Backpressure, Barrier, Barriers, BatchPlanner, ChunkPolicy,
+    ConsistencyMode, ErrorCaching, InFlight, KeyCostFn, KeyFilter, LoadError, MissingKeyAction,
+    MissingKeyHandler, MissingKeyPolicy, NoopObserver, NormalizeFn, Observer, ResultPolicy,
+    RetryPolicy, SendBatchFn, Sendable, TryBatchFn, Wait, WaitForWorkFn, Waiting,
+};
+use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet};
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
+use std::future::{poll_fn, Future};
 use std::hash::{BuildHasher, Hash};
-use std::io::{Error, ErrorKind};
 use std::iter::IntoIterator;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Weak;
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+pub use crate::async_cache::{AsyncCache, SyncCache};
+pub use crate::bitset::{BitsetCache, DenseKey};
+pub use crate::codec::{CodecCache, ValueCodec};
+pub use crate::tiered::TieredCache;
+pub use crate::watch::Subscription;
+pub use crate::weak::WeakCache;
+pub use crate::weighted::WeightedCache;
 
 pub trait Cache {
     type Key;
@@ -13,6 +54,36 @@ pub trait Cache {
     fn insert(&mut self, key: Self::Key, val: Self::Val);
     fn remove(&mut self, key: &Self::Key) -> Option<Self::Val>;
     fn clear(&mut self);
+
+    /// The number of cached values, if the cache keeps count, e.g. for the `Debug` output of the
+    /// loader. Defaults to `None`.
+    fn len_hint(&self) -> Option<usize> {
+        None
+    }
+
+    /// How much `val` cached under `key` counts against the capacity of a cache bounded by
+    /// weight, e.g. an estimate of its size in bytes, see [`WeightedCache`]. Defaults to 1, so
+    /// that the weight of a cache is its number of values.
+    fn weight(&self, key: &Self::Key, val: &Self::Val) -> usize {
+        let _ = (key, val);
+        1
+    }
+
+    /// Looks up all of `keys` at once, e.g. with a single `MGET` of a remote store. Defaults to
+    /// one `get` after another.
+    fn get_many(&mut self, keys: &[Self::Key]) -> Vec<Option<Self::Val>>
+    where
+        Self::Val: Clone,
+    {
+        keys.iter().map(|key| self.get(key).cloned()).collect()
+    }
+
+    /// Inserts all of `entries` at once. Defaults to one `insert` after another.
+    fn insert_many(&mut self, entries: Vec<(Self::Key, Self::Val)>) {
+        for (key, val) in entries.into_iter() {
+            self.insert(key, val);
+        }
+    }
 }
 
 impl<K, V, S: BuildHasher> Cache for HashMap<K, V, S>
@@ -41,236 +112,3117 @@ where
     fn clear(&mut self) {
         HashMap::clear(self)
     }
+
+    #[inline]
+    fn len_hint(&self) -> Option<usize> {
+        Some(HashMap::len(self))
+    }
 }
 
-struct State<K, V, C = HashMap<K, V>>
-where
-    C: Cache<Key = K, Val = V>,
-{
-    completed: C,
-    pending: HashSet<K>,
+/// A [`Cache`] which can list its entries, so that the cache of a loader can be exported, see
+/// [`Loader::export_cache`].
+pub trait CacheEntries: Cache {
+    fn entries(&self) -> Vec<(Self::Key, Self::Val)>;
+
+    fn keys(&self) -> Vec<Self::Key> {
+        self.entries().into_iter().map(|(k, _)| k).collect()
+    }
 }
 
-impl<K: Eq + Hash, V, C> State<K, V, C>
+impl<K, V, S: BuildHasher> CacheEntries for HashMap<K, V, S>
 where
-    C: Cache<Key = K, Val = V>,
+    K: Eq + Hash + Clone,
+    V: Clone,
 {
-    fn with_cache(cache: C) -> Self {
-        State {
-            completed: cache,
-            pending: HashSet::new(),
-        }
+    fn entries(&self) -> Vec<(K, V)> {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    fn keys(&self) -> Vec<K> {
+        HashMap::keys(self).cloned().collect()
     }
 }
 
-pub struct Loader<K, V, F, C = HashMap<K, V>>
+type Version = u64;
+
+type Ticket = u64;
+
+/// Identifies the principal, e.g. the user, a loader clone loads values on behalf of, see
+/// [`Loader::for_principal`].
+pub type Principal = Arc<str>;
+
+/// A change of a value pushed to a loader from outside, e.g. from a change data capture stream,
+/// see [`Loader::apply_update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Update<V> {
+    /// The value was created or changed.
+    Upsert(V),
+    /// The value was deleted.
+    Delete,
+}
+
+/// Applies `update` of `key` to `cache`, recording `version` so that the results of batches
+/// which were already in flight cannot overwrite it. Returns whether the cache was changed, i.e.
+/// unless [`ConsistencyMode::Snapshot`] kept the cached value.
+fn apply_update<K, V, C, S>(
+    cache: &mut C,
+    versions: &mut HashMap<K, Version, S>,
+    key: K,
+    update: Update<V>,
+    version: Option<Version>,
+    mode: ConsistencyMode,
+) -> bool
 where
     K: Eq + Hash + Clone,
-    V: Clone,
-    F: BatchFn<K, V>,
     C: Cache<Key = K, Val = V>,
+    S: BuildHasher,
 {
-    state: Arc<Mutex<State<K, V, C>>>,
-    load_fn: Arc<Mutex<F>>,
-    wait_for_work_fn: Arc<dyn WaitForWorkFn>,
-    max_batch_size: usize,
+    if mode == ConsistencyMode::Snapshot
+        && matches!(update, Update::Upsert(_))
+        && cache.get(&key).is_some()
+    {
+        return false;
+    }
+    if let Some(version) = version {
+        versions.insert(key.clone(), version);
+    }
+    match update {
+        Update::Upsert(val) => cache.insert(key, val),
+        Update::Delete => {
+            cache.remove(&key);
+        }
+    }
+    true
+}
+
+/// The cache of the values loaded on behalf of a single principal.
+struct Scope<K, V, S> {
+    completed: HashMap<K, V, S>,
+    versions: HashMap<K, Version, S>,
 }
 
-impl<K, V, F, C> Clone for Loader<K, V, F, C>
+impl<K, V, S: Clone> Scope<K, V, S> {
+    fn with_hasher(hasher: &S) -> Self {
+        Scope {
+            completed: HashMap::with_hasher(hasher.clone()),
+            versions: HashMap::with_hasher(hasher.clone()),
+        }
+    }
+}
+
+/// The state lock of a loader as injected into the batching core, see [`Loader::lock_state`].
+struct StateLock<'a, L>(&'a L);
+
+impl<'a, K, V, F, C, O, S> Lock for StateLock<'a, Loader<K, V, F, C, O, S>>
 where
     K: Eq + Hash + Clone,
     V: Clone,
-    F: BatchFn<K, V>,
+    F: TryBatchFn<K, V>,
     C: Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
 {
-    fn clone(&self) -> Self {
-        Loader {
-            state: self.state.clone(),
-            max_batch_size: self.max_batch_size,
-            load_fn: self.load_fn.clone(),
-            wait_for_work_fn: self.wait_for_work_fn.clone(),
-        }
+    type State = State<K, V, C, S>;
+    type Guard = MutexGuard<'a, State<K, V, C, S>>;
+
+    fn lock(&self) -> impl Future<Output = Self::Guard> {
+        self.0.lock_state()
     }
 }
 
-#[allow(clippy::implicit_hasher)]
-impl<K, V, F> Loader<K, V, F, HashMap<K, V>>
+/// Who requested a pending key, so that its value is only cached for them: whether the key was
+/// requested without a principal too, and by which principals.
+type Requesters = (bool, HashSet<Principal>);
+
+/// The outcome of the batch of a key, with the tickets of the callers which haven't read it.
+type Delivery<V> = (Result<V, LoadError>, HashSet<Ticket>);
+
+/// How errors are cached: the function telling errors apart from other values, and the policy.
+type ErrorPolicy<V> = (fn(&V) -> bool, ErrorCaching);
+
+/// Returns the TTL of a loaded value, or `None` to cache it for good, see
+/// [`Loader::with_cache_policy`].
+type CachePolicyFn<V> = dyn Fn(&V) -> Option<Duration> + Send + Sync;
+
+/// Spawns the refresh of a stale key on behalf of a loader with the given settings and
+/// principal, see [`Loader::with_stale_while_revalidate`].
+type RevalidateFn<K, V, F> = dyn Fn(&Arc<Config<K, V, F>>, Option<&Principal>, K) + Send + Sync;
+
+/// How long a value loaded by a batch is cached.
+enum Lifetime {
+    Forever,
+    Ttl(Duration),
+    Never,
+}
+
+struct State<K, V, C = HashMap<K, V>, S = RandomState>
 where
-    K: Eq + Hash + Clone + Debug,
-    V: Clone,
-    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
 {
-    pub fn new(load_fn: F) -> Loader<K, V, F, HashMap<K, V>> {
-        Loader::with_cache(load_fn, HashMap::new())
-    }
+    completed: C,
+    pending: Pending<K, S>,
+    // Version of the last direct write (e.g. `prime`) per key, only tracked while a batch is in
+    // flight so that results fetched before the write cannot overwrite it.
+    versions: HashMap<K, Version, S>,
+    version_seq: Version,
+    // Keys deleted by an update while a batch was in flight, whose callers get `NotFound` rather
+    // than the value the batch fetched before the deletion. Cleared along with the versions.
+    deleted: HashSet<K, S>,
+    // Tickets of the callers waiting for each pending key, so that a key is only dropped from
+    // the next batch once all of its callers are gone.
+    waiters: HashMap<Arc<K>, HashSet<Ticket>, S>,
+    ticket_seq: Ticket,
+    // Outcomes of completed batches with the tickets of the callers that haven't read them yet,
+    // so that they get the value even if it was cleared or evicted from the cache meanwhile.
+    delivered: HashMap<Arc<K>, Delivery<V>, S>,
+    // Number of keys queued so far, which tells waiting callers whether keys are still arriving.
+    enqueued: usize,
+    // The turns in which the pending keys were queued, see `ChunkPolicy::RoundRobin`.
+    turns: Turns,
+    // Caches per principal, and the requesters of pending keys requested by any principal.
+    scoped: HashMap<Principal, Scope<K, V, S>>,
+    requesters: HashMap<Arc<K>, Requesters, S>,
+    // Pending keys requested fresh, which are not looked up in the async cache.
+    fresh: HashSet<Arc<K>, S>,
+    // Pending keys loaded once more after they were missing, see `MissingKeyAction::Retry`.
+    retried: HashSet<Arc<K>, S>,
+    // When cached values expire: errors cached with `ErrorCaching::Ttl`, values primed with a
+    // TTL and values given a TTL by the cache policy.
+    expiry: HashMap<K, Instant, S>,
+    // The subscribers of keys, see `Loader::subscribe`.
+    watchers: Watchers<K, V, S>,
+    // The current batching window and the number of batches dispatched within it.
+    window: u64,
+    window_batches: usize,
+    // Number of batch function calls made so far and the keys passed to them.
+    batches: usize,
+    batched_keys: usize,
+    // The hasher of the maps keyed by keys, cloned into the maps created later on.
+    hasher: S,
 }
 
-impl<K, V, F, C> Loader<K, V, F, C>
+impl<K: Eq + Hash, V, C, S> State<K, V, C, S>
 where
-    K: Eq + Hash + Clone + Debug,
-    V: Clone,
-    F: BatchFn<K, V>,
     C: Cache<Key = K, Val = V>,
+    S: BuildHasher + Clone,
 {
-    pub fn with_cache(load_fn: F, cache: C) -> Loader<K, V, F, C> {
-        Loader {
-            state: Arc::new(Mutex::new(State::with_cache(cache))),
-            load_fn: Arc::new(Mutex::new(load_fn)),
-            max_batch_size: 200,
-            wait_for_work_fn: Arc::new(yield_fn(10)),
+    fn with_cache(cache: C, hasher: S) -> Self {
+        State {
+            completed: cache,
+            pending: Pending::with_hasher(hasher.clone()),
+            versions: HashMap::with_hasher(hasher.clone()),
+            version_seq: 0,
+            deleted: HashSet::with_hasher(hasher.clone()),
+            waiters: HashMap::with_hasher(hasher.clone()),
+            ticket_seq: 0,
+            delivered: HashMap::with_hasher(hasher.clone()),
+            enqueued: 0,
+            turns: Turns::default(),
+            scoped: HashMap::new(),
+            requesters: HashMap::with_hasher(hasher.clone()),
+            fresh: HashSet::with_hasher(hasher.clone()),
+            retried: HashSet::with_hasher(hasher.clone()),
+            expiry: HashMap::with_hasher(hasher.clone()),
+            watchers: Watchers::with_hasher(hasher.clone()),
+            window: 0,
+            window_batches: 0,
+            batches: 0,
+            batched_keys: 0,
+            hasher,
         }
     }
 
-    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
-        self.max_batch_size = max_batch_size;
-        self
+    /// The cache of `principal`, created on first use.
+    fn scope_mut(&mut self, principal: Principal) -> &mut Scope<K, V, S> {
+        let hasher = &self.hasher;
+        self.scoped
+            .entry(principal)
+            .or_insert_with(|| Scope::with_hasher(hasher))
     }
 
-    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
-        self.wait_for_work_fn = Arc::new(yield_fn(yield_count));
-        self
+    fn next_version(&mut self) -> Version {
+        self.version_seq = self.version_seq.wrapping_add(1);
+        self.version_seq
     }
 
-    /// Replaces the yielding for work behavior with an arbitrary future. Rather than yielding
-    /// the runtime repeatedly this will generate and `.await` a future of your choice.
-    /// ***This is incompatible with*** [`Self::with_yield_count()`].
-    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
-        self.wait_for_work_fn = Arc::new(wait_for_work_fn);
-        self
+    /// Publishes `update` of `key` in the cache of `principal`, or the shared cache without one,
+    /// to the subscribers of the key, numbered by a new version.
+    fn publish(&mut self, principal: Option<&Principal>, key: &K, update: &Update<V>)
+    where
+        V: Clone,
+    {
+        if self.watchers.is_watched(key) {
+            let version = self.next_version();
+            self.watchers.publish(principal, key, update, version);
+        }
     }
 
-    pub fn max_batch_size(&self) -> usize {
-        self.max_batch_size
+    /// Publishes the deletion of every key to the subscribers watching the caches of the
+    /// principals for which `cleared` returns true, as these caches are cleared.
+    fn publish_clear(&mut self, cleared: impl Fn(Option<&Principal>) -> bool)
+    where
+        V: Clone,
+    {
+        if !self.watchers.is_empty() {
+            let version = self.next_version();
+            self.watchers.publish_all(cleared, &Update::Delete, version);
+        }
+    }
+
+    /// Returns the version the results of a batch starting now are written with.
+    fn begin_batch(&self) -> Version {
+        self.version_seq
     }
 
-    pub async fn try_load(&self, key: K) -> Result<V, Error> {
-        let mut state = self.state.lock().await;
-        if let Some(v) = state.completed.get(&key) {
-            return Ok((*v).clone());
+    /// Returns the version a write is recorded with, if `mode` tracks writes while batches are
+    /// `in_flight`.
+    fn write_version(&mut self, in_flight: bool, mode: ConsistencyMode) -> Option<Version> {
+        if in_flight && mode != ConsistencyMode::Eventual {
+            Some(self.next_version())
+        } else {
+            None
         }
+    }
 
-        if !state.pending.contains(&key) {
-            state.pending.insert(key.clone());
-            if state.pending.len() >= self.max_batch_size {
-                let keys = state.pending.drain().collect::<Vec<K>>();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (k, v) in load_ret.into_iter() {
-                    state.completed.insert(k, v);
+    /// Writes the results of a batch started at `version` into the cache, discarding values of
+    /// keys which have been written with a newer version in the meantime, and with
+    /// [`ConsistencyMode::Snapshot`] values of keys which are cached already. `in_flight` tells
+    /// whether other batches are still in flight. Each value is cached for the [`Lifetime`]
+    /// `lifetime` returns for it.
+    fn complete_batch(
+        &mut self,
+        version: Version,
+        keys: Vec<K>,
+        ret: Result<HashMap<K, V>, LoadError>,
+        in_flight: bool,
+        mode: ConsistencyMode,
+        lifetime: &dyn Fn(&V) -> Lifetime,
+    ) where
+        K: Clone,
+        V: Clone,
+    {
+        let mut requesters = HashMap::with_hasher(self.hasher.clone());
+        let mut waiters = HashMap::with_hasher(self.hasher.clone());
+        for k in keys.iter() {
+            self.pending.remove(k);
+            if let Some((k, tickets)) = self.waiters.remove_entry(k) {
+                waiters.insert(k, tickets);
+            }
+            self.fresh.remove(k);
+            if let Some((k, r)) = self.requesters.remove_entry(k) {
+                requesters.insert(k, r);
+            }
+        }
+        let snapshot = mode == ConsistencyMode::Snapshot;
+        match ret {
+            Ok(values) => {
+                let mut shared = Vec::with_capacity(values.len());
+                let now = Instant::now();
+                for (k, v) in values.into_iter() {
+                    // keys without requesters were only requested without a principal, if at all
+                    let (unscoped, principals) =
+                        requesters.remove(&k).unwrap_or((true, HashSet::new()));
+                    let cache = match lifetime(&v) {
+                        Lifetime::Forever => {
+                            if !self.expiry.is_empty() {
+                                self.expiry.remove(&k);
+                            }
+                            true
+                        }
+                        Lifetime::Ttl(ttl) => {
+                            self.expiry.insert(k.clone(), now + ttl);
+                            true
+                        }
+                        Lifetime::Never => false,
+                    };
+                    let principals = principals.into_iter().filter(|_| cache);
+                    let mut overwritten = false;
+                    for p in principals {
+                        let scope = self.scope_mut(p.clone());
+                        let newer =
+                            matches!(scope.versions.get(&k), Some(written) if *written > version);
+                        let insert = !(newer || (snapshot && scope.completed.contains_key(&k)));
+                        if insert {
+                            scope.completed.insert(k.clone(), v.clone());
+                            self.publish(Some(&p), &k, &Update::Upsert(v.clone()));
+                        }
+                        overwritten |= newer;
+                    }
+                    let newer =
+                        matches!(self.versions.get(&k), Some(written) if *written > version);
+                    overwritten |= unscoped && newer;
+                    // callers read values written meanwhile from the cache first
+                    let deleted = overwritten && self.deleted.contains(&k);
+                    if let Some((key, tickets)) = waiters.remove_entry(&k).filter(|_| !deleted) {
+                        self.deliver(key, Ok(v.clone()), tickets);
+                    }
+                    if unscoped && !newer && cache {
+                        shared.push((k, v));
+                    }
+                }
+                if snapshot {
+                    let keys = shared.iter().map(|(k, _)| k.clone()).collect::<Vec<K>>();
+                    let mut cached = self.completed.get_many(&keys).into_iter();
+                    shared.retain(|_| cached.next().flatten().is_none());
+                }
+                if !self.watchers.is_empty() {
+                    for (k, v) in shared.iter() {
+                        self.publish(None, k, &Update::Upsert(v.clone()));
+                    }
+                }
+                self.completed.insert_many(shared);
+            }
+            Err(e) => {
+                for (k, tickets) in waiters.into_iter() {
+                    self.deliver(k, Err(e.clone()), tickets);
                 }
-                return state.completed.get(&key).cloned().ok_or(Error::new(
-                    ErrorKind::NotFound,
-                    format!("could not lookup result for given key: {:?}", key),
-                ));
             }
         }
-        drop(state);
+        if !in_flight {
+            self.settle();
+        }
+    }
 
-        (self.wait_for_work_fn)().await;
+    /// Forgets the versions of the writes made while batches were in flight, once none is.
+    fn settle(&mut self) {
+        self.versions.clear();
+        self.deleted.clear();
+        for scope in self.scoped.values_mut() {
+            scope.versions.clear();
+        }
+    }
+
+    /// Whether `key` was written to the cache of `principal`, or to the shared cache without
+    /// one, after `version` while batches were in flight.
+    fn written_since(&self, principal: Option<&Principal>, key: &K, version: Version) -> bool {
+        let versions = match principal {
+            None => &self.versions,
+            Some(p) => match self.scoped.get(p) {
+                Some(scope) => &scope.versions,
+                None => return false,
+            },
+        };
+        matches!(versions.get(key), Some(written) if *written > version)
+    }
 
-        let mut state = self.state.lock().await;
-        if let Some(v) = state.completed.get(&key) {
-            return Ok((*v).clone());
+    fn write(
+        &mut self,
+        principal: Option<&Principal>,
+        key: K,
+        update: Update<V>,
+        in_flight: bool,
+        mode: ConsistencyMode,
+    ) where
+        K: Clone,
+        V: Clone,
+    {
+        // direct writes are cached for good
+        self.expiry.remove(&key);
+        let version = self.write_version(in_flight, mode);
+        let change = self
+            .watchers
+            .is_watched(&key)
+            .then(|| (key.clone(), update.clone()));
+        let applied = match principal {
+            None => apply_update(
+                &mut self.completed,
+                &mut self.versions,
+                key,
+                update,
+                version,
+                mode,
+            ),
+            Some(p) => {
+                let scope = self.scope_mut(p.clone());
+                apply_update(
+                    &mut scope.completed,
+                    &mut scope.versions,
+                    key,
+                    update,
+                    version,
+                    mode,
+                )
+            }
+        };
+        if let Some((key, update)) = change.filter(|_| applied) {
+            self.publish(principal, &key, &update);
         }
+    }
 
-        if !state.pending.is_empty() {
-            let keys = state.pending.drain().collect::<Vec<K>>();
-            let mut load_fn = self.load_fn.lock().await;
-            let load_ret = load_fn.load(keys.as_ref()).await;
-            drop(load_fn);
-            for (k, v) in load_ret.into_iter() {
-                state.completed.insert(k, v);
+    /// Applies `update` to the shared cache, and to the caches of the principals which have
+    /// loaded `key` or are waiting for it.
+    fn update(&mut self, key: K, update: Update<V>, in_flight: bool, mode: ConsistencyMode)
+    where
+        K: Clone,
+        V: Clone,
+    {
+        // direct writes are cached for good
+        self.expiry.remove(&key);
+        let version = self.write_version(in_flight, mode);
+        let mut principals = self
+            .scoped
+            .iter()
+            .filter(|(_, scope)| scope.completed.contains_key(&key))
+            .map(|(p, _)| p.clone())
+            .collect::<Vec<_>>();
+        if let Some((_, requesters)) = self.requesters.get(&key) {
+            principals.extend(requesters.iter().cloned());
+        }
+        for p in principals.into_iter() {
+            let scope = self.scope_mut(p.clone());
+            let applied = apply_update(
+                &mut scope.completed,
+                &mut scope.versions,
+                key.clone(),
+                update.clone(),
+                version,
+                mode,
+            );
+            if applied {
+                self.publish(Some(&p), &key, &update);
             }
         }
+        let change = self.watchers.is_watched(&key).then(|| key.clone());
+        let applied = apply_update(
+            &mut self.completed,
+            &mut self.versions,
+            key,
+            update.clone(),
+            version,
+            mode,
+        );
+        if let Some(key) = change.filter(|_| applied) {
+            self.publish(None, &key, &update);
+        }
+    }
 
-        state.completed.get(&key).cloned().ok_or(Error::new(
-            ErrorKind::NotFound,
-            format!("could not lookup result for given key: {:?}", key),
-        ))
+    /// Returns the cached value of `key` for `principal`, or the shared one without a principal.
+    fn lookup(&mut self, principal: Option<&Principal>, key: &K) -> Option<&V> {
+        match principal {
+            None => self.completed.get(key),
+            Some(p) => self.scoped.get(p)?.completed.get(key),
+        }
     }
 
-    pub async fn load(&self, key: K) -> V {
-        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    /// Returns the cached values of `keys` for `principal`, like [`State::lookup`] for each key.
+    fn lookup_many(&mut self, principal: Option<&Principal>, keys: &[K]) -> Vec<Option<V>>
+    where
+        V: Clone,
+    {
+        match principal {
+            None => self.completed.get_many(keys),
+            Some(p) => match self.scoped.get_mut(p) {
+                Some(scope) => scope.completed.get_many(keys),
+                None => keys.iter().map(|_| None).collect(),
+            },
+        }
     }
 
-    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, Error> {
-        let mut state = self.state.lock().await;
-        let mut ret = HashMap::new();
-        let mut rest = Vec::new();
-        for key in keys.into_iter() {
-            if let Some(v) = state.completed.get(&key).cloned() {
-                ret.insert(key, v);
-                continue;
+    /// How long ago the cached value of `key` expired, if it did.
+    fn stale_for(&self, key: &K) -> Option<Duration> {
+        let expiry = self.expiry.get(key)?;
+        Instant::now().checked_duration_since(*expiry)
+    }
+
+    /// Whether the cached value of `key` expired, see [`Loader::prime_with_ttl`].
+    fn expired(&self, key: &K) -> bool {
+        !self.expiry.is_empty()
+            && self
+                .expiry
+                .get(key)
+                .is_some_and(|expiry| *expiry <= Instant::now())
+    }
+
+    /// Removes the cached value of `key` for `principal`, or the shared one without a principal.
+    fn remove(&mut self, principal: Option<&Principal>, key: &K) {
+        match principal {
+            None => {
+                self.completed.remove(key);
             }
-            if !state.pending.contains(&key) {
-                state.pending.insert(key.clone());
-                if state.pending.len() >= self.max_batch_size {
-                    let keys = state.pending.drain().collect::<Vec<K>>();
-                    let mut load_fn = self.load_fn.lock().await;
-                    let load_ret = load_fn.load(keys.as_ref()).await;
-                    drop(load_fn);
-                    for (k, v) in load_ret.into_iter() {
-                        state.completed.insert(k, v);
-                    }
+            Some(p) => {
+                if let Some(scope) = self.scoped.get_mut(p) {
+                    scope.completed.remove(key);
                 }
             }
-            rest.push(key);
         }
-        drop(state);
+    }
+
+    /// Queues `key`, costing `cost`, for the next batch on behalf of `principal`, returning the
+    /// pending key. The key is cloned unless it is pending already.
+    fn enqueue(&mut self, principal: Option<&Principal>, key: &K, cost: usize) -> Arc<K>
+    where
+        K: Clone,
+    {
+        let (key, pending) = match self.pending.get(key) {
+            Some(key) => (key.clone(), true),
+            None => {
+                let key = Arc::new(key.clone());
+                self.pending.insert(key.clone(), self.enqueued, cost);
+                self.turns.queued(self.enqueued);
+                self.enqueued = self.enqueued.wrapping_add(1);
+                (key, false)
+            }
+        };
+        match principal {
+            // a pending key without requesters has only been requested without a principal
+            Some(p) => {
+                self.requesters
+                    .entry(key.clone())
+                    .or_insert_with(|| (pending, HashSet::new()))
+                    .1
+                    .insert(p.clone());
+            }
+            None => {
+                if let Some((unscoped, _)) = self.requesters.get_mut(&key) {
+                    *unscoped = true;
+                }
+            }
+        }
+        key
+    }
 
-        (self.wait_for_work_fn)().await;
+    /// Registers a caller waiting for the pending `key`, returning its ticket.
+    fn wait(&mut self, key: &Arc<K>) -> Ticket {
+        self.ticket_seq = self.ticket_seq.wrapping_add(1);
+        self.waiters
+            .entry(key.clone())
+            .or_default()
+            .insert(self.ticket_seq);
+        self.ticket_seq
+    }
 
-        if !rest.is_empty() {
-            let mut state = self.state.lock().await;
-            if !state.pending.is_empty() {
-                let keys = state.pending.drain().collect::<Vec<K>>();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (k, v) in load_ret.into_iter() {
-                    state.completed.insert(k, v);
+    /// Forgets the tickets of dropped callers, removing pending keys nobody waits for anymore.
+    fn abandon(&mut self, abandoned: Vec<(Arc<K>, Ticket)>) {
+        for (key, ticket) in abandoned.into_iter() {
+            if let Some(tickets) = self.waiters.get_mut(&key) {
+                tickets.remove(&ticket);
+                if tickets.is_empty() {
+                    self.waiters.remove(&key);
+                    self.pending.remove(&key);
+                    self.requesters.remove(&key);
+                    self.fresh.remove(&key);
+                    self.retried.remove(&key);
+                }
+            } else if let Some((_, tickets)) = self.delivered.get_mut(&key) {
+                tickets.remove(&ticket);
+                if tickets.is_empty() {
+                    self.delivered.remove(&key);
                 }
             }
+        }
+    }
+
+    /// Whether `key` is only pending for callers without a principal, so that its value may be
+    /// shared through an async cache.
+    fn is_shared(&self, key: &K) -> bool {
+        self.requesters
+            .get(key)
+            .is_none_or(|(_, principals)| principals.is_empty())
+    }
+
+    /// Delivers the outcome of the batch of `key` to the callers holding `tickets`.
+    fn deliver(&mut self, key: Arc<K>, r: Result<V, LoadError>, mut tickets: HashSet<Ticket>) {
+        // callers which haven't read the outcome of an earlier batch get this one
+        if let Some((_, earlier)) = self.delivered.remove(&key) {
+            tickets.extend(earlier);
+        }
+        self.delivered.insert(key, (r, tickets));
+    }
+
+    /// Takes the outcome of the batch of `key` delivered to the caller holding `ticket`, if any.
+    fn take_delivered(&mut self, key: &K, ticket: Ticket) -> Option<Result<V, LoadError>>
+    where
+        V: Clone,
+    {
+        let (v, tickets) = self.delivered.get_mut(key)?;
+        if !tickets.remove(&ticket) {
+            return None;
+        }
+        if !tickets.is_empty() {
+            return Some(v.clone());
+        }
+        self.delivered.remove(key).map(|(v, _)| v)
+    }
+
+    /// Returns the value of `key` for `principal` once the caller holding `ticket` was woken by
+    /// its batch, or why it is missing, formatting the key with `redactor`.
+    fn get(
+        &mut self,
+        principal: Option<&Principal>,
+        key: &K,
+        ticket: Ticket,
+        redactor: Option<&dyn KeyRedactor<K>>,
+    ) -> Result<V, LoadError>
+    where
+        V: Clone,
+    {
+        let delivered = self.take_delivered(key, ticket);
+        match self.lookup(principal, key) {
+            Some(v) => Ok(v.clone()),
+            None => delivered.unwrap_or_else(|| Err(LoadError::NotFound(describe(redactor, key)))),
+        }
+    }
+
+    /// Returns the values of `keys` for the callers holding `tickets` like [`State::get`],
+    /// looking them up at once.
+    fn get_many(
+        &mut self,
+        principal: Option<&Principal>,
+        keys: &[K],
+        tickets: &[Ticket],
+        redactor: Option<&dyn KeyRedactor<K>>,
+    ) -> Vec<Result<V, LoadError>>
+    where
+        V: Clone,
+    {
+        let delivered = keys
+            .iter()
+            .zip(tickets)
+            .map(|(key, ticket)| self.take_delivered(key, *ticket))
+            .collect::<Vec<_>>();
+        let values = self.lookup_many(principal, keys);
+        keys.iter()
+            .zip(values)
+            .zip(delivered)
+            .map(|((key, v), delivered)| match v {
+                Some(v) => Ok(v),
+                None => {
+                    delivered.unwrap_or_else(|| Err(LoadError::NotFound(describe(redactor, key))))
+                }
+            })
+            .collect()
+    }
+}
+
+/// A batching loader which caches results in `C`.
+///
+/// A requested key is cloned once when it is queued, unless it is pending already, and once
+/// into the batch loading it.
+///
+/// Keys are batched in the order they were first requested: the oldest pending keys always go
+/// out in the first batch of a flush, and keys which don't fit into a flush go out before keys
+/// requested after them, so under sustained load no caller is starved by later callers.
+///
+/// Dropping a load future before it completes withdraws its keys from the next batch, unless
+/// other callers are waiting for them too.
+pub struct Loader<K, V, F, C = HashMap<K, V>, O = NoopObserver, S = RandomState>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    shared: Arc<Shared<K, V, F, C, S>>,
+    config: Arc<Config<K, V, F>>,
+    principal: Option<Principal>,
+    observer: O,
+}
 
-            for key in rest.into_iter() {
-                let v = state.completed.get(&key).cloned().ok_or(Error::new(
-                    ErrorKind::NotFound,
-                    format!("could not lookup result for given key: {:?}", key),
-                ))?;
+/// The state of a loader shared by its clones, which its weak handles don't keep alive.
+struct Shared<K, V, F, C, S>
+where
+    C: Cache<Key = K, Val = V>,
+{
+    state: Mutex<State<K, V, C, S>>,
+    load_fns: Arc<LoadFns<F>>,
+    in_flight: AtomicUsize,
+    barriers: Arc<Barriers>,
+    abandoned: Abandoned<(Arc<K>, Ticket)>,
+    // Keys of flushes dropped while in flight, queued again the next time the state is locked.
+    unloaded: Abandoned<Arc<K>>,
+    completions: Completions,
+    #[cfg(feature = "debug-diagnostics")]
+    lock_holds: LockHolds,
+}
+
+impl<K, V, F, C, S> Shared<K, V, F, C, S>
+where
+    C: Cache<Key = K, Val = V>,
+{
+    fn new(state: State<K, V, C, S>, load_fns: Arc<LoadFns<F>>) -> Arc<Self> {
+        Arc::new(Shared {
+            state: Mutex::new(state),
+            load_fns,
+            in_flight: AtomicUsize::new(0),
+            barriers: Arc::default(),
+            abandoned: Abandoned::default(),
+            unloaded: Abandoned::default(),
+            completions: Completions::default(),
+            #[cfg(feature = "debug-diagnostics")]
+            lock_holds: LockHolds::default(),
+        })
+    }
+}
+
+/// Wakes the callers waiting for keys whose batch is in flight, whenever a batch completes or a
+/// flush is dropped before completing.
+#[derive(Default)]
+struct Completions(std::sync::Mutex<(u64, Vec<Waker>)>);
+
+impl Completions {
+    fn lock(&self) -> std::sync::MutexGuard<'_, (u64, Vec<Waker>)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The number of completions so far.
+    fn count(&self) -> u64 {
+        self.lock().0
+    }
+
+    fn complete(&self) {
+        let wakers = {
+            let mut completions = self.lock();
+            completions.0 = completions.0.wrapping_add(1);
+            mem::take(&mut completions.1)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
 
-                ret.insert(key, v);
+    /// Waits for a completion after the first `seen` ones.
+    async fn after(&self, seen: u64) {
+        poll_fn(|cx| {
+            let mut completions = self.lock();
+            if completions.0 != seen {
+                return Poll::Ready(());
             }
+            completions.1.push(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// The keys a flush took out of the queue, queued again once it is over unless their batch
+/// completed, also if the flush is dropped while in flight so that the remaining callers load
+/// them.
+struct Loading<'a, K> {
+    unloaded: &'a Abandoned<Arc<K>>,
+    completions: &'a Completions,
+    keys: Vec<Arc<K>>,
+}
+
+impl<K: Eq + Hash> Loading<'_, K> {
+    fn finish<S: BuildHasher>(mut self, pending: &mut Pending<K, S>) {
+        for key in mem::take(&mut self.keys).iter() {
+            pending.requeue(key);
         }
+        self.completions.complete();
+    }
+}
 
-        Ok(ret)
+impl<K> Drop for Loading<'_, K> {
+    fn drop(&mut self) {
+        if !self.keys.is_empty() {
+            let mut unloaded = self.unloaded.lock().unwrap_or_else(|e| e.into_inner());
+            unloaded.append(&mut self.keys);
+            drop(unloaded);
+            self.completions.complete();
+        }
     }
+}
 
-    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, V> {
-        self.try_load_many(keys)
-            .await
-            .unwrap_or_else(|e| panic!("{}", e))
+/// The settings of a loader, shared by its clones and weak handles. Setting them on a clone
+/// copies them first, leaving the other clones as they are.
+struct Config<K, V, F> {
+    wait: Wait,
+    runtime: Arc<dyn Runtime>,
+    max_batch_size: usize,
+    max_batches_per_window: usize,
+    max_concurrent_batches: usize,
+    clone_load_fn: Option<fn(&F) -> F>,
+    chunk_policy: ChunkPolicy,
+    max_wait_rounds: usize,
+    min_batch_size: Option<(usize, Duration)>,
+    max_pending: Option<(usize, Backpressure)>,
+    load_timeout: Option<Duration>,
+    retry: Option<Arc<dyn RetryPolicy>>,
+    result_policy: ResultPolicy,
+    shadow: Option<ShadowHook<K>>,
+    journal: Option<Journal<K>>,
+    redactor: Option<Arc<dyn KeyRedactor<K>>>,
+    key_filter: Option<Arc<dyn KeyFilter<K>>>,
+    missing_key_policy: MissingKeyPolicy<V>,
+    missing_key_handler: Option<Arc<MissingKeyHandler<K, V>>>,
+    consistency: ConsistencyMode,
+    group_by: Option<Arc<GroupFn<K>>>,
+    planner: Option<PlanFn<F, K>>,
+    chunk_size: Option<usize>,
+    normalizer: Option<Arc<NormalizeFn<K>>>,
+    key_cost: Option<Arc<KeyCostFn<K>>>,
+    async_cache: Option<Arc<dyn DynAsyncCache<K, V>>>,
+    refresh_errors: Option<fn(&V) -> bool>,
+    cache_policy: Option<Arc<CachePolicyFn<V>>>,
+    name: Option<Arc<str>>,
+    #[cfg(feature = "debug-diagnostics")]
+    lock_hold_threshold: Duration,
+    stale_while_revalidate: Option<Duration>,
+    revalidate: Option<Arc<RevalidateFn<K, V, F>>>,
+    error_caching: Option<ErrorPolicy<V>>,
+}
+
+impl<K, V: Clone, F> Clone for Config<K, V, F> {
+    fn clone(&self) -> Self {
+        Config {
+            wait: self.wait.clone(),
+            runtime: self.runtime.clone(),
+            max_batch_size: self.max_batch_size,
+            max_batches_per_window: self.max_batches_per_window,
+            max_concurrent_batches: self.max_concurrent_batches,
+            clone_load_fn: self.clone_load_fn,
+            chunk_policy: self.chunk_policy,
+            max_wait_rounds: self.max_wait_rounds,
+            min_batch_size: self.min_batch_size,
+            max_pending: self.max_pending,
+            load_timeout: self.load_timeout,
+            retry: self.retry.clone(),
+            result_policy: self.result_policy,
+            shadow: self.shadow.clone(),
+            journal: self.journal.clone(),
+            redactor: self.redactor.clone(),
+            key_filter: self.key_filter.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_handler: self.missing_key_handler.clone(),
+            consistency: self.consistency,
+            group_by: self.group_by.clone(),
+            planner: self.planner,
+            chunk_size: self.chunk_size,
+            normalizer: self.normalizer.clone(),
+            key_cost: self.key_cost.clone(),
+            async_cache: self.async_cache.clone(),
+            refresh_errors: self.refresh_errors,
+            cache_policy: self.cache_policy.clone(),
+            name: self.name.clone(),
+            #[cfg(feature = "debug-diagnostics")]
+            lock_hold_threshold: self.lock_hold_threshold,
+            stale_while_revalidate: self.stale_while_revalidate,
+            revalidate: self.revalidate.clone(),
+            error_caching: self.error_caching,
+        }
     }
+}
 
-    pub async fn prime(&self, key: K, val: V) {
-        let mut state = self.state.lock().await;
-        state.completed.insert(key, val);
+/// Shows the name and the main settings of the loader, along with the number of cached values
+/// and pending keys unless another caller holds the loader state.
+impl<K, V, F, C, O, S> Debug for Loader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Loader");
+        debug
+            .field("name", &self.config.name)
+            .field("max_batch_size", &self.config.max_batch_size)
+            .field("max_wait_rounds", &self.config.max_wait_rounds)
+            .field("load_timeout", &self.config.load_timeout)
+            .field("consistency", &self.config.consistency);
+        match try_lock(&self.shared.state) {
+            Some(state) => debug
+                .field("cached", &state.completed.len_hint())
+                .field("pending", &state.pending.len()),
+            None => debug.field("state", &format_args!("<locked>")),
+        };
+        debug.finish_non_exhaustive()
     }
+}
 
-    pub async fn prime_many(&self, values: impl IntoIterator<Item = (K, V)>) {
-        let mut state = self.state.lock().await;
-        for (k, v) in values.into_iter() {
-            state.completed.insert(k, v);
+impl<K, V, F, C, O, S> Clone for Loader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    O: Clone,
+{
+    fn clone(&self) -> Self {
+        Loader {
+            shared: self.shared.clone(),
+            config: self.config.clone(),
+            principal: self.principal.clone(),
+            observer: self.observer.clone(),
         }
     }
+}
 
-    pub async fn clear(&self, key: K) {
-        let mut state = self.state.lock().await;
-        state.completed.remove(&key);
+/// A handle to a [`Loader`] which doesn't keep its state alive, for background tasks which
+/// should stop once the last [`Loader`] handle is dropped, see [`Loader::downgrade`].
+pub struct WeakLoader<K, V, F, C = HashMap<K, V>, O = NoopObserver, S = RandomState>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    shared: Weak<Shared<K, V, F, C, S>>,
+    config: Arc<Config<K, V, F>>,
+    principal: Option<Principal>,
+    observer: O,
+}
+
+impl<K, V, F, C, O, S> Clone for WeakLoader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    O: Clone,
+{
+    fn clone(&self) -> Self {
+        WeakLoader {
+            shared: self.shared.clone(),
+            config: self.config.clone(),
+            principal: self.principal.clone(),
+            observer: self.observer.clone(),
+        }
     }
+}
 
-    pub async fn clear_all(&self) {
-        let mut state = self.state.lock().await;
-        state.completed.clear()
+impl<K, V, F, C, O, S> WeakLoader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    O: Clone,
+{
+    /// Returns a [`Loader`] sharing the state of the loader this handle was downgraded from,
+    /// unless all of its [`Loader`] handles have been dropped.
+    pub fn upgrade(&self) -> Option<Loader<K, V, F, C, O, S>> {
+        Some(Loader {
+            shared: self.shared.upgrade()?,
+            config: self.config.clone(),
+            principal: self.principal.clone(),
+            observer: self.observer.clone(),
+        })
+    }
+}
+
+/// Where the value of an [`Entry`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The value was cached.
+    Cache,
+    /// The value was loaded by a batch, either dispatched for this entry or already in flight.
+    Batch,
+    /// The key was ruled out by the existence filter, see
+    /// [`Loader::with_existence_filter`], and resolved like a missing key.
+    Filtered,
+}
+
+/// How a key of [`Loader::load_many_detailed`] was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    /// The value was cached.
+    Hit,
+    /// The value was loaded by a batch, either dispatched for this call or already in flight.
+    Loaded,
+    /// The batch function returned no value for the key, or the existence filter ruled it out.
+    /// The key has a value only if the [`MissingKeyPolicy`] or the missing key handler gave it
+    /// one.
+    Missing,
+}
+
+/// The values of [`Loader::load_many_detailed`] along with how every key was resolved, e.g. to
+/// set cache-related response headers.
+#[derive(Debug, Clone)]
+pub struct LoadManyResult<K, V> {
+    pub values: HashMap<K, V>,
+    pub provenance: HashMap<K, Provenance>,
+    /// How long the call took, including the wait for its batches.
+    pub elapsed: Duration,
+}
+
+impl<K: Eq + Hash, V> LoadManyResult<K, V> {
+    /// Number of keys served from the cache.
+    pub fn hits(&self) -> usize {
+        self.count(Provenance::Hit)
+    }
+
+    /// Number of keys loaded by a batch, including missing ones.
+    pub fn misses(&self) -> usize {
+        self.provenance.len() - self.hits()
+    }
+
+    fn count(&self, provenance: Provenance) -> usize {
+        self.provenance
+            .values()
+            .filter(|p| **p == provenance)
+            .count()
+    }
+}
+
+type InsertFn<'a, K, V> = Box<dyn FnOnce(&K, &V) + Send + 'a>;
+
+/// The entry of a key of a [`Loader`], see [`Loader::entry`]. Loading it tells whether the
+/// value was cached or loaded by a batch, e.g. to record its provenance or to tell cold from
+/// warm resolution in metrics.
+pub struct Entry<'a, K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    loader: &'a Loader<K, V, F, C, O, S>,
+    key: K,
+    on_insert: Option<InsertFn<'a, K, V>>,
+}
+
+impl<'a, K, V, F, C, O, S> Entry<'a, K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the cached value of the key without loading it, see [`Loader::get_cached`].
+    pub async fn get(&self) -> Option<V> {
+        self.loader.get_cached(self.key.clone()).await
+    }
+
+    /// Runs `on_insert` with the key and its value when the value is loaded by a batch rather
+    /// than taken from the cache.
+    pub fn on_insert(mut self, on_insert: impl FnOnce(&K, &V) + Send + 'a) -> Self {
+        self.on_insert = Some(Box::new(on_insert));
+        self
+    }
+
+    /// Returns the cached value of the key, or loads it like [`Loader::try_load`].
+    pub async fn try_or_load(self) -> Result<(V, Source), LoadError> {
+        let mut waiting = Waiting::new(&self.loader.shared.abandoned);
+        let ret = self
+            .loader
+            .try_load_waiting(self.key.clone(), false, &mut waiting)
+            .await;
+        waiting.done();
+        let (v, source) = ret?;
+        if let (Source::Batch, Some(on_insert)) = (source, self.on_insert) {
+            on_insert(&self.key, &v);
+        }
+        Ok((v, source))
+    }
+
+    pub async fn or_load(self) -> (V, Source) {
+        self.try_or_load().await.unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// The cache of a parent loader as seen by its children, see [`Loader::child`].
+struct ParentCache<L>(L);
+
+/// Counts a batch of a child as in flight in its parent for as long as it is alive, so that the
+/// parent versions its writes meanwhile, see [`ParentCache::begin`].
+struct ChildBatch<K, V, F, C, S>(Arc<Shared<K, V, F, C, S>>)
+where
+    C: Cache<Key = K, Val = V>;
+
+impl<K, V, F, C, S> Drop for ChildBatch<K, V, F, C, S>
+where
+    C: Cache<Key = K, Val = V>,
+{
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<K, V, F, C, O, S> DynAsyncCache<K, V> for ParentCache<Loader<K, V, F, C, O, S>>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    F: TryBatchFn<K, V> + Send + 'static,
+    C: Cache<Key = K, Val = V> + Send + 'static,
+    O: Observer + Send + Sync,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Snapshots the version of the parent's writes as a batch of the child starts, so that its
+    /// values don't overwrite keys the parent writes while the batch is in flight.
+    fn begin(&self) -> BoxFuture<'_, Option<BatchStart>> {
+        Box::pin(async move {
+            let parent = &self.0;
+            let state = parent.lock_state().await;
+            parent.shared.in_flight.fetch_add(1, Ordering::SeqCst);
+            Some(BatchStart {
+                version: state.begin_batch(),
+                _versioning: Box::new(ChildBatch(parent.shared.clone())),
+            })
+        })
+    }
+
+    fn get_many<'a>(&'a self, keys: &'a [K]) -> BoxFuture<'a, HashMap<K, V>> {
+        Box::pin(async move {
+            let parent = &self.0;
+            let mut state = parent.lock_state().await;
+            let mut ret = HashMap::new();
+            for key in keys.iter() {
+                let expired = state.expired(key);
+                match state.lookup(parent.principal.as_ref(), key) {
+                    Some(v) if !parent.refreshes(v, expired) => {
+                        ret.insert(key.clone(), v.clone());
+                    }
+                    _ => {}
+                }
+            }
+            ret
+        })
+    }
+
+    fn insert_many(&self, values: Vec<(K, V)>, start: Option<BatchStart>) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let parent = &self.0;
+            let mut state = parent.lock_state().await;
+            // the batch counts as in flight in the parent until `start` is dropped
+            let in_flight = start.is_some();
+            let now = Instant::now();
+            let principal = parent.principal.as_ref();
+            for (key, v) in values.into_iter() {
+                if let Some(start) = &start {
+                    if state.written_since(principal, &key, start.version) {
+                        continue;
+                    }
+                }
+                let ttl = match parent.lifetime(&v) {
+                    Lifetime::Forever => None,
+                    Lifetime::Ttl(ttl) => Some(ttl),
+                    Lifetime::Never => continue,
+                };
+                let update = Update::Upsert(v);
+                state.write(
+                    principal,
+                    key.clone(),
+                    update,
+                    in_flight,
+                    parent.config.consistency,
+                );
+                if let Some(ttl) = ttl {
+                    state.expiry.insert(key, now + ttl);
+                }
+            }
+            drop(start);
+            if parent.shared.in_flight.load(Ordering::SeqCst) == 0 {
+                state.settle();
+            }
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a K) -> BoxFuture<'a, ()> {
+        Box::pin(self.0.clear(key.clone()))
+    }
+
+    fn clear(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.0.clear_all())
+    }
+}
+
+/// A loader of boolean values backed by a [`BitsetCache`], built with [`Loader::with_cache`].
+pub type BitsetLoader<K, F> = Loader<K, bool, F, BitsetCache<K>>;
+
+/// A loader of values behind an `Arc`, whose cache hits hand out refcounted pointers rather
+/// than deep clones of the values, e.g. `ArcLoader::new(ArcBatchFn(load_fn))`.
+pub type ArcLoader<K, V, F> = Loader<K, Arc<V>, ArcBatchFn<F>>;
+
+#[allow(clippy::implicit_hasher)]
+impl<K, V, F> Loader<K, V, F, HashMap<K, V>>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    pub fn new(load_fn: F) -> Loader<K, V, F, HashMap<K, V>> {
+        Loader::with_cache(load_fn, HashMap::new())
+    }
+
+    /// Returns a [`LoaderBuilder`] validating its settings, finished with
+    /// [`LoaderBuilder::build_cached`].
+    pub fn builder(load_fn: F) -> LoaderBuilder<K, V, F> {
+        LoaderBuilder::new(load_fn)
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    pub fn with_cache(load_fn: F, cache: C) -> Loader<K, V, F, C> {
+        Loader::with_cache_and_hasher(load_fn, cache, RandomState::new())
+    }
+}
+
+impl<K, V, F, S> Loader<K, V, F, HashMap<K, V, S>, NoopObserver, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    S: BuildHasher + Clone,
+{
+    /// Creates a loader hashing keys with `hasher` rather than the default SipHash, both in
+    /// its cache and in the maps tracking pending keys, e.g. with a faster non-cryptographic
+    /// hasher where keys are not attacker controlled.
+    pub fn with_hasher(load_fn: F, hasher: S) -> Self {
+        Loader::with_cache_and_hasher(load_fn, HashMap::with_hasher(hasher.clone()), hasher)
+    }
+}
+
+impl<K, V, F, C, S> Loader<K, V, F, C, NoopObserver, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    S: BuildHasher + Clone,
+{
+    /// Like [`Loader::with_cache`], hashing keys with `hasher` in the maps tracking pending
+    /// keys, see [`Loader::with_hasher`].
+    pub fn with_cache_and_hasher(load_fn: F, cache: C, hasher: S) -> Self {
+        let load_fns = Arc::new(LoadFns::new(load_fn));
+        Loader {
+            shared: Shared::new(State::with_cache(cache, hasher), load_fns),
+            config: Arc::new(Config {
+                wait: Wait::Yield(10),
+                runtime: Arc::new(DefaultRuntime::default()),
+                max_batch_size: 200,
+                max_batches_per_window: usize::MAX,
+                max_concurrent_batches: 1,
+                clone_load_fn: None,
+                chunk_policy: ChunkPolicy::Fifo,
+                max_wait_rounds: 1,
+                min_batch_size: None,
+                max_pending: None,
+                load_timeout: None,
+                retry: None,
+                result_policy: ResultPolicy::default(),
+                shadow: None,
+                journal: None,
+                redactor: None,
+                key_filter: None,
+                missing_key_policy: MissingKeyPolicy::default(),
+                missing_key_handler: None,
+                consistency: ConsistencyMode::default(),
+                group_by: None,
+                planner: None,
+                chunk_size: None,
+                normalizer: None,
+                key_cost: None,
+                async_cache: None,
+                refresh_errors: None,
+                cache_policy: None,
+                name: None,
+                #[cfg(feature = "debug-diagnostics")]
+                lock_hold_threshold: crate::diagnostics::DEFAULT_THRESHOLD,
+                stale_while_revalidate: None,
+                revalidate: None,
+                error_caching: None,
+            }),
+            principal: None,
+            observer: NoopObserver,
+        }
+    }
+}
+
+impl<K, V, F, C, O, S> Loader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// Records OpenTelemetry metrics of this loader with `meter`, labelled with the name given
+    /// by [`Loader::with_name`] before, see [`crate::otel`].
+    #[cfg(feature = "otel")]
+    pub fn with_otel_metrics(
+        self,
+        meter: &opentelemetry::metrics::Meter,
+    ) -> Loader<K, V, F, C, OtelObserver, S> {
+        let observer = OtelObserver::new(meter, self.config.name.as_deref());
+        self.with_observer(observer)
+    }
+
+    /// Reports the events of this loader to `observer`, see [`Observer`].
+    pub fn with_observer<P: Observer>(self, observer: P) -> Loader<K, V, F, C, P, S> {
+        Loader {
+            shared: self.shared,
+            config: self.config,
+            principal: self.principal,
+            observer,
+        }
+    }
+
+    fn config_mut(&mut self) -> &mut Config<K, V, F> {
+        Arc::make_mut(&mut self.config)
+    }
+
+    /// Caps the number of keys passed to the batch function at once. Defaults to 200. A size of
+    /// 0 loads one key at a time; [`LoaderBuilder`] rejects it instead.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.config_mut().max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Weighs every key with `cost`, e.g. by the number of fields a composite key requests,
+    /// turning `max_batch_size` into a budget of the summed cost of a batch rather than of its
+    /// number of keys. The pending keys are flushed once their cost reaches the budget, and a
+    /// key costing more than the budget is loaded in a batch of its own. Costs below 1 count
+    /// as 1.
+    pub fn with_key_cost(mut self, cost: impl Fn(&K) -> usize + Send + Sync + 'static) -> Self {
+        self.config_mut().key_cost = Some(Arc::new(cost));
+        self
+    }
+
+    /// Caps the number of batches dispatched within a batching window, i.e. while callers wait
+    /// for work once, to smooth bursts of keys into the backend. Keys exceeding the cap roll
+    /// over to the next window, where the oldest keys are batched first so that they are not
+    /// starved by keys queued later. Unlimited by default.
+    pub fn with_max_batches_per_window(mut self, max_batches_per_window: usize) -> Self {
+        self.config_mut().max_batches_per_window = max_batches_per_window.max(1);
+        self
+    }
+
+    /// Sets how a flush with more pending keys than it dispatches at once picks the keys of its
+    /// batches. Defaults to [`ChunkPolicy::Fifo`]; with [`ChunkPolicy::RoundRobin`], a single
+    /// load racing a large `load_many` isn't held back until all of its keys were loaded.
+    pub fn with_chunk_policy(mut self, chunk_policy: ChunkPolicy) -> Self {
+        self.config_mut().chunk_policy = chunk_policy;
+        self
+    }
+
+    /// Yields to the runtime `yield_count` times before dispatching, letting other callers
+    /// join the batch. Defaults to 10.
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.config_mut().wait = Wait::Yield(yield_count);
+        self
+    }
+
+    /// Adapts the wait for work to the load: a caller yields until no new keys were queued
+    /// during `idle_yields` yields in a row, or for at most `max_yields` yields, then dispatches.
+    /// Use [`Self::effective_batch_size()`] to check how well keys are batched.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`] and
+    /// [`Self::with_max_wait_rounds()`].
+    pub fn with_adaptive_wait(mut self, idle_yields: usize, max_yields: usize) -> Self {
+        let idle = idle_yields.max(1);
+        self.config_mut().wait = Wait::Adaptive {
+            idle,
+            max: max_yields.max(idle),
+        };
+        self
+    }
+
+    /// Dispatches once the queue has been idle for `idle`: a caller sleeps for `idle` until no
+    /// new requests were queued during its sleep, like the event loop tick dispatch of the JS
+    /// dataloader, or until `max_delay` passed under a steady trickle of requests.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`] and
+    /// [`Self::with_max_wait_rounds()`].
+    ///
+    /// The idle time is measured by a task spawned on the runtime of the loader, which ticks
+    /// every `idle` while callers wait and wakes them to look at the queue, rather than by
+    /// sleeps in the load calls. The batch itself runs in the woken caller, as the futures of
+    /// the batch function need not be `Send`. This is not available with the `runtime-futures`
+    /// runtime, which has no timer.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_idle_dispatch(mut self, idle: Duration, max_delay: Duration) -> Self {
+        let rounds = max_delay.as_nanos() / idle.as_nanos().max(1);
+        self.config_mut().wait = Wait::Idle {
+            ticker: Arc::new(IdleTicker::new(idle)),
+            max: rounds.min(usize::MAX as u128).max(1) as usize,
+        };
+        self
+    }
+
+    /// Replaces the yielding for work behavior with an arbitrary future. Rather than yielding
+    /// the runtime repeatedly this will generate and `.await` a future of your choice.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`].
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.config_mut().wait = Wait::Custom(Arc::new(wait_for_work_fn));
+        self
+    }
+
+    /// Runs this loader on `runtime` instead of the [`DefaultRuntime`] of the enabled cargo
+    /// features.
+    pub fn with_runtime(mut self, runtime: impl Runtime) -> Self {
+        self.config_mut().runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Lets a caller wait for work up to `max_wait_rounds` times before dispatching, for as long
+    /// as other keys keep being queued during its previous wait. On a saturated executor this
+    /// merges callers arriving one after another into a single batch, instead of each of them
+    /// dispatching a batch of its own key. Defaults to 1, a single wait.
+    pub fn with_max_wait_rounds(mut self, max_wait_rounds: usize) -> Self {
+        self.config_mut().max_wait_rounds = max_wait_rounds.max(1);
+        self
+    }
+
+    /// Holds small batches back: once a caller is done waiting for work, it keeps waiting while
+    /// fewer than `min_batch_size` keys are pending, until `max_delay` after it started
+    /// waiting. This lets trickling callers share a batch while bounding their latency. Full
+    /// batches are still dispatched right away.
+    pub fn with_min_batch_size(mut self, min_batch_size: usize, max_delay: Duration) -> Self {
+        self.config_mut().min_batch_size = Some((min_batch_size, max_delay));
+        self
+    }
+
+    /// Names this loader, e.g. `user_loader`, to tell it apart from the other loaders in
+    /// metrics and in its `Debug` output.
+    pub fn with_name(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.config_mut().name = Some(name.into());
+        self
+    }
+
+    /// The name given by [`Loader::with_name`].
+    pub fn name(&self) -> Option<&str> {
+        self.config.name.as_deref()
+    }
+
+    /// Logs a warning whenever a flush of this loader holds its state lock for longer than
+    /// `threshold`, see [`Loader::longest_lock_hold`]. Defaults to 100ms.
+    #[cfg(feature = "debug-diagnostics")]
+    pub fn with_lock_hold_threshold(mut self, threshold: Duration) -> Self {
+        self.config_mut().lock_hold_threshold = threshold;
+        self
+    }
+
+    /// The longest time a flush of this loader, or of one of its clones, held the state lock
+    /// so far, from building its batches until its batch function calls completed.
+    #[cfg(feature = "debug-diagnostics")]
+    pub fn longest_lock_hold(&self) -> Duration {
+        self.shared.lock_holds.longest()
+    }
+
+    /// Bounds the pending queue: a load call finding `max_pending` keys pending can't queue
+    /// more keys until the queue has room again, and waits or fails according to
+    /// `backpressure`. Keys which are cached or already pending are served as usual, and
+    /// [`Loader::prefetch`] skips keys it can't queue.
+    pub fn with_max_pending(mut self, max_pending: usize, backpressure: Backpressure) -> Self {
+        self.config_mut().max_pending = Some((max_pending.max(1), backpressure));
+        self
+    }
+
+    /// Fails a batch with [`LoadError::Timeout`] when the batch function doesn't complete within
+    /// `timeout`, resolving all callers waiting on it. Values the batch function already
+    /// delivered, see [`Streaming`](crate::Streaming), are cached and resolve their callers; the
+    /// other keys are not cached and are loaded again when requested next.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_load_timeout(mut self, timeout: Duration) -> Self {
+        self.config_mut().load_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries batches whose batch function failed or timed out according to `retry`, e.g.
+    /// `Retry::exponential(3, Duration::from_millis(50))`. Callers keep waiting for the batch
+    /// until it succeeds or `retry` gives up.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_retry(mut self, retry: impl RetryPolicy + 'static) -> Self {
+        self.config_mut().retry = Some(Arc::new(retry));
+        self
+    }
+
+    /// Sets how values returned by the batch function for keys that were not requested are
+    /// handled. Defaults to [`ResultPolicy::Accept`], which caches them.
+    pub fn with_result_policy(mut self, result_policy: ResultPolicy) -> Self {
+        self.config_mut().result_policy = result_policy;
+        self
+    }
+
+    /// Mirrors the keys of cache misses to `shadow`, which records how it would have batched
+    /// them, see [`Shadow::report`].
+    pub fn with_shadow(mut self, shadow: &Shadow<K>) -> Self
+    where
+        K: Send + Sync + 'static,
+    {
+        self.config_mut().shadow = Some(shadow.hook());
+        self
+    }
+
+    /// Sets how keys for which the batch function returned no value resolve, see
+    /// [`MissingKeyPolicy`]. Defaults to [`MissingKeyPolicy::Error`].
+    pub fn with_missing_key_policy(mut self, missing_key_policy: MissingKeyPolicy<V>) -> Self {
+        self.config_mut().missing_key_policy = missing_key_policy;
+        self
+    }
+
+    /// Decides with `handler` what to do with each key the batch function returned no value
+    /// for, see [`MissingKeyAction`]. Keys resolving to an error are then resolved by the
+    /// [`MissingKeyPolicy`].
+    pub fn with_missing_key_handler(
+        mut self,
+        handler: impl Fn(&K) -> MissingKeyAction<V> + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().missing_key_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets how primes, updates and clears interact with the results of batches, see
+    /// [`ConsistencyMode`]. Defaults to [`ConsistencyMode::ReadYourWrites`].
+    pub fn with_consistency(mut self, consistency: ConsistencyMode) -> Self {
+        self.config_mut().consistency = consistency;
+        self
+    }
+
+    /// Splits every batch into groups of keys for which `group_of` returns the same value, e.g.
+    /// the shard of the key, and calls the batch function once per group. Each group is a batch
+    /// of its own for the observer, the journal and shadows. The groups are loaded one after
+    /// another, unless [`Loader::with_max_concurrent_batches`] allows more.
+    pub fn with_group_by<G: Eq + Hash>(
+        mut self,
+        group_of: impl Fn(&K) -> G + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().group_by = Some(group_by(group_of));
+        self
+    }
+
+    /// Splits every batch, and every group of [`Loader::with_group_by`], into chunks of up to
+    /// `chunk_size` keys and calls the batch function once per chunk, e.g. for a backend
+    /// limiting the number of parameters per query. Unlike `max_batch_size`, which caps how many
+    /// keys a flush dispatches at once, chunking leaves flushes as they are. Each chunk is a
+    /// batch of its own for the observer, the journal and shadows. The chunks are loaded one
+    /// after another, unless [`Loader::with_max_concurrent_batches`] allows more.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.config_mut().chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Lets the batch function split or reorder every batch, after [`Loader::with_group_by`]
+    /// and [`Loader::with_chunk_size`], with [`BatchPlanner::plan`] and calls it once per group
+    /// of the plan. Each group is a batch of its own for the observer, the journal and shadows.
+    /// The groups are loaded one after another, unless [`Loader::with_max_concurrent_batches`]
+    /// allows more.
+    pub fn with_batch_planner(mut self) -> Self
+    where
+        F: BatchPlanner<K>,
+    {
+        self.config_mut().planner = Some(F::plan);
+        self
+    }
+
+    /// Expires each loaded value after the TTL `policy` returns for it, or caches it for good
+    /// when `policy` returns `None`, e.g. to keep immutable rows while refreshing others every few
+    /// seconds. Expired values are loaded again on their next load. Errors are cached according
+    /// to [`Loader::with_error_caching`] instead, where it applies.
+    pub fn with_cache_policy(
+        mut self,
+        policy: impl Fn(&V) -> Option<Duration> + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().cache_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Puts `cache`, e.g. a Redis backed [`AsyncCache`] shared by several processes, between
+    /// this loader's cache and the batch function, see [`AsyncCache`].
+    pub fn with_async_cache(mut self, cache: impl AsyncCache<Key = K, Val = V>) -> Self {
+        self.config_mut().async_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Loads up to `max_concurrent_batches` batches at once when a flush has more than one, i.e.
+    /// when more than `max_batch_size` keys are pending or with [`Loader::with_group_by`] or
+    /// [`Loader::with_chunk_size`], each on its own clone of the batch function. The batches
+    /// touch the loader's state only to start and to record their results, so none of them
+    /// waits for another one to complete.
+    /// Defaults to 1.
+    ///
+    /// The batch function is cloned the first time a flush needs the clones.
+    pub fn with_max_concurrent_batches(mut self, max_concurrent_batches: usize) -> Self
+    where
+        F: Clone,
+    {
+        let config = self.config_mut();
+        config.max_concurrent_batches = max_concurrent_batches.max(1);
+        config.clone_load_fn = Some(F::clone);
+        self
+    }
+
+    /// Records every batch this loader dispatches in `journal`.
+    pub fn with_journal(mut self, journal: &Journal<K>) -> Self {
+        self.config_mut().journal = Some(journal.clone());
+        self
+    }
+
+    /// Resolves keys which `filter` rules out like keys missing from a batch, without queuing
+    /// them, see [`KeyFilter`].
+    pub fn with_existence_filter(mut self, filter: impl KeyFilter<K> + 'static) -> Self {
+        self.config_mut().key_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Formats keys in errors with `redactor`, e.g. [`SaltedHash`](crate::SaltedHash) for keys
+    /// containing personal data. Without a redactor, keys are formatted as `<key>`.
+    pub fn with_key_redactor(mut self, redactor: impl KeyRedactor<K> + 'static) -> Self {
+        self.config_mut().redactor = Some(Arc::new(redactor));
+        self
+    }
+
+    /// Formats keys in errors with their `Debug` implementation rather than as `<key>`.
+    pub fn with_debug_keys(self) -> Self
+    where
+        K: Debug,
+    {
+        self.with_key_redactor(DebugKeys)
+    }
+
+    /// Normalizes every key passed to this loader with `normalize`, e.g. lowercasing emails,
+    /// before it is looked up in the cache or queued, so that keys equal after normalization
+    /// share a cache entry and are loaded once. The batch function, the cache and the maps
+    /// returned by `load_many` see the normalized keys only.
+    pub fn with_key_normalizer(
+        mut self,
+        normalize: impl Fn(K) -> K + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().normalizer = Some(Arc::new(normalize));
+        self
+    }
+
+    /// Returns a clone of this loader which loads on behalf of `principal`, e.g. a user id. Its
+    /// values are cached for `principal` alone, so they are never served to other principals or
+    /// to clones without a principal, while its keys are still loaded in the same batches as
+    /// those of all other clones. Use a [`PartitionedLoader`](crate::partitioned::PartitionedLoader)
+    /// instead if the batch function itself has to load on behalf of the principal.
+    pub fn for_principal(&self, principal: impl ToString) -> Self
+    where
+        O: Clone,
+    {
+        let mut loader = self.clone();
+        loader.principal = Some(Principal::from(principal.to_string()));
+        loader
+    }
+
+    /// Returns a child of this loader which batches on its own but shares this loader's cache,
+    /// e.g. to batch the keys of each event of a subscription together while reusing a
+    /// long-lived cache.
+    ///
+    /// The child has its own pending keys and dispatches its own batches: its loads never join
+    /// the batches of the parent or of other children and vice versa, so a key requested by both
+    /// at once may be loaded twice. The batch function is shared, so the batches of the parent
+    /// and of its children still run one at a time. Keys the child misses in its own cache are
+    /// looked up in the parent's cache when the child dispatches them, like in an async cache
+    /// (see [`Loader::with_async_cache`]), and the values it loads are cached in both. Clearing
+    /// a key of the child clears it in the parent too. The parent's own async cache is not
+    /// consulted by the child, and the child caches values on behalf of the parent's principal.
+    /// Writes to the parent made while a batch of the child is in flight take precedence over
+    /// the values of that batch in the parent's cache, according to the parent's
+    /// [`ConsistencyMode`].
+    pub async fn child(&self) -> Loader<K, V, F, HashMap<K, V, S>, O, S>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        F: Send + 'static,
+        C: Send + 'static,
+        O: Clone + Send + Sync + 'static,
+        S: Send + Sync + 'static,
+    {
+        let hasher = self.lock_state().await.hasher.clone();
+        let cache = HashMap::with_hasher(hasher.clone());
+        let state = State::with_cache(cache, hasher);
+        let mut config = Config::clone(&self.config);
+        config.async_cache = Some(Arc::new(ParentCache(self.clone())));
+        Loader {
+            shared: Shared::new(state, self.shared.load_fns.clone()),
+            config: Arc::new(config),
+            principal: None,
+            observer: self.observer.clone(),
+        }
+    }
+
+    /// Holds back the batches of this loader and of its clones until the returned barrier and
+    /// all others are dropped, see [`Barrier`].
+    pub fn barrier(&self) -> Barrier {
+        self.shared.barriers.hold()
+    }
+
+    /// Returns a handle to this loader which doesn't keep its state alive, see [`WeakLoader`].
+    pub fn downgrade(&self) -> WeakLoader<K, V, F, C, O, S>
+    where
+        O: Clone,
+    {
+        WeakLoader {
+            shared: Arc::downgrade(&self.shared),
+            config: self.config.clone(),
+            principal: self.principal.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.config.max_batch_size
+    }
+
+    /// Number of keys queued for the next batch. Waits for any batch being dispatched while
+    /// holding the loader state to finish.
+    pub async fn pending_len(&self) -> usize {
+        self.lock_state().await.pending.len()
+    }
+
+    /// The mean number of keys passed to the batch function per call so far, or 0 before the
+    /// first batch. Values close to 1 under concurrent load mean that callers don't wait long
+    /// enough for each other to share batches.
+    pub async fn effective_batch_size(&self) -> f64 {
+        let state = self.lock_state().await;
+        if state.batches == 0 {
+            return 0.0;
+        }
+        state.batched_keys as f64 / state.batches as f64
+    }
+
+    /// Whether a batch function call is currently in flight.
+    pub fn is_loading(&self) -> bool {
+        self.shared.in_flight.load(Ordering::SeqCst) > 0
+    }
+
+    /// Returns the cached value of `key` without loading it, e.g. to decide whether to resolve
+    /// extra fields. Values which would be loaded again, e.g. expired ones, are not returned, and
+    /// the observer records neither a hit nor a miss.
+    pub async fn get_cached(&self, key: K) -> Option<V> {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        let expired = state.expired(&key);
+        match state.lookup(self.principal.as_ref(), &key) {
+            Some(v) if !self.refreshes(v, expired) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the value of `key` is cached, see [`Loader::get_cached`].
+    pub async fn contains(&self, key: K) -> bool {
+        self.get_cached(key).await.is_some()
+    }
+
+    /// Returns the keys cached for this loader's principal, or in the shared cache without one.
+    /// The keys are a snapshot meant for diagnostics, and include errors which would be loaded
+    /// again.
+    pub async fn cached_keys(&self) -> Vec<K>
+    where
+        C: CacheEntries,
+    {
+        let state = self.lock_state().await;
+        match &self.principal {
+            None => state.completed.keys(),
+            Some(p) => state
+                .scoped
+                .get(p)
+                .map(|scope| scope.completed.keys().cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the cached value of `key`, unless it should be loaded again.
+    fn cached(&self, state: &mut State<K, V, C, S>, key: &K) -> Option<V> {
+        let expired = state.expired(key) && !self.revalidates(state, key);
+        let v = match state.lookup(self.principal.as_ref(), key) {
+            Some(v) if !self.refreshes(v, expired) => v.clone(),
+            _ => {
+                if O::ENABLED {
+                    self.observer.cache_miss();
+                }
+                return None;
+            }
+        };
+        if O::ENABLED {
+            self.observer.cache_hit();
+        }
+        Some(v)
+    }
+
+    /// Whether the expired value of `key` is still served, queuing a refresh of the key and
+    /// spawning a task to dispatch it unless it is pending already, see
+    /// [`Loader::with_stale_while_revalidate`].
+    fn revalidates(&self, state: &mut State<K, V, C, S>, key: &K) -> bool {
+        let stale = match (self.config.stale_while_revalidate, state.stale_for(key)) {
+            (Some(max_stale), Some(stale)) => stale <= max_stale,
+            _ => false,
+        };
+        if stale {
+            let pending = state.pending.contains_key(key);
+            state.enqueue(self.principal.as_ref(), key, self.cost(key));
+            match &self.config.revalidate {
+                Some(revalidate) if !pending => {
+                    revalidate(&self.config, self.principal.as_ref(), key.clone())
+                }
+                _ => {}
+            }
+        }
+        stale
+    }
+
+    /// Dispatches the refresh of the stale `key` queued by a load, see
+    /// [`Loader::with_stale_while_revalidate`].
+    async fn revalidate(&self, key: K) {
+        let state = self.lock_state().await;
+        drop(
+            self.wait_and_dispatch(state, |state| state.pending.contains_key(&key))
+                .await,
+        );
+    }
+
+    /// Whether the cached `v` should be loaded again, being an error to refresh or `expired`.
+    fn refreshes(&self, v: &V, expired: bool) -> bool {
+        expired || self.config.refresh_errors.is_some_and(|is_err| is_err(v))
+    }
+
+    /// How long `v` is cached, according to the error caching policy for errors and to the
+    /// cache policy for other values.
+    fn lifetime(&self, v: &V) -> Lifetime {
+        match self.config.error_caching {
+            Some((is_err, policy)) if is_err(v) => match policy {
+                ErrorCaching::Forever => Lifetime::Forever,
+                ErrorCaching::Ttl(ttl) => Lifetime::Ttl(ttl),
+                ErrorCaching::Never => Lifetime::Never,
+            },
+            _ => match self
+                .config
+                .cache_policy
+                .as_ref()
+                .and_then(|policy| policy(v))
+            {
+                Some(ttl) => Lifetime::Ttl(ttl),
+                None => Lifetime::Forever,
+            },
+        }
+    }
+
+    /// Whether `v` is an error which is not cached for good, and so is not shared through an
+    /// async cache either.
+    fn is_transient_error(&self, v: &V) -> bool {
+        match self.config.error_caching {
+            Some((is_err, ErrorCaching::Ttl(_) | ErrorCaching::Never)) => is_err(v),
+            _ => false,
+        }
+    }
+
+    /// Returns the cached values of `keys` like [`Self::cached`], looking them up at once.
+    fn cached_many(&self, state: &mut State<K, V, C, S>, keys: &[K]) -> Vec<Option<V>> {
+        let mut values = state.lookup_many(self.principal.as_ref(), keys);
+        for (key, v) in keys.iter().zip(values.iter_mut()) {
+            let expired = state.expired(key) && !self.revalidates(state, key);
+            if v.as_ref().is_some_and(|v| self.refreshes(v, expired)) {
+                *v = None;
+            }
+            if O::ENABLED {
+                match v {
+                    Some(_) => self.observer.cache_hit(),
+                    None => self.observer.cache_miss(),
+                }
+            }
+        }
+        values
+    }
+
+    /// Locks the state, withdrawing the keys of dropped load calls from the next batch.
+    async fn lock_state(&self) -> MutexGuard<'_, State<K, V, C, S>> {
+        let mut state = self.shared.state.lock().await;
+        state.turns.next();
+        self.reap(&mut state);
+        state
+    }
+
+    fn reap(&self, state: &mut State<K, V, C, S>) {
+        let abandoned = mem::take(
+            &mut *self
+                .shared
+                .abandoned
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        state.abandon(abandoned);
+        let unloaded = mem::take(
+            &mut *self
+                .shared
+                .unloaded
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        for key in unloaded.iter() {
+            state.pending.requeue(key);
+        }
+    }
+
+    /// Takes `keys` out of the queue while they are loaded, see [`Loading`].
+    fn start_loading<'k>(
+        &self,
+        state: &mut State<K, V, C, S>,
+        keys: impl IntoIterator<Item = &'k K>,
+    ) -> Loading<'_, K>
+    where
+        K: 'k,
+    {
+        Loading {
+            unloaded: &self.shared.unloaded,
+            completions: &self.shared.completions,
+            keys: keys
+                .into_iter()
+                .filter_map(|key| state.pending.start(key))
+                .collect(),
+        }
+    }
+
+    /// Waits until a batch in flight completes, for callers whose keys are all loaded by the
+    /// flushes of other callers.
+    async fn completion<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<K, V, C, S>>,
+    ) -> MutexGuard<'a, State<K, V, C, S>> {
+        let seen = self.shared.completions.count();
+        drop(state);
+        self.shared.completions.after(seen).await;
+        self.lock_state().await
+    }
+
+    /// Dispatches the oldest queued keys, releasing the state lock while their batches are in
+    /// flight, so that other loads and writes such as primes and clears go ahead meanwhile. The
+    /// results are merged into the cache as each batch completes, see [`State::complete_batch`].
+    async fn dispatch<'a>(
+        &'a self,
+        mut guard: MutexGuard<'a, State<K, V, C, S>>,
+    ) -> MutexGuard<'a, State<K, V, C, S>> {
+        #[cfg(feature = "debug-diagnostics")]
+        let locked = Instant::now();
+        let max_batch_size = self.config.max_batch_size.max(1);
+        // the number of batch function clones `LoadFns::take` returns
+        let load_fns = match self.config.clone_load_fn {
+            Some(_) => self.config.max_concurrent_batches.max(1),
+            None => 1,
+        };
+        let concurrent = load_fns.min(self.config.max_batches_per_window - guard.window_batches);
+        let state = &mut *guard;
+        if let Some((seq, _)) = state.pending.numbered().next() {
+            state.turns.prune(seq);
+        }
+        let n = max_batch_size.saturating_mul(concurrent.max(1));
+        let oldest = match self.config.chunk_policy {
+            ChunkPolicy::Fifo => state.pending.oldest(n).cloned().collect::<Vec<K>>(),
+            ChunkPolicy::RoundRobin => state
+                .turns
+                .round_robin(state.pending.numbered(), n)
+                .into_iter()
+                .cloned()
+                .collect(),
+        };
+        let mut batches = split_by_cost(oldest, |k| self.cost(k), max_batch_size, concurrent);
+        state.window_batches += batches.len();
+        if let Some(group_by) = &self.config.group_by {
+            batches = batches
+                .into_iter()
+                .flat_map(|keys| group_by(keys))
+                .collect();
+        }
+        if let Some(chunk_size) = self.config.chunk_size {
+            batches = chunk(batches, chunk_size);
+        }
+        let loading = self.start_loading(state, batches.iter().flatten());
+        #[cfg(feature = "debug-diagnostics")]
+        self.shared.lock_holds.record(
+            self.config.name.as_deref(),
+            self.config.lock_hold_threshold,
+            loading.keys.len(),
+            locked.elapsed(),
+            None,
+        );
+        drop(guard);
+
+        let load_fns = self
+            .shared
+            .load_fns
+            .take(
+                self.config.max_concurrent_batches,
+                self.config.clone_load_fn,
+            )
+            .await;
+        if let Some(plan) = self.config.planner {
+            let mut load_fn = load_fns[0].lock().await;
+            batches = batches
+                .into_iter()
+                .flat_map(|keys| planned(keys, |keys| plan(&mut load_fn, keys)))
+                .collect();
+        }
+        {
+            let mut state = self.shared.state.lock().await;
+            state.batches += batches.len();
+            state.batched_keys += batches.iter().map(Vec::len).sum::<usize>();
+        }
+        run_concurrently(batches, load_fns.len(), |slot, keys| {
+            self.load_keys(&load_fns[slot], keys)
+        })
+        .await;
+        let mut state = self.lock_state().await;
+        loading.finish(&mut state.pending);
+        state
+    }
+
+    /// Loads `keys` with a single call of `load_fn`, locking the state only around the updates
+    /// of the cache and the pending keys. Writes to the cache made while the batch is in flight
+    /// take precedence over its results according to the [`ConsistencyMode`].
+    async fn load_keys(&self, load_fn: &Mutex<F>, mut keys: Vec<K>) {
+        let in_flight = InFlight::start(&self.shared.in_flight);
+        let version = self.shared.state.lock().await.begin_batch();
+        let mut start = match &self.config.async_cache {
+            Some(async_cache) => async_cache.begin().await,
+            None => None,
+        };
+        if let Some(async_cache) = &self.config.async_cache {
+            let shared = {
+                let state = self.shared.state.lock().await;
+                keys.iter()
+                    .filter(|k| !state.fresh.contains(*k) && state.is_shared(k))
+                    .cloned()
+                    .collect::<Vec<K>>()
+            };
+            if !shared.is_empty() {
+                let cached = async_cache.get_many(&shared).await;
+                if !cached.is_empty() {
+                    keys.retain(|k| !cached.contains_key(k));
+                    let hits = cached.keys().cloned().collect();
+                    self.shared.state.lock().await.complete_batch(
+                        version,
+                        hits,
+                        Ok(cached),
+                        true,
+                        self.config.consistency,
+                        &|v| self.lifetime(v),
+                    );
+                    self.shared.completions.complete();
+                }
+            }
+            if keys.is_empty() {
+                return;
+            }
+        }
+        if let Some(shadow) = &self.config.shadow {
+            shadow.record(keys.len());
+        }
+        if O::ENABLED {
+            self.observer.batch_dispatched(keys.len());
+        }
+        let started = if O::ENABLED {
+            Some(Instant::now())
+        } else {
+            None
+        };
+        let dispatched_at = self.config.journal.as_ref().map(|_| SystemTime::now());
+        let mut load_fn = load_fn.lock().await;
+        let (load_ret, salvaged) = load_batch(
+            &*self.config.runtime,
+            &mut *load_fn,
+            &mut keys,
+            self.config.load_timeout,
+            self.config.retry.as_deref(),
+            |keys| {
+                let keys = keys.to_vec();
+                async move {
+                    let state = self.shared.state.lock().await;
+                    keys.iter()
+                        .map(|key| state.waiters.get(key).map_or(0, HashSet::len))
+                        .collect()
+                }
+            },
+            |mut keys| async move {
+                let mut state = self.shared.state.lock().await;
+                self.reap(&mut state);
+                keys.retain(|key| state.pending.contains_key(key));
+                keys
+            },
+        )
+        .await;
+        drop(load_fn);
+        let mut load_ret = load_ret.and_then(|mut load_ret| {
+            self.config
+                .result_policy
+                .apply(&keys, &mut load_ret)
+                .map(|_| load_ret)
+        });
+        if let (Some(journal), Some(dispatched_at)) = (&self.config.journal, dispatched_at) {
+            journal.record(&keys, dispatched_at, &load_ret).await;
+        }
+        if let Some(started) = started {
+            let error = load_ret.as_ref().err();
+            self.observer
+                .batch_completed(keys.len(), started.elapsed(), error);
+        }
+        if !salvaged.is_empty() {
+            if O::ENABLED {
+                self.observer
+                    .partial_batch(keys.len() + salvaged.len(), salvaged.len());
+            }
+            let arrived = salvaged.keys().cloned().collect();
+            self.shared.state.lock().await.complete_batch(
+                version,
+                arrived,
+                Ok(salvaged),
+                true,
+                self.config.consistency,
+                &|v| self.lifetime(v),
+            );
+            self.shared.completions.complete();
+        }
+        if let Some(handler) = &self.config.missing_key_handler {
+            let mut state = self.shared.state.lock().await;
+            let mut values = load_ret.as_mut().ok();
+            keys.retain(|key| {
+                // a retried key is found or resolved this time
+                let retried = !state.retried.is_empty() && state.retried.remove(key);
+                let values = match values.as_mut() {
+                    Some(values) if !values.contains_key(key) => values,
+                    _ => return true,
+                };
+                match handler(key) {
+                    MissingKeyAction::Error => true,
+                    MissingKeyAction::Default(v) => {
+                        values.insert(key.clone(), v);
+                        true
+                    }
+                    // keys left out of the completed batch are queued again once the flush is
+                    // over, for the next batch
+                    MissingKeyAction::Retry if !retried => match state.pending.get(key).cloned() {
+                        Some(key) => !state.retried.insert(key),
+                        None => true,
+                    },
+                    MissingKeyAction::Retry => true,
+                }
+            });
+        }
+        if let (Some(async_cache), Ok(values)) = (&self.config.async_cache, &load_ret) {
+            let shared = {
+                let state = self.shared.state.lock().await;
+                values
+                    .iter()
+                    .filter(|(k, v)| state.is_shared(k) && !self.is_transient_error(v))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<(K, V)>>()
+            };
+            async_cache.insert_many(shared, start.take()).await;
+        }
+        // writes made up to here are versioned, as the batch counts as in flight until the state
+        // is locked to complete it
+        let mut state = self.shared.state.lock().await;
+        drop(in_flight);
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        state.complete_batch(
+            version,
+            keys,
+            load_ret,
+            in_flight,
+            self.config.consistency,
+            &|v| self.lifetime(v),
+        );
+        drop(state);
+        self.shared.completions.complete();
+    }
+
+    /// The number of pending keys dispatched right away rather than after waiting for work, a
+    /// full batch for each of the batches loaded at once.
+    fn flush_size(&self) -> usize {
+        self.config
+            .max_batch_size
+            .saturating_mul(self.config.max_concurrent_batches)
+    }
+
+    /// The cost of `key` counted against the batch size, see [`Loader::with_key_cost`].
+    fn cost(&self, key: &K) -> usize {
+        self.config
+            .key_cost
+            .as_ref()
+            .map_or(1, |cost| cost(key).max(1))
+    }
+
+    fn normalize(&self, key: K) -> K {
+        match &self.config.normalizer {
+            Some(normalize) => normalize(key),
+            None => key,
+        }
+    }
+
+    fn normalize_many(&self, keys: Vec<K>) -> Vec<K> {
+        match &self.config.normalizer {
+            Some(normalize) => keys.into_iter().map(|k| normalize(k)).collect(),
+            None => keys,
+        }
+    }
+
+    /// Whether the existence filter rules `key` out, see [`Loader::with_existence_filter`].
+    fn filtered(&self, key: &K) -> bool {
+        self.config
+            .key_filter
+            .as_ref()
+            .is_some_and(|filter| !filter.may_contain(key))
+    }
+
+    /// Whether another batch may be dispatched within the current window.
+    fn may_dispatch(&self, state: &State<K, V, C, S>) -> bool {
+        state.window_batches < self.config.max_batches_per_window
+    }
+
+    /// How queuing `key` is held back, if the pending queue is full, see
+    /// [`Loader::with_max_pending`].
+    fn overflow(&self, state: &State<K, V, C, S>, key: &K) -> Option<Backpressure> {
+        match self.config.max_pending {
+            Some((max_pending, backpressure))
+                if state.pending.len() >= max_pending && !state.pending.contains_key(key) =>
+            {
+                Some(backpressure)
+            }
+            _ => None,
+        }
+    }
+
+    /// Dispatches the oldest pending keys until `key` may be queued.
+    async fn make_room<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<K, V, C, S>>,
+        key: &K,
+    ) -> MutexGuard<'a, State<K, V, C, S>> {
+        self.wait_and_dispatch(state, |state| self.overflow(state, key).is_some())
+            .await
+    }
+
+    /// Waits for work and locks the state, waiting another round while `waiting` still has keys
+    /// pending and other keys were queued recently, up to `max_wait_rounds` or the limits of an
+    /// adaptive wait, and beyond them while fewer than the minimum batch size are pending until
+    /// its deadline. Starts a new window unless another caller did so while this one was
+    /// waiting.
+    async fn wait_for_work<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<K, V, C, S>>,
+        waiting: impl Fn(&State<K, V, C, S>) -> bool,
+    ) -> MutexGuard<'a, State<K, V, C, S>> {
+        let window = state.window;
+        let (min_batch_size, deadline) = match self.config.min_batch_size {
+            Some((min_batch_size, max_delay)) => (min_batch_size, Some(Instant::now() + max_delay)),
+            None => (0, None),
+        };
+        let mut state = wait_for_work(
+            &StateLock(self),
+            &self.config.wait.on(&self.config.runtime),
+            state,
+            self.config.wait.rounds(self.config.max_wait_rounds),
+            |state| state.enqueued,
+            |state, over| {
+                let small = state.pending.queued() < min_batch_size
+                    && matches!(deadline, Some(deadline) if Instant::now() < deadline);
+                (over && !small) || !waiting(state)
+            },
+        )
+        .await;
+        // the rounds are over, unless the keys of `waiting` are loaded already
+        if waiting(&state) && self.shared.barriers.is_held() {
+            drop(state);
+            self.shared.barriers.released().await;
+            state = self.lock_state().await;
+        }
+        if state.window == window {
+            state.window = state.window.wrapping_add(1);
+            state.window_batches = 0;
+        }
+        state
+    }
+
+    /// Waits for work, then dispatches batches of the oldest queued keys until `waiting` has
+    /// no keys pending anymore, waiting for the next window whenever the current one is full,
+    /// and for the batches of other callers while only their keys are left.
+    async fn wait_and_dispatch<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, C, S>>,
+        waiting: impl Fn(&State<K, V, C, S>) -> bool,
+    ) -> MutexGuard<'a, State<K, V, C, S>> {
+        loop {
+            state = self.wait_for_work(state, &waiting).await;
+            while waiting(&state) && self.may_dispatch(&state) && state.pending.queued() > 0 {
+                state = self.dispatch(state).await;
+            }
+            while waiting(&state) && state.pending.queued() == 0 {
+                state = self.completion(state).await;
+            }
+            if !waiting(&state) {
+                return state;
+            }
+        }
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<V, LoadError> {
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self.try_load_waiting(key, false, &mut waiting).await;
+        waiting.done();
+        ret.map(|(v, _)| v)
+    }
+
+    /// Loads `key` in the next batch even if it is cached, e.g. right after a mutation, and
+    /// caches the fresh result for subsequent loads. The cached value is dropped right away, so
+    /// that concurrent loads of `key` wait for the fresh result as well.
+    pub async fn try_load_fresh(&self, key: K) -> Result<V, LoadError> {
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self.try_load_waiting(key, true, &mut waiting).await;
+        waiting.done();
+        ret.map(|(v, _)| v)
+    }
+
+    /// Returns the entry of `key`, to load its value while telling whether it was cached, see
+    /// [`Entry`].
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, F, C, O, S> {
+        Entry {
+            loader: self,
+            key,
+            on_insert: None,
+        }
+    }
+
+    async fn try_load_waiting(
+        &self,
+        key: K,
+        fresh: bool,
+        waiting: &mut Waiting<'_, (Arc<K>, Ticket)>,
+    ) -> Result<(V, Source), LoadError> {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        if fresh {
+            state.remove(self.principal.as_ref(), &key);
+        }
+        if let Some(v) = self.cached(&mut state, &key) {
+            return Ok((v, Source::Cache));
+        }
+        if self.filtered(&key) {
+            let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+            return self
+                .config
+                .missing_key_policy
+                .resolve(Err(e))
+                .map(|v| (v, Source::Filtered));
+        }
+        match self.overflow(&state, &key) {
+            Some(Backpressure::Fail) => return Err(LoadError::QueueFull),
+            Some(Backpressure::Wait) => state = self.make_room(state, &key).await,
+            None => {}
+        }
+        if let Some(shadow) = &self.config.shadow {
+            shadow.mirror(vec![key.clone()]);
+        }
+
+        let pending = state.enqueue(self.principal.as_ref(), &key, self.cost(&key));
+        if fresh {
+            state.fresh.insert(pending.clone());
+        }
+        let ticket = state.wait(&pending);
+        waiting.push((pending, ticket));
+        if state.pending.cost() >= self.flush_size() && self.may_dispatch(&state) {
+            state = self.dispatch(state).await;
+        }
+        if state.pending.contains_key(&key) {
+            state = self
+                .wait_and_dispatch(state, |state| state.pending.contains_key(&key))
+                .await;
+        }
+
+        let r = state.get(
+            self.principal.as_ref(),
+            &key,
+            ticket,
+            self.config.redactor.as_deref(),
+        );
+        self.config
+            .missing_key_policy
+            .resolve(r)
+            .map(|v| (v, Source::Batch))
+    }
+
+    pub async fn load(&self, key: K) -> V {
+        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub async fn load_fresh(&self, key: K) -> V {
+        self.try_load_fresh(key)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub async fn try_load_many(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.load_results(keys)
+            .await
+            .into_iter()
+            .map(|(k, r)| r.map(|v| (k, v)))
+            .collect()
+    }
+
+    /// Loads `keys`, returning the outcome of every key individually, so that one failed key
+    /// doesn't discard the values of the others.
+    pub async fn load_results(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> HashMap<K, Result<V, LoadError>> {
+        let keys = keys.into_iter().collect();
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self
+            .load_results_waiting(keys, false, &mut waiting, None)
+            .await;
+        waiting.done();
+        ret
+    }
+
+    /// Loads `keys` like [`Self::try_load_many`], also telling for every key whether it was
+    /// cached, loaded or missing, and how long the call took. Missing keys don't fail the call,
+    /// unlike keys failed by the batch function.
+    pub async fn try_load_many_detailed(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<LoadManyResult<K, V>, LoadError> {
+        let start = Instant::now();
+        let keys = keys.into_iter().collect();
+        let mut provenance = HashMap::new();
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self
+            .load_results_waiting(keys, false, &mut waiting, Some(&mut provenance))
+            .await;
+        waiting.done();
+        let mut values = HashMap::with_capacity(ret.len());
+        for (key, r) in ret.into_iter() {
+            match r {
+                Ok(v) => {
+                    values.insert(key, v);
+                }
+                Err(LoadError::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(LoadManyResult {
+            values,
+            provenance,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    pub async fn load_many_detailed(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> LoadManyResult<K, V> {
+        self.try_load_many_detailed(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Reloads `key` like [`Self::try_load_fresh`], returning its new value.
+    pub async fn try_refresh(&self, key: K) -> Result<V, LoadError> {
+        self.try_load_fresh(key).await
+    }
+
+    pub async fn refresh(&self, key: K) -> V {
+        self.load_fresh(key).await
+    }
+
+    /// Removes `keys` from the cache and reloads them in the same batches, returning their new
+    /// values. Unlike [`Self::clear`] followed by a load, no concurrent load can serve or cache
+    /// the stale values in between, as both happen under the same lock of the loader state.
+    pub async fn try_refresh_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, LoadError> {
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self
+            .load_results_waiting(keys, true, &mut waiting, None)
+            .await;
+        waiting.done();
+        ret.into_iter().map(|(k, r)| r.map(|v| (k, v))).collect()
+    }
+
+    pub async fn refresh_many(&self, keys: Vec<K>) -> HashMap<K, V> {
+        self.try_refresh_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    async fn load_results_waiting(
+        &self,
+        keys: Vec<K>,
+        fresh: bool,
+        waiting: &mut Waiting<'_, (Arc<K>, Ticket)>,
+        mut provenance: Option<&mut HashMap<K, Provenance>>,
+    ) -> HashMap<K, Result<V, LoadError>> {
+        let keys = self.normalize_many(keys);
+        let mut state = self.lock_state().await;
+        let mut ret = HashMap::new();
+        let mut rest = Vec::new();
+        let mut tickets = Vec::new();
+        let mut mirrored = Vec::new();
+        if fresh {
+            for key in keys.iter() {
+                state.remove(self.principal.as_ref(), key);
+            }
+        }
+        let cached = self.cached_many(&mut state, &keys);
+        let mut dispatched = false;
+        for (key, cached) in keys.into_iter().zip(cached) {
+            // a batch dispatched for earlier keys may have loaded a repeated key meanwhile
+            let cached = match cached {
+                None if dispatched && !state.pending.contains_key(&key) => {
+                    self.cached(&mut state, &key)
+                }
+                cached => cached,
+            };
+            if let Some(v) = cached {
+                if let Some(provenance) = provenance.as_deref_mut() {
+                    provenance.insert(key.clone(), Provenance::Hit);
+                }
+                ret.insert(key, Ok(v));
+                continue;
+            }
+            if self.filtered(&key) {
+                if let Some(provenance) = provenance.as_deref_mut() {
+                    provenance.insert(key.clone(), Provenance::Missing);
+                }
+                let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+                if let Some(r) = self.config.missing_key_policy.resolve_many(Err(e)) {
+                    ret.insert(key, r);
+                }
+                continue;
+            }
+            match self.overflow(&state, &key) {
+                Some(Backpressure::Fail) => {
+                    ret.insert(key, Err(LoadError::QueueFull));
+                    continue;
+                }
+                Some(Backpressure::Wait) => {
+                    state = self.make_room(state, &key).await;
+                    dispatched = true;
+                }
+                None => {}
+            }
+            if self.config.shadow.is_some() {
+                mirrored.push(key.clone());
+            }
+            let pending = state.enqueue(self.principal.as_ref(), &key, self.cost(&key));
+            if fresh {
+                state.fresh.insert(pending.clone());
+            }
+            let ticket = state.wait(&pending);
+            waiting.push((pending, ticket));
+            if state.pending.cost() >= self.flush_size() && self.may_dispatch(&state) {
+                state = self.dispatch(state).await;
+                dispatched = true;
+            }
+            rest.push(key);
+            tickets.push(ticket);
+        }
+        if let Some(shadow) = &self.config.shadow {
+            shadow.mirror(mirrored);
+        }
+
+        if rest.is_empty() {
+            drop(state);
+            self.config.wait.wait(&self.config.runtime).await;
+        } else {
+            let mut state = self
+                .wait_and_dispatch(state, |state| {
+                    rest.iter().any(|key| state.pending.contains_key(key))
+                })
+                .await;
+
+            let redactor = self.config.redactor.as_deref();
+            let results = state.get_many(self.principal.as_ref(), &rest, &tickets, redactor);
+            for (key, r) in rest.into_iter().zip(results) {
+                if let Some(provenance) = provenance.as_deref_mut() {
+                    let p = match r {
+                        Err(LoadError::NotFound(_)) => Provenance::Missing,
+                        _ => Provenance::Loaded,
+                    };
+                    provenance.insert(key.clone(), p);
+                }
+                if let Some(r) = self.config.missing_key_policy.resolve_many(r) {
+                    ret.insert(key, r);
+                }
+            }
+        }
+
+        ret
+    }
+
+    pub async fn load_many(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Loads `keys` together in a single call of the batch function, regardless of
+    /// [`Self::with_max_batch_size`], [`Self::with_group_by`] and [`Self::with_chunk_size`], e.g.
+    /// for a backend computing aggregates across the keys. Cached values are not served: every
+    /// key of the group is loaded again like with [`Self::try_refresh_many`], and the results are
+    /// cached. Other pending keys are left to their own batches, while callers waiting for keys of
+    /// the group get the values of its call. Keys the missing key handler would retry resolve
+    /// like missing keys, as the group is not loaded again.
+    pub async fn try_load_group(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        let keys = self.normalize_many(keys.into_iter().collect());
+        let principal = self.principal.as_ref();
+        // dropped rather than done, which withdraws the keys the missing key handler retries
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let mut state = self.lock_state().await;
+        let mut ret = HashMap::new();
+        let mut group = Vec::new();
+        let mut tickets = Vec::new();
+        let mut unique = HashSet::with_hasher(state.hasher.clone());
+        for key in keys.into_iter() {
+            if self.filtered(&key) {
+                let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+                if let Some(r) = self.config.missing_key_policy.resolve_many(Err(e)) {
+                    ret.insert(key, r);
+                }
+                continue;
+            }
+            if !unique.insert(key.clone()) {
+                continue;
+            }
+            state.remove(principal, &key);
+            let pending = state.enqueue(principal, &key, self.cost(&key));
+            state.fresh.insert(pending.clone());
+            let ticket = state.wait(&pending);
+            waiting.push((pending, ticket));
+            group.push(key);
+            tickets.push(ticket);
+        }
+        if !group.is_empty() {
+            state.batches += 1;
+            state.batched_keys += group.len();
+            let loading = self.start_loading(&mut state, &group);
+            drop(state);
+            let load_fns = self.shared.load_fns.take(1, None).await;
+            self.load_keys(&load_fns[0], group.clone()).await;
+            let mut state = self.lock_state().await;
+            loading.finish(&mut state.pending);
+            let redactor = self.config.redactor.as_deref();
+            let results = state.get_many(principal, &group, &tickets, redactor);
+            for (key, r) in group.into_iter().zip(results) {
+                if let Some(r) = self.config.missing_key_policy.resolve_many(r) {
+                    ret.insert(key, r);
+                }
+            }
+        }
+        ret.into_iter().map(|(k, r)| r.map(|v| (k, v))).collect()
+    }
+
+    pub async fn load_group(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_group(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Loads the values of two keys in the same batch, unlike awaiting `load(a)` before
+    /// `load(b)`, e.g. the author and the editor of a post. A key left out of the results by
+    /// [`MissingKeyPolicy::Skip`] resolves to [`LoadError::NotFound`], as if it was loaded on its
+    /// own.
+    pub async fn try_load2(&self, a: K, b: K) -> Result<(V, V), LoadError> {
+        let results = self.load_results(vec![a.clone(), b.clone()]).await;
+        Ok((self.result(&results, a)?, self.result(&results, b)?))
+    }
+
+    pub async fn load2(&self, a: K, b: K) -> (V, V) {
+        self.try_load2(a, b)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Loads the values of three keys in the same batch, see [`Self::try_load2`].
+    pub async fn try_load3(&self, a: K, b: K, c: K) -> Result<(V, V, V), LoadError> {
+        let results = self
+            .load_results(vec![a.clone(), b.clone(), c.clone()])
+            .await;
+        Ok((
+            self.result(&results, a)?,
+            self.result(&results, b)?,
+            self.result(&results, c)?,
+        ))
+    }
+
+    pub async fn load3(&self, a: K, b: K, c: K) -> (V, V, V) {
+        self.try_load3(a, b, c)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// The outcome of `key` within the `results` of [`Self::load_results`].
+    fn result(&self, results: &HashMap<K, Result<V, LoadError>>, key: K) -> Result<V, LoadError> {
+        let key = self.normalize(key);
+        match results.get(&key) {
+            Some(r) => r.clone(),
+            None => Err(LoadError::NotFound(describe(
+                self.config.redactor.as_deref(),
+                &key,
+            ))),
+        }
+    }
+
+    /// Loads the values of borrowed `keys`, cloning only the keys which are not cached to
+    /// queue them, and returns the values by the borrowed keys. With
+    /// [`Self::with_key_normalizer`] every key is cloned to normalize it.
+    pub async fn try_load_many_ref<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a K>,
+    ) -> Result<HashMap<&'a K, V>, LoadError>
+    where
+        K: 'a,
+    {
+        let mut ret = HashMap::new();
+        let mut missing = Vec::new();
+        let mut state = self.lock_state().await;
+        for key in keys.into_iter() {
+            // misses are reported to the observer when the missing keys are loaded
+            let expired = state.expired(key) && !self.revalidates(&mut state, key);
+            let v = match &self.config.normalizer {
+                None => state.lookup(self.principal.as_ref(), key),
+                Some(_) => None,
+            };
+            match v {
+                Some(v) if !self.refreshes(v, expired) => {
+                    if O::ENABLED {
+                        self.observer.cache_hit();
+                    }
+                    ret.insert(key, v.clone());
+                }
+                _ => missing.push(key),
+            }
+        }
+        drop(state);
+        if missing.is_empty() {
+            return Ok(ret);
+        }
+        let values = self
+            .try_load_many(missing.iter().map(|key| (*key).clone()))
+            .await?;
+        for key in missing.into_iter() {
+            let v = match &self.config.normalizer {
+                None => values.get(key),
+                Some(normalize) => values.get(&normalize(key.clone())),
+            };
+            if let Some(v) = v {
+                ret.insert(key, v.clone());
+            }
+        }
+        Ok(ret)
+    }
+
+    pub async fn load_many_ref<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a K>,
+    ) -> HashMap<&'a K, V>
+    where
+        K: 'a,
+    {
+        self.try_load_many_ref(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Queues `keys` into the batch being gathered without waiting for their values, e.g. as
+    /// soon as a GraphQL selection set tells which keys will be loaded. Keys that are cached or
+    /// already pending are skipped. The prefetched values are cached when the batch is
+    /// dispatched, by the next load call or right away once `max_batch_size` keys are pending.
+    pub async fn prefetch(&self, keys: impl IntoIterator<Item = K>) {
+        let keys = self.normalize_many(keys.into_iter().collect());
+        let mut state = self.lock_state().await;
+        let cached = self.cached_many(&mut state, &keys);
+        let mut mirrored = Vec::new();
+        for (key, v) in keys.into_iter().zip(cached) {
+            if v.is_none()
+                && !self.filtered(&key)
+                && !state.pending.contains_key(&key)
+                && self.overflow(&state, &key).is_none()
+            {
+                state.enqueue(self.principal.as_ref(), &key, self.cost(&key));
+                mirrored.push(key);
+            }
+        }
+        if let Some(shadow) = &self.config.shadow {
+            shadow.mirror(mirrored);
+        }
+        if state.pending.cost() >= self.flush_size() && self.may_dispatch(&state) {
+            drop(self.dispatch(state).await);
+        }
+    }
+
+    /// Queues `key` into the batch being gathered, see [`Self::prefetch`].
+    pub async fn prefetch_one(&self, key: K) {
+        self.prefetch(vec![key]).await
+    }
+
+    /// Primes the cache with the given value. Unless the [`ConsistencyMode`] is
+    /// [`ConsistencyMode::Eventual`], primed values take precedence over the results of batches
+    /// that were already in flight when `prime` was called.
+    pub async fn prime(&self, key: K, val: V) {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        let update = Update::Upsert(val);
+        state.write(
+            self.principal.as_ref(),
+            key,
+            update,
+            in_flight,
+            self.config.consistency,
+        );
+    }
+
+    /// Primes the cache with `val` for `key` like [`Loader::prime`], expiring it after `ttl`, e.g.
+    /// for values which change every few seconds while others are immutable.
+    pub async fn prime_with_ttl(&self, key: K, val: V, ttl: Duration) {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        state.write(
+            self.principal.as_ref(),
+            key.clone(),
+            Update::Upsert(val),
+            in_flight,
+            self.config.consistency,
+        );
+        state.expiry.insert(key, Instant::now() + ttl);
+    }
+
+    /// Applies an update pushed from outside, e.g. from a change data capture stream, to the
+    /// caches of all principals. Like primed values, updates take precedence over the results of
+    /// batches that were already in flight, so a key deleted meanwhile resolves to
+    /// [`LoadError::NotFound`] instead of its stale value.
+    pub async fn apply_update(&self, key: K, update: Update<V>) {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        if in_flight && matches!(update, Update::Delete) {
+            state.deleted.insert(key.clone());
+        }
+        state.update(key, update, in_flight, self.config.consistency);
+    }
+
+    pub async fn prime_many(&self, values: impl IntoIterator<Item = (K, V)>) {
+        let mut state = self.lock_state().await;
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        for (k, v) in values.into_iter() {
+            let k = self.normalize(k);
+            let update = Update::Upsert(v);
+            state.write(
+                self.principal.as_ref(),
+                k,
+                update,
+                in_flight,
+                self.config.consistency,
+            );
+        }
+    }
+
+    /// Returns the entries of the cache of this loader's principal, or of the shared cache
+    /// without one, e.g. to persist them between runs of a CLI tool.
+    pub async fn export_cache(&self) -> Vec<(K, V)>
+    where
+        C: CacheEntries,
+    {
+        let state = self.lock_state().await;
+        match &self.principal {
+            None => state.completed.entries(),
+            Some(p) => state
+                .scoped
+                .get(p)
+                .map(|scope| scope.completed.entries())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Primes the cache with `entries` exported by [`Loader::export_cache`].
+    pub async fn import_cache(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        self.prime_many(entries).await
+    }
+
+    /// Writes the entries of [`Loader::export_cache`] to a JSON file at `path`. The file is
+    /// written synchronously, as this is meant for CLI tools and tests.
+    #[cfg(feature = "serde")]
+    pub async fn save_snapshot(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()>
+    where
+        C: CacheEntries,
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let entries = self.export_cache().await;
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, &entries)?;
+        Ok(())
+    }
+
+    /// Primes the cache with the entries of a file written by [`Loader::save_snapshot`].
+    #[cfg(feature = "serde")]
+    pub async fn load_snapshot(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()>
+    where
+        K: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let entries: Vec<(K, V)> = serde_json::from_reader(file)?;
+        self.import_cache(entries).await;
+        Ok(())
+    }
+
+    /// Removes `key` from the cache of this loader's principal, or from the caches of all
+    /// principals without one. Like primes, clears take precedence over the results of batches
+    /// that were already in flight, whose callers still get the values of these batches.
+    pub async fn clear(&self, key: K) {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        let in_flight = self.shared.in_flight.load(Ordering::SeqCst) > 0;
+        match &self.principal {
+            Some(p) => {
+                let p = Some(p);
+                state.write(p, key, Update::Delete, in_flight, self.config.consistency);
+            }
+            None => {
+                state.update(
+                    key.clone(),
+                    Update::Delete,
+                    in_flight,
+                    self.config.consistency,
+                );
+                drop(state);
+                if let Some(async_cache) = &self.config.async_cache {
+                    async_cache.remove(&key).await;
+                }
+            }
+        }
+    }
+
+    /// Subscribes to the changes of the cached value of `key` for this loader's principal, or of
+    /// the shared value without one: the values loaded by batches, primed or pushed by
+    /// [`Loader::apply_update`], and clears as [`Update::Delete`]. The changes are yielded in
+    /// the order they were applied to the cache, so that a subscriber never sees the result of
+    /// a batch after a write which took precedence over it.
+    pub async fn subscribe(&self, key: K) -> Subscription<V> {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        state.watchers.subscribe(self.principal.clone(), key)
+    }
+
+    /// Clears the cache of this loader's principal, or the caches of all principals without one.
+    pub async fn clear_all(&self) {
+        let mut state = self.lock_state().await;
+        match &self.principal {
+            Some(p) => {
+                state.scoped.remove(p);
+                state.publish_clear(|watched| watched == Some(p));
+            }
+            None => {
+                state.completed.clear();
+                state.scoped.clear();
+                state.expiry.clear();
+                state.publish_clear(|_| true);
+                drop(state);
+                if let Some(async_cache) = &self.config.async_cache {
+                    async_cache.clear().await;
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, F, C, O, S> Loader<K, V, Sendable<F>, C, O, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    F: SendBatchFn<K, V>,
+    C: Cache<Key = K, Val = V> + Send + 'static,
+    O: Observer + Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Keeps serving an expired value for up to `max_stale` past its TTL, queuing a refresh of
+    /// the key instead of waiting for it. A task spawned on the runtime of the loader dispatches
+    /// the refresh, so that it completes without another load, reporting to the observer set
+    /// before this call. Once the value is older, loads wait for the refresh as usual.
+    /// [`Loader::get_cached`] still treats expired values as missing.
+    pub fn with_stale_while_revalidate(mut self, max_stale: Duration) -> Self {
+        let shared = Arc::downgrade(&self.shared);
+        let observer = self.observer.clone();
+        let revalidate = move |config: &Arc<Config<K, V, Sendable<F>>>,
+                               principal: Option<&Principal>,
+                               key: K| {
+            if let Some(shared) = shared.upgrade() {
+                let loader = Loader {
+                    shared,
+                    config: config.clone(),
+                    principal: principal.cloned(),
+                    observer: observer.clone(),
+                };
+                let runtime = config.runtime.clone();
+                runtime.spawn(Box::pin(async move { loader.revalidate(key).await }));
+            }
+        };
+        let config = self.config_mut();
+        config.stale_while_revalidate = Some(max_stale);
+        config.revalidate = Some(Arc::new(revalidate));
+        self
+    }
+}
+
+impl<K, T, E, F, C, O, S> Loader<K, Result<T, E>, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+    E: Clone,
+    F: TryBatchFn<K, Result<T, E>>,
+    C: Cache<Key = K, Val = Result<T, E>>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// When enabled, cached `Err` values are treated as soft: a load hitting one adds the key to
+    /// the next batch to be re-fetched instead of returning the cached error, while `Ok` values
+    /// are served from the cache as usual.
+    pub fn with_error_refresh_on_dispatch(mut self, enabled: bool) -> Self {
+        self.config_mut().refresh_errors = if enabled { Some(Result::is_err) } else { None };
+        self
+    }
+
+    /// Sets how `Err` values returned by the batch function are cached, so that transient
+    /// per-key failures need not stick for the life of the loader. Defaults to
+    /// [`ErrorCaching::Forever`]. Errors which are not cached for good are not written to the
+    /// async cache.
+    pub fn with_error_caching(mut self, error_caching: ErrorCaching) -> Self {
+        self.config_mut().error_caching = Some((Result::is_err, error_caching));
+        self
+    }
+}
+
+impl<K, T, F, C, O, S> Loader<K, Vec<T>, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+    F: TryBatchFn<K, Vec<T>>,
+    C: Cache<Key = K, Val = Vec<T>>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// Loads the values of `key` from a batch function returning several values per key, e.g.
+    /// one wrapped in a [`Many`](crate::Many). A key the batch function returned nothing for
+    /// has no values rather than being not found, unless the [`MissingKeyPolicy`] resolves it
+    /// otherwise.
+    pub async fn try_load_values(&self, key: K) -> Result<Vec<T>, LoadError> {
+        match self.try_load(key).await {
+            Err(LoadError::NotFound(_)) => Ok(Vec::new()),
+            r => r,
+        }
+    }
+
+    pub async fn load_values(&self, key: K) -> Vec<T> {
+        self.try_load_values(key)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 }
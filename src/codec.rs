@@ -0,0 +1,136 @@
+use crate::cached::{Cache, CacheEntries};
+
+/// Converts the values of a [`CodecCache`] to the form they are stored in, e.g. compressed
+/// bytes of large JSON documents.
+///
+/// A pair of closures `(encode, decode)` is a codec as well.
+pub trait ValueCodec<V> {
+    type Encoded;
+
+    fn encode(&self, val: &V) -> Self::Encoded;
+
+    /// Restores a value, `None` if it cannot be decoded, which makes the lookup a miss so that
+    /// the key is loaded again.
+    fn decode(&self, encoded: &Self::Encoded) -> Option<V>;
+}
+
+impl<V, X, E, D> ValueCodec<V> for (E, D)
+where
+    E: Fn(&V) -> X,
+    D: Fn(&X) -> Option<V>,
+{
+    type Encoded = X;
+
+    fn encode(&self, val: &V) -> X {
+        (self.0)(val)
+    }
+
+    fn decode(&self, encoded: &X) -> Option<V> {
+        (self.1)(encoded)
+    }
+}
+
+/// A cache storing its values encoded by a [`ValueCodec`] in another cache, independently of
+/// what the batch function returns, e.g.
+/// `Loader::with_cache(load_fn, CodecCache::new(HashMap::new(), (compress, decompress)))`.
+///
+/// Every hit decodes the value, which is held until the next lookup, as [`Cache::get`] returns a
+/// reference.
+pub struct CodecCache<C, X, V> {
+    cache: C,
+    codec: X,
+    hit: Option<V>,
+}
+
+impl<C, X, V> CodecCache<C, X, V> {
+    pub fn new(cache: C, codec: X) -> Self {
+        CodecCache {
+            cache,
+            codec,
+            hit: None,
+        }
+    }
+
+    /// The cache holding the encoded values, e.g. to tell their size.
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+}
+
+impl<K, V, C, X> Cache for CodecCache<C, X, V>
+where
+    C: Cache<Key = K, Val = X::Encoded>,
+    X: ValueCodec<V>,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let codec = &self.codec;
+        self.hit = self
+            .cache
+            .get(key)
+            .and_then(|encoded| codec.decode(encoded));
+        self.hit.as_ref()
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.cache.insert(key, self.codec.encode(&val));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.hit = None;
+        self.cache
+            .remove(key)
+            .and_then(|encoded| self.codec.decode(&encoded))
+    }
+
+    fn clear(&mut self) {
+        self.cache.clear();
+        self.hit = None;
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        self.cache.len_hint()
+    }
+
+    /// The weight of the encoded value in the inner cache.
+    fn weight(&self, key: &K, val: &V) -> usize {
+        self.cache.weight(key, &self.codec.encode(val))
+    }
+
+    fn get_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        let (cache, codec) = (&mut self.cache, &self.codec);
+        self.hit = None;
+        keys.iter()
+            .map(|key| cache.get(key).and_then(|encoded| codec.decode(encoded)))
+            .collect()
+    }
+
+    fn insert_many(&mut self, entries: Vec<(K, V)>) {
+        let codec = &self.codec;
+        let entries = entries
+            .into_iter()
+            .map(|(key, val)| (key, codec.encode(&val)))
+            .collect();
+        self.cache.insert_many(entries);
+    }
+}
+
+impl<K, V, C, X> CacheEntries for CodecCache<C, X, V>
+where
+    C: CacheEntries<Key = K, Val = X::Encoded>,
+    X: ValueCodec<V>,
+{
+    fn entries(&self) -> Vec<(K, V)> {
+        self.cache
+            .entries()
+            .into_iter()
+            .filter_map(|(key, encoded)| self.codec.decode(&encoded).map(|val| (key, val)))
+            .collect()
+    }
+
+    fn keys(&self) -> Vec<K> {
+        self.cache.keys()
+    }
+}
@@ -0,0 +1,243 @@
+//! Combinators deriving loaders from other loaders, sharing their batches and caches.
+use crate::spawned::SpawnedLoader;
+use crate::{cached, non_cached, LoadError, Observer, TryBatchFn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The loading methods shared by the loaders of this crate, which the combinators build on.
+/// The loaders of the combinators are used through this trait.
+pub trait Load<K, V> {
+    fn try_load(&self, key: K) -> impl Future<Output = Result<V, LoadError>>;
+
+    /// Loads `keys`, returning the outcome of every key individually.
+    fn load_results(&self, keys: Vec<K>) -> impl Future<Output = HashMap<K, Result<V, LoadError>>>;
+
+    fn load(&self, key: K) -> impl Future<Output = V> {
+        async move { self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e)) }
+    }
+
+    fn try_load_many(&self, keys: Vec<K>) -> impl Future<Output = Result<HashMap<K, V>, LoadError>>
+    where
+        K: Eq + Hash,
+    {
+        async move {
+            self.load_results(keys)
+                .await
+                .into_iter()
+                .map(|(k, r)| r.map(|v| (k, v)))
+                .collect()
+        }
+    }
+
+    fn load_many(&self, keys: Vec<K>) -> impl Future<Output = HashMap<K, V>>
+    where
+        K: Eq + Hash,
+    {
+        async move {
+            self.try_load_many(keys)
+                .await
+                .unwrap_or_else(|e| panic!("{}", e))
+        }
+    }
+
+    /// Derives a loader of the values of this one mapped by `map`, e.g. the names of users
+    /// from a loader of users. Values are mapped on the way out, so the derived loader shares
+    /// the batches and the cache of this one.
+    fn map_values<U, M>(self, map: M) -> MappedLoader<Self, V, M>
+    where
+        Self: Sized,
+        M: Fn(V) -> U,
+    {
+        MappedLoader {
+            loader: self,
+            map: Arc::new(map),
+            _value: PhantomData,
+        }
+    }
+
+    /// Chains `next` to this loader: the derived loader loads a value of this one, e.g. a post,
+    /// then the value of `next` for the key returned by `key_of`, e.g. the post's author, and
+    /// resolves to both. Each step is batched by its own loader, so loading many keys calls
+    /// each batch function once rather than once per key.
+    fn then_load<K2, V2, L2, E>(self, key_of: E, next: L2) -> ChainedLoader<Self, L2, V, E>
+    where
+        Self: Sized,
+        L2: Load<K2, V2>,
+        E: Fn(&V) -> K2,
+    {
+        ChainedLoader {
+            first: self,
+            next,
+            key_of: Arc::new(key_of),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<K, V, F, C, O, S> Load<K, V> for cached::Loader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: cached::Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load(&self, key: K) -> impl Future<Output = Result<V, LoadError>> {
+        cached::Loader::try_load(self, key)
+    }
+
+    fn load_results(&self, keys: Vec<K>) -> impl Future<Output = HashMap<K, Result<V, LoadError>>> {
+        cached::Loader::load_results(self, keys)
+    }
+}
+
+impl<K, V, F, O, S> Load<K, V> for non_cached::Loader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load(&self, key: K) -> impl Future<Output = Result<V, LoadError>> {
+        non_cached::Loader::try_load(self, key)
+    }
+
+    fn load_results(&self, keys: Vec<K>) -> impl Future<Output = HashMap<K, Result<V, LoadError>>> {
+        non_cached::Loader::load_results(self, keys)
+    }
+}
+
+impl<K, V> Load<K, V> for SpawnedLoader<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn try_load(&self, key: K) -> impl Future<Output = Result<V, LoadError>> {
+        SpawnedLoader::try_load(self, key)
+    }
+
+    fn load_results(&self, keys: Vec<K>) -> impl Future<Output = HashMap<K, Result<V, LoadError>>> {
+        SpawnedLoader::load_results(self, keys)
+    }
+}
+
+/// A loader mapping the values of another loader, see [`Load::map_values`].
+pub struct MappedLoader<L, V, M> {
+    loader: L,
+    map: Arc<M>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<L: Clone, V, M> Clone for MappedLoader<L, V, M> {
+    fn clone(&self) -> Self {
+        MappedLoader {
+            loader: self.loader.clone(),
+            map: self.map.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<L, V, M> MappedLoader<L, V, M> {
+    /// The underlying loader, e.g. to prime or clear keys.
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+}
+
+impl<K, V, U, L, M> Load<K, U> for MappedLoader<L, V, M>
+where
+    K: Eq + Hash,
+    L: Load<K, V>,
+    M: Fn(V) -> U,
+{
+    async fn try_load(&self, key: K) -> Result<U, LoadError> {
+        self.loader.try_load(key).await.map(|v| (self.map)(v))
+    }
+
+    async fn load_results(&self, keys: Vec<K>) -> HashMap<K, Result<U, LoadError>> {
+        self.loader
+            .load_results(keys)
+            .await
+            .into_iter()
+            .map(|(k, r)| (k, r.map(|v| (self.map)(v))))
+            .collect()
+    }
+}
+
+/// A loader chaining two loaders, see [`Load::then_load`].
+pub struct ChainedLoader<L1, L2, V, E> {
+    first: L1,
+    next: L2,
+    key_of: Arc<E>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<L1: Clone, L2: Clone, V, E> Clone for ChainedLoader<L1, L2, V, E> {
+    fn clone(&self) -> Self {
+        ChainedLoader {
+            first: self.first.clone(),
+            next: self.next.clone(),
+            key_of: self.key_of.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<L1, L2, V, E> ChainedLoader<L1, L2, V, E> {
+    /// The loader of the first step.
+    pub fn first(&self) -> &L1 {
+        &self.first
+    }
+
+    /// The loader of the second step.
+    pub fn next(&self) -> &L2 {
+        &self.next
+    }
+}
+
+impl<K, V, K2, V2, L1, L2, E> Load<K, (V, V2)> for ChainedLoader<L1, L2, V, E>
+where
+    K: Eq + Hash,
+    K2: Eq + Hash + Clone,
+    V2: Clone,
+    L1: Load<K, V>,
+    L2: Load<K2, V2>,
+    E: Fn(&V) -> K2,
+{
+    async fn try_load(&self, key: K) -> Result<(V, V2), LoadError> {
+        let v = self.first.try_load(key).await?;
+        let v2 = self.next.try_load((self.key_of)(&v)).await?;
+        Ok((v, v2))
+    }
+
+    async fn load_results(&self, keys: Vec<K>) -> HashMap<K, Result<(V, V2), LoadError>> {
+        let mut ret = HashMap::new();
+        let mut loaded = Vec::new();
+        for (k, r) in self.first.load_results(keys).await.into_iter() {
+            match r {
+                Ok(v) => loaded.push((k, v)),
+                Err(e) => {
+                    ret.insert(k, Err(e));
+                }
+            }
+        }
+        let next_keys = loaded
+            .iter()
+            .map(|(_, v)| (self.key_of)(v))
+            .collect::<Vec<K2>>();
+        let next_values = self.next.load_results(next_keys.clone()).await;
+        for ((k, v), k2) in loaded.into_iter().zip(next_keys) {
+            // keys dropped by the missing key policy of `next` are dropped here as well
+            if let Some(r) = next_values.get(&k2) {
+                ret.insert(k, r.clone().map(|v2| (v, v2)));
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,126 @@
+use crate::cached::Loader;
+use crate::{BatchFn, LoadError};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// A batch function which gets the context its keys were requested with, e.g. the auth token,
+/// locale or tracing span of the request, without the context being part of every key.
+pub trait ContextBatchFn<K, V, C> {
+    fn load(&mut self, keys: &[K], ctx: &C) -> impl std::future::Future<Output = HashMap<K, V>>;
+}
+
+/// The [`BatchFn`] of a [`ContextLoader`], calling the context batch function once per context
+/// of a batch.
+pub struct ContextFn<F>(F);
+
+impl<C, K, V, F> BatchFn<(C, K), V> for ContextFn<F>
+where
+    C: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    F: ContextBatchFn<K, V, C>,
+{
+    async fn load(&mut self, keys: &[(C, K)]) -> HashMap<(C, K), V> {
+        // batches are grouped by context, so there is a single group unless the loader was
+        // configured with another grouping
+        let mut groups: Vec<(&C, Vec<K>)> = Vec::new();
+        for (ctx, key) in keys.iter() {
+            match groups.iter_mut().find(|(c, _)| *c == ctx) {
+                Some((_, keys)) => keys.push(key.clone()),
+                None => groups.push((ctx, vec![key.clone()])),
+            }
+        }
+        let mut ret = HashMap::new();
+        for (ctx, keys) in groups.into_iter() {
+            let values = self.0.load(&keys, ctx).await;
+            ret.extend(values.into_iter().map(|(k, v)| ((ctx.clone(), k), v)));
+        }
+        ret
+    }
+}
+
+/// The cached loader of a [`ContextLoader`], keyed by context and key.
+pub type ContextKeyLoader<K, V, C, F> = Loader<(C, K), V, ContextFn<F>>;
+
+/// A cached loader passing the context keys were requested with to a [`ContextBatchFn`]. Keys
+/// requested with different contexts are batched together and split by context before
+/// dispatch, so that the batch function is called once per context.
+///
+/// Values are cached per context, as they may depend on it, e.g. on the locale. Contexts appear
+/// in errors along with keys, so contexts holding secrets should be redacted with
+/// `with_loader_config(|loader| loader.with_key_redactor(..))`.
+pub struct ContextLoader<K, V, C, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    C: Eq + Hash + Clone,
+    F: ContextBatchFn<K, V, C>,
+{
+    loader: ContextKeyLoader<K, V, C, F>,
+}
+
+impl<K, V, C, F> Clone for ContextLoader<K, V, C, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    C: Eq + Hash + Clone,
+    F: ContextBatchFn<K, V, C>,
+{
+    fn clone(&self) -> Self {
+        ContextLoader {
+            loader: self.loader.clone(),
+        }
+    }
+}
+
+impl<K, V, C, F> ContextLoader<K, V, C, F>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: Clone,
+    C: Eq + Hash + Clone + Debug + Send + Sync + 'static,
+    F: ContextBatchFn<K, V, C>,
+{
+    pub fn new(load_fn: F) -> Self {
+        let loader = Loader::new(ContextFn(load_fn)).with_group_by(|(ctx, _): &(C, K)| ctx.clone());
+        ContextLoader { loader }
+    }
+
+    /// Configures the underlying loader, e.g.
+    /// `.with_loader_config(|loader| loader.with_max_batch_size(50))`.
+    pub fn with_loader_config(
+        mut self,
+        configure: impl FnOnce(ContextKeyLoader<K, V, C, F>) -> ContextKeyLoader<K, V, C, F>,
+    ) -> Self {
+        self.loader = configure(self.loader);
+        self
+    }
+
+    /// The underlying loader, e.g. to prime or clear keys of a context.
+    pub fn loader(&self) -> &ContextKeyLoader<K, V, C, F> {
+        &self.loader
+    }
+
+    pub async fn try_load_with_ctx(&self, key: K, ctx: C) -> Result<V, LoadError> {
+        self.loader.try_load((ctx, key)).await
+    }
+
+    pub async fn load_with_ctx(&self, key: K, ctx: C) -> V {
+        self.loader.load((ctx, key)).await
+    }
+
+    pub async fn try_load_many_with_ctx(
+        &self,
+        keys: Vec<K>,
+        ctx: C,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        let keys = keys.into_iter().map(|k| (ctx.clone(), k));
+        let values = self.loader.try_load_many(keys).await?;
+        Ok(values.into_iter().map(|((_, k), v)| (k, v)).collect())
+    }
+
+    pub async fn load_many_with_ctx(&self, keys: Vec<K>, ctx: C) -> HashMap<K, V> {
+        self.try_load_many_with_ctx(keys, ctx)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
@@ -0,0 +1,81 @@
+//! Diagnostics of how long the loaders hold the lock of their state, enabled by the
+//! `debug-diagnostics` feature.
+//!
+//! A loader holds the lock of its state while it dispatches a flush, from building its batches
+//! until every batch function call of the flush completed, so that keys requested meanwhile
+//! join the next batch rather than race the running one. Every load of the loader, even a cache
+//! hit, waits for the lock meanwhile, so a slow batch function stalls all callers. Flushes
+//! which hold the lock longer than the threshold set by `with_lock_hold_threshold` are logged
+//! as warnings with the `log` crate, along with the name of the loader, the number of keys of
+//! the flush and how long its longest batch function call took.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How long a flush may hold the lock before it is logged, unless set per loader.
+pub(crate) const DEFAULT_THRESHOLD: Duration = Duration::from_millis(100);
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// The longest time a flush of a loader held its state lock so far.
+#[derive(Default)]
+pub(crate) struct LockHolds {
+    longest: AtomicU64,
+}
+
+impl LockHolds {
+    pub(crate) fn longest(&self) -> Duration {
+        Duration::from_nanos(self.longest.load(Ordering::Relaxed))
+    }
+
+    /// Records that a flush of `keys` keys held the lock of the loader named `name` for `held`,
+    /// its longest batch function call taking `batch` if the batches ran under the lock, warning if it held it for longer than
+    /// `threshold`.
+    pub(crate) fn record(
+        &self,
+        name: Option<&str>,
+        threshold: Duration,
+        keys: usize,
+        held: Duration,
+        batch: Option<Duration>,
+    ) {
+        self.longest.fetch_max(nanos(held), Ordering::Relaxed);
+        if held <= threshold {
+            return;
+        }
+        let name = name.unwrap_or("<unnamed>");
+        match batch {
+            Some(batch) => log::warn!(
+                "dataloader {} held its state lock for {:?} while dispatching {} keys, its batch \
+                 function taking {:?}",
+                name,
+                held,
+                keys,
+                batch
+            ),
+            None => log::warn!(
+                "dataloader {} held its state lock for {:?} while dispatching {} keys",
+                name,
+                held,
+                keys
+            ),
+        }
+    }
+}
+
+/// The longest batch function call of a flush.
+#[derive(Default)]
+pub(crate) struct LongestBatch(AtomicU64);
+
+impl LongestBatch {
+    pub(crate) fn record(&self, took: Duration) {
+        self.0.fetch_max(nanos(took), Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
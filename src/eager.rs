@@ -0,0 +1,29 @@
+//! Loaders which dispatch eagerly: every load immediately batches whatever is pending, without
+//! yielding to the runtime first to let other loads join the batch.
+//!
+//! Batches then only depend on the order of the loads, not on how the runtime schedules them.
+//! Keys are still batched when loaded together, e.g. with `load_many`.
+
+use crate::{cached, non_cached, TryBatchFn};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Creates a [`cached::Loader`] which dispatches eagerly.
+pub fn cached<K, V, F>(load_fn: F) -> cached::Loader<K, V, F, HashMap<K, V>>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    cached::Loader::new(load_fn).with_yield_count(0)
+}
+
+/// Creates a [`non_cached::Loader`] which dispatches eagerly.
+pub fn non_cached<K, V, F>(load_fn: F) -> non_cached::Loader<K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    non_cached::Loader::new(load_fn).with_yield_count(0)
+}
@@ -0,0 +1,260 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// The error returned by the `try_load*` methods when a key could not be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[non_exhaustive]
+pub enum LoadError {
+    /// The batch function did not return a value for the key, which is included as formatted
+    /// by the key redactor of the loader or by `with_debug_keys`, or as `<key>` otherwise.
+    #[cfg_attr(
+        feature = "thiserror",
+        error("could not lookup result for given key: {0}")
+    )]
+    NotFound(String),
+    /// The batch function returned values for keys which were not requested and the loader is
+    /// configured with [`ResultPolicy::Reject`](crate::ResultPolicy::Reject) or
+    /// [`ResultPolicy::Exact`](crate::ResultPolicy::Exact).
+    #[cfg_attr(
+        feature = "thiserror",
+        error("batch returned {count} unrequested key(s)")
+    )]
+    UnrequestedKeys { count: usize },
+    /// The batch function did not return exactly one value per requested key and the loader is
+    /// configured with [`ResultPolicy::Exact`](crate::ResultPolicy::Exact).
+    #[cfg_attr(
+        feature = "thiserror",
+        error("batch returned {value_count} value(s) for {key_count} key(s)")
+    )]
+    UnequalKeyValueSize {
+        key_count: usize,
+        value_count: usize,
+    },
+    /// The [`TryBatchFn`](crate::TryBatchFn) failed with the included error, which is the
+    /// [`source`](Error::source) of this one.
+    #[cfg_attr(feature = "thiserror", error("batch function failed"))]
+    Batch(#[cfg_attr(feature = "thiserror", source)] BatchError),
+    /// The batch function did not complete within the timeout the loader is configured with.
+    #[cfg_attr(feature = "thiserror", error("batch function timed out"))]
+    Timeout,
+    /// The batch function panicked with the included message. The panic is not propagated to
+    /// the caller which happened to run the batch, and the loader stays usable.
+    #[cfg_attr(feature = "thiserror", error("batch function panicked: {0}"))]
+    Panicked(String),
+    /// The key was not queued because the pending queue of the loader was full and the loader
+    /// is configured with [`Backpressure::Fail`](crate::Backpressure::Fail).
+    #[cfg_attr(feature = "thiserror", error("pending queue is full"))]
+    QueueFull,
+    /// A [`BlockingLoader`](crate::blocking::BlockingLoader) could not block the calling thread
+    /// on its executor, so the key was not loaded.
+    #[cfg_attr(feature = "thiserror", error("cannot block the calling thread: {0}"))]
+    Blocking(#[cfg_attr(feature = "thiserror", source)] BlockingError),
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotFound(key) => {
+                write!(f, "could not lookup result for given key: {}", key)
+            }
+            LoadError::UnrequestedKeys { count } => {
+                write!(f, "batch returned {} unrequested key(s)", count)
+            }
+            LoadError::UnequalKeyValueSize {
+                key_count,
+                value_count,
+            } => write!(
+                f,
+                "batch returned {} value(s) for {} key(s)",
+                value_count, key_count
+            ),
+            LoadError::Batch(_) => write!(f, "batch function failed"),
+            LoadError::Timeout => write!(f, "batch function timed out"),
+            LoadError::Panicked(message) => write!(f, "batch function panicked: {}", message),
+            LoadError::QueueFull => write!(f, "pending queue is full"),
+            LoadError::Blocking(e) => write!(f, "cannot block the calling thread: {}", e),
+        }
+    }
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Batch(e) => Some(e),
+            LoadError::Blocking(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The error returned by [`LoaderBuilder`](crate::builder::LoaderBuilder) for settings which
+/// don't make sense together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The max batch size is 0, so no key would ever be loaded.
+    #[cfg_attr(feature = "thiserror", error("max batch size must not be 0"))]
+    ZeroMaxBatchSize,
+    /// The chunk size is 0, so no key would ever be loaded.
+    #[cfg_attr(feature = "thiserror", error("chunk size must not be 0"))]
+    ZeroChunkSize,
+    /// The min batch size exceeds the max batch size, so batches never reach it and always
+    /// wait for the max delay.
+    #[cfg_attr(
+        feature = "thiserror",
+        error("min batch size {min_batch_size} exceeds max batch size {max_batch_size}")
+    )]
+    MinBatchSizeAboveMax {
+        min_batch_size: usize,
+        max_batch_size: usize,
+    },
+    /// Both a yield count and a delay were set to wait for work, only one of them is used.
+    #[cfg_attr(
+        feature = "thiserror",
+        error("both a yield count and a delay were set")
+    )]
+    ConflictingWait,
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::ZeroMaxBatchSize => write!(f, "max batch size must not be 0"),
+            BuildError::ZeroChunkSize => write!(f, "chunk size must not be 0"),
+            BuildError::MinBatchSizeAboveMax {
+                min_batch_size,
+                max_batch_size,
+            } => write!(
+                f,
+                "min batch size {} exceeds max batch size {}",
+                min_batch_size, max_batch_size
+            ),
+            BuildError::ConflictingWait => write!(f, "both a yield count and a delay were set"),
+        }
+    }
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl Error for BuildError {}
+
+/// Why a [`BlockingLoader`](crate::blocking::BlockingLoader) could not block the calling
+/// thread on its executor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[non_exhaustive]
+pub enum BlockingError {
+    /// The executor needs a runtime which the calling thread doesn't belong to, e.g. a rayon
+    /// worker asking for the current tokio runtime.
+    #[cfg_attr(feature = "thiserror", error("no runtime on this thread"))]
+    NoRuntime,
+    /// The calling thread is running an async task, which blocking would stall along with the
+    /// other tasks of its worker. Blocking calls from async code belong in `spawn_blocking`.
+    #[cfg_attr(feature = "thiserror", error("called from an async task"))]
+    InAsyncTask,
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl fmt::Display for BlockingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockingError::NoRuntime => write!(f, "no runtime on this thread"),
+            BlockingError::InAsyncTask => write!(f, "called from an async task"),
+        }
+    }
+}
+
+#[cfg(not(feature = "thiserror"))]
+impl Error for BlockingError {}
+
+impl From<BlockingError> for LoadError {
+    fn from(err: BlockingError) -> Self {
+        LoadError::Blocking(err)
+    }
+}
+
+impl From<BatchError> for LoadError {
+    fn from(err: BatchError) -> Self {
+        LoadError::Batch(err)
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(err: LoadError) -> Self {
+        let kind = match err {
+            LoadError::NotFound(_) => io::ErrorKind::NotFound,
+            LoadError::Batch(_) | LoadError::Panicked(_) => io::ErrorKind::Other,
+            LoadError::Timeout => io::ErrorKind::TimedOut,
+            LoadError::Blocking(_) => io::ErrorKind::WouldBlock,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// The error a [`TryBatchFn`](crate::TryBatchFn) failed with, shared by every key of the
+/// batch. It displays as, and chains to the sources of, the original error.
+#[derive(Clone)]
+pub struct BatchError(Arc<dyn Error + Send + Sync>);
+
+impl BatchError {
+    pub fn new(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        BatchError(Arc::from(err.into()))
+    }
+
+    /// The original error, e.g. to [`downcast_ref`](trait.Error.html#method.downcast_ref) it.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for BatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Errors are equal when they display the same, as the original error need not be comparable.
+impl PartialEq for BatchError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for BatchError {}
+
+impl From<&str> for BatchError {
+    fn from(err: &str) -> Self {
+        BatchError::new(err)
+    }
+}
+
+impl From<String> for BatchError {
+    fn from(err: String) -> Self {
+        BatchError::new(err)
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for BatchError {
+    fn from(err: Box<dyn Error + Send + Sync>) -> Self {
+        BatchError::new(err)
+    }
+}
@@ -0,0 +1,18 @@
+/// Tells whether a key may exist, e.g. backed by a bloom filter of the key space, see
+/// `with_existence_filter` of the loaders. Keys which can't exist resolve like keys missing
+/// from a batch without being queued, so that lookups of mostly absent keys don't reach the
+/// batch function.
+pub trait KeyFilter<K>: Send + Sync {
+    /// Whether `key` may exist. A false positive only costs a load, while a false negative
+    /// hides the value of `key`.
+    fn may_contain(&self, key: &K) -> bool;
+}
+
+impl<K, F> KeyFilter<K> for F
+where
+    F: Fn(&K) -> bool + Send + Sync,
+{
+    fn may_contain(&self, key: &K) -> bool {
+        self(key)
+    }
+}
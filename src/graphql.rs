@@ -0,0 +1,466 @@
+//! Helpers for GraphQL servers which build loader keys from the resolver's execution context.
+//!
+//! Enable the `async-graphql` or `juniper` feature for [`LoaderExt`] methods taking the
+//! respective framework's resolver context. The `juniper` feature also provides a per-request
+//! [`LoaderContext`] and the [`selected_fields`] of the look-ahead. The `async-graphql` feature
+//! also adapts batch functions to and from async-graphql's own `dataloader::Loader` trait, see
+//! [`AsyncGraphqlLoader`] and [`AsyncGraphqlBatchFn`], and finds the ids a request names
+//! before it executes to warm a loader with, see [`lookahead_ids`] and [`prefetch_lookahead`].
+use crate::{cached, non_cached, LoadError, Observer, TryBatchFn};
+#[cfg(feature = "async-graphql")]
+use crate::{runtime, BatchFn, SendBatchFn};
+#[cfg(feature = "async-graphql")]
+use std::collections::HashMap;
+#[cfg(feature = "async-graphql")]
+use std::convert::Infallible;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+#[cfg(feature = "async-graphql")]
+use std::marker::PhantomData;
+#[cfg(feature = "juniper")]
+use std::sync::Arc;
+
+/// A composite key of an id, the fields selected on it by the query and the request locale, so
+/// that the batch function can fetch exactly what the resolver needs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FieldKey<K> {
+    pub id: K,
+    /// Selected field names, sorted and deduplicated so equal selections batch together.
+    pub fields: Vec<String>,
+    pub locale: Option<String>,
+}
+
+impl<K> FieldKey<K> {
+    pub fn new<I, S>(id: K, fields: I, locale: Option<String>) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut fields = fields.into_iter().map(Into::into).collect::<Vec<_>>();
+        fields.sort();
+        fields.dedup();
+        FieldKey { id, fields, locale }
+    }
+
+    /// The key of `id` with the fields selected on the current field and the locale of the
+    /// context.
+    #[cfg(feature = "juniper")]
+    pub fn from_executor<CtxT, S>(id: K, executor: &juniper::Executor<'_, '_, CtxT, S>) -> Self
+    where
+        CtxT: RequestLocale,
+        S: juniper::ScalarValue,
+    {
+        let locale = executor.context().locale();
+        FieldKey::new(id, selected_fields(executor), locale)
+    }
+}
+
+/// The names of the fields selected on the current field, as written in the schema rather than
+/// aliased by the query.
+#[cfg(feature = "juniper")]
+pub fn selected_fields<CtxT, S>(executor: &juniper::Executor<'_, '_, CtxT, S>) -> Vec<String>
+where
+    S: juniper::ScalarValue,
+{
+    executor
+        .look_ahead()
+        .children()
+        .iter()
+        .map(|c| c.field_original_name().to_owned())
+        .collect()
+}
+
+/// The locale of a request. With async-graphql, add it to the request data; with juniper,
+/// return it from [`RequestLocale::locale`] of the context.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Locale(pub String);
+
+/// Implemented by juniper contexts which carry a request locale.
+pub trait RequestLocale {
+    fn locale(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A juniper context holding the loaders of a single request, so that nothing is cached across
+/// requests, and the request locale. Build one per request with a [`LoaderContextFactory`] and
+/// reach the loaders through [`LoaderContext::loaders`] or `Deref`.
+#[cfg(feature = "juniper")]
+pub struct LoaderContext<L> {
+    loaders: L,
+    locale: Option<String>,
+}
+
+#[cfg(feature = "juniper")]
+impl<L> LoaderContext<L> {
+    pub fn new(loaders: L, locale: Option<String>) -> Self {
+        LoaderContext { loaders, locale }
+    }
+
+    pub fn loaders(&self) -> &L {
+        &self.loaders
+    }
+}
+
+#[cfg(feature = "juniper")]
+impl<L> std::ops::Deref for LoaderContext<L> {
+    type Target = L;
+
+    fn deref(&self) -> &L {
+        &self.loaders
+    }
+}
+
+#[cfg(feature = "juniper")]
+impl<L> juniper::Context for LoaderContext<L> {}
+
+#[cfg(feature = "juniper")]
+impl<L> RequestLocale for LoaderContext<L> {
+    fn locale(&self) -> Option<String> {
+        self.locale.clone()
+    }
+}
+
+/// Builds a fresh [`LoaderContext`] for every request from a function constructing the
+/// request's loaders, e.g. a struct of `cached::Loader`s sharing a connection pool. Cloning the
+/// factory is cheap.
+#[cfg(feature = "juniper")]
+pub struct LoaderContextFactory<L> {
+    loaders: Arc<dyn Fn() -> L + Send + Sync>,
+}
+
+#[cfg(feature = "juniper")]
+impl<L> Clone for LoaderContextFactory<L> {
+    fn clone(&self) -> Self {
+        LoaderContextFactory {
+            loaders: self.loaders.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "juniper")]
+impl<L> LoaderContextFactory<L> {
+    pub fn new(loaders: impl Fn() -> L + Send + Sync + 'static) -> Self {
+        LoaderContextFactory {
+            loaders: Arc::new(loaders),
+        }
+    }
+
+    /// The context of a new request in `locale`.
+    pub fn context(&self, locale: Option<String>) -> LoaderContext<L> {
+        LoaderContext::new((self.loaders)(), locale)
+    }
+}
+
+/// Extension methods for loaders keyed by [`FieldKey`].
+pub trait LoaderExt<K, V> {
+    fn try_load_key(&self, key: FieldKey<K>) -> impl Future<Output = Result<V, LoadError>>;
+
+    /// Loads `id` with the fields selected on the current field and the [`Locale`] from the
+    /// request data.
+    #[cfg(feature = "async-graphql")]
+    fn load_from_info(
+        &self,
+        id: K,
+        ctx: &async_graphql::Context<'_>,
+    ) -> impl Future<Output = Result<V, LoadError>> {
+        let field = ctx.field();
+        let fields = field
+            .selection_set()
+            .map(|f| f.name().to_owned())
+            .collect::<Vec<_>>();
+        let locale = ctx.data_opt::<Locale>().map(|l| l.0.clone());
+        self.try_load_key(FieldKey::new(id, fields, locale))
+    }
+
+    /// Loads `id` with the fields selected on the current field and the locale of the context.
+    #[cfg(feature = "juniper")]
+    fn load_from_executor<CtxT, S>(
+        &self,
+        id: K,
+        executor: &juniper::Executor<'_, '_, CtxT, S>,
+    ) -> impl Future<Output = Result<V, LoadError>>
+    where
+        CtxT: RequestLocale,
+        S: juniper::ScalarValue,
+    {
+        self.try_load_key(FieldKey::from_executor(id, executor))
+    }
+}
+
+impl<K, V, F, C, O, S> LoaderExt<K, V> for cached::Loader<FieldKey<K>, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<FieldKey<K>, V>,
+    C: cached::Cache<Key = FieldKey<K>, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load_key(&self, key: FieldKey<K>) -> impl Future<Output = Result<V, LoadError>> {
+        self.try_load(key)
+    }
+}
+
+impl<K, V, F, O, S> LoaderExt<K, V> for non_cached::Loader<FieldKey<K>, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<FieldKey<K>, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load_key(&self, key: FieldKey<K>) -> impl Future<Output = Result<V, LoadError>> {
+        self.try_load(key)
+    }
+}
+
+/// Exposes a [`SendBatchFn`] as an async-graphql `dataloader::Loader`, to use it with
+/// async-graphql's `DataLoader` without rewriting it.
+///
+/// async-graphql requires its loaders to return `Send` futures, so the futures of the batch
+/// function must be `Send` too. async-graphql may load several batches at once, which call the
+/// batch function one after another.
+#[cfg(feature = "async-graphql")]
+pub struct AsyncGraphqlLoader<F, V> {
+    load_fn: runtime::Mutex<F>,
+    values: PhantomData<fn() -> V>,
+}
+
+#[cfg(feature = "async-graphql")]
+impl<F, V> AsyncGraphqlLoader<F, V> {
+    pub fn new(load_fn: F) -> Self {
+        AsyncGraphqlLoader {
+            load_fn: runtime::Mutex::new(load_fn),
+            values: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "async-graphql")]
+impl<K, V, F> async_graphql::dataloader::Loader<K> for AsyncGraphqlLoader<F, V>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    V: Send + Sync + Clone + 'static,
+    F: SendBatchFn<K, V> + Sync,
+{
+    type Value = V;
+    type Error = Infallible;
+
+    async fn load(&self, keys: &[K]) -> Result<HashMap<K, V>, Infallible> {
+        Ok(self.load_fn.lock().await.load(keys).await)
+    }
+}
+
+/// Uses an async-graphql `dataloader::Loader` as the batch function of a loader of this crate.
+/// The values are results, so that a failed load resolves every key of the batch to the
+/// loader's error rather than dropping it.
+#[cfg(feature = "async-graphql")]
+pub struct AsyncGraphqlBatchFn<L>(pub L);
+
+#[cfg(feature = "async-graphql")]
+impl<K, L> BatchFn<K, Result<L::Value, L::Error>> for AsyncGraphqlBatchFn<L>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    L: async_graphql::dataloader::Loader<K>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Result<L::Value, L::Error>> {
+        match self.0.load(keys).await {
+            Ok(values) => values.into_iter().map(|(k, v)| (k, Ok(v))).collect(),
+            Err(e) => keys.iter().map(|k| (k.clone(), Err(e.clone()))).collect(),
+        }
+    }
+}
+
+/// An id a query document passes to a field, found by [`lookahead_ids`] before the query
+/// executes.
+#[cfg(feature = "async-graphql")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookaheadId {
+    /// The value of the argument, with variables resolved. Every item of a list argument is an
+    /// id of its own.
+    pub id: async_graphql::Value,
+    /// The names of the fields selected on the field, as [`LoaderExt::load_from_info`] passes
+    /// them to [`FieldKey::new`].
+    pub fields: Vec<String>,
+}
+
+/// The ids `request` passes as `argument` wherever its operation selects `field`, at any depth
+/// and through fragments, e.g. the ids of `user(id: 1)` and `users(ids: [2, 3])`. A document
+/// which doesn't parse names no ids, leaving the error to the execution of the request.
+#[cfg(feature = "async-graphql")]
+pub fn lookahead_ids(
+    request: &async_graphql::Request,
+    field: &str,
+    argument: &str,
+) -> Vec<LookaheadId> {
+    use async_graphql::parser::parse_query;
+
+    let document = match parse_query(&request.query) {
+        Ok(document) => document,
+        Err(_) => return Vec::new(),
+    };
+    let mut lookahead = Lookahead {
+        document: &document,
+        request,
+        field,
+        argument,
+        defaults: HashMap::new(),
+        expanding: Vec::new(),
+        ids: Vec::new(),
+    };
+    for (name, operation) in document.operations.iter() {
+        let selected = match (&request.operation_name, name) {
+            (Some(wanted), Some(name)) => wanted.as_str() == name.as_str(),
+            _ => true,
+        };
+        if !selected {
+            continue;
+        }
+        lookahead.defaults = operation
+            .node
+            .variable_definitions
+            .iter()
+            .filter_map(|v| {
+                let default = v.node.default_value.as_ref()?;
+                Some((v.node.name.node.clone(), default.node.clone()))
+            })
+            .collect();
+        lookahead.walk(&operation.node.selection_set.node);
+    }
+    lookahead.ids
+}
+
+/// Prefetches the ids `request` passes as `argument` of `field` into `loader`, see
+/// [`lookahead_ids`], so that the first wave of resolvers joins a batch gathered already rather
+/// than waiting for work. `key` builds the key of an id, or skips it, e.g. a [`FieldKey`] of
+/// the id, its fields and the request [`Locale`]. Ids cached by the loader, e.g. imported from a
+/// persistent cache, are not loaded again.
+#[cfg(feature = "async-graphql")]
+pub async fn prefetch_lookahead<K, V, F, C, O, S>(
+    loader: &cached::Loader<K, V, F, C, O, S>,
+    request: &async_graphql::Request,
+    field: &str,
+    argument: &str,
+    key: impl FnMut(LookaheadId) -> Option<K>,
+) where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: cached::Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    let keys = lookahead_ids(request, field, argument)
+        .into_iter()
+        .filter_map(key)
+        .collect::<Vec<_>>();
+    if !keys.is_empty() {
+        loader.prefetch(keys).await;
+    }
+}
+
+#[cfg(feature = "async-graphql")]
+struct Lookahead<'a> {
+    document: &'a async_graphql::parser::types::ExecutableDocument,
+    request: &'a async_graphql::Request,
+    field: &'a str,
+    argument: &'a str,
+    // default values of the variables of the operation being walked
+    defaults: HashMap<async_graphql::Name, async_graphql::Value>,
+    // fragments being expanded, so that a cycle of fragment spreads ends
+    expanding: Vec<&'a str>,
+    ids: Vec<LookaheadId>,
+}
+
+#[cfg(feature = "async-graphql")]
+impl<'a> Lookahead<'a> {
+    fn walk(&mut self, selection_set: &'a async_graphql::parser::types::SelectionSet) {
+        use async_graphql::parser::types::Selection;
+
+        for selection in selection_set.items.iter() {
+            match &selection.node {
+                Selection::Field(field) => {
+                    let field = &field.node;
+                    if field.name.node == self.field {
+                        if let Some(value) = field.get_argument(self.argument) {
+                            let (variables, defaults) = (&self.request.variables, &self.defaults);
+                            let value = value.node.clone().into_const_with(|name| {
+                                let value = variables.get(&name).or_else(|| defaults.get(&name));
+                                Ok::<_, Infallible>(value.cloned().unwrap_or_default())
+                            });
+                            match value {
+                                Ok(value) => self.collect(value, &field.selection_set.node),
+                                Err(infallible) => match infallible {},
+                            }
+                        }
+                    }
+                    self.walk(&field.selection_set.node);
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.node.fragment_name.node.as_str();
+                    if self.expanding.contains(&name) {
+                        continue;
+                    }
+                    if let Some(fragment) = self.document.fragments.get(name) {
+                        self.expanding.push(name);
+                        self.walk(&fragment.node.selection_set.node);
+                        self.expanding.pop();
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    self.walk(&fragment.node.selection_set.node);
+                }
+            }
+        }
+    }
+
+    fn collect(
+        &mut self,
+        value: async_graphql::Value,
+        selection_set: &'a async_graphql::parser::types::SelectionSet,
+    ) {
+        let ids = match value {
+            async_graphql::Value::List(ids) => ids,
+            async_graphql::Value::Null => return,
+            id => vec![id],
+        };
+        let mut fields = Vec::new();
+        self.fields(selection_set, &mut fields);
+        for id in ids.into_iter() {
+            self.ids.push(LookaheadId {
+                id,
+                fields: fields.clone(),
+            });
+        }
+    }
+
+    /// The names of the fields of `selection_set`, through fragments.
+    fn fields(
+        &mut self,
+        selection_set: &'a async_graphql::parser::types::SelectionSet,
+        fields: &mut Vec<String>,
+    ) {
+        use async_graphql::parser::types::Selection;
+
+        for selection in selection_set.items.iter() {
+            match &selection.node {
+                Selection::Field(field) => fields.push(field.node.name.node.to_string()),
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.node.fragment_name.node.as_str();
+                    if self.expanding.contains(&name) {
+                        continue;
+                    }
+                    if let Some(fragment) = self.document.fragments.get(name) {
+                        self.expanding.push(name);
+                        self.fields(&fragment.node.selection_set.node, fields);
+                        self.expanding.pop();
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    self.fields(&fragment.node.selection_set.node, fields);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::runtime::{Arc, Runtime};
+use std::future::poll_fn;
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Ticks {
+    tick: u64,
+    running: bool,
+    wakers: Vec<Waker>,
+}
+
+/// The timer of `with_idle_dispatch`: a task spawned on the runtime of the loader ticks every
+/// `idle` for as long as callers wait for work, so that the callers await its ticks rather than
+/// sleeping in their own futures. The task stops after a tick nobody waited for, and the next
+/// waiting caller spawns it again.
+pub(crate) struct IdleTicker {
+    idle: Duration,
+    ticks: Mutex<Ticks>,
+}
+
+impl IdleTicker {
+    pub(crate) fn new(idle: Duration) -> Self {
+        IdleTicker {
+            idle,
+            ticks: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Ticks> {
+        self.ticks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for the next tick, spawning the ticking task on `runtime` unless it runs already.
+    pub(crate) async fn tick(self: &Arc<Self>, runtime: &Arc<dyn Runtime>) {
+        let start = {
+            let mut ticks = self.lock();
+            if !ticks.running {
+                ticks.running = true;
+                runtime.spawn(Box::pin(self.clone().run(runtime.clone())));
+            }
+            ticks.tick
+        };
+        poll_fn(|cx| {
+            let mut ticks = self.lock();
+            if ticks.tick > start {
+                return Poll::Ready(());
+            }
+            ticks.wakers.push(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    async fn run(self: Arc<Self>, runtime: Arc<dyn Runtime>) {
+        loop {
+            match runtime.sleep(self.idle) {
+                Some(sleep) => sleep.await,
+                None => runtime.yield_now().await,
+            }
+            let wakers = {
+                let mut ticks = self.lock();
+                ticks.tick += 1;
+                if ticks.wakers.is_empty() {
+                    ticks.running = false;
+                    return;
+                }
+                std::mem::take(&mut ticks.wakers)
+            };
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
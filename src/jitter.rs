@@ -0,0 +1,72 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// A source of randomness for [`Jitter`], pluggable so tests can be deterministic.
+pub trait JitterRng: Send {
+    fn next_u64(&mut self) -> u64;
+}
+
+/// A small xorshift generator, seeded randomly by default.
+#[derive(Debug, Clone)]
+pub struct XorShiftRng(u64);
+
+impl XorShiftRng {
+    pub fn seeded(seed: u64) -> Self {
+        XorShiftRng(seed.max(1))
+    }
+}
+
+impl Default for XorShiftRng {
+    fn default() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        XorShiftRng::seeded(hasher.finish())
+    }
+}
+
+impl JitterRng for XorShiftRng {
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+/// Random variation applied to durations, so that many processes using the same configuration
+/// don't synchronize their batch windows onto the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+    #[default]
+    None,
+    /// Varies the duration by up to the given percentage in either direction.
+    Percent(u8),
+    /// Adds a random duration between the two bounds (inclusive) to the duration.
+    Range(Duration, Duration),
+}
+
+impl Jitter {
+    pub fn apply(&self, duration: Duration, rng: &mut dyn JitterRng) -> Duration {
+        match *self {
+            Jitter::None => duration,
+            Jitter::Percent(percent) => {
+                let max = duration.as_nanos() as u64 * u64::from(percent.min(100)) / 100;
+                let offset = rng.next_u64() % (2 * max + 1);
+                if offset >= max {
+                    duration + Duration::from_nanos(offset - max)
+                } else {
+                    duration - Duration::from_nanos(max - offset)
+                }
+            }
+            Jitter::Range(min, max) => {
+                let (min, max) = (min.as_nanos() as u64, max.as_nanos() as u64);
+                let span = max.saturating_sub(min);
+                let extra = min + rng.next_u64() % (span + 1);
+                duration + Duration::from_nanos(extra)
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Journaling of dispatched batches, e.g. for audit logs of data access.
+//!
+//! A [`Journal`] attached to loaders with `with_journal` records a [`JournalEntry`] for every
+//! batch they dispatch, buffers up to `capacity` entries and then writes them to its
+//! [`JournalSink`]. While the sink is writing, loaders recording further batches wait for it, so
+//! a slow sink slows down dispatching instead of growing the buffer without bound.
+
+use crate::runtime::{Arc, Mutex, SystemTime};
+use crate::{KeyRedactor, LoadError};
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::pin::Pin;
+
+/// The record of a dispatched batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The name of the journal, identifying the loaders it is attached to.
+    pub loader: String,
+    /// The keys of the batch, hashed unless configured otherwise with
+    /// [`Journal::with_redaction`].
+    pub keys: Vec<String>,
+    /// The correlation id of the caller which dispatched the batch, see
+    /// [`Journal::with_correlation`].
+    pub correlation_id: Option<String>,
+    /// When the batch was dispatched.
+    pub timestamp: SystemTime,
+    /// Whether the batch failed as a whole, see [`LoadError`].
+    pub outcome: Result<(), LoadError>,
+}
+
+/// Where a [`Journal`] writes its entries.
+pub trait JournalSink: Send + 'static {
+    fn write(&mut self, entries: Vec<JournalEntry>) -> impl Future<Output = ()> + Send;
+}
+
+// The object safe counterpart of `JournalSink`.
+trait DynSink: Send {
+    fn write(
+        &mut self,
+        entries: Vec<JournalEntry>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+impl<S: JournalSink> DynSink for S {
+    fn write(
+        &mut self,
+        entries: Vec<JournalEntry>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(JournalSink::write(self, entries))
+    }
+}
+
+struct Buffer {
+    entries: Vec<JournalEntry>,
+    sink: Box<dyn DynSink>,
+}
+
+/// Records the batches of the loaders it is attached to, see the [module docs](self).
+pub struct Journal<K> {
+    name: String,
+    capacity: usize,
+    buffer: Arc<Mutex<Buffer>>,
+    redact: Arc<dyn KeyRedactor<K>>,
+    correlation: Option<Arc<dyn Fn() -> Option<String> + Send + Sync>>,
+}
+
+impl<K> Clone for Journal<K> {
+    fn clone(&self) -> Self {
+        Journal {
+            name: self.name.clone(),
+            capacity: self.capacity,
+            buffer: self.buffer.clone(),
+            redact: self.redact.clone(),
+            correlation: self.correlation.clone(),
+        }
+    }
+}
+
+impl<K: Hash> Journal<K> {
+    /// Creates a journal named `name`, writing to `sink` whenever `capacity` entries are
+    /// buffered. Keys are recorded as hashes.
+    pub fn new(name: impl Into<String>, sink: impl JournalSink, capacity: usize) -> Self {
+        Journal {
+            name: name.into(),
+            capacity: capacity.max(1),
+            buffer: Arc::new(Mutex::new(Buffer {
+                entries: Vec::new(),
+                sink: Box::new(sink),
+            })),
+            redact: Arc::new(|key: &K| {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                format!("{:016x}", hasher.finish())
+            }),
+            correlation: None,
+        }
+    }
+}
+
+impl<K> Journal<K> {
+    /// Records keys as returned by `redact` instead of their hashes, e.g. a
+    /// [`SaltedHash`](crate::SaltedHash).
+    pub fn with_redaction(mut self, redact: impl KeyRedactor<K> + 'static) -> Self {
+        self.redact = Arc::new(redact);
+        self
+    }
+
+    /// Records the correlation id returned by `correlation` when a batch is dispatched, e.g.
+    /// from a task local of the request being served. As a batch usually serves many callers,
+    /// this is the id of the caller which dispatched it.
+    pub fn with_correlation(
+        mut self,
+        correlation: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.correlation = Some(Arc::new(correlation));
+        self
+    }
+
+    /// Writes all buffered entries to the sink.
+    pub async fn flush(&self) {
+        let mut buffer = self.buffer.lock().await;
+        let entries = mem::take(&mut buffer.entries);
+        if !entries.is_empty() {
+            buffer.sink.write(entries).await;
+        }
+    }
+
+    pub(crate) async fn record<L>(
+        &self,
+        keys: &[K],
+        timestamp: SystemTime,
+        outcome: &Result<L, LoadError>,
+    ) {
+        let entry = JournalEntry {
+            loader: self.name.clone(),
+            keys: keys.iter().map(|k| self.redact.redact(k)).collect(),
+            correlation_id: self
+                .correlation
+                .as_ref()
+                .and_then(|correlation| correlation()),
+            timestamp,
+            outcome: outcome.as_ref().map(|_| ()).map_err(LoadError::clone),
+        };
+        let mut buffer = self.buffer.lock().await;
+        buffer.entries.push(entry);
+        if buffer.entries.len() >= self.capacity {
+            let entries = mem::take(&mut buffer.entries);
+            buffer.sink.write(entries).await;
+        }
+    }
+}
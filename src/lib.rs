@@ -1,11 +1,102 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// Declares items which need `std`, i.e. all but the batching core.
+macro_rules! cfg_std {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    };
+}
+
+pub mod batching;
+
+cfg_std! {
+mod async_cache;
+mod barrier;
 mod batch_fn;
+mod bitset;
+pub mod blocking;
+pub mod builder;
 pub mod cached;
+mod codec;
+pub mod compose;
+pub mod context;
+#[cfg(feature = "debug-diagnostics")]
+mod diagnostics;
+pub mod eager;
+mod error;
+mod filter;
+pub mod graphql;
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+mod idle;
+mod jitter;
+pub mod journal;
+pub mod multi;
 pub mod non_cached;
+mod observer;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod partitioned;
+mod policy;
+mod redact;
+mod retry;
 mod runtime;
+pub mod shadow;
+pub mod spawned;
+#[cfg(any(feature = "sqlx", feature = "diesel-async"))]
+pub mod sql;
+#[cfg(feature = "stream-ext")]
+pub mod stream;
+pub mod testing;
+mod tiered;
+mod watch;
+mod weak;
+mod weighted;
+pub mod writer;
 
-pub use batch_fn::BatchFn;
+pub use barrier::Barrier;
+pub(crate) use barrier::Barriers;
+pub use batch_fn::{
+    ArcBatchFn, Arrived, BatchFn, BatchFnMany, BatchPlanner, BatchStoreFn, Many, Positional,
+    PositionalBatchFn, SendBatchFn, Sendable, Streaming, StreamingBatchFn, TryBatchFn,
+};
+pub use error::{BatchError, BlockingError, BuildError, LoadError};
+pub use filter::KeyFilter;
+pub use jitter::{Jitter, JitterRng, XorShiftRng};
+pub use observer::{NoopObserver, Observer};
+pub(crate) use policy::MissingKeyHandler;
+pub use policy::{
+    Backpressure, ChunkPolicy, ConsistencyMode, ErrorCaching, MissingKeyAction, MissingKeyPolicy,
+    ResultPolicy,
+};
+pub use redact::{KeyRedactor, SaltedHash};
+pub use retry::{Retry, RetryPolicy};
+#[cfg(feature = "runtime-async-std")]
+pub use runtime::AsyncStdRuntime;
+#[cfg(feature = "runtime-futures")]
+pub use runtime::FuturesRuntime;
+#[cfg(feature = "runtime-tokio")]
+pub use runtime::TokioRuntime;
+#[cfg(feature = "runtime-wasm")]
+pub use runtime::WasmRuntime;
+pub use runtime::{DefaultRuntime, Runtime, RuntimeFuture};
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{future::Future, pin::Pin};
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+use std::{sync::Mutex, time::Duration};
 
 /// A trait alias. Read as "a function which returns a pinned box containing a future"
 pub trait WaitForWorkFn:
@@ -18,13 +109,205 @@ impl<T> WaitForWorkFn for T where
 {
 }
 
-pub(crate) fn yield_fn(count: usize) -> impl WaitForWorkFn {
-    move || {
-        Box::pin(async move {
-            // yield for other load to append request
-            for _ in 0..count {
-                runtime::yield_now().await;
+/// Maps a key to its normalized form, e.g. a lowercased email, see `with_key_normalizer`.
+pub(crate) type NormalizeFn<K> = dyn Fn(K) -> K + Send + Sync;
+
+/// The cost of loading a key, counted against `max_batch_size`, see `with_key_cost`.
+pub(crate) type KeyCostFn<K> = dyn Fn(&K) -> usize + Send + Sync;
+
+/// How a loader waits for other loads to join the pending batch.
+#[derive(Clone)]
+pub(crate) enum Wait {
+    Yield(usize),
+    Custom(std::sync::Arc<dyn WaitForWorkFn>),
+    // Yields once per round, until no keys were queued for `idle` rounds in a row or after
+    // `max` rounds.
+    Adaptive {
+        idle: usize,
+        max: usize,
+    },
+    // Waits for a tick of the idle ticker per round, until no keys were queued during a round
+    // or after `max` rounds, dispatching once the queue went idle like the event loop tick of
+    // the JS dataloader.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    Idle {
+        ticker: std::sync::Arc<idle::IdleTicker>,
+        max: usize,
+    },
+}
+
+/// A [`Wait`] on the runtime of a loader, the yield the loaders inject into the batching core.
+pub(crate) struct WaitOn<'a>(&'a Wait, &'a std::sync::Arc<dyn Runtime>);
+
+impl batching::Yield for WaitOn<'_> {
+    fn yield_now(&self) -> impl Future<Output = ()> {
+        self.0.wait(self.1)
+    }
+}
+
+impl Wait {
+    /// This wait on `runtime`, see [`WaitOn`].
+    pub(crate) fn on<'a>(&'a self, runtime: &'a std::sync::Arc<dyn Runtime>) -> WaitOn<'a> {
+        WaitOn(self, runtime)
+    }
+
+    pub(crate) async fn wait(&self, runtime: &std::sync::Arc<dyn Runtime>) {
+        match self {
+            Wait::Yield(count) => {
+                // yield for other load to append request
+                for _ in 0..*count {
+                    runtime.yield_now().await;
+                }
             }
-        })
+            Wait::Custom(wait_for_work_fn) => wait_for_work_fn().await,
+            Wait::Adaptive { .. } => runtime.yield_now().await,
+            #[cfg(any(
+                feature = "runtime-async-std",
+                feature = "runtime-tokio",
+                feature = "runtime-wasm"
+            ))]
+            Wait::Idle { ticker, .. } => ticker.tick(runtime).await,
+        }
+    }
+
+    /// The number of rounds without new work after which a caller stops waiting, and the
+    /// number of rounds after which it stops waiting anyway.
+    pub(crate) fn rounds(&self, max_wait_rounds: usize) -> batching::Rounds {
+        let (idle, max) = match self {
+            Wait::Adaptive { idle, max } => (*idle, *max),
+            #[cfg(any(
+                feature = "runtime-async-std",
+                feature = "runtime-tokio",
+                feature = "runtime-wasm"
+            ))]
+            Wait::Idle { max, .. } => (1, *max),
+            _ => (1, max_wait_rounds),
+        };
+        batching::Rounds { idle, max }
+    }
+}
+
+/// Counts a batch call as in flight for as long as it is alive.
+pub(crate) struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    pub(crate) fn start(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        InFlight(counter)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Requests of load calls which were dropped before completing, cleaned up by the loader the
+/// next time it locks its state.
+pub(crate) type Abandoned<T> = std::sync::Mutex<Vec<T>>;
+
+/// The state of a loader shared by the batches of one flush, which run concurrently while the
+/// dispatching caller holds the state lock. Each batch locks it only briefly, never across an
+/// await.
+pub(crate) struct Flush<'a, S> {
+    state: std::sync::Mutex<&'a mut S>,
+    #[cfg(feature = "debug-diagnostics")]
+    longest_batch: diagnostics::LongestBatch,
+}
+
+impl<'a, S> Flush<'a, S> {
+    pub(crate) fn new(state: &'a mut S) -> Self {
+        Flush {
+            state: std::sync::Mutex::new(state),
+            #[cfg(feature = "debug-diagnostics")]
+            longest_batch: diagnostics::LongestBatch::default(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> std::sync::MutexGuard<'_, &'a mut S> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records that a batch function call of the flush took `took`.
+    #[cfg(feature = "debug-diagnostics")]
+    pub(crate) fn batch_called(&self, took: std::time::Duration) {
+        self.longest_batch.record(took);
+    }
+
+    /// The longest batch function call of the flush so far.
+    #[cfg(feature = "debug-diagnostics")]
+    pub(crate) fn longest_batch(&self) -> std::time::Duration {
+        self.longest_batch.get()
+    }
+}
+
+/// Tracks the requests of a load call, reporting them as abandoned if the call is dropped
+/// before it completes.
+pub(crate) struct Waiting<'a, T> {
+    abandoned: &'a Abandoned<T>,
+    requests: Vec<T>,
+}
+
+impl<'a, T> Waiting<'a, T> {
+    pub(crate) fn new(abandoned: &'a Abandoned<T>) -> Self {
+        Waiting {
+            abandoned,
+            requests: Vec::new(),
+        }
     }
+
+    pub(crate) fn push(&mut self, request: T) {
+        self.requests.push(request);
+    }
+
+    /// Marks the load call as completed.
+    pub(crate) fn done(mut self) {
+        self.requests.clear();
+    }
+}
+
+impl<T> Drop for Waiting<'_, T> {
+    fn drop(&mut self) {
+        if !self.requests.is_empty() {
+            let mut abandoned = self.abandoned.lock().unwrap_or_else(|e| e.into_inner());
+            abandoned.append(&mut self.requests);
+        }
+    }
+}
+
+/// Waits for `delay`, varied by `jitter`, before the pending batch is dispatched. Use with
+/// `with_custom_wait_for_work` for timer-based rather than yield-based batching.
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+pub fn delay_fn(delay: Duration, jitter: Jitter) -> impl WaitForWorkFn {
+    delay_fn_with_rng(delay, jitter, XorShiftRng::default())
+}
+
+/// Like [`delay_fn`], drawing the jitter from the given `rng`.
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+pub fn delay_fn_with_rng(
+    delay: Duration,
+    jitter: Jitter,
+    rng: impl JitterRng + 'static,
+) -> impl WaitForWorkFn {
+    let rng = Mutex::new(rng);
+    move || {
+        let delay = jitter.apply(delay, &mut *rng.lock().unwrap());
+        DefaultRuntime::default()
+            .sleep(delay)
+            .expect("runtime with a timer")
+    }
+}
 }
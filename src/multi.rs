@@ -0,0 +1,90 @@
+//! A loader for heterogeneous keys, e.g. an enum with a variant per entity, whose keys are
+//! routed to different batch functions. All routes share one dispatch cycle and one cache, and
+//! their batch functions run concurrently.
+
+use crate::{cached, BatchFn};
+use std::collections::HashMap;
+use std::future::{poll_fn, Future};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::Poll;
+
+type RouteFuture<K, V> = Pin<Box<dyn Future<Output = HashMap<K, V>> + Send>>;
+
+type RouteFn<K, V> = Box<dyn FnMut(Vec<K>) -> RouteFuture<K, V> + Send>;
+
+type Route<K, V> = (fn(&K) -> bool, RouteFn<K, V>);
+
+/// A cached loader of heterogeneous keys, see [`MultiBatchFn`].
+pub type MultiLoader<K, V> = cached::Loader<K, V, MultiBatchFn<K, V>>;
+
+/// A batch function which routes each key of a batch to the first route matching it, e.g.
+/// `MultiBatchFn::new().route(|key| matches!(key, Key::User(_)), load_users)`. Keys matching no
+/// route are not found.
+pub struct MultiBatchFn<K, V> {
+    routes: Vec<Route<K, V>>,
+}
+
+impl<K, V> Default for MultiBatchFn<K, V> {
+    fn default() -> Self {
+        MultiBatchFn { routes: Vec::new() }
+    }
+}
+
+impl<K, V> MultiBatchFn<K, V> {
+    pub fn new() -> Self {
+        MultiBatchFn::default()
+    }
+
+    /// Routes the keys for which `matches` returns true to `load_fn`, which is called with all
+    /// of them per batch.
+    pub fn route<F, Fut>(mut self, matches: fn(&K) -> bool, mut load_fn: F) -> Self
+    where
+        F: FnMut(Vec<K>) -> Fut + Send + 'static,
+        Fut: Future<Output = HashMap<K, V>> + Send + 'static,
+    {
+        self.routes
+            .push((matches, Box::new(move |keys| Box::pin(load_fn(keys)))));
+        self
+    }
+}
+
+impl<K, V> BatchFn<K, V> for MultiBatchFn<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        let mut routed = self.routes.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        for key in keys.iter() {
+            if let Some(i) = self.routes.iter().position(|(matches, _)| matches(key)) {
+                routed[i].push(key.clone());
+            }
+        }
+        let mut loads = self
+            .routes
+            .iter_mut()
+            .zip(routed)
+            .filter(|(_, keys)| !keys.is_empty())
+            .map(|((_, load_fn), keys)| Some(load_fn(keys)))
+            .collect::<Vec<_>>();
+
+        let mut ret = HashMap::with_capacity(keys.len());
+        poll_fn(|cx| {
+            for load in loads.iter_mut() {
+                if let Some(fut) = load {
+                    if let Poll::Ready(values) = fut.as_mut().poll(cx) {
+                        ret.extend(values);
+                        *load = None;
+                    }
+                }
+            }
+            if loads.iter().all(Option::is_none) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        ret
+    }
+}
@@ -1,84 +1,634 @@
-use crate::runtime::{Arc, Mutex};
-use crate::{yield_fn, BatchFn, WaitForWorkFn};
-use std::collections::{HashMap, HashSet};
-use std::fmt::Debug;
-use std::hash::Hash;
-use std::io::{Error, ErrorKind};
+use crate::batch_fn::{group_by, load_batch, planned, GroupFn, LoadFns, PlanFn};
+use crate::batching::{chunk, run_concurrently, wait_for_work, Lock, Turns};
+use crate::builder::LoaderBuilder;
+#[cfg(feature = "debug-diagnostics")]
+use crate::diagnostics::LockHolds;
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+use crate::idle::IdleTicker;
+use crate::journal::Journal;
+#[cfg(feature = "otel")]
+use crate::otel::OtelObserver;
+use crate::redact::{describe, DebugKeys, KeyRedactor};
+use crate::runtime::{
+    try_lock, Arc, DefaultRuntime, Instant, Mutex, MutexGuard, Runtime, SystemTime,
+};
+use crate::shadow::{Shadow, ShadowHook};
+use crate::spawned::{Dispatch, SpawnedLoader};
+use crate::{
+    Abandoned, Backpressure, Barrier, Barriers, BatchPlanner, ChunkPolicy, Flush, InFlight,
+    KeyCostFn, KeyFilter, LoadError, MissingKeyAction, MissingKeyHandler, MissingKeyPolicy,
+    NoopObserver, NormalizeFn, Observer, ResultPolicy, RetryPolicy, SendBatchFn, Sendable,
+    TryBatchFn, Wait, WaitForWorkFn, Waiting,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::future::{ready, Future};
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Weak;
+use std::time::Duration;
 
 type RequestId = usize;
 
-struct State<K, V> {
-    completed: HashMap<RequestId, V>,
-    failed: HashMap<RequestId, K>,
-    pending: HashMap<RequestId, K>,
+/// Where the result of a request is delivered, shared by its caller and its pending entry. The
+/// result is handed over exactly once and dropped along with the slot if the caller is gone, so
+/// the loader never holds on to unclaimed results.
+struct Slot<K, V>(std::sync::Arc<std::sync::Mutex<Option<Outcome<K, V>>>>);
+
+type Outcome<K, V> = (K, Result<V, LoadError>);
+
+impl<K, V> Slot<K, V> {
+    fn new() -> Self {
+        Slot(std::sync::Arc::new(std::sync::Mutex::new(None)))
+    }
+
+    fn put(&self, key: K, result: Result<V, LoadError>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some((key, result));
+    }
+
+    fn take(&self) -> Outcome<K, V> {
+        let result = self.0.lock().unwrap_or_else(|e| e.into_inner()).take();
+        result.expect("request completed")
+    }
+}
+
+impl<K, V> Clone for Slot<K, V> {
+    fn clone(&self) -> Self {
+        Slot(self.0.clone())
+    }
+}
+
+struct HotKey<V> {
+    since: Instant,
+    requests: usize,
+    value: Option<(V, Instant)>,
+}
+
+struct State<K, V, S = RandomState> {
+    // Keys are moved along with their requests and handed back with the result, so each key is
+    // cloned at most once per batch, when deduplicating keys for the batch function. Requests
+    // are ordered by their increasing ids, oldest first, along with the cost of their key.
+    pending: BTreeMap<RequestId, (K, Slot<K, V>, usize)>,
+    // The summed cost of the pending requests, see `Loader::with_key_cost`.
+    pending_cost: usize,
     id_seq: RequestId,
+    // Number of requests queued so far, which tells waiting callers whether requests are still
+    // arriving.
+    enqueued: usize,
+    // The turns in which the pending requests were queued, see `ChunkPolicy::RoundRobin`.
+    turns: Turns,
+    // Request counts per key within the current window, when the hot key cache is enabled.
+    hot: HashMap<K, HotKey<V>, S>,
+    // The current batching window and the number of batches dispatched within it.
+    window: u64,
+    window_batches: usize,
+    // Number of batch function calls made so far and the keys passed to them.
+    batches: usize,
+    batched_keys: usize,
+    // Pending requests loaded once more after their key was missing, see
+    // `MissingKeyAction::Retry`.
+    retried: HashSet<RequestId>,
+    // The hasher of the maps keyed by keys, cloned into the maps created while dispatching.
+    hasher: S,
 }
 
-impl<K, V> State<K, V> {
-    fn new() -> Self {
+impl<K, V, S: BuildHasher + Clone> State<K, V, S> {
+    fn with_hasher(hasher: S) -> Self {
         State {
-            completed: HashMap::new(),
-            failed: HashMap::new(),
-            pending: HashMap::new(),
+            pending: BTreeMap::new(),
+            pending_cost: 0,
             id_seq: 0,
+            enqueued: 0,
+            turns: Turns::default(),
+            hot: HashMap::with_hasher(hasher.clone()),
+            window: 0,
+            window_batches: 0,
+            batches: 0,
+            batched_keys: 0,
+            retried: HashSet::new(),
+            hasher,
         }
     }
     fn next_request_id(&mut self) -> RequestId {
         self.id_seq = self.id_seq.wrapping_add(1);
         self.id_seq
     }
+
+    /// Withdraws the pending requests of dropped callers.
+    fn abandon(&mut self, abandoned: Vec<RequestId>) {
+        for request_id in abandoned.into_iter() {
+            self.withdraw(&request_id);
+            self.retried.remove(&request_id);
+        }
+    }
+
+    /// Removes a pending request, returning its key and slot.
+    fn withdraw(&mut self, request_id: &RequestId) -> Option<(K, Slot<K, V>)> {
+        let (key, slot, cost) = self.pending.remove(request_id)?;
+        self.pending_cost = self.pending_cost.saturating_sub(cost);
+        Some((key, slot))
+    }
+
+    /// Queues a request of `key`, costing `cost`.
+    fn enqueue(&mut self, key: K, cost: usize) -> (RequestId, Slot<K, V>) {
+        let request_id = self.next_request_id();
+        let slot = Slot::new();
+        self.pending.insert(request_id, (key, slot.clone(), cost));
+        self.pending_cost = self.pending_cost.saturating_add(cost);
+        self.turns.queued(request_id);
+        self.enqueued = self.enqueued.wrapping_add(1);
+        (request_id, slot)
+    }
+
+    /// Counts a request of `key`, returning its value if the key is hot and the value is fresh.
+    fn hot_get(&mut self, key: &K, ttl: Duration, now: Instant) -> Option<V>
+    where
+        K: Eq + Hash + Clone,
+        V: Clone,
+    {
+        if !self.hot.contains_key(key) {
+            let hot = HotKey {
+                since: now,
+                requests: 0,
+                value: None,
+            };
+            self.hot.insert(key.clone(), hot);
+        }
+        let hot = self.hot.get_mut(key).expect("hot key");
+        if now.duration_since(hot.since) > ttl {
+            hot.since = now;
+            hot.requests = 0;
+        }
+        hot.requests += 1;
+        match &hot.value {
+            Some((v, expires)) if *expires > now => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    /// Caches the values of keys which reached `threshold` requests within their window and
+    /// forgets keys whose window and value have both expired.
+    fn hot_update(&mut self, values: &HashMap<K, V>, threshold: usize, ttl: Duration)
+    where
+        K: Eq + Hash,
+        V: Clone,
+    {
+        let now = Instant::now();
+        for (k, v) in values.iter() {
+            if let Some(hot) = self.hot.get_mut(k) {
+                if hot.requests >= threshold {
+                    hot.value = Some((v.clone(), now + ttl));
+                }
+            }
+        }
+        self.hot.retain(|_, hot| {
+            now.duration_since(hot.since) <= ttl
+                || matches!(hot.value, Some((_, expires)) if expires > now)
+        });
+    }
 }
 
-pub struct Loader<K, V, F>
+/// Splits the pending `requests`, oldest first, into up to `max_batches` batches of distinct
+/// keys costing up to `max_batch_size` in total, attaching every request of a key to the batch which loads it,
+/// even requests which would otherwise wait for a later flush.
+fn single_flight_chunks<K, V, S>(
+    state: &State<K, V, S>,
+    requests: &[RequestId],
+    max_batch_size: usize,
+    max_batches: usize,
+) -> Vec<(Vec<RequestId>, Vec<K>)>
+where
+    K: Eq + Hash + Clone,
+    S: BuildHasher + Clone,
+{
+    let mut chunks: Vec<(Vec<RequestId>, Vec<K>)> = Vec::new();
+    let mut assigned = HashMap::with_hasher(state.hasher.clone());
+    // the cost of the keys of the last chunk
+    let mut spent = 0usize;
+    for request_id in requests.iter() {
+        let (key, _, cost) = &state.pending[request_id];
+        let i = match assigned.get(key) {
+            Some(i) => *i,
+            None => {
+                match chunks.last() {
+                    Some(_) if spent.saturating_add(*cost) <= max_batch_size => {}
+                    _ if chunks.len() < max_batches => {
+                        chunks.push((Vec::new(), Vec::new()));
+                        spent = 0;
+                    }
+                    _ => continue,
+                }
+                spent = spent.saturating_add(*cost);
+                let i = chunks.len() - 1;
+                chunks[i].1.push(key.clone());
+                assigned.insert(key, i);
+                i
+            }
+        };
+        chunks[i].0.push(*request_id);
+    }
+    chunks
+}
+
+/// The state lock of a loader as injected into the batching core, see [`Loader::lock_state`].
+struct StateLock<'a, L>(&'a L);
+
+impl<'a, K, V, F, O, S> Lock for StateLock<'a, Loader<K, V, F, O, S>>
 where
     K: Eq + Hash + Clone,
     V: Clone,
-    F: BatchFn<K, V>,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
 {
-    state: Arc<Mutex<State<K, V>>>,
-    load_fn: Arc<Mutex<F>>,
-    wait_for_work_fn: Arc<dyn WaitForWorkFn>,
+    type State = State<K, V, S>;
+    type Guard = MutexGuard<'a, State<K, V, S>>;
+
+    fn lock(&self) -> impl Future<Output = Self::Guard> {
+        self.0.lock_state()
+    }
+}
+
+/// A batching loader which does not cache results between batches.
+///
+/// Each distinct key is cloned once per batch to build the slice passed to the batch function;
+/// keys which are expensive to clone can be wrapped in an `Arc`.
+///
+/// Requests are batched in the order they were made: the oldest pending requests always go out
+/// in the first batch of a flush, and requests which don't fit into a flush go out before
+/// requests made after them, so under sustained load no caller is starved by later callers.
+///
+/// Dropping a load future before it completes withdraws its requests from the next batch and
+/// discards their results.
+pub struct Loader<K, V, F, O = NoopObserver, S = RandomState>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    shared: Arc<Shared<K, V, F, S>>,
+    config: Arc<Config<K, V, F>>,
+    observer: O,
+}
+
+/// The state of a loader shared by its clones, which its weak handles don't keep alive.
+struct Shared<K, V, F, S> {
+    state: Mutex<State<K, V, S>>,
+    load_fns: Arc<LoadFns<F>>,
+    in_flight: AtomicUsize,
+    barriers: Arc<Barriers>,
+    abandoned: Abandoned<RequestId>,
+    #[cfg(feature = "debug-diagnostics")]
+    lock_holds: LockHolds,
+}
+
+/// The settings of a loader, shared by its clones and weak handles. Setting them on a clone
+/// copies them first, leaving the other clones as they are.
+struct Config<K, V, F> {
+    wait: Wait,
+    runtime: Arc<dyn Runtime>,
     max_batch_size: usize,
+    max_batches_per_window: usize,
+    max_concurrent_batches: usize,
+    clone_load_fn: Option<fn(&F) -> F>,
+    chunk_policy: ChunkPolicy,
+    max_wait_rounds: usize,
+    min_batch_size: Option<(usize, Duration)>,
+    max_pending: Option<(usize, Backpressure)>,
+    load_timeout: Option<Duration>,
+    retry: Option<Arc<dyn RetryPolicy>>,
+    result_policy: ResultPolicy,
+    shadow: Option<ShadowHook<K>>,
+    journal: Option<Journal<K>>,
+    redactor: Option<Arc<dyn KeyRedactor<K>>>,
+    key_filter: Option<Arc<dyn KeyFilter<K>>>,
+    missing_key_policy: MissingKeyPolicy<V>,
+    missing_key_handler: Option<Arc<MissingKeyHandler<K, V>>>,
+    group_by: Option<Arc<GroupFn<K>>>,
+    planner: Option<PlanFn<F, K>>,
+    chunk_size: Option<usize>,
+    normalizer: Option<Arc<NormalizeFn<K>>>,
+    key_cost: Option<Arc<KeyCostFn<K>>>,
+    name: Option<Arc<str>>,
+    #[cfg(feature = "debug-diagnostics")]
+    lock_hold_threshold: Duration,
+    hot_key_cache: Option<(usize, Duration)>,
+    single_flight: bool,
+}
+
+impl<K, V: Clone, F> Clone for Config<K, V, F> {
+    fn clone(&self) -> Self {
+        Config {
+            wait: self.wait.clone(),
+            runtime: self.runtime.clone(),
+            max_batch_size: self.max_batch_size,
+            max_batches_per_window: self.max_batches_per_window,
+            max_concurrent_batches: self.max_concurrent_batches,
+            clone_load_fn: self.clone_load_fn,
+            chunk_policy: self.chunk_policy,
+            max_wait_rounds: self.max_wait_rounds,
+            min_batch_size: self.min_batch_size,
+            max_pending: self.max_pending,
+            load_timeout: self.load_timeout,
+            retry: self.retry.clone(),
+            result_policy: self.result_policy,
+            shadow: self.shadow.clone(),
+            journal: self.journal.clone(),
+            redactor: self.redactor.clone(),
+            key_filter: self.key_filter.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_handler: self.missing_key_handler.clone(),
+            group_by: self.group_by.clone(),
+            planner: self.planner,
+            chunk_size: self.chunk_size,
+            normalizer: self.normalizer.clone(),
+            key_cost: self.key_cost.clone(),
+            name: self.name.clone(),
+            #[cfg(feature = "debug-diagnostics")]
+            lock_hold_threshold: self.lock_hold_threshold,
+            hot_key_cache: self.hot_key_cache,
+            single_flight: self.single_flight,
+        }
+    }
 }
 
-impl<K, V, F> Clone for Loader<K, V, F>
+/// Shows the name and the main settings of the loader, along with the number of pending
+/// requests unless another caller holds the loader state.
+impl<K, V, F, O, S> Debug for Loader<K, V, F, O, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
-    F: BatchFn<K, V>,
+    F: TryBatchFn<K, V>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Loader");
+        debug
+            .field("name", &self.config.name)
+            .field("max_batch_size", &self.config.max_batch_size)
+            .field("max_wait_rounds", &self.config.max_wait_rounds)
+            .field("load_timeout", &self.config.load_timeout);
+        match try_lock(&self.shared.state) {
+            Some(state) => debug.field("pending", &state.pending.len()),
+            None => debug.field("state", &format_args!("<locked>")),
+        };
+        debug.finish_non_exhaustive()
+    }
+}
+
+impl<K, V, F, O, S> Clone for Loader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Clone,
 {
     fn clone(&self) -> Self {
         Loader {
-            state: self.state.clone(),
-            load_fn: self.load_fn.clone(),
-            max_batch_size: self.max_batch_size,
-            wait_for_work_fn: self.wait_for_work_fn.clone(),
+            shared: self.shared.clone(),
+            config: self.config.clone(),
+            observer: self.observer.clone(),
         }
     }
 }
 
+/// A handle to a [`Loader`] which doesn't keep its state alive, for background tasks which
+/// should stop once the last [`Loader`] handle is dropped, see [`Loader::downgrade`].
+pub struct WeakLoader<K, V, F, O = NoopObserver, S = RandomState>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    shared: Weak<Shared<K, V, F, S>>,
+    config: Arc<Config<K, V, F>>,
+    observer: O,
+}
+
+impl<K, V, F, O, S> Clone for WeakLoader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Clone,
+{
+    fn clone(&self) -> Self {
+        WeakLoader {
+            shared: self.shared.clone(),
+            config: self.config.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<K, V, F, O, S> WeakLoader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Clone,
+{
+    /// Returns a [`Loader`] sharing the state of the loader this handle was downgraded from,
+    /// unless all of its [`Loader`] handles have been dropped.
+    pub fn upgrade(&self) -> Option<Loader<K, V, F, O, S>> {
+        Some(Loader {
+            shared: self.shared.upgrade()?,
+            config: self.config.clone(),
+            observer: self.observer.clone(),
+        })
+    }
+}
+
 impl<K, V, F> Loader<K, V, F>
 where
-    K: Eq + Hash + Clone + Debug,
+    K: Eq + Hash + Clone,
     V: Clone,
-    F: BatchFn<K, V>,
+    F: TryBatchFn<K, V>,
 {
     pub fn new(load_fn: F) -> Loader<K, V, F> {
+        Loader::with_hasher(load_fn, RandomState::new())
+    }
+
+    /// Returns a [`LoaderBuilder`] validating its settings, finished with
+    /// [`LoaderBuilder::build`].
+    pub fn builder(load_fn: F) -> LoaderBuilder<K, V, F> {
+        LoaderBuilder::new(load_fn)
+    }
+}
+
+impl<K, V, F, S> Loader<K, V, F, NoopObserver, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    S: BuildHasher + Clone,
+{
+    /// Creates a loader hashing keys with `hasher` rather than the default SipHash when
+    /// deduplicating the keys of a batch, e.g. with a faster non-cryptographic hasher where
+    /// keys are not attacker controlled.
+    pub fn with_hasher(load_fn: F, hasher: S) -> Self {
         Loader {
-            state: Arc::new(Mutex::new(State::new())),
-            load_fn: Arc::new(Mutex::new(load_fn)),
-            max_batch_size: 200,
-            wait_for_work_fn: Arc::new(yield_fn(10)),
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::with_hasher(hasher)),
+                load_fns: Arc::new(LoadFns::new(load_fn)),
+                in_flight: AtomicUsize::new(0),
+                barriers: Arc::default(),
+                abandoned: Abandoned::default(),
+                #[cfg(feature = "debug-diagnostics")]
+                lock_holds: LockHolds::default(),
+            }),
+            config: Arc::new(Config {
+                wait: Wait::Yield(10),
+                runtime: Arc::new(DefaultRuntime::default()),
+                max_batch_size: 200,
+                max_batches_per_window: usize::MAX,
+                max_concurrent_batches: 1,
+                clone_load_fn: None,
+                chunk_policy: ChunkPolicy::Fifo,
+                max_wait_rounds: 1,
+                min_batch_size: None,
+                max_pending: None,
+                load_timeout: None,
+                retry: None,
+                result_policy: ResultPolicy::default(),
+                shadow: None,
+                journal: None,
+                redactor: None,
+                key_filter: None,
+                missing_key_policy: MissingKeyPolicy::default(),
+                missing_key_handler: None,
+                group_by: None,
+                planner: None,
+                chunk_size: None,
+                normalizer: None,
+                key_cost: None,
+                name: None,
+                #[cfg(feature = "debug-diagnostics")]
+                lock_hold_threshold: crate::diagnostics::DEFAULT_THRESHOLD,
+                hot_key_cache: None,
+                single_flight: false,
+            }),
+            observer: NoopObserver,
         }
     }
+}
 
+impl<K, V, F, O, S> Loader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// Records OpenTelemetry metrics of this loader with `meter`, labelled with the name given
+    /// by [`Loader::with_name`] before, see [`crate::otel`].
+    #[cfg(feature = "otel")]
+    pub fn with_otel_metrics(
+        self,
+        meter: &opentelemetry::metrics::Meter,
+    ) -> Loader<K, V, F, OtelObserver, S> {
+        let observer = OtelObserver::new(meter, self.config.name.as_deref());
+        self.with_observer(observer)
+    }
+
+    /// Reports the events of this loader to `observer`, see [`Observer`].
+    pub fn with_observer<P: Observer>(self, observer: P) -> Loader<K, V, F, P, S> {
+        Loader {
+            shared: self.shared,
+            config: self.config,
+            observer,
+        }
+    }
+
+    fn config_mut(&mut self) -> &mut Config<K, V, F> {
+        Arc::make_mut(&mut self.config)
+    }
+
+    /// Caps the number of keys passed to the batch function at once. Defaults to 200. A size of
+    /// 0 loads one key at a time; [`LoaderBuilder`] rejects it instead.
     pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
-        self.max_batch_size = max_batch_size;
+        self.config_mut().max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Weighs every key with `cost`, e.g. by the number of fields a composite key requests,
+    /// turning `max_batch_size` into a budget of the summed cost of a batch rather than of its
+    /// number of keys. The pending requests are flushed once their cost reaches the budget, and a
+    /// key costing more than the budget is loaded in a batch of its own. Costs below 1 count
+    /// as 1.
+    pub fn with_key_cost(mut self, cost: impl Fn(&K) -> usize + Send + Sync + 'static) -> Self {
+        self.config_mut().key_cost = Some(Arc::new(cost));
+        self
+    }
+
+    /// Caps the number of batches dispatched within a batching window, i.e. while callers wait
+    /// for work once, to smooth bursts of requests into the backend. Requests exceeding the cap
+    /// roll over to the next window, where the oldest requests are batched first so that they
+    /// are not starved by requests queued later. Unlimited by default.
+    pub fn with_max_batches_per_window(mut self, max_batches_per_window: usize) -> Self {
+        self.config_mut().max_batches_per_window = max_batches_per_window.max(1);
+        self
+    }
+
+    /// Sets how a flush with more pending requests than it dispatches at once picks the
+    /// requests of its batches. Defaults to [`ChunkPolicy::Fifo`]; with
+    /// [`ChunkPolicy::RoundRobin`], a single load racing a large `load_many` isn't held back
+    /// until all of its keys were loaded.
+    pub fn with_chunk_policy(mut self, chunk_policy: ChunkPolicy) -> Self {
+        self.config_mut().chunk_policy = chunk_policy;
         self
     }
 
+    /// Yields to the runtime `yield_count` times before dispatching, letting other callers
+    /// join the batch. Defaults to 10.
     pub fn with_yield_count(mut self, yield_count: usize) -> Self {
-        self.wait_for_work_fn = Arc::new(yield_fn(yield_count));
+        self.config_mut().wait = Wait::Yield(yield_count);
+        self
+    }
+
+    /// Adapts the wait for work to the load: a caller yields until no new requests were queued
+    /// during `idle_yields` yields in a row, or for at most `max_yields` yields, then dispatches.
+    /// Use [`Self::effective_batch_size()`] to check how well requests are batched.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`] and
+    /// [`Self::with_max_wait_rounds()`].
+    pub fn with_adaptive_wait(mut self, idle_yields: usize, max_yields: usize) -> Self {
+        let idle = idle_yields.max(1);
+        self.config_mut().wait = Wait::Adaptive {
+            idle,
+            max: max_yields.max(idle),
+        };
+        self
+    }
+
+    /// Dispatches once the queue has been idle for `idle`: a caller sleeps for `idle` until no
+    /// new requests were queued during its sleep, like the event loop tick dispatch of the JS
+    /// dataloader, or until `max_delay` passed under a steady trickle of requests.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`] and
+    /// [`Self::with_max_wait_rounds()`].
+    ///
+    /// The idle time is measured by a task spawned on the runtime of the loader, which ticks
+    /// every `idle` while callers wait and wakes them to look at the queue, rather than by
+    /// sleeps in the load calls. The batch itself runs in the woken caller, as the futures of
+    /// the batch function need not be `Send`. This is not available with the `runtime-futures`
+    /// runtime, which has no timer.
+    /// [`Self::spawn`] runs the batches on the dispatcher task as well.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_idle_dispatch(mut self, idle: Duration, max_delay: Duration) -> Self {
+        let rounds = max_delay.as_nanos() / idle.as_nanos().max(1);
+        self.config_mut().wait = Wait::Idle {
+            ticker: Arc::new(IdleTicker::new(idle)),
+            max: rounds.min(usize::MAX as u128).max(1) as usize,
+        };
         self
     }
 
@@ -86,181 +636,1123 @@ where
     /// the runtime repeatedly this will generate and `.await` a future of your choice.
     /// ***This is incompatible with*** [`Self::with_yield_count()`].
     pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
-        self.wait_for_work_fn = Arc::new(wait_for_work_fn);
+        self.config_mut().wait = Wait::Custom(Arc::new(wait_for_work_fn));
+        self
+    }
+
+    /// Runs this loader on `runtime` instead of the [`DefaultRuntime`] of the enabled cargo
+    /// features.
+    pub fn with_runtime(mut self, runtime: impl Runtime) -> Self {
+        self.config_mut().runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Lets a caller wait for work up to `max_wait_rounds` times before dispatching, for as long
+    /// as other requests keep being queued during its previous wait. On a saturated executor
+    /// this merges callers arriving one after another into a single batch, instead of each of
+    /// them dispatching a batch of its own key. Defaults to 1, a single wait.
+    pub fn with_max_wait_rounds(mut self, max_wait_rounds: usize) -> Self {
+        self.config_mut().max_wait_rounds = max_wait_rounds.max(1);
+        self
+    }
+
+    /// Holds small batches back: once a caller is done waiting for work, it keeps waiting while
+    /// fewer than `min_batch_size` requests are pending, until `max_delay` after it started
+    /// waiting. This lets trickling callers share a batch while bounding their latency. Full
+    /// batches are still dispatched right away.
+    pub fn with_min_batch_size(mut self, min_batch_size: usize, max_delay: Duration) -> Self {
+        self.config_mut().min_batch_size = Some((min_batch_size, max_delay));
+        self
+    }
+
+    /// Names this loader, e.g. `user_loader`, to tell it apart from the other loaders in
+    /// metrics and in its `Debug` output.
+    pub fn with_name(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.config_mut().name = Some(name.into());
+        self
+    }
+
+    /// The name given by [`Loader::with_name`].
+    pub fn name(&self) -> Option<&str> {
+        self.config.name.as_deref()
+    }
+
+    /// Logs a warning whenever a flush of this loader holds its state lock for longer than
+    /// `threshold`, see [`Loader::longest_lock_hold`]. Defaults to 100ms.
+    #[cfg(feature = "debug-diagnostics")]
+    pub fn with_lock_hold_threshold(mut self, threshold: Duration) -> Self {
+        self.config_mut().lock_hold_threshold = threshold;
         self
     }
 
+    /// The longest time a flush of this loader, or of one of its clones, held the state lock
+    /// so far, from building its batches until its batch function calls completed.
+    #[cfg(feature = "debug-diagnostics")]
+    pub fn longest_lock_hold(&self) -> Duration {
+        self.shared.lock_holds.longest()
+    }
+
+    /// Bounds the pending queue: a load call finding `max_pending` requests pending can't queue
+    /// more requests until the queue has room again, and waits or fails according to
+    /// `backpressure`. Keys served by the hot key cache are served as usual. A
+    /// [`SpawnedLoader`] counts the keys queued for its dispatcher task as pending too, until
+    /// they are answered.
+    pub fn with_max_pending(mut self, max_pending: usize, backpressure: Backpressure) -> Self {
+        self.config_mut().max_pending = Some((max_pending.max(1), backpressure));
+        self
+    }
+
+    /// Fails a batch with [`LoadError::Timeout`] when the batch function doesn't complete within
+    /// `timeout`, resolving all callers waiting on it. Values the batch function already
+    /// delivered, see [`Streaming`](crate::Streaming), resolve their callers; only the other keys
+    /// fail.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_load_timeout(mut self, timeout: Duration) -> Self {
+        self.config_mut().load_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries batches whose batch function failed or timed out according to `retry`, e.g.
+    /// `Retry::exponential(3, Duration::from_millis(50))`. Callers keep waiting for the batch
+    /// until it succeeds or `retry` gives up.
+    #[cfg(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))]
+    pub fn with_retry(mut self, retry: impl RetryPolicy + 'static) -> Self {
+        self.config_mut().retry = Some(Arc::new(retry));
+        self
+    }
+
+    /// Sets how values returned by the batch function for keys that were not requested are
+    /// handled. Defaults to [`ResultPolicy::Accept`], which drops them as there is no cache.
+    pub fn with_result_policy(mut self, result_policy: ResultPolicy) -> Self {
+        self.config_mut().result_policy = result_policy;
+        self
+    }
+
+    /// Caches the values of hot keys, requested at least `threshold` times within a `ttl`
+    /// window, for `ttl`. This protects the backend from storms of requests for a single key,
+    /// while all other keys keep the non-caching behavior.
+    pub fn with_hot_key_cache(mut self, threshold: usize, ttl: Duration) -> Self {
+        self.config_mut().hot_key_cache = Some((threshold, ttl));
+        self
+    }
+
+    /// Loads every key once per flush, however many requests of it are pending: all requests of
+    /// a key wait for the single batch loading it, instead of the key being loaded again by
+    /// another batch of the flush or by the next flush. Without a cache, results are still not
+    /// kept once the batch completed. Disabled by default, where requests beyond `max_batch_size`
+    /// go to the next batch even if their key is loaded already.
+    pub fn with_single_flight(mut self, enabled: bool) -> Self {
+        self.config_mut().single_flight = enabled;
+        self
+    }
+
+    /// Mirrors requested keys to `shadow`, which records how it would have batched them, see
+    /// [`Shadow::report`].
+    pub fn with_shadow(mut self, shadow: &Shadow<K>) -> Self
+    where
+        K: Send + Sync + 'static,
+    {
+        self.config_mut().shadow = Some(shadow.hook());
+        self
+    }
+
+    /// Sets how keys for which the batch function returned no value resolve, see
+    /// [`MissingKeyPolicy`]. Defaults to [`MissingKeyPolicy::Error`].
+    pub fn with_missing_key_policy(mut self, missing_key_policy: MissingKeyPolicy<V>) -> Self {
+        self.config_mut().missing_key_policy = missing_key_policy;
+        self
+    }
+
+    /// Decides with `handler` what to do with each key the batch function returned no value
+    /// for, see [`MissingKeyAction`]. Keys resolving to an error are then resolved by the
+    /// [`MissingKeyPolicy`].
+    pub fn with_missing_key_handler(
+        mut self,
+        handler: impl Fn(&K) -> MissingKeyAction<V> + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().missing_key_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Splits every batch into groups of keys for which `group_of` returns the same value, e.g.
+    /// the shard of the key, and calls the batch function once per group. Each group is a batch
+    /// of its own for the observer, the journal and shadows. The groups are loaded one after
+    /// another, unless [`Loader::with_max_concurrent_batches`] allows more.
+    pub fn with_group_by<G: Eq + Hash>(
+        mut self,
+        group_of: impl Fn(&K) -> G + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().group_by = Some(group_by(group_of));
+        self
+    }
+
+    /// Splits every batch, and every group of [`Loader::with_group_by`], into chunks of up to
+    /// `chunk_size` keys and calls the batch function once per chunk, e.g. for a backend
+    /// limiting the number of parameters per query. Unlike `max_batch_size`, which caps how many
+    /// keys a flush dispatches at once, chunking leaves flushes as they are. Each chunk is a
+    /// batch of its own for the observer, the journal and shadows. The chunks are loaded one
+    /// after another, unless [`Loader::with_max_concurrent_batches`] allows more.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.config_mut().chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Lets the batch function split or reorder every batch, after [`Loader::with_group_by`]
+    /// and [`Loader::with_chunk_size`], with [`BatchPlanner::plan`] and calls it once per group
+    /// of the plan. Each group is a batch of its own for the observer, the journal and shadows.
+    /// The groups are loaded one after another, unless [`Loader::with_max_concurrent_batches`]
+    /// allows more.
+    pub fn with_batch_planner(mut self) -> Self
+    where
+        F: BatchPlanner<K>,
+    {
+        self.config_mut().planner = Some(F::plan);
+        self
+    }
+
+    /// Loads up to `max_concurrent_batches` batches at once when a flush has more than one, i.e.
+    /// when more than `max_batch_size` requests are pending or with [`Loader::with_group_by`] or
+    /// [`Loader::with_chunk_size`], each on its own clone of the batch function. The batches
+    /// touch the loader's state only to start and to record their results, so none of them
+    /// waits for another one to complete.
+    /// Defaults to 1.
+    ///
+    /// The batch function is cloned the first time a flush needs the clones.
+    pub fn with_max_concurrent_batches(mut self, max_concurrent_batches: usize) -> Self
+    where
+        F: Clone,
+    {
+        let config = self.config_mut();
+        config.max_concurrent_batches = max_concurrent_batches.max(1);
+        config.clone_load_fn = Some(F::clone);
+        self
+    }
+
+    /// Records every batch this loader dispatches in `journal`.
+    pub fn with_journal(mut self, journal: &Journal<K>) -> Self {
+        self.config_mut().journal = Some(journal.clone());
+        self
+    }
+
+    /// Resolves keys which `filter` rules out like keys missing from a batch, without queuing
+    /// them, see [`KeyFilter`].
+    pub fn with_existence_filter(mut self, filter: impl KeyFilter<K> + 'static) -> Self {
+        self.config_mut().key_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Formats keys in errors with `redactor`, e.g. [`SaltedHash`](crate::SaltedHash) for keys
+    /// containing personal data. Without a redactor, keys are formatted as `<key>`.
+    pub fn with_key_redactor(mut self, redactor: impl KeyRedactor<K> + 'static) -> Self {
+        self.config_mut().redactor = Some(Arc::new(redactor));
+        self
+    }
+
+    /// Formats keys in errors with their `Debug` implementation rather than as `<key>`.
+    pub fn with_debug_keys(self) -> Self
+    where
+        K: Debug,
+    {
+        self.with_key_redactor(DebugKeys)
+    }
+
+    /// Normalizes every key passed to this loader with `normalize`, e.g. lowercasing emails,
+    /// before it is queued, so that keys equal after normalization are loaded once per batch.
+    /// The batch function and the maps returned by `load_many` see the normalized keys only.
+    pub fn with_key_normalizer(
+        mut self,
+        normalize: impl Fn(K) -> K + Send + Sync + 'static,
+    ) -> Self {
+        self.config_mut().normalizer = Some(Arc::new(normalize));
+        self
+    }
+
+    /// Holds back the batches of this loader and of its clones until the returned barrier and
+    /// all others are dropped, see [`Barrier`].
+    pub fn barrier(&self) -> Barrier {
+        self.shared.barriers.hold()
+    }
+
+    /// Returns a handle to this loader which doesn't keep its state alive, see [`WeakLoader`].
+    pub fn downgrade(&self) -> WeakLoader<K, V, F, O, S>
+    where
+        O: Clone,
+    {
+        WeakLoader {
+            shared: Arc::downgrade(&self.shared),
+            config: self.config.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+
     pub fn max_batch_size(&self) -> usize {
-        self.max_batch_size
-    }
-
-    pub async fn try_load(&self, key: K) -> Result<V, Error> {
-        let mut state = self.state.lock().await;
-        let request_id = state.next_request_id();
-        state.pending.insert(request_id, key);
-        if state.pending.len() >= self.max_batch_size {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            let keys: Vec<K> = batch
-                .values()
-                .cloned()
-                .collect::<HashSet<K>>()
-                .into_iter()
-                .collect();
-            let mut load_fn = self.load_fn.lock().await;
-            let load_ret = load_fn.load(keys.as_ref()).await;
-            drop(load_fn);
-            for (request_id, key) in batch.into_iter() {
-                if load_ret
-                    .get(&key)
-                    .and_then(|v| state.completed.insert(request_id, v.clone()))
-                    .is_none()
-                {
-                    state.failed.insert(request_id, key);
-                }
-            }
-            return state.completed.remove(&request_id).ok_or_else(|| {
-                Error::new(
-                    ErrorKind::NotFound,
-                    format!(
-                        "could not lookup result for given key: {:?}",
-                        state.failed.remove(&request_id).expect("failed")
-                    ),
-                )
-            });
+        self.config.max_batch_size
+    }
+
+    /// Number of keys queued for the next batch. Waits for any batch being dispatched while
+    /// holding the loader state to finish.
+    pub async fn pending_len(&self) -> usize {
+        self.lock_state().await.pending.len()
+    }
+
+    /// The mean number of keys passed to the batch function per call so far, or 0 before the
+    /// first batch. Values close to 1 under concurrent load mean that callers don't wait long
+    /// enough for each other to share batches.
+    pub async fn effective_batch_size(&self) -> f64 {
+        let state = self.lock_state().await;
+        if state.batches == 0 {
+            return 0.0;
         }
-        drop(state);
+        state.batched_keys as f64 / state.batches as f64
+    }
+
+    /// Whether a batch function call is currently in flight.
+    pub fn is_loading(&self) -> bool {
+        self.shared.in_flight.load(Ordering::SeqCst) > 0
+    }
 
-        (self.wait_for_work_fn)().await;
+    /// Locks the state, cleaning up the requests of dropped load calls.
+    async fn lock_state(&self) -> MutexGuard<'_, State<K, V, S>> {
+        let mut state = self.shared.state.lock().await;
+        state.turns.next();
+        self.reap(&mut state);
+        state
+    }
 
-        let mut state = self.state.lock().await;
+    fn reap(&self, state: &mut State<K, V, S>) {
+        let abandoned = mem::take(
+            &mut *self
+                .shared
+                .abandoned
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        state.abandon(abandoned);
+    }
 
-        if !state.completed.contains_key(&request_id) {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            if !batch.is_empty() {
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
+    async fn dispatch(&self, state: &mut State<K, V, S>) {
+        #[cfg(feature = "debug-diagnostics")]
+        let locked = Instant::now();
+        // Requests stay pending until the batch completes, so that they are loaded by the
+        // remaining callers if this one is dropped while the batch function is running.
+        let mut requests = state.pending.keys().copied().collect::<Vec<RequestId>>();
+        if requests.is_empty() {
+            return;
+        }
+        let max_batch_size = self.config.max_batch_size.max(1);
+        let concurrent = if state.pending_cost > max_batch_size {
+            self.config
+                .max_concurrent_batches
+                .min(self.config.max_batches_per_window - state.window_batches)
+                .max(1)
+        } else {
+            1
+        };
+        let load_fns = self
+            .shared
+            .load_fns
+            .take(concurrent, self.config.clone_load_fn)
+            .await;
+        let concurrent = load_fns.len();
+        state.turns.prune(requests[0]);
+        if self.config.chunk_policy == ChunkPolicy::RoundRobin
+            && state.pending_cost > max_batch_size
+        {
+            requests = state
+                .turns
+                .round_robin(requests.iter().map(|id| (*id, *id)), requests.len());
+        }
+        // batches bounded by the cost of their keys count every distinct key once
+        let chunks = if self.config.single_flight || self.config.key_cost.is_some() {
+            single_flight_chunks(state, &requests, max_batch_size, concurrent)
+        } else {
+            requests.truncate(max_batch_size.saturating_mul(concurrent));
+            requests
+                .chunks(max_batch_size)
+                .map(|batch| {
+                    let mut unique = HashSet::with_hasher(state.hasher.clone());
+                    let keys: Vec<K> = batch
+                        .iter()
+                        .map(|request_id| &state.pending[request_id].0)
+                        .filter(|k| unique.insert(*k))
+                        .cloned()
+                        .collect();
+                    (batch.to_vec(), keys)
+                })
+                .collect::<Vec<_>>()
+        };
+        state.window_batches += chunks.len();
+        let mut planner = match self.config.planner {
+            Some(plan) => Some((plan, load_fns[0].lock().await)),
+            None => None,
+        };
+        let mut batches = Vec::new();
+        for (batch, keys) in chunks.into_iter() {
+            let mut groups = match &self.config.group_by {
+                Some(group_by) => group_by(keys),
+                None => vec![keys],
+            };
+            if let Some(chunk_size) = self.config.chunk_size {
+                groups = chunk(groups, chunk_size);
+            }
+            if let Some((plan, load_fn)) = &mut planner {
+                groups = groups
                     .into_iter()
+                    .flat_map(|keys| planned(keys, |keys| plan(load_fn, keys)))
                     .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
+            }
+            if groups.len() == 1 {
+                batches.push((batch, groups.pop().expect("one group")));
+                continue;
+            }
+            for group in groups.into_iter() {
+                let mut members = HashSet::with_hasher(state.hasher.clone());
+                members.extend(group.iter());
+                let batch = batch
+                    .iter()
+                    .copied()
+                    .filter(|request_id| members.contains(&state.pending[request_id].0))
+                    .collect();
+                drop(members);
+                batches.push((batch, group));
+            }
+        }
+        drop(planner);
+        state.batches += batches.len();
+        let keys = batches.iter().map(|(_, keys)| keys.len()).sum::<usize>();
+        state.batched_keys += keys;
+        let state = Flush::new(state);
+        run_concurrently(batches, load_fns.len(), |slot, (batch, keys)| {
+            self.load_requests(&state, &load_fns[slot], batch, keys)
+        })
+        .await;
+        #[cfg(feature = "debug-diagnostics")]
+        self.shared.lock_holds.record(
+            self.config.name.as_deref(),
+            self.config.lock_hold_threshold,
+            keys,
+            locked.elapsed(),
+            Some(state.longest_batch()),
+        );
+    }
+
+    /// Loads the distinct `keys` of the requests in `batch` with a single call of `load_fn`.
+    async fn load_requests(
+        &self,
+        state: &Flush<'_, State<K, V, S>>,
+        load_fn: &Mutex<F>,
+        batch: Vec<RequestId>,
+        mut keys: Vec<K>,
+    ) {
+        if let Some(shadow) = &self.config.shadow {
+            shadow.record(keys.len());
+        }
+        if O::ENABLED {
+            self.observer.batch_dispatched(keys.len());
+        }
+        let started = if O::ENABLED {
+            Some(Instant::now())
+        } else {
+            None
+        };
+        let dispatched_at = self.config.journal.as_ref().map(|_| SystemTime::now());
+        let in_flight = InFlight::start(&self.shared.in_flight);
+        let mut load_fn = load_fn.lock().await;
+        #[cfg(feature = "debug-diagnostics")]
+        let called = Instant::now();
+        let (load_ret, salvaged) = load_batch(
+            &*self.config.runtime,
+            &mut *load_fn,
+            &mut keys,
+            self.config.load_timeout,
+            self.config.retry.as_deref(),
+            |keys| {
+                let state = state.lock();
+                let mut counts: HashMap<&K, usize, S> = HashMap::with_hasher(state.hasher.clone());
+                for (k, _, _) in batch
+                    .iter()
+                    .filter_map(|request_id| state.pending.get(request_id))
+                {
+                    *counts.entry(k).or_default() += 1;
+                }
+                ready(
+                    keys.iter()
+                        .map(|key| counts.get(key).copied().unwrap_or(0))
+                        .collect(),
+                )
+            },
+            |mut keys| {
+                let mut state = state.lock();
+                self.reap(&mut state);
+                let mut alive = HashSet::with_hasher(state.hasher.clone());
+                alive.extend(
+                    batch
+                        .iter()
+                        .filter_map(|request_id| state.pending.get(request_id))
+                        .map(|(k, _, _)| k),
+                );
+                keys.retain(|key| alive.contains(key));
+                ready(keys)
+            },
+        )
+        .await;
+        drop(load_fn);
+        drop(in_flight);
+        #[cfg(feature = "debug-diagnostics")]
+        state.batch_called(called.elapsed());
+        let load_ret = load_ret.and_then(|mut load_ret| {
+            self.config
+                .result_policy
+                .apply(&keys, &mut load_ret)
+                .map(|_| load_ret)
+        });
+        if let (Some(journal), Some(dispatched_at)) = (&self.config.journal, dispatched_at) {
+            journal.record(&keys, dispatched_at, &load_ret).await;
+        }
+        if let Some(started) = started {
+            let error = load_ret.as_ref().err();
+            self.observer
+                .batch_completed(keys.len(), started.elapsed(), error);
+        }
+        let mut state = state.lock();
+        if !salvaged.is_empty() {
+            if O::ENABLED {
+                self.observer
+                    .partial_batch(keys.len() + salvaged.len(), salvaged.len());
+            }
+            for request_id in batch.iter() {
+                let salvaged = match state.pending.get(request_id) {
+                    Some((key, _, _)) => salvaged.get(key).cloned(),
+                    None => None,
+                };
+                if let Some(v) = salvaged {
+                    state.retried.remove(request_id);
+                    if let Some((key, slot)) = state.withdraw(request_id) {
+                        slot.put(key, Ok(v));
                     }
                 }
             }
         }
-        state.completed.remove(&request_id).ok_or_else(|| {
-            Error::new(
-                ErrorKind::NotFound,
-                format!(
-                    "could not lookup result for given key: {:?}",
-                    state.failed.remove(&request_id).expect("failed")
-                ),
-            )
-        })
+        match load_ret {
+            Ok(mut load_ret) => {
+                if let Some((threshold, ttl)) = self.config.hot_key_cache {
+                    state.hot_update(&load_ret, threshold, ttl);
+                }
+                for request_id in batch.into_iter() {
+                    // a retried request is found or resolved this time
+                    let retried = !state.retried.is_empty() && state.retried.remove(&request_id);
+                    let key = match state.pending.get(&request_id) {
+                        Some((key, _, _)) => key,
+                        None => continue,
+                    };
+                    let action = match &self.config.missing_key_handler {
+                        Some(handler) if !load_ret.contains_key(key) => handler(key),
+                        _ => MissingKeyAction::Error,
+                    };
+                    match action {
+                        MissingKeyAction::Default(v) => {
+                            load_ret.insert(key.clone(), v);
+                        }
+                        // the request stays pending for the next batch
+                        MissingKeyAction::Retry if !retried => {
+                            state.retried.insert(request_id);
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if let Some((key, slot)) = state.withdraw(&request_id) {
+                        let r = match load_ret.get(&key) {
+                            Some(v) => Ok(v.clone()),
+                            None => Err(LoadError::NotFound(describe(
+                                self.config.redactor.as_deref(),
+                                &key,
+                            ))),
+                        };
+                        slot.put(key, r);
+                    }
+                }
+            }
+            Err(e) => {
+                for request_id in batch.into_iter() {
+                    state.retried.remove(&request_id);
+                    if let Some((key, slot)) = state.withdraw(&request_id) {
+                        slot.put(key, Err(e.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn runtime(&self) -> &Arc<dyn Runtime> {
+        &self.config.runtime
+    }
+
+    /// The number of pending requests dispatched right away rather than after waiting for work, a
+    /// full batch for each of the batches loaded at once.
+    fn flush_size(&self) -> usize {
+        self.config
+            .max_batch_size
+            .saturating_mul(self.config.max_concurrent_batches)
+    }
+
+    /// The cost of `key` counted against the batch size, see [`Loader::with_key_cost`].
+    fn cost(&self, key: &K) -> usize {
+        self.config
+            .key_cost
+            .as_ref()
+            .map_or(1, |cost| cost(key).max(1))
+    }
+
+    pub(crate) fn redactor(&self) -> Option<&dyn KeyRedactor<K>> {
+        self.config.redactor.as_deref()
+    }
+
+    pub(crate) fn normalize(&self, key: K) -> K {
+        match &self.config.normalizer {
+            Some(normalize) => normalize(key),
+            None => key,
+        }
+    }
+
+    fn normalize_many(&self, keys: Vec<K>) -> Vec<K> {
+        match &self.config.normalizer {
+            Some(normalize) => keys.into_iter().map(|k| normalize(k)).collect(),
+            None => keys,
+        }
+    }
+
+    /// Whether the existence filter rules `key` out, see [`Loader::with_existence_filter`].
+    fn filtered(&self, key: &K) -> bool {
+        self.config
+            .key_filter
+            .as_ref()
+            .is_some_and(|filter| !filter.may_contain(key))
+    }
+
+    /// Whether another batch may be dispatched within the current window.
+    fn may_dispatch(&self, state: &State<K, V, S>) -> bool {
+        state.window_batches < self.config.max_batches_per_window
+    }
+
+    /// Waits for work and locks the state, waiting another round while `waiting` still has
+    /// requests pending and other requests were queued recently, up to `max_wait_rounds` or
+    /// the limits of an adaptive wait, and beyond them while fewer than the minimum batch size are
+    /// pending until its deadline. Starts a new window unless another caller did so while this
+    /// one was waiting.
+    async fn wait_for_work<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<K, V, S>>,
+        waiting: impl Fn(&State<K, V, S>) -> bool,
+    ) -> MutexGuard<'a, State<K, V, S>> {
+        let window = state.window;
+        let (min_batch_size, deadline) = match self.config.min_batch_size {
+            Some((min_batch_size, max_delay)) => (min_batch_size, Some(Instant::now() + max_delay)),
+            None => (0, None),
+        };
+        let mut state = wait_for_work(
+            &StateLock(self),
+            &self.config.wait.on(&self.config.runtime),
+            state,
+            self.config.wait.rounds(self.config.max_wait_rounds),
+            |state| state.enqueued,
+            |state, over| {
+                let small = state.pending.len() < min_batch_size
+                    && matches!(deadline, Some(deadline) if Instant::now() < deadline);
+                (over && !small) || !waiting(state)
+            },
+        )
+        .await;
+        // the rounds are over, unless the keys of `waiting` are loaded already
+        if waiting(&state) && self.shared.barriers.is_held() {
+            drop(state);
+            self.shared.barriers.released().await;
+            state = self.lock_state().await;
+        }
+        if state.window == window {
+            state.window = state.window.wrapping_add(1);
+            state.window_batches = 0;
+        }
+        state
+    }
+
+    /// How queuing another request is held back, if the pending queue is full, see
+    /// [`Loader::with_max_pending`].
+    fn overflow(&self, state: &State<K, V, S>) -> Option<Backpressure> {
+        match self.config.max_pending {
+            Some((max_pending, backpressure)) if state.pending.len() >= max_pending => {
+                Some(backpressure)
+            }
+            _ => None,
+        }
+    }
+
+    /// Dispatches the oldest pending requests until another request may be queued.
+    async fn make_room<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<K, V, S>>,
+    ) -> MutexGuard<'a, State<K, V, S>> {
+        self.wait_and_dispatch(state, |state| self.overflow(state).is_some())
+            .await
+    }
+
+    /// Waits for work, then dispatches batches of the oldest pending requests until `waiting`
+    /// has no requests pending anymore, waiting for the next window whenever the current one is
+    /// full.
+    async fn wait_and_dispatch<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, S>>,
+        waiting: impl Fn(&State<K, V, S>) -> bool,
+    ) -> MutexGuard<'a, State<K, V, S>> {
+        loop {
+            state = self.wait_for_work(state, &waiting).await;
+            while waiting(&state) && self.may_dispatch(&state) {
+                self.dispatch(&mut state).await;
+            }
+            if !waiting(&state) {
+                return state;
+            }
+        }
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<V, LoadError> {
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self.try_load_waiting(key, &mut waiting).await;
+        waiting.done();
+        ret
+    }
+
+    async fn try_load_waiting(
+        &self,
+        key: K,
+        waiting: &mut Waiting<'_, RequestId>,
+    ) -> Result<V, LoadError> {
+        let key = self.normalize(key);
+        let mut state = self.lock_state().await;
+        if let Some((_, ttl)) = self.config.hot_key_cache {
+            if let Some(v) = state.hot_get(&key, ttl, Instant::now()) {
+                return Ok(v);
+            }
+        }
+        if self.filtered(&key) {
+            let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+            return self.config.missing_key_policy.resolve(Err(e));
+        }
+        match self.overflow(&state) {
+            Some(Backpressure::Fail) => return Err(LoadError::QueueFull),
+            Some(Backpressure::Wait) => state = self.make_room(state).await,
+            None => {}
+        }
+        if let Some(shadow) = &self.config.shadow {
+            shadow.mirror(vec![key.clone()]);
+        }
+        let cost = self.cost(&key);
+        let (request_id, slot) = state.enqueue(key, cost);
+        waiting.push(request_id);
+        if state.pending_cost >= self.flush_size() && self.may_dispatch(&state) {
+            self.dispatch(&mut state).await;
+        }
+        if state.pending.contains_key(&request_id) {
+            let state = self
+                .wait_and_dispatch(state, |state| state.pending.contains_key(&request_id))
+                .await;
+            drop(state);
+        }
+        self.config.missing_key_policy.resolve(slot.take().1)
     }
 
     pub async fn load(&self, key: K) -> V {
         self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, V> {
+    pub async fn load_many(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
         self.try_load_many(keys)
             .await
             .unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, Error> {
-        let mut state = self.state.lock().await;
+    /// Loads `keys` together in a single call of the batch function, regardless of
+    /// [`Self::with_max_batch_size`], e.g. for a backend computing aggregates across the keys.
+    /// Hot values are not served, and other pending keys are left to their own batches. Keys the
+    /// missing key handler would retry resolve like missing keys, as the group is not loaded
+    /// again.
+    pub async fn try_load_group(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        let keys = self.normalize_many(keys.into_iter().collect());
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let mut state = self.lock_state().await;
         let mut ret = HashMap::new();
         let mut requests = Vec::new();
+        let mut group = Vec::new();
+        let mut unique = HashSet::with_hasher(state.hasher.clone());
         for key in keys.into_iter() {
-            let request_id = state.next_request_id();
-            requests.push((request_id, key.clone()));
-            state.pending.insert(request_id, key);
-            if state.pending.len() >= self.max_batch_size {
-                let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
-                    .into_iter()
-                    .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
-                    }
+            if self.filtered(&key) {
+                let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+                if let Some(r) = self.config.missing_key_policy.resolve_many(Err(e)) {
+                    ret.insert(key, r);
+                }
+                continue;
+            }
+            if unique.insert(key.clone()) {
+                group.push(key.clone());
+            }
+            let cost = self.cost(&key);
+            let (request_id, slot) = state.enqueue(key, cost);
+            waiting.push(request_id);
+            requests.push((request_id, slot));
+        }
+        if !requests.is_empty() {
+            let batch = requests.iter().map(|(id, _)| *id).collect();
+            let load_fns = self.shared.load_fns.take(1, None).await;
+            self.load_requests(&Flush::new(&mut *state), &load_fns[0], batch, group)
+                .await;
+            // requests the missing key handler retries are still pending
+            for (request_id, _) in requests.iter() {
+                state.retried.remove(request_id);
+                if let Some((key, slot)) = state.withdraw(request_id) {
+                    let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+                    slot.put(key, Err(e));
                 }
             }
         }
-
         drop(state);
+        waiting.done();
+        for (_, slot) in requests.into_iter() {
+            let (key, r) = slot.take();
+            if let Some(r) = self.config.missing_key_policy.resolve_many(r) {
+                ret.insert(key, r);
+            }
+        }
+        ret.into_iter().map(|(k, r)| r.map(|v| (k, v))).collect()
+    }
 
-        (self.wait_for_work_fn)().await;
+    pub async fn load_group(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_group(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        let mut state = self.state.lock().await;
+    /// Loads the values of two keys in the same batch, unlike awaiting `load(a)` before
+    /// `load(b)`, e.g. the author and the editor of a post. A key left out of the results by
+    /// [`MissingKeyPolicy::Skip`] resolves to [`LoadError::NotFound`], as if it was loaded on its
+    /// own.
+    pub async fn try_load2(&self, a: K, b: K) -> Result<(V, V), LoadError> {
+        let results = self.load_results(vec![a.clone(), b.clone()]).await;
+        Ok((self.result(&results, a)?, self.result(&results, b)?))
+    }
 
-        let mut rest = Vec::new();
-        for (request_id, key) in requests.into_iter() {
-            if let Some(v) = state.completed.remove(&request_id) {
-                ret.insert(key, v);
-            } else {
-                rest.push((request_id, key));
-            }
+    pub async fn load2(&self, a: K, b: K) -> (V, V) {
+        self.try_load2(a, b)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Loads the values of three keys in the same batch, see [`Self::try_load2`].
+    pub async fn try_load3(&self, a: K, b: K, c: K) -> Result<(V, V, V), LoadError> {
+        let results = self
+            .load_results(vec![a.clone(), b.clone(), c.clone()])
+            .await;
+        Ok((
+            self.result(&results, a)?,
+            self.result(&results, b)?,
+            self.result(&results, c)?,
+        ))
+    }
+
+    pub async fn load3(&self, a: K, b: K, c: K) -> (V, V, V) {
+        self.try_load3(a, b, c)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// The outcome of `key` within the `results` of [`Self::load_results`].
+    fn result(&self, results: &HashMap<K, Result<V, LoadError>>, key: K) -> Result<V, LoadError> {
+        let key = self.normalize(key);
+        match results.get(&key) {
+            Some(r) => r.clone(),
+            None => Err(LoadError::NotFound(describe(
+                self.config.redactor.as_deref(),
+                &key,
+            ))),
         }
+    }
 
-        if !rest.is_empty() {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            if !batch.is_empty() {
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
-                    .into_iter()
-                    .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
-                    }
+    pub async fn try_load_many(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.load_results(keys)
+            .await
+            .into_iter()
+            .map(|(k, r)| r.map(|v| (k, v)))
+            .collect()
+    }
+
+    /// Loads `keys`, returning the outcome of every key individually, so that one failed key
+    /// doesn't discard the values of the others.
+    pub async fn load_results(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> HashMap<K, Result<V, LoadError>> {
+        let keys = keys.into_iter().collect();
+        let mut waiting = Waiting::new(&self.shared.abandoned);
+        let ret = self.load_results_waiting(keys, &mut waiting).await;
+        waiting.done();
+        ret
+    }
+
+    async fn load_results_waiting(
+        &self,
+        keys: Vec<K>,
+        waiting: &mut Waiting<'_, RequestId>,
+    ) -> HashMap<K, Result<V, LoadError>> {
+        let keys = self.normalize_many(keys);
+        let mut state = self.lock_state().await;
+        let mut ret = HashMap::new();
+        let mut requests = Vec::new();
+        let mut mirrored = Vec::new();
+        let now = Instant::now();
+        for key in keys.into_iter() {
+            if let Some((_, ttl)) = self.config.hot_key_cache {
+                if let Some(v) = state.hot_get(&key, ttl, now) {
+                    ret.insert(key, Ok(v));
+                    continue;
+                }
+            }
+            if self.filtered(&key) {
+                let e = LoadError::NotFound(describe(self.config.redactor.as_deref(), &key));
+                if let Some(r) = self.config.missing_key_policy.resolve_many(Err(e)) {
+                    ret.insert(key, r);
                 }
+                continue;
+            }
+            match self.overflow(&state) {
+                Some(Backpressure::Fail) => {
+                    ret.insert(key, Err(LoadError::QueueFull));
+                    continue;
+                }
+                Some(Backpressure::Wait) => state = self.make_room(state).await,
+                None => {}
+            }
+            if self.config.shadow.is_some() {
+                mirrored.push(key.clone());
+            }
+            let cost = self.cost(&key);
+            let (request_id, slot) = state.enqueue(key, cost);
+            waiting.push(request_id);
+            requests.push((request_id, slot));
+            if state.pending_cost >= self.flush_size() && self.may_dispatch(&state) {
+                self.dispatch(&mut state).await;
+            }
+        }
+
+        if let Some(shadow) = &self.config.shadow {
+            shadow.mirror(mirrored);
+        }
+
+        let state = self
+            .wait_and_dispatch(state, |state| {
+                requests
+                    .iter()
+                    .any(|(id, _)| state.pending.contains_key(id))
+            })
+            .await;
+        drop(state);
+        for (_, slot) in requests.into_iter() {
+            let (key, r) = slot.take();
+            if let Some(r) = self.config.missing_key_policy.resolve_many(r) {
+                ret.insert(key, r);
             }
-            for (request_id, key) in rest.into_iter() {
-                let v = state.completed.remove(&request_id).ok_or_else(|| {
-                    Error::new(
-                        ErrorKind::NotFound,
-                        format!(
-                            "could not lookup result for given key: {:?}",
-                            state.failed.remove(&request_id).expect("failed")
-                        ),
-                    )
-                })?;
-
-                ret.insert(key, v);
+        }
+
+        ret
+    }
+}
+
+impl<K, V, F> Loader<K, V, Sendable<F>>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    F: SendBatchFn<K, V>,
+{
+    /// Creates a loader batching on a dispatcher task spawned on the [`DefaultRuntime`], see
+    /// [`Loader::spawn`].
+    pub fn spawned(load_fn: F) -> SpawnedLoader<K, V> {
+        Loader::new(Sendable(load_fn)).spawn()
+    }
+}
+
+impl<K, V, F, O, S> Loader<K, V, Sendable<F>, O, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    F: SendBatchFn<K, V>,
+    O: Observer + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Moves the batching of this loader to a dispatcher task spawned on its runtime, see
+    /// [`SpawnedLoader`]. The dispatcher waits for work as configured on this loader, e.g. with
+    /// [`Self::with_yield_count`] or [`Self::with_idle_dispatch`], then loads every key queued
+    /// meanwhile with the batch function, applying the other settings of this loader.
+    pub fn spawn(mut self) -> SpawnedLoader<K, V> {
+        let dispatch = Dispatch {
+            wait: mem::replace(&mut self.config_mut().wait, Wait::Yield(0)),
+            max_batch_size: self.config.max_batch_size,
+            max_wait_rounds: self.config.max_wait_rounds,
+            max_pending: self.config.max_pending,
+            runtime: self.config.runtime.clone(),
+        };
+        let missing_key_policy = self.config.missing_key_policy.clone();
+        SpawnedLoader::spawn(Dispatcher::new(self), dispatch, missing_key_policy)
+    }
+}
+
+/// The batching of a [`SpawnedLoader`], driven by its dispatcher task. The dispatcher keeps
+/// its requests in a state of its own, so that no lock is held while the batch function runs,
+/// and answers each of them through the reply `R` of its caller once it completed.
+pub(crate) struct Dispatcher<K, V, F, O, S, R>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+{
+    loader: Loader<K, V, F, O, S>,
+    state: State<K, V, S>,
+    requests: Vec<(RequestId, Slot<K, V>, R)>,
+}
+
+impl<K, V, F, O, S, R> Dispatcher<K, V, F, O, S, R>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// Takes over the batch function of `loader`. Loads of the other clones of `loader` are
+    /// batched apart from the requests of the dispatcher.
+    pub(crate) async fn new(loader: Loader<K, V, F, O, S>) -> Self {
+        let hasher = loader.lock_state().await.hasher.clone();
+        let loader = Loader {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::with_hasher(hasher.clone())),
+                load_fns: loader.shared.load_fns.clone(),
+                in_flight: AtomicUsize::new(0),
+                barriers: loader.shared.barriers.clone(),
+                abandoned: Abandoned::default(),
+                #[cfg(feature = "debug-diagnostics")]
+                lock_holds: LockHolds::default(),
+            }),
+            config: loader.config,
+            observer: loader.observer,
+        };
+        Dispatcher {
+            loader,
+            state: State::with_hasher(hasher),
+            requests: Vec::new(),
+        }
+    }
+
+    /// Queues a request of `key` answered through `reply`, or returns `reply` along with the
+    /// outcome of `key` if it is not loaded, e.g. because the existence filter rules it out.
+    pub(crate) fn enqueue(&mut self, key: K, reply: R) -> Option<(R, Result<V, LoadError>)> {
+        let loader = &self.loader;
+        let key = loader.normalize(key);
+        if let Some((_, ttl)) = loader.config.hot_key_cache {
+            if let Some(v) = self.state.hot_get(&key, ttl, Instant::now()) {
+                return Some((reply, Ok(v)));
             }
         }
+        if loader.filtered(&key) {
+            let e = LoadError::NotFound(describe(loader.redactor(), &key));
+            return Some((reply, Err(e)));
+        }
+        if let Some(shadow) = &loader.config.shadow {
+            shadow.mirror(vec![key.clone()]);
+        }
+        let cost = loader.cost(&key);
+        let (request_id, slot) = self.state.enqueue(key, cost);
+        self.requests.push((request_id, slot, reply));
+        None
+    }
+
+    /// Whether no request is waiting to be answered.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Number of requests waiting to be answered.
+    pub(crate) fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Withdraws the requests whose reply is `abandoned`, loads the others in as many batches as
+    /// a new window allows and returns the replies of the requests which completed. Requests
+    /// left pending are loaded by the next call.
+    pub(crate) async fn dispatch(
+        &mut self,
+        abandoned: impl Fn(&R) -> bool,
+    ) -> Vec<(R, Result<V, LoadError>)> {
+        let (gone, requests) = mem::take(&mut self.requests)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, _, reply)| abandoned(reply));
+        self.requests = requests;
+        self.state.abandon(
+            gone.into_iter()
+                .map(|(request_id, _, _)| request_id)
+                .collect(),
+        );
+        let shared = &self.loader.shared;
+        if shared.barriers.is_held() {
+            shared.barriers.released().await;
+        }
+        self.state.window = self.state.window.wrapping_add(1);
+        self.state.window_batches = 0;
+        while !self.state.pending.is_empty() && self.loader.may_dispatch(&self.state) {
+            self.loader.dispatch(&mut self.state).await;
+        }
+        let pending = &self.state.pending;
+        let (done, requests) = mem::take(&mut self.requests)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(request_id, _, _)| !pending.contains_key(request_id));
+        self.requests = requests;
+        done.into_iter()
+            .map(|(_, slot, reply)| (reply, slot.take().1))
+            .collect()
+    }
+}
+
+impl<K, T, F, O, S> Loader<K, Vec<T>, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+    F: TryBatchFn<K, Vec<T>>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    /// Loads the values of `key` from a batch function returning several values per key, e.g.
+    /// one wrapped in a [`Many`](crate::Many). A key the batch function returned nothing for
+    /// has no values rather than being not found, unless the [`MissingKeyPolicy`] resolves it
+    /// otherwise.
+    pub async fn try_load_values(&self, key: K) -> Result<Vec<T>, LoadError> {
+        match self.try_load(key).await {
+            Err(LoadError::NotFound(_)) => Ok(Vec::new()),
+            r => r,
+        }
+    }
 
-        Ok(ret)
+    pub async fn load_values(&self, key: K) -> Vec<T> {
+        self.try_load_values(key)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 }
@@ -0,0 +1,37 @@
+use crate::LoadError;
+use std::time::Duration;
+
+/// Receives events of a loader, e.g. to record metrics, see `with_observer` of the loaders.
+///
+/// All methods default to doing nothing. Loaders use [`NoopObserver`] unless configured
+/// otherwise, for which [`Observer::ENABLED`] is `false`: every call site, including reading the
+/// clock to time batches, is then behind a constant `false` branch and compiled out.
+pub trait Observer {
+    /// Whether the loader reports events to this observer at all.
+    const ENABLED: bool = true;
+
+    /// A batch of `keys` distinct keys is passed to the batch function.
+    fn batch_dispatched(&self, _keys: usize) {}
+
+    /// A batch of `keys` distinct keys completed after `elapsed`, with `error` if it failed.
+    fn batch_completed(&self, _keys: usize, _elapsed: Duration, _error: Option<&LoadError>) {}
+
+    /// A batch of `keys` distinct keys timed out after the values of `salvaged` of them arrived,
+    /// which resolve normally while the others fail with [`LoadError::Timeout`], see
+    /// [`Streaming`](crate::Streaming).
+    fn partial_batch(&self, _keys: usize, _salvaged: usize) {}
+
+    /// A key was served from the cache of a [`cached::Loader`](crate::cached::Loader).
+    fn cache_hit(&self) {}
+
+    /// A key missed the cache of a [`cached::Loader`](crate::cached::Loader) and is loaded.
+    fn cache_miss(&self) {}
+}
+
+/// The default [`Observer`], which ignores all events at no cost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {
+    const ENABLED: bool = false;
+}
@@ -0,0 +1,100 @@
+//! OpenTelemetry metrics of the loaders, enabled by the `otel` feature.
+//!
+//! An [`OtelObserver`] records the events of a loader with the instruments of a [`Meter`],
+//! labelled with the name of the loader, see `with_otel_metrics` and `with_name` of the
+//! loaders:
+//!
+//! - `dataloader.batch.size`, a histogram of the distinct keys per batch;
+//! - `dataloader.batch.duration`, a histogram of the seconds a batch took to load;
+//! - `dataloader.batch.errors`, a counter of failed batches;
+//! - `dataloader.batch.salvaged`, a counter of the keys which timed out batches resolved from
+//!   the values that arrived before, see [`Streaming`](crate::Streaming);
+//! - `dataloader.cache.lookups`, a counter of cache lookups with a boolean `cache.hit` label,
+//!   giving the cache hit ratio of a cached loader.
+use crate::{LoadError, Observer};
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+/// An [`Observer`] recording OpenTelemetry metrics, see the [module docs](self).
+#[derive(Clone)]
+pub struct OtelObserver {
+    batch_size: Histogram<u64>,
+    batch_duration: Histogram<f64>,
+    batch_errors: Counter<u64>,
+    batch_salvaged: Counter<u64>,
+    cache_lookups: Counter<u64>,
+    labels: Vec<KeyValue>,
+    hit_labels: Vec<KeyValue>,
+    miss_labels: Vec<KeyValue>,
+}
+
+impl OtelObserver {
+    /// Creates the instruments with `meter`, labelling them with `loader = name` if a name is
+    /// given.
+    pub fn new(meter: &Meter, name: Option<&str>) -> Self {
+        let labels = name
+            .map(|name| KeyValue::new("loader", name.to_owned()))
+            .into_iter()
+            .collect::<Vec<_>>();
+        let with_hit = |hit: bool| {
+            let mut labels = labels.clone();
+            labels.push(KeyValue::new("cache.hit", hit));
+            labels
+        };
+        OtelObserver {
+            batch_size: meter
+                .u64_histogram("dataloader.batch.size")
+                .with_description("Distinct keys per batch")
+                .with_unit("{key}")
+                .build(),
+            batch_duration: meter
+                .f64_histogram("dataloader.batch.duration")
+                .with_description("Time a batch took to load")
+                .with_unit("s")
+                .build(),
+            batch_errors: meter
+                .u64_counter("dataloader.batch.errors")
+                .with_description("Failed batches")
+                .build(),
+            batch_salvaged: meter
+                .u64_counter("dataloader.batch.salvaged")
+                .with_description("Keys resolved by batches which timed out")
+                .with_unit("{key}")
+                .build(),
+            cache_lookups: meter
+                .u64_counter("dataloader.cache.lookups")
+                .with_description("Cache lookups, by whether they hit")
+                .build(),
+            hit_labels: with_hit(true),
+            miss_labels: with_hit(false),
+            labels,
+        }
+    }
+}
+
+impl Observer for OtelObserver {
+    fn batch_dispatched(&self, keys: usize) {
+        self.batch_size.record(keys as u64, &self.labels);
+    }
+
+    fn batch_completed(&self, _keys: usize, elapsed: Duration, error: Option<&LoadError>) {
+        self.batch_duration
+            .record(elapsed.as_secs_f64(), &self.labels);
+        if error.is_some() {
+            self.batch_errors.add(1, &self.labels);
+        }
+    }
+
+    fn partial_batch(&self, _keys: usize, salvaged: usize) {
+        self.batch_salvaged.add(salvaged as u64, &self.labels);
+    }
+
+    fn cache_hit(&self) {
+        self.cache_lookups.add(1, &self.hit_labels);
+    }
+
+    fn cache_miss(&self) {
+        self.cache_lookups.add(1, &self.miss_labels);
+    }
+}
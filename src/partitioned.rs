@@ -0,0 +1,142 @@
+use crate::cached::Loader;
+use crate::runtime::{Arc, Mutex};
+use crate::{BatchFn, LoadError};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A batch function which loads keys within a partition, e.g. the tenant the keys belong to.
+pub trait PartitionedBatchFn<P, K, V> {
+    fn load(
+        &mut self,
+        partition: &P,
+        keys: &[K],
+    ) -> impl std::future::Future<Output = HashMap<K, V>>;
+}
+
+/// The [`BatchFn`] of the loader of a single partition, sharing the partitioned batch function.
+pub struct PartitionFn<P, F> {
+    partition: P,
+    load_fn: Arc<Mutex<F>>,
+}
+
+impl<P, K, V, F> BatchFn<K, V> for PartitionFn<P, F>
+where
+    F: PartitionedBatchFn<P, K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        let mut load_fn = self.load_fn.lock().await;
+        load_fn.load(&self.partition, keys).await
+    }
+}
+
+/// The loader of a single partition.
+pub type PartitionLoader<P, K, V, F> = Loader<K, V, PartitionFn<P, F>>;
+
+type Partitions<P, K, V, F> = HashMap<P, PartitionLoader<P, K, V, F>>;
+
+type ConfigureFn<P, K, V, F> =
+    dyn Fn(PartitionLoader<P, K, V, F>) -> PartitionLoader<P, K, V, F> + Send + Sync;
+
+/// A cached loader whose cache and batches are segregated per partition value, so the same key
+/// can resolve to different values in different partitions (e.g. per tenant).
+///
+/// Each partition gets its own [`cached::Loader`](crate::cached::Loader), created on first use.
+pub struct PartitionedLoader<P, K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: PartitionedBatchFn<P, K, V>,
+{
+    load_fn: Arc<Mutex<F>>,
+    loaders: Arc<Mutex<Partitions<P, K, V, F>>>,
+    configure: Arc<ConfigureFn<P, K, V, F>>,
+}
+
+impl<P, K, V, F> Clone for PartitionedLoader<P, K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: PartitionedBatchFn<P, K, V>,
+{
+    fn clone(&self) -> Self {
+        PartitionedLoader {
+            load_fn: self.load_fn.clone(),
+            loaders: self.loaders.clone(),
+            configure: self.configure.clone(),
+        }
+    }
+}
+
+impl<P, K, V, F> PartitionedLoader<P, K, V, F>
+where
+    P: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: PartitionedBatchFn<P, K, V>,
+{
+    pub fn new(load_fn: F) -> Self {
+        PartitionedLoader {
+            load_fn: Arc::new(Mutex::new(load_fn)),
+            loaders: Arc::new(Mutex::new(HashMap::new())),
+            configure: Arc::new(|loader| loader),
+        }
+    }
+
+    /// Configures the loader of each partition when it is created, e.g.
+    /// `.with_loader_config(|loader| loader.with_max_batch_size(50))`.
+    pub fn with_loader_config(
+        mut self,
+        configure: impl Fn(PartitionLoader<P, K, V, F>) -> PartitionLoader<P, K, V, F>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.configure = Arc::new(configure);
+        self
+    }
+
+    /// Returns the loader of `partition`, creating it if necessary.
+    pub async fn partition(&self, partition: P) -> PartitionLoader<P, K, V, F> {
+        let mut loaders = self.loaders.lock().await;
+        loaders
+            .entry(partition.clone())
+            .or_insert_with(|| {
+                (self.configure)(Loader::new(PartitionFn {
+                    partition,
+                    load_fn: self.load_fn.clone(),
+                }))
+            })
+            .clone()
+    }
+
+    pub async fn try_load(&self, partition: P, key: K) -> Result<V, LoadError> {
+        self.partition(partition).await.try_load(key).await
+    }
+
+    pub async fn load(&self, partition: P, key: K) -> V {
+        self.partition(partition).await.load(key).await
+    }
+
+    pub async fn try_load_many(
+        &self,
+        partition: P,
+        keys: Vec<K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.partition(partition).await.try_load_many(keys).await
+    }
+
+    pub async fn load_many(&self, partition: P, keys: Vec<K>) -> HashMap<K, V> {
+        self.partition(partition).await.load_many(keys).await
+    }
+
+    /// Drops the loader of `partition` along with its cache.
+    pub async fn clear_partition(&self, partition: &P) {
+        let mut loaders = self.loaders.lock().await;
+        loaders.remove(partition);
+    }
+
+    pub async fn clear_all(&self) {
+        let mut loaders = self.loaders.lock().await;
+        loaders.clear();
+    }
+}
@@ -0,0 +1,168 @@
+use crate::LoadError;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::time::Duration;
+
+/// Controls how a loader treats the values returned by a batch function which do not match the
+/// requested keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultPolicy {
+    /// Values of keys which were not requested are kept; the cached loader caches them.
+    #[default]
+    Accept,
+    /// Values of keys which were not requested are silently dropped.
+    Ignore,
+    /// The whole batch fails with [`LoadError::UnrequestedKeys`] if it contains keys which were
+    /// not requested.
+    Reject,
+    /// Like [`ResultPolicy::Reject`], and additionally the whole batch fails with
+    /// [`LoadError::UnequalKeyValueSize`] unless it contains a value for every requested key.
+    Exact,
+}
+
+impl ResultPolicy {
+    /// Checks `values` returned for the (deduplicated) `keys`, dropping unrequested values when
+    /// the policy says so.
+    pub(crate) fn apply<K, V>(
+        &self,
+        keys: &[K],
+        values: &mut HashMap<K, V>,
+    ) -> Result<(), LoadError>
+    where
+        K: Eq + Hash,
+    {
+        if *self == ResultPolicy::Accept {
+            return Ok(());
+        }
+        let requested = keys.iter().collect::<HashSet<_>>();
+        match self {
+            ResultPolicy::Accept => Ok(()),
+            ResultPolicy::Ignore => {
+                values.retain(|k, _| requested.contains(k));
+                Ok(())
+            }
+            ResultPolicy::Reject | ResultPolicy::Exact => {
+                let count = values.keys().filter(|k| !requested.contains(k)).count();
+                if count > 0 {
+                    return Err(LoadError::UnrequestedKeys { count });
+                }
+                if *self == ResultPolicy::Exact && values.len() != requested.len() {
+                    return Err(LoadError::UnequalKeyValueSize {
+                        key_count: requested.len(),
+                        value_count: values.len(),
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Controls how a cached loader orders writes to its cache, i.e. primes, updates and clears,
+/// against the results of batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsistencyMode {
+    /// A write made while a batch is in flight takes precedence over the batch's result for the
+    /// key, so that a caller always reads its own prime, update or clear, e.g. a clear right
+    /// after a mutation is not undone by a batch which fetched the key before the mutation.
+    #[default]
+    ReadYourWrites,
+    /// Like [`ConsistencyMode::ReadYourWrites`], and a cached value doesn't change until it is
+    /// cleared: batch results, primes and upserting updates only fill keys which are not cached,
+    /// so that repeated loads of a key return the same value.
+    Snapshot,
+    /// The last write wins, whether it is a batch result or a write made while the batch was in
+    /// flight. Nothing is tracked while batches are in flight.
+    Eventual,
+}
+
+/// Controls how a cached loader of `Result` values caches the `Err` values returned by the batch
+/// function, see [`Loader::with_error_caching`](crate::cached::Loader::with_error_caching).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorCaching {
+    /// Errors are cached like any other value, until they are cleared.
+    #[default]
+    Forever,
+    /// Errors are cached for the included duration, after which loading the key loads it again.
+    Ttl(Duration),
+    /// Errors are returned to the callers waiting for them, but not cached.
+    Never,
+}
+
+/// Controls what a load call does when the pending queue of a loader is full, see
+/// `with_max_pending` of the loaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// The call dispatches the oldest pending keys and waits for their batches to complete
+    /// until the queue has room again.
+    #[default]
+    Wait,
+    /// The keys the call would queue fail right away with [`LoadError::QueueFull`].
+    Fail,
+}
+
+/// How a flush with more pending keys than it dispatches at once picks the keys of its batches,
+/// see `with_chunk_policy` of the loaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkPolicy {
+    /// The keys queued first are dispatched first, so that the keys of a large `load_many`
+    /// hold back every load queued after it until they were all dispatched.
+    #[default]
+    Fifo,
+    /// Every call queuing keys gets one key into the flush in turn, oldest call first, so that
+    /// a single load queued after a large `load_many` joins the next batch. The keys a call
+    /// queues while it holds the loader state count as one call.
+    RoundRobin,
+}
+
+/// Controls how a loader resolves requested keys for which the batch function returned no value.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum MissingKeyPolicy<V> {
+    /// Missing keys resolve to [`LoadError::NotFound`], so `load` and `load_many` panic.
+    #[default]
+    Error,
+    /// Missing keys resolve to the included value, e.g. `None` for optional values.
+    Default(V),
+    /// Missing keys are left out of the results of `load_many`, `try_load_many` and
+    /// `load_results`, and resolve to [`LoadError::NotFound`] when loaded on their own.
+    Skip,
+    /// Loading a missing key panics, even with `try_load`.
+    Panic,
+}
+
+/// What a loader does with a key the batch function returned no value for, as decided by the
+/// handler passed to `with_missing_key_handler` of the loaders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MissingKeyAction<V> {
+    /// The key resolves to [`LoadError::NotFound`], or as the [`MissingKeyPolicy`] says.
+    Error,
+    /// The key resolves to the included value, which a cached loader caches like a loaded one.
+    Default(V),
+    /// The key is loaded once more with the next batch, e.g. when reading from a replica
+    /// lagging behind, and resolves like [`MissingKeyAction::Error`] if it is missing again.
+    Retry,
+}
+
+pub(crate) type MissingKeyHandler<K, V> = dyn Fn(&K) -> MissingKeyAction<V> + Send + Sync;
+
+impl<V: Clone> MissingKeyPolicy<V> {
+    /// Resolves the outcome of a key loaded on its own.
+    pub(crate) fn resolve(&self, result: Result<V, LoadError>) -> Result<V, LoadError> {
+        match (self, result) {
+            (MissingKeyPolicy::Default(v), Err(LoadError::NotFound(_))) => Ok(v.clone()),
+            (MissingKeyPolicy::Panic, Err(e @ LoadError::NotFound(_))) => panic!("{}", e),
+            (_, result) => result,
+        }
+    }
+
+    /// Resolves the outcome of a key loaded along with others, `None` if it is skipped.
+    pub(crate) fn resolve_many(
+        &self,
+        result: Result<V, LoadError>,
+    ) -> Option<Result<V, LoadError>> {
+        match (self, result) {
+            (MissingKeyPolicy::Skip, Err(LoadError::NotFound(_))) => None,
+            (_, result) => Some(self.resolve(result)),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+/// Formats keys wherever a loader exposes them, e.g. in [`LoadError::NotFound`] errors or in
+/// a [`Journal`](crate::journal::Journal), so that sensitive keys never appear in plain text.
+///
+/// [`LoadError::NotFound`]: crate::LoadError::NotFound
+pub trait KeyRedactor<K>: Send + Sync {
+    fn redact(&self, key: &K) -> String;
+}
+
+impl<K, F> KeyRedactor<K> for F
+where
+    F: Fn(&K) -> String + Send + Sync,
+{
+    fn redact(&self, key: &K) -> String {
+        self(key)
+    }
+}
+
+/// Redacts keys to a salted hash, which identifies a key without revealing it to anyone
+/// who does not know the salt.
+///
+/// The hash is only stable across builds using the same Rust version, as it relies on the
+/// hasher of the standard library.
+#[derive(Debug, Clone)]
+pub struct SaltedHash {
+    salt: String,
+}
+
+impl SaltedHash {
+    pub fn new(salt: impl Into<String>) -> Self {
+        SaltedHash { salt: salt.into() }
+    }
+}
+
+impl<K: Hash> KeyRedactor<K> for SaltedHash {
+    fn redact(&self, key: &K) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.salt.hash(&mut hasher);
+        key.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// Formats `key` with `redactor`, or as a placeholder without one, as keys are not required to
+/// implement `Debug`.
+pub(crate) fn describe<K>(redactor: Option<&dyn KeyRedactor<K>>, key: &K) -> String {
+    match redactor {
+        Some(redactor) => redactor.redact(key),
+        None => "<key>".to_owned(),
+    }
+}
+
+/// Formats keys with their `Debug` implementation, for the `with_debug_keys` methods of the
+/// loaders.
+pub(crate) struct DebugKeys;
+
+impl<K: Debug> KeyRedactor<K> for DebugKeys {
+    fn redact(&self, key: &K) -> String {
+        format!("{:?}", key)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::LoadError;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// Decides whether a failed batch is retried, see `with_retry` of the loaders.
+///
+/// Only failures of the batch function itself are retried, that is errors of a
+/// [`TryBatchFn`](crate::TryBatchFn) and timeouts, not violations of the
+/// [`ResultPolicy`](crate::ResultPolicy).
+pub trait RetryPolicy: Send + Sync {
+    /// Returns how long to wait before retry number `attempt`, starting at 1, of a batch which
+    /// failed with `error`, or `None` to fail the batch.
+    fn retry_after(&self, attempt: usize, error: &LoadError) -> Option<Duration>;
+}
+
+/// The built-in [`RetryPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    max_retries: usize,
+    delay: Duration,
+    factor: u32,
+}
+
+impl Retry {
+    /// Retries up to `max_retries` times, waiting `delay` before each retry.
+    pub fn fixed(max_retries: usize, delay: Duration) -> Self {
+        Retry {
+            max_retries,
+            delay,
+            factor: 1,
+        }
+    }
+
+    /// Retries up to `max_retries` times, waiting `delay` before the first retry and doubling
+    /// the wait for every following one.
+    pub fn exponential(max_retries: usize, delay: Duration) -> Self {
+        Retry {
+            max_retries,
+            delay,
+            factor: 2,
+        }
+    }
+}
+
+impl RetryPolicy for Retry {
+    fn retry_after(&self, attempt: usize, _error: &LoadError) -> Option<Duration> {
+        if attempt > self.max_retries {
+            return None;
+        }
+        let exp = u32::try_from(attempt - 1).unwrap_or(u32::MAX);
+        let factor = self.factor.checked_pow(exp).unwrap_or(u32::MAX);
+        Some(self.delay.saturating_mul(factor))
+    }
+}
@@ -0,0 +1,22 @@
+use super::{Runtime, RuntimeFuture};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The [async-std](https://async.rs) runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime;
+
+impl Runtime for AsyncStdRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(async_std::task::yield_now())
+    }
+
+    fn sleep(&self, duration: Duration) -> Option<RuntimeFuture> {
+        Some(Box::pin(async_std::task::sleep(duration)))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        async_std::task::spawn(future);
+    }
+}
@@ -0,0 +1,51 @@
+use super::{Runtime, RuntimeFuture};
+use futures::executor::ThreadPool;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A runtime agnostic implementation on top of the `futures` crate, which works with any
+/// executor but has no timer. Background tasks run on a small thread pool shared by all
+/// loaders.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuturesRuntime;
+
+/// Returns `Pending` once, waking the task right away so that other tasks get to run.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl Runtime for FuturesRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(YieldNow(false))
+    }
+
+    fn sleep(&self, _duration: Duration) -> Option<RuntimeFuture> {
+        None
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        static POOL: OnceLock<ThreadPool> = OnceLock::new();
+        POOL.get_or_init(|| {
+            ThreadPool::builder()
+                .pool_size(1)
+                .name_prefix("dataloader-")
+                .create()
+                .expect("failed to create thread pool")
+        })
+        .spawn_ok(future);
+    }
+}
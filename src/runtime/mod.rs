@@ -0,0 +1,152 @@
+//! The async runtimes the loaders can run on.
+//!
+//! Loaders use the [`Runtime`] given to `with_runtime`, or [`DefaultRuntime`], which is the
+//! runtime of the enabled cargo features: `runtime-tokio`, else `runtime-async-std`, else
+//! `runtime-wasm`, else `runtime-futures`. The features are additive, so a library depending on this crate doesn't
+//! need to choose a runtime for its users. Supporting another runtime means implementing
+//! [`Runtime`] in a module here.
+//!
+//! This precedence is a breaking change for loaders relying on [`DefaultRuntime`]: a
+//! `runtime-tokio` feature enabled by any crate in the dependency graph moves them onto Tokio,
+//! whose timer and spawning panic outside a Tokio runtime. Pass the runtime to `with_runtime`
+//! to keep a loader on it regardless of the enabled features.
+
+use crate::LoadError;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+#[cfg(not(any(
+    feature = "runtime-futures",
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+)))]
+compile_error!(
+    "one of the features `runtime-futures`, `runtime-async-std`, `runtime-tokio` or `runtime-wasm` must be enabled"
+);
+
+/// A future returned by a [`Runtime`].
+pub type RuntimeFuture = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// The scheduling primitives a loader needs from an async runtime.
+pub trait Runtime: Send + Sync + 'static {
+    /// Yields to other tasks once.
+    fn yield_now(&self) -> RuntimeFuture;
+
+    /// Sleeps for `duration`, or returns `None` if the runtime has no timer. On runtimes without
+    /// a timer, batches don't time out and failed batches are retried right away.
+    fn sleep(&self, duration: Duration) -> Option<RuntimeFuture>;
+
+    /// Runs `future` in the background.
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>);
+}
+
+#[cfg(feature = "runtime-async-std")]
+mod async_std_rt;
+#[cfg(feature = "runtime-async-std")]
+pub use async_std_rt::AsyncStdRuntime;
+
+#[cfg(feature = "runtime-tokio")]
+mod tokio_rt;
+#[cfg(feature = "runtime-tokio")]
+pub use tokio_rt::TokioRuntime;
+
+#[cfg(feature = "runtime-wasm")]
+mod wasm_rt;
+#[cfg(feature = "runtime-wasm")]
+pub use wasm_rt::WasmRuntime;
+
+#[cfg(feature = "runtime-futures")]
+mod futures_rt;
+#[cfg(feature = "runtime-futures")]
+pub use futures_rt::FuturesRuntime;
+
+pub type Arc<T> = std::sync::Arc<T>;
+
+#[cfg(feature = "runtime-tokio")]
+mod default {
+    pub type DefaultRuntime = super::TokioRuntime;
+    pub type Mutex<T> = tokio::sync::Mutex<T>;
+    pub type MutexGuard<'a, T> = tokio::sync::MutexGuard<'a, T>;
+
+    pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+        mutex.try_lock().ok()
+    }
+}
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+mod default {
+    pub type DefaultRuntime = super::AsyncStdRuntime;
+    pub type Mutex<T> = async_std::sync::Mutex<T>;
+    pub type MutexGuard<'a, T> = async_std::sync::MutexGuard<'a, T>;
+
+    pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+        mutex.try_lock()
+    }
+}
+
+#[cfg(all(
+    feature = "runtime-wasm",
+    not(any(feature = "runtime-async-std", feature = "runtime-tokio"))
+))]
+mod default {
+    pub type DefaultRuntime = super::WasmRuntime;
+    pub type Mutex<T> = futures::lock::Mutex<T>;
+    pub type MutexGuard<'a, T> = futures::lock::MutexGuard<'a, T>;
+
+    pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+        mutex.try_lock()
+    }
+}
+
+#[cfg(all(
+    feature = "runtime-futures",
+    not(any(
+        feature = "runtime-async-std",
+        feature = "runtime-tokio",
+        feature = "runtime-wasm"
+    ))
+))]
+mod default {
+    pub type DefaultRuntime = super::FuturesRuntime;
+    pub type Mutex<T> = futures::lock::Mutex<T>;
+    pub type MutexGuard<'a, T> = futures::lock::MutexGuard<'a, T>;
+
+    pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+        mutex.try_lock()
+    }
+}
+
+/// Locks `mutex` if it is free, e.g. to inspect the loader state in `Debug` output.
+pub(crate) use default::try_lock;
+pub use default::{DefaultRuntime, Mutex, MutexGuard};
+
+// `std::time` panics on `wasm32-unknown-unknown`, where `web-time` reads the clock of the
+// browser instead. Elsewhere `web-time` re-exports `std::time`.
+#[cfg(not(feature = "runtime-wasm"))]
+pub(crate) use std::time::{Instant, SystemTime};
+#[cfg(feature = "runtime-wasm")]
+pub(crate) use web_time::{Instant, SystemTime};
+
+/// Fails `future` with [`LoadError::Timeout`] unless it completes within `duration`. Never
+/// fails if `runtime` has no timer.
+pub(crate) async fn timeout<T>(
+    runtime: &dyn Runtime,
+    duration: Duration,
+    future: impl Future<Output = T>,
+) -> Result<T, LoadError> {
+    let mut sleep = match runtime.sleep(duration) {
+        Some(sleep) => sleep,
+        None => return Ok(future.await),
+    };
+    let mut future = Box::pin(future);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(v) = future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(v));
+        }
+        sleep.as_mut().poll(cx).map(|_| Err(LoadError::Timeout))
+    })
+    .await
+}
@@ -0,0 +1,23 @@
+use super::{Runtime, RuntimeFuture};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The [Tokio](https://tokio.rs) runtime. Sleeping and spawning require being called within a
+/// Tokio runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(tokio::task::yield_now())
+    }
+
+    fn sleep(&self, duration: Duration) -> Option<RuntimeFuture> {
+        Some(Box::pin(tokio::time::sleep(duration)))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        tokio::spawn(future);
+    }
+}
@@ -0,0 +1,37 @@
+use super::{Runtime, RuntimeFuture};
+use gloo_timers::future::TimeoutFuture;
+use send_wrapper::SendWrapper;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use wasm_bindgen_futures::js_sys::Promise;
+use wasm_bindgen_futures::wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+/// A runtime for `wasm32-unknown-unknown` in the browser, on top of `wasm-bindgen-futures`.
+/// Yielding waits for the next microtask, so that loads made in the same tick of the event loop
+/// are batched together, and sleeping uses `setTimeout`.
+///
+/// The browser runs futures on a single thread; the futures of this runtime panic if they are
+/// polled from another thread, e.g. a web worker they were sent to.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WasmRuntime;
+
+impl Runtime for WasmRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        let microtask = JsFuture::from(Promise::resolve(&JsValue::UNDEFINED));
+        Box::pin(SendWrapper::new(async move {
+            let _ = microtask.await;
+        }))
+    }
+
+    fn sleep(&self, duration: Duration) -> Option<RuntimeFuture> {
+        let millis = u32::try_from(duration.as_millis()).unwrap_or(u32::MAX);
+        Some(Box::pin(SendWrapper::new(TimeoutFuture::new(millis))))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+}
@@ -0,0 +1,156 @@
+//! Shadow mode: mirror the keys requested from a loader to a second batching configuration,
+//! which only records how it would have batched them, to compare configurations against real
+//! traffic before switching.
+use crate::non_cached::Loader;
+use crate::runtime::Runtime;
+use crate::BatchFn;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+/// Counts of dispatched batches and their keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub batches: u64,
+    pub keys: u64,
+    pub largest_batch: usize,
+}
+
+impl BatchStats {
+    pub(crate) fn record(&mut self, batch_size: usize) {
+        self.batches += 1;
+        self.keys += batch_size as u64;
+        self.largest_batch = self.largest_batch.max(batch_size);
+    }
+
+    pub fn mean_batch_size(&self) -> f64 {
+        if self.batches == 0 {
+            0.0
+        } else {
+            self.keys as f64 / self.batches as f64
+        }
+    }
+}
+
+/// The batch stats of a loader side by side with those of its shadow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShadowReport {
+    pub primary: BatchStats,
+    pub shadow: BatchStats,
+}
+
+/// The batch function of a shadow loader, which records batches instead of loading them.
+pub struct RecordStats {
+    stats: Arc<Mutex<BatchStats>>,
+}
+
+impl<K> BatchFn<K, ()> for RecordStats
+where
+    K: Eq + Hash + Clone,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, ()> {
+        self.stats.lock().unwrap().record(keys.len());
+        keys.iter().map(|k| (k.clone(), ())).collect()
+    }
+}
+
+/// The loader configuration under evaluation, attached to a loader with `with_shadow`.
+pub struct Shadow<K>
+where
+    K: Eq + Hash + Clone,
+{
+    loader: Loader<K, (), RecordStats>,
+    stats: Arc<Mutex<BatchStats>>,
+    primary: Arc<Mutex<BatchStats>>,
+}
+
+impl<K> Clone for Shadow<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn clone(&self) -> Self {
+        Shadow {
+            loader: self.loader.clone(),
+            stats: self.stats.clone(),
+            primary: self.primary.clone(),
+        }
+    }
+}
+
+impl<K> Shadow<K>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+{
+    /// Creates a shadow whose batching is configured by `configure`, e.g.
+    /// `Shadow::new(TokioRuntime, |loader| loader.with_max_batch_size(50))`. The mirrored keys
+    /// are loaded by tasks spawned on `runtime`, which must be able to spawn wherever the
+    /// primary loader loads, rather than on the runtime the primary loader happens to run on.
+    pub fn new(
+        runtime: impl Runtime,
+        configure: impl FnOnce(Loader<K, (), RecordStats>) -> Loader<K, (), RecordStats>,
+    ) -> Self {
+        let stats = Arc::new(Mutex::new(BatchStats::default()));
+        let loader = configure(
+            Loader::new(RecordStats {
+                stats: stats.clone(),
+            })
+            .with_runtime(runtime),
+        );
+        Shadow {
+            loader,
+            stats,
+            primary: Arc::new(Mutex::new(BatchStats::default())),
+        }
+    }
+
+    pub fn report(&self) -> ShadowReport {
+        ShadowReport {
+            primary: *self.primary.lock().unwrap(),
+            shadow: *self.stats.lock().unwrap(),
+        }
+    }
+
+    /// The hook through which a primary loader feeds requested keys into this shadow, in tasks
+    /// spawned on the runtime of the shadow, and records its own batches. Keys are no longer mirrored once all handles of
+    /// this shadow are dropped.
+    pub(crate) fn hook(&self) -> ShadowHook<K> {
+        let loader = self.loader.downgrade();
+        ShadowHook {
+            mirror: Arc::new(move |keys| {
+                let Some(loader) = loader.upgrade() else {
+                    return;
+                };
+                loader.runtime().clone().spawn(Box::pin(async move {
+                    let _ = loader.try_load_many(keys).await;
+                }));
+            }),
+            primary: self.primary.clone(),
+        }
+    }
+}
+
+pub(crate) struct ShadowHook<K> {
+    mirror: Arc<dyn Fn(Vec<K>) + Send + Sync>,
+    primary: Arc<Mutex<BatchStats>>,
+}
+
+impl<K> Clone for ShadowHook<K> {
+    fn clone(&self) -> Self {
+        ShadowHook {
+            mirror: self.mirror.clone(),
+            primary: self.primary.clone(),
+        }
+    }
+}
+
+impl<K> ShadowHook<K> {
+    pub(crate) fn mirror(&self, keys: Vec<K>) {
+        if !keys.is_empty() {
+            (self.mirror)(keys)
+        }
+    }
+
+    pub(crate) fn record(&self, batch_size: usize) {
+        self.primary.lock().unwrap().record(batch_size);
+    }
+}
@@ -0,0 +1,321 @@
+//! A loader whose batches are formed and loaded by a dedicated dispatcher task rather than by
+//! whichever caller happens to flush, see
+//! [`Loader::spawned`](crate::non_cached::Loader::spawned).
+//!
+//! Loads queue their keys in the inbox of the dispatcher and wait for a reply of their own. The
+//! dispatcher waits for work like the loader it was spawned from, then loads every key queued
+//! meanwhile with the batch function, which it owns, and answers each caller. Callers never run
+//! a batch nor lock the state of the loader, so the futures of the batch function must be
+//! `Send`, see [`SendBatchFn`]. A bounded loader, see `with_max_pending`, counts the keys queued
+//! in the inbox along with those the dispatcher has yet to answer, and callers finding it full
+//! wait for the dispatcher to make room or fail according to the [`Backpressure`].
+
+use crate::batching::Rounds;
+use crate::non_cached::Dispatcher;
+use crate::{
+    Backpressure, LoadError, MissingKeyPolicy, Observer, Runtime, SendBatchFn, Sendable, Wait,
+};
+use std::collections::HashMap;
+use std::future::{poll_fn, Future};
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+/// How the dispatcher waits for work, taken over from the loader it was spawned from.
+pub(crate) struct Dispatch {
+    pub(crate) wait: Wait,
+    pub(crate) max_batch_size: usize,
+    pub(crate) max_wait_rounds: usize,
+    pub(crate) max_pending: Option<(usize, Backpressure)>,
+    pub(crate) runtime: Arc<dyn Runtime>,
+}
+
+/// The reply to a queued key, answered once by the dispatcher.
+pub(crate) struct Slot<V> {
+    result: Option<Result<V, LoadError>>,
+    waker: Option<Waker>,
+}
+
+pub(crate) type Reply<V> = Arc<Mutex<Slot<V>>>;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn answer<V>(reply: Reply<V>, result: Result<V, LoadError>) {
+    let mut slot = lock(&reply);
+    slot.result = Some(result);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+/// Waits for the answer to `reply`.
+async fn receive<V>(reply: &Reply<V>) -> Result<V, LoadError> {
+    poll_fn(|cx| {
+        let mut slot = lock(reply);
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+struct Inbox<K, V> {
+    queue: Vec<(K, Reply<V>)>,
+    waker: Option<Waker>,
+    closed: bool,
+    /// The keys taken out of the queue which the dispatcher has yet to answer.
+    dispatched: usize,
+    /// The callers waiting for room in a full queue.
+    senders: Vec<Waker>,
+}
+
+impl<K, V> Inbox<K, V> {
+    fn wake_dispatcher(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+type SharedInbox<K, V> = Arc<Mutex<Inbox<K, V>>>;
+
+/// Closes the inbox once the last [`SpawnedLoader`] handle is dropped, stopping the
+/// dispatcher after it answered the keys queued so far.
+struct Sender<K, V> {
+    inbox: SharedInbox<K, V>,
+    max_pending: Option<(usize, Backpressure)>,
+}
+
+impl<K, V> Sender<K, V> {
+    /// Queues `keys` in order, as many of them at once as the queue has room for. While the
+    /// queue is full, the next key waits for room or fails with [`LoadError::QueueFull`], like
+    /// loads of an in-process loader.
+    async fn send(&self, keys: impl IntoIterator<Item = K>) -> Vec<Reply<V>> {
+        let mut keys = keys.into_iter().peekable();
+        let mut replies = Vec::new();
+        poll_fn(|cx| {
+            let mut inbox = lock(&self.inbox);
+            while keys.peek().is_some() {
+                let reply = Arc::new(Mutex::new(Slot {
+                    result: None,
+                    waker: None,
+                }));
+                match self.max_pending {
+                    Some((max_pending, backpressure))
+                        if inbox.queue.len() + inbox.dispatched >= max_pending =>
+                    {
+                        if backpressure == Backpressure::Wait {
+                            inbox.senders.push(cx.waker().clone());
+                            // the dispatcher stops waiting for work once the queue is full
+                            inbox.wake_dispatcher();
+                            return Poll::Pending;
+                        }
+                        keys.next();
+                        lock(&reply).result = Some(Err(LoadError::QueueFull));
+                    }
+                    _ => inbox.queue.push((keys.next().unwrap(), reply.clone())),
+                }
+                replies.push(reply);
+            }
+            inbox.wake_dispatcher();
+            Poll::Ready(())
+        })
+        .await;
+        replies
+    }
+}
+
+impl<K, V> Drop for Sender<K, V> {
+    fn drop(&mut self) {
+        let mut inbox = lock(&self.inbox);
+        inbox.closed = true;
+        inbox.wake_dispatcher();
+    }
+}
+
+/// A loader batching on a dispatcher task, created by
+/// [`Loader::spawned`](crate::non_cached::Loader::spawned) or
+/// [`Loader::spawn`](crate::non_cached::Loader::spawn). Clones share the dispatcher, which stops once the last handle is
+/// dropped.
+pub struct SpawnedLoader<K, V> {
+    sender: Arc<Sender<K, V>>,
+    missing_key_policy: MissingKeyPolicy<V>,
+}
+
+impl<K, V: Clone> Clone for SpawnedLoader<K, V> {
+    fn clone(&self) -> Self {
+        SpawnedLoader {
+            sender: self.sender.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+        }
+    }
+}
+
+impl<K, V> SpawnedLoader<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Spawns the dispatcher task on the runtime of `dispatch`, which loads the keys with the
+    /// [`Dispatcher`] that `dispatcher` resolves to.
+    pub(crate) fn spawn<F, O, S>(
+        dispatcher: impl Future<Output = Dispatcher<K, V, Sendable<F>, O, S, Reply<V>>> + Send + 'static,
+        dispatch: Dispatch,
+        missing_key_policy: MissingKeyPolicy<V>,
+    ) -> Self
+    where
+        F: SendBatchFn<K, V>,
+        O: Observer + Send + Sync + 'static,
+        S: BuildHasher + Clone + Send + Sync + 'static,
+    {
+        let inbox = Arc::new(Mutex::new(Inbox {
+            queue: Vec::new(),
+            waker: None,
+            closed: false,
+            dispatched: 0,
+            senders: Vec::new(),
+        }));
+        let runtime = dispatch.runtime.clone();
+        let sender = Sender {
+            inbox: inbox.clone(),
+            max_pending: dispatch.max_pending,
+        };
+        runtime.spawn(Box::pin(run(inbox, dispatcher, dispatch)));
+        SpawnedLoader {
+            sender: Arc::new(sender),
+            missing_key_policy,
+        }
+    }
+
+    /// Number of keys queued for the dispatcher.
+    pub fn pending_len(&self) -> usize {
+        lock(&self.sender.inbox).queue.len()
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<V, LoadError> {
+        let reply = self.sender.send(Some(key)).await.remove(0);
+        self.missing_key_policy.resolve(receive(&reply).await)
+    }
+
+    pub async fn load(&self, key: K) -> V {
+        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Loads `keys`, returning the outcome of every key individually.
+    pub async fn load_results(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> HashMap<K, Result<V, LoadError>> {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let replies = self.sender.send(keys.iter().cloned()).await;
+        let mut ret = HashMap::with_capacity(keys.len());
+        for (key, reply) in keys.into_iter().zip(replies) {
+            if let Some(result) = self.missing_key_policy.resolve_many(receive(&reply).await) {
+                ret.insert(key, result);
+            }
+        }
+        ret
+    }
+
+    pub async fn try_load_many(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<HashMap<K, V>, LoadError> {
+        self.load_results(keys)
+            .await
+            .into_iter()
+            .map(|(k, r)| r.map(|v| (k, v)))
+            .collect()
+    }
+
+    pub async fn load_many(&self, keys: impl IntoIterator<Item = K>) -> HashMap<K, V> {
+        self.try_load_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Waits until keys are queued, returning false once the inbox is closed and empty.
+async fn next<K, V>(inbox: &Mutex<Inbox<K, V>>) -> bool {
+    poll_fn(|cx| {
+        let mut inbox = lock(inbox);
+        if !inbox.queue.is_empty() {
+            Poll::Ready(true)
+        } else if inbox.closed {
+            Poll::Ready(false)
+        } else {
+            inbox.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// The dispatcher loop: waits for keys, waits for work like a loader does, then loads every
+/// key queued meanwhile and answers their callers. Keys whose callers stopped waiting are not
+/// loaded, and keys left pending, e.g. because the batching window is full, are loaded along
+/// with the keys of the next round.
+async fn run<K, V, F, O, S>(
+    inbox: SharedInbox<K, V>,
+    dispatcher: impl Future<Output = Dispatcher<K, V, Sendable<F>, O, S, Reply<V>>>,
+    dispatch: Dispatch,
+) where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: SendBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    let mut dispatcher = dispatcher.await;
+    let Rounds {
+        idle: max_idle,
+        max: max_rounds,
+    } = dispatch.wait.rounds(dispatch.max_wait_rounds);
+    while !dispatcher.is_idle() || next(&inbox).await {
+        let (mut rounds, mut idle, mut queued) = (0, 0, lock(&inbox).queue.len());
+        while queued < dispatch.max_batch_size {
+            dispatch.wait.wait(&dispatch.runtime).await;
+            rounds += 1;
+            let (len, full) = {
+                let inbox = lock(&inbox);
+                (inbox.queue.len(), !inbox.senders.is_empty())
+            };
+            idle = if len == queued { idle + 1 } else { 0 };
+            queued = len;
+            if rounds >= max_rounds || idle >= max_idle || full {
+                break;
+            }
+        }
+
+        let queue = {
+            let mut inbox = lock(&inbox);
+            inbox.dispatched += inbox.queue.len();
+            std::mem::take(&mut inbox.queue)
+        };
+        for (key, reply) in queue {
+            if let Some((reply, result)) = dispatcher.enqueue(key, reply) {
+                answer(reply, result);
+            }
+        }
+        // a caller which stopped waiting dropped its handle of the reply
+        let answers = dispatcher
+            .dispatch(|reply| Arc::strong_count(reply) == 1)
+            .await;
+        // the answered keys leave the queue before their callers resume
+        let senders = {
+            let mut inbox = lock(&inbox);
+            inbox.dispatched = dispatcher.len();
+            std::mem::take(&mut inbox.senders)
+        };
+        for (reply, result) in answers {
+            answer(reply, result);
+        }
+        senders.into_iter().for_each(Waker::wake);
+    }
+}
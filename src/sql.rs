@@ -0,0 +1,156 @@
+//! Batch functions running a SQL query, enabled by the `sqlx` and `diesel-async` features.
+//!
+//! The query selects the rows of all keys of a batch at once, e.g. with `WHERE id = ANY($1)` on
+//! Postgres, and every row is mapped to the key it belongs to. Keys without a row are missing
+//! from the batch result and resolve according to the loader's
+//! [`MissingKeyPolicy`](crate::MissingKeyPolicy).
+use crate::{BatchError, BatchFn};
+#[cfg(feature = "diesel-async")]
+use diesel_async::{methods::LoadQuery, AsyncConnection, RunQueryDsl};
+#[cfg(feature = "sqlx")]
+use sqlx::query::QueryAs;
+#[cfg(feature = "sqlx")]
+use sqlx::{Database, FromRow, IntoArguments, Pool};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A [`BatchFn`] loading the rows returned by a sqlx query for the keys of a batch. The values
+/// are results, so that a failed query resolves every key of the batch to the `sqlx::Error`,
+/// kept as the [`BatchError`], rather than to a missing value.
+///
+/// ```ignore
+/// let users = Loader::new(SqlxBatchFn::new(
+///     pool,
+///     |ids: &[i64]| sqlx::query_as("SELECT id, name FROM users WHERE id = ANY($1)").bind(ids),
+///     |user: &User| user.id,
+/// ));
+/// ```
+#[cfg(feature = "sqlx")]
+pub struct SqlxBatchFn<DB: Database, Q, E> {
+    pool: Pool<DB>,
+    query: Q,
+    key_of: E,
+}
+
+#[cfg(feature = "sqlx")]
+impl<DB: Database, Q, E> SqlxBatchFn<DB, Q, E> {
+    /// Runs the query built by `query` for the keys of a batch on `pool`, keying every row by
+    /// `key_of`.
+    pub fn new<K, V>(pool: Pool<DB>, query: Q, key_of: E) -> Self
+    where
+        Q: for<'k> Fn(&'k [K]) -> QueryAs<'k, DB, V, <DB as Database>::Arguments<'k>>,
+        E: Fn(&V) -> K,
+    {
+        SqlxBatchFn {
+            pool,
+            query,
+            key_of,
+        }
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<DB, K, V, Q, E> BatchFn<K, Result<V, BatchError>> for SqlxBatchFn<DB, Q, E>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+    for<'k> <DB as Database>::Arguments<'k>: IntoArguments<'k, DB>,
+    K: Eq + Hash + Clone,
+    V: for<'r> FromRow<'r, DB::Row> + Send + Unpin,
+    Q: for<'k> Fn(&'k [K]) -> QueryAs<'k, DB, V, <DB as Database>::Arguments<'k>>,
+    E: Fn(&V) -> K,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Result<V, BatchError>> {
+        match (self.query)(keys).fetch_all(&self.pool).await {
+            Ok(rows) => rows
+                .into_iter()
+                .map(|row| ((self.key_of)(&row), Ok(row)))
+                .collect(),
+            Err(e) => {
+                let e = BatchError::new(e);
+                keys.iter().map(|k| (k.clone(), Err(e.clone()))).collect()
+            }
+        }
+    }
+}
+
+/// The number of keys a [`DieselBatchFn`] binds to a single query by default, the lowest limit
+/// of bind parameters per statement among the databases supported by diesel, SQLite's.
+#[cfg(feature = "diesel-async")]
+pub const DEFAULT_MAX_PARAMS: usize = 32766;
+
+/// A [`BatchFn`] loading the rows returned by a diesel query, typically filtering with
+/// `eq_any(keys)`, for the keys of a batch on its own diesel-async connection. The values are
+/// results like those of [`SqlxBatchFn`].
+///
+/// Batches with more keys than the database accepts bind parameters are split into chunks of
+/// up to [`DieselBatchFn::with_max_params`] keys, queried one after another, so that the
+/// loader's `max_batch_size` need not be lowered to the database's limit.
+///
+/// ```ignore
+/// let users = Loader::new(DieselBatchFn::new(
+///     conn,
+///     |ids: &[i32]| users::table.filter(users::id.eq_any(ids.to_vec())),
+///     |user: &User| user.id,
+/// ));
+/// ```
+#[cfg(feature = "diesel-async")]
+pub struct DieselBatchFn<C, Q, E> {
+    conn: C,
+    query: Q,
+    key_of: E,
+    max_params: usize,
+}
+
+#[cfg(feature = "diesel-async")]
+impl<C, Q, E> DieselBatchFn<C, Q, E>
+where
+    C: AsyncConnection,
+{
+    /// Runs the query built by `query` for the keys of a batch on `conn`, keying every row by
+    /// `key_of`.
+    pub fn new<K, V, R>(conn: C, query: Q, key_of: E) -> Self
+    where
+        Q: Fn(&[K]) -> R,
+        E: Fn(&V) -> K,
+    {
+        DieselBatchFn {
+            conn,
+            query,
+            key_of,
+            max_params: DEFAULT_MAX_PARAMS,
+        }
+    }
+
+    /// Caps the number of keys bound to a single query, at least 1. Defaults to
+    /// [`DEFAULT_MAX_PARAMS`].
+    pub fn with_max_params(mut self, max_params: usize) -> Self {
+        self.max_params = max_params.max(1);
+        self
+    }
+}
+
+#[cfg(feature = "diesel-async")]
+impl<C, K, V, Q, E, R> BatchFn<K, Result<V, BatchError>> for DieselBatchFn<C, Q, E>
+where
+    C: AsyncConnection,
+    K: Eq + Hash + Clone,
+    V: Send,
+    Q: Fn(&[K]) -> R,
+    R: LoadQuery<'static, C, V> + 'static,
+    E: Fn(&V) -> K,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Result<V, BatchError>> {
+        let mut ret = HashMap::new();
+        for chunk in keys.chunks(self.max_params) {
+            match (self.query)(chunk).load(&mut self.conn).await {
+                Ok(rows) => ret.extend(rows.into_iter().map(|row| ((self.key_of)(&row), Ok(row)))),
+                Err(e) => {
+                    let e = BatchError::new(e);
+                    ret.extend(chunk.iter().map(|k| (k.clone(), Err(e.clone()))));
+                }
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,91 @@
+//! Loading the keys of a [`Stream`] through a loader, for data pipelines rather than servers.
+//!
+//! Enable the `stream-ext` feature, then `keys.batch_load(&loader)` yields every key with its
+//! value in the order of the input stream. Up to a window of keys, by default the loader's
+//! `max_batch_size`, are loaded at once, so that they share batches; the input stream is not
+//! polled for more keys until the oldest of them is yielded, which gives backpressure to the
+//! producer.
+use crate::{cached, non_cached, LoadError, Observer, TryBatchFn};
+use futures::stream::{Stream, StreamExt};
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+
+/// A loader the keys of a stream can be loaded through, implemented by both loaders.
+pub trait StreamLoader<K, V> {
+    fn try_load_key(&self, key: K) -> impl Future<Output = Result<V, LoadError>>;
+
+    /// The default number of keys loaded at once by [`BatchLoadExt::batch_load`].
+    fn window(&self) -> usize;
+}
+
+impl<K, V, F, C, O, S> StreamLoader<K, V> for cached::Loader<K, V, F, C, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    C: cached::Cache<Key = K, Val = V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load_key(&self, key: K) -> impl Future<Output = Result<V, LoadError>> {
+        self.try_load(key)
+    }
+
+    fn window(&self) -> usize {
+        self.max_batch_size()
+    }
+}
+
+impl<K, V, F, O, S> StreamLoader<K, V> for non_cached::Loader<K, V, F, O, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: TryBatchFn<K, V>,
+    O: Observer,
+    S: BuildHasher + Clone,
+{
+    fn try_load_key(&self, key: K) -> impl Future<Output = Result<V, LoadError>> {
+        self.try_load(key)
+    }
+
+    fn window(&self) -> usize {
+        self.max_batch_size()
+    }
+}
+
+/// Extension methods for streams of keys.
+pub trait BatchLoadExt<K: Clone>: Stream<Item = K> + Sized {
+    /// Loads every key of this stream through `loader`, yielding `(key, result)` in input order.
+    fn batch_load<'a, V, L>(
+        self,
+        loader: &'a L,
+    ) -> impl Stream<Item = (K, Result<V, LoadError>)> + 'a
+    where
+        Self: 'a,
+        K: 'a,
+        L: StreamLoader<K, V>,
+    {
+        let window = loader.window();
+        self.batch_load_windowed(loader, window)
+    }
+
+    /// Like [`BatchLoadExt::batch_load`], loading at most `window` keys at once.
+    fn batch_load_windowed<'a, V, L>(
+        self,
+        loader: &'a L,
+        window: usize,
+    ) -> impl Stream<Item = (K, Result<V, LoadError>)> + 'a
+    where
+        Self: 'a,
+        K: 'a,
+        L: StreamLoader<K, V>,
+    {
+        self.map(move |key| async move {
+            let ret = loader.try_load_key(key.clone()).await;
+            (key, ret)
+        })
+        .buffered(window.max(1))
+    }
+}
+
+impl<K: Clone, S: Stream<Item = K>> BatchLoadExt<K> for S {}
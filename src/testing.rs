@@ -0,0 +1,233 @@
+//! Utilities for testing code built on the loaders: a [`RecordingBatchFn`] capturing the
+//! batches of a loader, and a [`ManualRuntime`] whose yields only end when the test says so,
+//! so that which loads share a batch doesn't depend on how the executor schedules them.
+//!
+//! ```
+//! use dataloader::cached::Loader;
+//! use dataloader::testing::{Identity, RecordingBatchFn};
+//! use futures::executor::block_on;
+//! use futures::future::join;
+//!
+//! let load_fn = RecordingBatchFn::new(Identity);
+//! let loader = Loader::new(load_fn.clone());
+//! block_on(join(loader.load(1), loader.load(2)));
+//! block_on(loader.load(1));
+//! load_fn.assert_batches(&[&[1, 2]]);
+//! ```
+
+use crate::{BatchFn, Runtime, RuntimeFuture};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A batch function loading every key as its own value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+impl<K: Eq + Hash + Clone> BatchFn<K, K> for Identity {
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, K> {
+        keys.iter().map(|k| (k.clone(), k.clone())).collect()
+    }
+}
+
+/// Records the keys of every call of the wrapped batch function. Clones share the record, so a
+/// test can keep a clone to inspect the batches of the loader it passed the other one to.
+pub struct RecordingBatchFn<K, F> {
+    load_fn: F,
+    batches: Arc<Mutex<Vec<Vec<K>>>>,
+}
+
+impl<K, F: Clone> Clone for RecordingBatchFn<K, F> {
+    fn clone(&self) -> Self {
+        RecordingBatchFn {
+            load_fn: self.load_fn.clone(),
+            batches: self.batches.clone(),
+        }
+    }
+}
+
+impl<K, F> RecordingBatchFn<K, F> {
+    pub fn new(load_fn: F) -> Self {
+        RecordingBatchFn {
+            load_fn,
+            batches: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The keys of every call so far, in the order of the calls.
+    pub fn batches(&self) -> Vec<Vec<K>>
+    where
+        K: Clone,
+    {
+        lock(&self.batches).clone()
+    }
+
+    /// The number of calls so far.
+    pub fn calls(&self) -> usize {
+        lock(&self.batches).len()
+    }
+
+    /// Forgets the calls so far, e.g. between the steps of a test.
+    pub fn clear(&self) {
+        lock(&self.batches).clear();
+    }
+
+    /// Panics unless the batch function was called exactly `calls` times, listing the batches.
+    pub fn assert_calls(&self, calls: usize)
+    where
+        K: Debug,
+    {
+        let batches = lock(&self.batches);
+        assert_eq!(
+            batches.len(),
+            calls,
+            "expected {} batch function calls, got {:?}",
+            calls,
+            *batches
+        );
+    }
+
+    /// Panics unless the calls so far loaded the `expected` batches, in order, ignoring the
+    /// order of the keys within a batch.
+    pub fn assert_batches(&self, expected: &[&[K]])
+    where
+        K: Eq + Hash + Debug,
+    {
+        let batches = lock(&self.batches);
+        let matches = batches.len() == expected.len()
+            && batches.iter().zip(expected).all(|(batch, expected)| {
+                batch.len() == expected.len()
+                    && batch.iter().collect::<HashSet<_>>() == expected.iter().collect()
+            });
+        assert!(
+            matches,
+            "expected batches {:?}, got {:?}",
+            expected, *batches
+        );
+    }
+}
+
+impl<K, V, F> BatchFn<K, V> for RecordingBatchFn<K, F>
+where
+    K: Clone,
+    F: BatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        lock(&self.batches).push(keys.to_vec());
+        self.load_fn.load(keys).await
+    }
+
+    fn salvage(&mut self) -> HashMap<K, V> {
+        self.load_fn.salvage()
+    }
+}
+
+type SpawnedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Default)]
+struct Rounds {
+    round: u64,
+    yielding: usize,
+    wakers: Vec<Waker>,
+    spawned: Vec<SpawnedFuture>,
+}
+
+/// A [`Runtime`] whose yields end only once the test calls [`ManualRuntime::advance`], e.g. to
+/// queue more loads into the batch of callers waiting for work, or to tell when a loader waits.
+///
+/// Every yield of a loader waits for one advance, so a loader built with `with_yield_count(1)`
+/// waits one advance per wait round. The runtime has no timer, so batches don't time out and
+/// failed batches are retried right away. Futures spawned on it are not run until the test takes
+/// them with [`ManualRuntime::take_spawned`].
+#[derive(Clone, Default)]
+pub struct ManualRuntime(Arc<Mutex<Rounds>>);
+
+impl ManualRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ends the yields of the callers yielding now, returning how many there were.
+    pub fn advance(&self) -> usize {
+        let (yielding, wakers) = {
+            let mut rounds = lock(&self.0);
+            rounds.round += 1;
+            (rounds.yielding, std::mem::take(&mut rounds.wakers))
+        };
+        wakers.into_iter().for_each(Waker::wake);
+        yielding
+    }
+
+    /// The number of callers yielding, waiting for the next advance.
+    pub fn yielding(&self) -> usize {
+        lock(&self.0).yielding
+    }
+
+    /// Takes the futures spawned on the runtime so far, for the test to run them.
+    pub fn take_spawned(&self) -> Vec<SpawnedFuture> {
+        std::mem::take(&mut lock(&self.0).spawned)
+    }
+}
+
+impl Runtime for ManualRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(Yield {
+            rounds: self.0.clone(),
+            round: None,
+        })
+    }
+
+    fn sleep(&self, _duration: Duration) -> Option<RuntimeFuture> {
+        None
+    }
+
+    fn spawn(&self, future: SpawnedFuture) {
+        lock(&self.0).spawned.push(future);
+    }
+}
+
+/// A yield of a [`ManualRuntime`], which ends after the round it started in.
+struct Yield {
+    rounds: Arc<Mutex<Rounds>>,
+    round: Option<u64>,
+}
+
+impl Future for Yield {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let rounds = self.rounds.clone();
+        let mut rounds = lock(&rounds);
+        match self.round {
+            Some(round) if rounds.round > round => {
+                rounds.yielding -= 1;
+                self.round = None;
+                return Poll::Ready(());
+            }
+            Some(_) => {}
+            None => {
+                self.round = Some(rounds.round);
+                rounds.yielding += 1;
+            }
+        }
+        rounds.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Yield {
+    fn drop(&mut self) {
+        if self.round.is_some() {
+            lock(&self.rounds).yielding -= 1;
+        }
+    }
+}
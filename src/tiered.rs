@@ -0,0 +1,126 @@
+use crate::cached::{Cache, CacheEntries};
+
+/// Stacks a small, fast cache in front of a larger or shared one, e.g. a [`WeightedCache`]
+/// bounded per loader in front of a `HashMap` shared by the loaders of a process.
+///
+/// Lookups try the first tier, then the second one, promoting its hits to the first tier.
+/// Inserts, removals and clears go to both tiers. For a second tier accessed asynchronously,
+/// e.g. Redis, see `with_async_cache` of the cached loader instead.
+///
+/// [`WeightedCache`]: crate::cached::WeightedCache
+pub struct TieredCache<L1, L2> {
+    l1: L1,
+    l2: L2,
+}
+
+impl<L1, L2> TieredCache<L1, L2> {
+    pub fn new(l1: L1, l2: L2) -> Self {
+        TieredCache { l1, l2 }
+    }
+
+    pub fn l1(&self) -> &L1 {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+
+    pub fn into_inner(self) -> (L1, L2) {
+        (self.l1, self.l2)
+    }
+}
+
+impl<K, V, L1, L2> Cache for TieredCache<L1, L2>
+where
+    K: Clone,
+    V: Clone,
+    L1: Cache<Key = K, Val = V>,
+    L2: Cache<Key = K, Val = V>,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        // looked up twice on a hit, as returning the borrow of the first lookup conditionally
+        // keeps the first tier borrowed for the rest of the function
+        if self.l1.get(key).is_none() {
+            let val = self.l2.get(key)?.clone();
+            self.l1.insert(key.clone(), val);
+            // the first tier may not keep the value, e.g. a weak cache without pinned values
+            if self.l1.get(key).is_none() {
+                return self.l2.get(key);
+            }
+        }
+        self.l1.get(key)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.l1.insert(key.clone(), val.clone());
+        self.l2.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let val = self.l1.remove(key);
+        self.l2.remove(key).or(val)
+    }
+
+    fn clear(&mut self) {
+        self.l1.clear();
+        self.l2.clear();
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        self.l2.len_hint()
+    }
+
+    fn weight(&self, key: &K, val: &V) -> usize {
+        self.l2.weight(key, val)
+    }
+
+    fn get_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        let mut ret = self.l1.get_many(keys);
+        let missing = keys
+            .iter()
+            .zip(ret.iter())
+            .filter(|(_, v)| v.is_none())
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return ret;
+        }
+        let mut found = self.l2.get_many(&missing).into_iter();
+        let mut promoted = Vec::new();
+        for (key, v) in keys.iter().zip(ret.iter_mut()) {
+            if v.is_none() {
+                *v = found.next().flatten();
+                if let Some(val) = v {
+                    promoted.push((key.clone(), val.clone()));
+                }
+            }
+        }
+        self.l1.insert_many(promoted);
+        ret
+    }
+
+    fn insert_many(&mut self, entries: Vec<(K, V)>) {
+        self.l1.insert_many(entries.clone());
+        self.l2.insert_many(entries);
+    }
+}
+
+impl<K, V, L1, L2> CacheEntries for TieredCache<L1, L2>
+where
+    K: Clone,
+    V: Clone,
+    L1: Cache<Key = K, Val = V>,
+    L2: CacheEntries<Key = K, Val = V>,
+{
+    fn entries(&self) -> Vec<(K, V)> {
+        self.l2.entries()
+    }
+
+    fn keys(&self) -> Vec<K> {
+        self.l2.keys()
+    }
+}
@@ -0,0 +1,188 @@
+use crate::cached::{Principal, Update};
+use crate::runtime::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// The changes published to a subscription which it hasn't yielded yet.
+struct Watch<V> {
+    // the version of the last change accepted, older changes are discarded
+    seen: u64,
+    queue: VecDeque<(u64, Update<V>)>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+fn lock<V>(watch: &Mutex<Watch<V>>) -> MutexGuard<'_, Watch<V>> {
+    watch.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The changes of the cached value of a key, see
+/// [`Loader::subscribe`](crate::cached::Loader::subscribe).
+///
+/// Every change is numbered by a version which increases with each write to the cache of the
+/// loader, and a subscription only ever yields changes in version order: a change older than
+/// one it has accepted already is discarded. Cache writes are published under the state lock of
+/// the loader, so that results of batches, primes, updates and clears reach the subscribers in
+/// the order they were applied to the cache.
+pub struct Subscription<V> {
+    watch: Arc<Mutex<Watch<V>>>,
+}
+
+impl<V> Subscription<V> {
+    /// Waits for the next change of the key, returning it with its version, or `None` once the
+    /// loader is dropped and the changes published before are yielded.
+    pub async fn next(&mut self) -> Option<(u64, Update<V>)> {
+        poll_fn(|cx| self.poll_change(cx)).await
+    }
+
+    /// Returns the next change of the key if one was published already, without waiting.
+    pub fn try_next(&mut self) -> Option<(u64, Update<V>)> {
+        lock(&self.watch).queue.pop_front()
+    }
+
+    fn poll_change(&self, cx: &mut Context<'_>) -> Poll<Option<(u64, Update<V>)>> {
+        let mut watch = lock(&self.watch);
+        if let Some(change) = watch.queue.pop_front() {
+            return Poll::Ready(Some(change));
+        }
+        if watch.closed {
+            return Poll::Ready(None);
+        }
+        watch.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "stream-ext")]
+impl<V> futures::Stream for Subscription<V> {
+    type Item = (u64, Update<V>);
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_change(cx)
+    }
+}
+
+/// A subscriber of a key: the principal whose cache it watches, or `None` for the shared cache.
+type Watcher<V> = (Option<Principal>, Weak<Mutex<Watch<V>>>);
+
+/// The subscriptions of a loader by key, holding them weakly so that a dropped subscription is
+/// forgotten on the next change of its key. The subscriptions are closed when the loader state
+/// is dropped.
+pub(crate) struct Watchers<K, V, S> {
+    keys: HashMap<K, Vec<Watcher<V>>, S>,
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> Watchers<K, V, S> {
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        Watchers {
+            keys: HashMap::with_hasher(hasher),
+        }
+    }
+
+    pub(crate) fn subscribe(&mut self, principal: Option<Principal>, key: K) -> Subscription<V> {
+        let watch = Arc::new(Mutex::new(Watch {
+            seen: 0,
+            queue: VecDeque::new(),
+            waker: None,
+            closed: false,
+        }));
+        let watchers = self.keys.entry(key).or_default();
+        watchers.retain(|(_, w)| w.strong_count() > 0);
+        watchers.push((principal, Arc::downgrade(&watch)));
+        Subscription { watch }
+    }
+
+    /// Whether `key` has subscribers, so that changes of other keys are not cloned.
+    pub(crate) fn is_watched(&self, key: &K) -> bool {
+        !self.keys.is_empty() && self.keys.contains_key(key)
+    }
+
+    /// Publishes `update` of `key` at `version` to the subscribers watching the cache of
+    /// `principal`.
+    pub(crate) fn publish(
+        &mut self,
+        principal: Option<&Principal>,
+        key: &K,
+        update: &Update<V>,
+        version: u64,
+    ) where
+        V: Clone,
+    {
+        let watchers = match self.keys.get_mut(key) {
+            Some(watchers) => watchers,
+            None => return,
+        };
+        watchers.retain(|(p, watch)| {
+            let watch = match watch.upgrade() {
+                Some(watch) => watch,
+                None => return false,
+            };
+            if p.as_ref() == principal {
+                push(&watch, version, update.clone());
+            }
+            true
+        });
+        if watchers.is_empty() {
+            self.keys.remove(key);
+        }
+    }
+
+    /// Publishes `update` at `version` to all subscribers for which `watched` returns true
+    /// given the principal whose cache they watch, e.g. when a cache is cleared.
+    pub(crate) fn publish_all(
+        &mut self,
+        watched: impl Fn(Option<&Principal>) -> bool,
+        update: &Update<V>,
+        version: u64,
+    ) where
+        V: Clone,
+    {
+        self.keys.retain(|_, watchers| {
+            watchers.retain(|(p, watch)| {
+                let watch = match watch.upgrade() {
+                    Some(watch) => watch,
+                    None => return false,
+                };
+                if watched(p.as_ref()) {
+                    push(&watch, version, update.clone());
+                }
+                true
+            });
+            !watchers.is_empty()
+        });
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Queues `update` at `version` unless a newer change was accepted already.
+fn push<V>(watch: &Mutex<Watch<V>>, version: u64, update: Update<V>) {
+    let mut watch = lock(watch);
+    if version <= watch.seen {
+        return;
+    }
+    watch.seen = version;
+    watch.queue.push_back((version, update));
+    if let Some(waker) = watch.waker.take() {
+        waker.wake();
+    }
+}
+
+impl<K, V, S> Drop for Watchers<K, V, S> {
+    fn drop(&mut self) {
+        for (_, watch) in self.keys.values().flatten() {
+            if let Some(watch) = watch.upgrade() {
+                let mut watch = lock(&watch);
+                watch.closed = true;
+                if let Some(waker) = watch.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
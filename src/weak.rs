@@ -0,0 +1,121 @@
+use crate::cached::Cache;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+/// Dead entries are swept on insert once the map has grown to twice its size after the last
+/// sweep, but not before it holds this many entries.
+const MIN_SWEEP: usize = 64;
+
+/// A cache of `Arc<T>` values which doesn't keep values alive on its own, for large shared
+/// values which should be dropped as soon as the rest of the program is done with them.
+///
+/// Entries hold a `Weak<T>`: a hit upgrades it, and an entry whose value was dropped is a miss
+/// and removed. The `pinned` most recently inserted values are held strongly as well, so that
+/// keys loaded shortly after each other aren't loaded twice. The value of the last hit is held
+/// until the next lookup, as [`Cache::get`] returns a reference.
+///
+/// When the values are cached elsewhere already and the batch function returns clones of
+/// those `Arc`s, `WeakCache::new(0)` avoids holding any of them twice: a key is served from
+/// this cache as long as the other cache holds its value, and loaded again afterwards.
+pub struct WeakCache<K, T> {
+    entries: HashMap<K, Weak<T>>,
+    pinned: VecDeque<Arc<T>>,
+    capacity: usize,
+    hit: Option<Arc<T>>,
+    sweep_at: usize,
+}
+
+impl<K, T> WeakCache<K, T>
+where
+    K: Eq + Hash,
+{
+    pub fn new(pinned: usize) -> Self {
+        WeakCache {
+            entries: HashMap::new(),
+            pinned: VecDeque::with_capacity(pinned),
+            capacity: pinned,
+            hit: None,
+            sweep_at: MIN_SWEEP,
+        }
+    }
+
+    /// The number of entries whose values are still alive.
+    pub fn len(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|v| v.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn unpin(&mut self, val: &Weak<T>) {
+        if let Some(i) = self
+            .pinned
+            .iter()
+            .position(|p| Arc::as_ptr(p) == val.as_ptr())
+        {
+            self.pinned.remove(i);
+        }
+    }
+}
+
+impl<K, T> Cache for WeakCache<K, T>
+where
+    K: Eq + Hash,
+{
+    type Key = K;
+    type Val = Arc<T>;
+
+    fn get(&mut self, key: &K) -> Option<&Arc<T>> {
+        self.hit = None;
+        match self.entries.get(key).map(Weak::upgrade) {
+            Some(Some(val)) => {
+                self.hit = Some(val);
+                self.hit.as_ref()
+            }
+            Some(None) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: K, val: Arc<T>) {
+        if let Some(old) = self.entries.insert(key, Arc::downgrade(&val)) {
+            self.unpin(&old);
+        }
+        if self.capacity > 0 {
+            if self.pinned.len() == self.capacity {
+                self.pinned.pop_front();
+            }
+            self.pinned.push_back(val);
+        }
+        if self.entries.len() >= self.sweep_at {
+            self.entries.retain(|_, v| v.strong_count() > 0);
+            self.sweep_at = (self.entries.len() * 2).max(MIN_SWEEP);
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Arc<T>> {
+        let val = self.entries.remove(key)?;
+        self.unpin(&val);
+        self.hit = None;
+        val.upgrade()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.pinned.clear();
+        self.hit = None;
+        self.sweep_at = MIN_SWEEP;
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::cached::{Cache, CacheEntries};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+struct Entry<V> {
+    val: V,
+    weight: usize,
+    tick: u64,
+}
+
+/// A cache bounded by the total weight of its values rather than by entry count, evicting the
+/// least recently used entries when `capacity` is exceeded.
+///
+/// The weight of each value is computed once on insert by the `weigher`, e.g. an estimate of its
+/// size in bytes, which is the [`Cache::weight`] of the cache. The most recently inserted value is never evicted by its own insertion, so a
+/// single value heavier than `capacity` is kept until the next insert; `capacity` should leave
+/// room for at least one full batch so loaded values are not evicted before they are read.
+pub struct WeightedCache<K, V, W = fn(&V) -> usize> {
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    weight: usize,
+    capacity: usize,
+    weigher: W,
+}
+
+impl<K, V, W> WeightedCache<K, V, W>
+where
+    K: Eq + Hash + Clone,
+    W: Fn(&V) -> usize,
+{
+    pub fn new(capacity: usize, weigher: W) -> Self {
+        WeightedCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            weight: 0,
+            capacity,
+            weigher,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The summed weight of all cached values.
+    pub fn total_weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+impl<K, V, W> Cache for WeightedCache<K, V, W>
+where
+    K: Eq + Hash + Clone,
+    W: Fn(&V) -> usize,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        let key = self.order.remove(&entry.tick).expect("ordered entry");
+        entry.tick = tick;
+        self.order.insert(tick, key);
+        Some(&entry.val)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.remove(&key);
+        let tick = self.next_tick();
+        let weight = self.weight(&key, &val);
+        self.weight += weight;
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, Entry { val, weight, tick });
+        while self.weight > self.capacity && self.order.len() > 1 {
+            let (_, oldest) = self.order.pop_first().expect("non-empty");
+            let entry = self.entries.remove(&oldest).expect("cached entry");
+            self.weight -= entry.weight;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.weight -= entry.weight;
+        Some(entry.val)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.weight = 0;
+    }
+
+    fn weight(&self, _key: &K, val: &V) -> usize {
+        (self.weigher)(val)
+    }
+
+    fn len_hint(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<K, V, W> CacheEntries for WeightedCache<K, V, W>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    W: Fn(&V) -> usize,
+{
+    fn entries(&self) -> Vec<(K, V)> {
+        self.entries
+            .iter()
+            .map(|(k, entry)| (k.clone(), entry.val.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,210 @@
+//! Batched writes: values stored through a [`Writer`] are coalesced into batches and written
+//! by a [`BatchStoreFn`], with the same batching policies as the loaders.
+
+use crate::cached::{self, Cache};
+use crate::runtime::{Arc, DefaultRuntime, Mutex, MutexGuard, Runtime};
+use crate::{BatchStoreFn, Observer, TryBatchFn, Wait, WaitForWorkFn};
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::pin::Pin;
+
+type WriteId = u64;
+
+type PrimeFn<K, V> = dyn Fn(Vec<(K, V)>) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync;
+
+struct State<K, V> {
+    // Writes in the order they were stored, each with its id.
+    pending: Vec<(WriteId, K, V)>,
+    id_seq: WriteId,
+    // Id of the last write flushed, as writes are flushed in order.
+    flushed: WriteId,
+}
+
+impl<K, V> State<K, V> {
+    fn enqueue(&mut self, key: K, val: V) -> WriteId {
+        self.id_seq += 1;
+        self.pending.push((self.id_seq, key, val));
+        self.id_seq
+    }
+}
+
+/// Coalesces the writes stored by concurrent callers into batches, which are written by `F`.
+///
+/// A write is flushed along with the writes stored before it. If a `store` call is dropped
+/// before it completes, its write stays pending and is flushed by the next `store` or `flush`.
+pub struct Writer<K, V, F>
+where
+    F: BatchStoreFn<K, V>,
+{
+    state: Arc<Mutex<State<K, V>>>,
+    store_fn: Arc<Mutex<F>>,
+    wait: Wait,
+    runtime: Arc<dyn Runtime>,
+    max_batch_size: usize,
+    max_wait_rounds: usize,
+    prime: Option<Arc<PrimeFn<K, V>>>,
+}
+
+impl<K, V, F> Clone for Writer<K, V, F>
+where
+    F: BatchStoreFn<K, V>,
+{
+    fn clone(&self) -> Self {
+        Writer {
+            state: self.state.clone(),
+            store_fn: self.store_fn.clone(),
+            wait: self.wait.clone(),
+            runtime: self.runtime.clone(),
+            max_batch_size: self.max_batch_size,
+            max_wait_rounds: self.max_wait_rounds,
+            prime: self.prime.clone(),
+        }
+    }
+}
+
+impl<K, V, F> Writer<K, V, F>
+where
+    K: Clone,
+    V: Clone,
+    F: BatchStoreFn<K, V>,
+{
+    pub fn new(store_fn: F) -> Self {
+        Writer {
+            state: Arc::new(Mutex::new(State {
+                pending: Vec::new(),
+                id_seq: 0,
+                flushed: 0,
+            })),
+            store_fn: Arc::new(Mutex::new(store_fn)),
+            wait: Wait::Yield(10),
+            runtime: Arc::new(DefaultRuntime::default()),
+            max_batch_size: 200,
+            max_wait_rounds: 1,
+            prime: None,
+        }
+    }
+
+    /// Caps the number of writes passed to the store function at once. Defaults to 200. A size
+    /// of 0 stores one write at a time.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Yields to the runtime `yield_count` times before flushing, letting other writers join
+    /// the batch. Defaults to 10.
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.wait = Wait::Yield(yield_count);
+        self
+    }
+
+    /// Replaces the yielding for work behavior with an arbitrary future, see
+    /// [`cached::Loader::with_custom_wait_for_work`].
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.wait = Wait::Custom(Arc::new(wait_for_work_fn));
+        self
+    }
+
+    /// Lets a caller wait for work up to `max_wait_rounds` times before flushing, see
+    /// [`cached::Loader::with_max_wait_rounds`].
+    pub fn with_max_wait_rounds(mut self, max_wait_rounds: usize) -> Self {
+        self.max_wait_rounds = max_wait_rounds.max(1);
+        self
+    }
+
+    /// Runs this writer on `runtime` instead of the [`DefaultRuntime`] of the enabled cargo
+    /// features.
+    pub fn with_runtime(mut self, runtime: impl Runtime) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Primes the cache of `loader` with the values of every flushed batch, so that it serves
+    /// the written values without loading them again.
+    pub fn with_cache<L, C, O, S>(mut self, loader: &cached::Loader<K, V, L, C, O, S>) -> Self
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        L: TryBatchFn<K, V> + Send + 'static,
+        C: Cache<Key = K, Val = V> + Send + 'static,
+        O: Observer + Clone + Send + Sync + 'static,
+        S: BuildHasher + Clone + Send + 'static,
+    {
+        let loader = loader.clone();
+        self.prime = Some(Arc::new(move |values| {
+            let loader = loader.clone();
+            Box::pin(async move { loader.prime_many(values).await })
+        }));
+        self
+    }
+
+    /// Number of writes queued for the next batch. Waits for any batch being flushed to finish.
+    pub async fn pending_len(&self) -> usize {
+        self.state.lock().await.pending.len()
+    }
+
+    /// Flushes the oldest pending writes, up to `max_batch_size` of them.
+    async fn flush_batch(&self, state: &mut State<K, V>) {
+        let count = state.pending.len().min(self.max_batch_size.max(1));
+        let mut values = Vec::with_capacity(count);
+        for (id, key, val) in state.pending.drain(..count) {
+            state.flushed = id;
+            values.push((key, val));
+        }
+        let primed = self.prime.as_ref().map(|_| values.clone());
+        self.store_fn.lock().await.store(values).await;
+        if let (Some(prime), Some(values)) = (&self.prime, primed) {
+            prime(values).await;
+        }
+    }
+
+    /// Waits for work and locks the state, waiting another round while the write `id` is still
+    /// pending and other writes were stored meanwhile, up to `max_wait_rounds`.
+    async fn wait_for_work(&self, id: WriteId) -> MutexGuard<'_, State<K, V>> {
+        let mut rounds = 0;
+        let mut stored = id;
+        loop {
+            self.wait.wait(&self.runtime).await;
+            rounds += 1;
+            let state = self.state.lock().await;
+            if rounds >= self.max_wait_rounds || state.id_seq == stored || state.flushed >= id {
+                return state;
+            }
+            stored = state.id_seq;
+        }
+    }
+
+    /// Stores `val` for `key`, returning once it has been written as part of a batch.
+    pub async fn store(&self, key: K, val: V) {
+        self.store_many(vec![(key, val)]).await
+    }
+
+    /// Stores `values`, returning once all of them have been written.
+    pub async fn store_many(&self, values: Vec<(K, V)>) {
+        let mut state = self.state.lock().await;
+        let mut last = state.flushed;
+        for (key, val) in values.into_iter() {
+            last = state.enqueue(key, val);
+            if state.pending.len() >= self.max_batch_size {
+                self.flush_batch(&mut state).await;
+            }
+        }
+        if state.flushed >= last {
+            return;
+        }
+        drop(state);
+
+        let mut state = self.wait_for_work(last).await;
+        while state.flushed < last {
+            self.flush_batch(&mut state).await;
+        }
+    }
+
+    /// Writes all pending writes right away.
+    pub async fn flush(&self) {
+        let mut state = self.state.lock().await;
+        while !state.pending.is_empty() {
+            self.flush_batch(&mut state).await;
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use dataloader::cached::{AsyncCache, Loader, SyncCache};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Stands in for a cache shared by several processes, e.g. Redis.
+#[derive(Clone, Default)]
+struct RemoteCache {
+    values: Arc<Mutex<HashMap<usize, usize>>>,
+}
+
+impl AsyncCache for RemoteCache {
+    type Key = usize;
+    type Val = usize;
+
+    async fn get(&self, key: &usize) -> Option<usize> {
+        self.values.lock().unwrap().get(key).copied()
+    }
+
+    async fn insert(&self, key: usize, val: usize) {
+        self.values.lock().unwrap().insert(key, val);
+    }
+
+    async fn remove(&self, key: &usize) {
+        self.values.lock().unwrap().remove(key);
+    }
+
+    async fn clear(&self) {
+        self.values.lock().unwrap().clear();
+    }
+}
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        self.batches.lock().unwrap().push(keys.clone());
+        keys.iter().map(|k| (*k, *k * 10)).collect()
+    }
+}
+
+#[test]
+fn test_async_cache_shared_between_loaders() {
+    let remote = RemoteCache::default();
+    let load_fn = BatchesLoadFn::default();
+    let first = Loader::new(load_fn.clone()).with_async_cache(remote.clone());
+    let second = Loader::new(load_fn.clone()).with_async_cache(remote.clone());
+
+    assert_eq!(block_on(first.load_many(vec![1, 2])).len(), 2);
+    let values = block_on(second.load_many(vec![1, 2, 3]));
+    assert_eq!(values, HashMap::from([(1, 10), (2, 20), (3, 30)]));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+
+    // fresh loads and loads on behalf of a principal bypass the shared cache
+    assert_eq!(block_on(second.load_fresh(1)), 10);
+    assert_eq!(block_on(second.for_principal("alice").load(2)), 20);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3], vec![1], vec![2]]
+    );
+
+    block_on(first.clear(1));
+    assert!(!remote.values.lock().unwrap().contains_key(&1));
+    block_on(first.clear_all());
+    assert!(remote.values.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_sync_cache_adapter() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_async_cache(SyncCache::new(HashMap::new()));
+    assert_eq!(block_on(loader.load(1)), 10);
+    block_on(loader.clear(1));
+    // cleared from both caches
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,151 @@
+use dataloader::batching::{
+    chunk, run_concurrently, split_by_cost, wait_for_work, Lock, Pending, Rounds, Turns, Yield,
+};
+use futures::executor::block_on;
+use futures::lock::{Mutex, MutexGuard};
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::future::{poll_fn, ready, Future};
+use std::sync::Arc;
+use std::task::Poll;
+
+struct Queue(Mutex<Vec<usize>>);
+
+impl<'a> Lock for &'a Queue {
+    type State = Vec<usize>;
+    type Guard = MutexGuard<'a, Vec<usize>>;
+
+    fn lock(&self) -> impl Future<Output = Self::Guard> {
+        self.0.lock()
+    }
+}
+
+/// Queues another key on each of its first `arrivals` yields.
+struct Arrivals<'a> {
+    queue: &'a Queue,
+    arrivals: Cell<usize>,
+    yields: Cell<usize>,
+}
+
+impl Yield for Arrivals<'_> {
+    fn yield_now(&self) -> impl Future<Output = ()> {
+        self.yields.set(self.yields.get() + 1);
+        if self.arrivals.get() > 0 {
+            self.arrivals.set(self.arrivals.get() - 1);
+            self.queue.0.try_lock().unwrap().push(self.yields.get());
+        }
+        ready(())
+    }
+}
+
+#[test]
+fn test_wait_for_work() {
+    let queue = Queue(Mutex::new(vec![0]));
+    let arrivals = Arrivals {
+        queue: &queue,
+        arrivals: Cell::new(3),
+        yields: Cell::new(0),
+    };
+    let rounds = Rounds { idle: 2, max: 10 };
+    let wait = |yielder: &Arrivals, done: fn(&mut Vec<usize>, bool) -> bool| {
+        let state = block_on((&queue).lock());
+        block_on(wait_for_work(
+            &&queue,
+            yielder,
+            state,
+            rounds,
+            Vec::len,
+            done,
+        ))
+        .len()
+    };
+
+    // waits while keys arrive, then for `idle` rounds without new keys
+    assert_eq!(wait(&arrivals, |_, over| over), 4);
+    assert_eq!(arrivals.yields.get(), 5);
+
+    // or until the caller is done
+    arrivals.arrivals.set(10);
+    assert_eq!(wait(&arrivals, |keys, _| keys.len() >= 6), 6);
+    assert_eq!(arrivals.yields.get(), 7);
+
+    // but not beyond the `max` rounds
+    arrivals.arrivals.set(100);
+    assert_eq!(wait(&arrivals, |_, over| over), 16);
+    assert_eq!(arrivals.yields.get(), 17);
+}
+
+#[test]
+fn test_pending() {
+    let mut pending = Pending::with_hasher(RandomState::new());
+    for (seq, key) in vec!["a", "b", "c"].into_iter().enumerate() {
+        pending.insert(Arc::new(key), seq, seq + 1);
+    }
+    assert_eq!(pending.oldest(2).collect::<Vec<_>>(), vec![&"a", &"b"]);
+    assert_eq!(pending.cost(), 6);
+
+    // a key in flight stays pending but leaves the queue
+    assert_eq!(pending.start(&"a").as_deref(), Some(&"a"));
+    assert_eq!(pending.start(&"a"), None);
+    assert!(pending.contains_key(&"a"));
+    assert_eq!((pending.len(), pending.queued(), pending.cost()), (3, 2, 5));
+
+    // and is queued again in its place if its batch leaves it out
+    pending.requeue(&"a");
+    let numbered = pending.numbered().collect::<Vec<_>>();
+    assert_eq!(numbered, vec![(0, &"a"), (1, &"b"), (2, &"c")]);
+
+    pending.start(&"b");
+    pending.remove(&"b");
+    pending.remove(&"c");
+    assert_eq!((pending.len(), pending.queued(), pending.cost()), (1, 1, 1));
+}
+
+#[test]
+fn test_split() {
+    let costs = vec![1, 2, 5, 1, 1, 1];
+    let batches = split_by_cost(costs.clone(), |c| *c, 3, 10);
+    assert_eq!(batches, vec![vec![1, 2], vec![5], vec![1, 1, 1]]);
+    assert_eq!(split_by_cost(costs, |c| *c, 3, 2).len(), 2);
+    assert_eq!(
+        chunk(vec![vec![1, 2, 3], vec![4]], 2),
+        vec![vec![1, 2], vec![3], vec![4]]
+    );
+
+    // two turns of three and one items
+    let mut turns = Turns::default();
+    (0..3).for_each(|seq| turns.queued(seq));
+    turns.next();
+    turns.queued(3);
+    let items = (0..4).map(|seq| (seq, seq));
+    assert_eq!(turns.round_robin(items, 3), vec![0, 3, 1]);
+}
+
+#[test]
+fn test_run_concurrently() {
+    let running = Cell::new(0);
+    let most = Cell::new(0);
+    let slots = std::cell::RefCell::new(Vec::new());
+    block_on(run_concurrently((0..5).collect(), 2, |slot, _| {
+        slots.borrow_mut().push(slot);
+        let (running, most) = (&running, &most);
+        async move {
+            running.set(running.get() + 1);
+            most.set(most.get().max(running.get()));
+            let mut yielded = false;
+            poll_fn(|cx| {
+                if yielded {
+                    return Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+            running.set(running.get() - 1);
+        }
+    }));
+    assert_eq!(most.get(), 2);
+    assert!(slots.borrow().iter().all(|slot| *slot < 2));
+    assert_eq!(slots.borrow().len(), 5);
+}
@@ -0,0 +1,38 @@
+use dataloader::cached::{BitsetCache, BitsetLoader, Cache, Loader};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+struct IsEvenFn;
+
+impl BatchFn<u32, bool> for IsEvenFn {
+    async fn load(&mut self, keys: &[u32]) -> HashMap<u32, bool> {
+        let ret = keys.iter().map(|k| (*k, k % 2 == 0)).collect();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_bitset_cache() {
+    let mut cache = BitsetCache::<u32>::new();
+    assert_eq!(cache.get(&3), None);
+    cache.insert(3, true);
+    cache.insert(200, false);
+    assert_eq!(cache.get(&3), Some(&true));
+    assert_eq!(cache.get(&200), Some(&false));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.remove(&3), Some(true));
+    assert_eq!(cache.get(&3), None);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_bitset_loader() {
+    let loader: BitsetLoader<u32, _> = Loader::with_cache(IsEvenFn, BitsetCache::new());
+    let ret = block_on(loader.load_many(vec![1, 2, 1000]));
+    assert!(!ret[&1]);
+    assert!(ret[&2]);
+    assert!(ret[&1000]);
+}
@@ -0,0 +1,131 @@
+#![cfg(any(
+    feature = "runtime-futures",
+    feature = "runtime-async-std",
+    feature = "runtime-tokio"
+))]
+
+#[cfg(feature = "runtime-async-std")]
+use dataloader::blocking::AsyncStdExecutor;
+use dataloader::blocking::BlockingLoader;
+#[cfg(feature = "runtime-futures")]
+use dataloader::blocking::FuturesExecutor;
+#[cfg(feature = "runtime-tokio")]
+use dataloader::blocking::TokioExecutor;
+#[cfg(feature = "runtime-futures")]
+use dataloader::cached;
+#[cfg(any(feature = "runtime-futures", feature = "runtime-async-std"))]
+use dataloader::LoadError;
+use dataloader::{non_cached, BatchFn, BlockingError};
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Clone, Default)]
+struct CountingLoadFn {
+    keys: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for CountingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        *self.keys.lock().unwrap() += keys.len();
+        ready(
+            keys.iter()
+                .filter(|k| **k > 0)
+                .map(|k| (*k, *k * 10))
+                .collect(),
+        )
+        .await
+    }
+}
+
+#[cfg(feature = "runtime-futures")]
+#[test]
+fn test_blocking_cached_loader() {
+    let load_fn = CountingLoadFn::default();
+    let loader = BlockingLoader::new(cached::Loader::new(load_fn.clone()), FuturesExecutor);
+
+    let handles = (1..=4)
+        .map(|i| {
+            let loader = loader.clone();
+            thread::spawn(move || loader.load_many_blocking(vec![i, i + 1]))
+        })
+        .collect::<Vec<_>>();
+    for (i, handle) in (1..=4).zip(handles) {
+        let values = handle.join().unwrap();
+        assert_eq!(values, HashMap::from([(i, i * 10), (i + 1, i * 10 + 10)]));
+    }
+    assert_eq!(loader.load_blocking(3), 30);
+    // every key is loaded once
+    assert_eq!(*load_fn.keys.lock().unwrap(), 5);
+    assert_eq!(
+        loader.try_load_blocking(0),
+        Err(LoadError::NotFound("<key>".to_owned()))
+    );
+}
+
+#[cfg(feature = "runtime-futures")]
+#[test]
+fn test_blocking_non_cached_loader() {
+    let loader = BlockingLoader::new(
+        non_cached::Loader::new(CountingLoadFn::default()),
+        FuturesExecutor,
+    );
+    assert_eq!(loader.load_blocking(1), 10);
+    assert_eq!(
+        loader.try_load_many_blocking(vec![1, 2]),
+        Ok(HashMap::from([(1, 10), (2, 20)]))
+    );
+    assert!(loader.try_load_many_blocking(vec![0, 1]).is_err());
+}
+
+#[cfg(feature = "runtime-futures")]
+#[test]
+fn test_blocking_within_executor_fails() {
+    let loader = BlockingLoader::new(
+        cached::Loader::new(CountingLoadFn::default()),
+        FuturesExecutor,
+    );
+    // blocking from a task of the executor would stall it rather than panic
+    let result = futures::executor::block_on(async { loader.try_load_blocking(1) });
+    assert_eq!(result, Err(LoadError::Blocking(BlockingError::InAsyncTask)));
+    assert_eq!(loader.try_load_blocking(1), Ok(10));
+}
+
+#[cfg(feature = "runtime-tokio")]
+#[test]
+fn test_blocking_on_tokio_handle() {
+    assert_eq!(
+        TokioExecutor::current().err(),
+        Some(BlockingError::NoRuntime)
+    );
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let loader = BlockingLoader::new(
+        non_cached::Loader::new(CountingLoadFn::default()),
+        TokioExecutor::new(runtime.handle().clone()),
+    );
+    // a thread outside of the runtime, e.g. a rayon worker, blocks on its handle
+    let other = loader.clone();
+    let result = thread::spawn(move || other.try_load_blocking(1));
+    assert_eq!(result.join().unwrap(), Ok(10));
+    runtime.block_on(async {
+        let blocking = tokio::task::spawn_blocking(move || loader.try_load_blocking(2));
+        assert_eq!(blocking.await.unwrap(), Ok(20));
+    });
+}
+
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_blocking_on_async_std() {
+    let loader = BlockingLoader::new(
+        non_cached::Loader::new(CountingLoadFn::default()),
+        AsyncStdExecutor,
+    );
+    let other = loader.clone();
+    let result = thread::spawn(move || other.try_load_blocking(1));
+    assert_eq!(result.join().unwrap(), Ok(10));
+    let result = async_std::task::block_on(async { loader.try_load_blocking(2) });
+    assert_eq!(result, Err(LoadError::Blocking(BlockingError::InAsyncTask)));
+}
@@ -0,0 +1,82 @@
+use dataloader::builder::{LoaderBuilder, Preset};
+use dataloader::{cached, non_cached, BatchFn, BuildError};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_build() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = non_cached::Loader::builder(load_fn.clone())
+        .max_batch_size(2)
+        .build()
+        .unwrap();
+    assert_eq!(loader.max_batch_size(), 2);
+    let values = block_on(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(values.len(), 3);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_build_cached() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = cached::Loader::builder(load_fn.clone())
+        .preset(Preset::LowLatency)
+        .chunk_size(1)
+        .build_cached()
+        .unwrap();
+    assert_eq!(loader.max_batch_size(), 100);
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1], vec![2]]);
+
+    let cache = HashMap::from([(7, 70)]);
+    let loader = LoaderBuilder::new(load_fn)
+        .cache(cache)
+        .build_cached()
+        .unwrap();
+    assert_eq!(block_on(loader.load(7)), 70);
+}
+
+#[test]
+fn test_build_errors() {
+    let r = LoaderBuilder::new(BatchesLoadFn::default())
+        .max_batch_size(0)
+        .build();
+    assert_eq!(r.err(), Some(BuildError::ZeroMaxBatchSize));
+
+    let r = LoaderBuilder::new(BatchesLoadFn::default())
+        .chunk_size(0)
+        .build_cached();
+    assert_eq!(r.err(), Some(BuildError::ZeroChunkSize));
+
+    let r = LoaderBuilder::new(BatchesLoadFn::default())
+        .preset(Preset::LowLatency)
+        .min_batch_size(500, Duration::from_millis(5))
+        .build();
+    let err = r.err().unwrap();
+    assert_eq!(
+        err,
+        BuildError::MinBatchSizeAboveMax {
+            min_batch_size: 500,
+            max_batch_size: 100
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "min batch size 500 exceeds max batch size 100"
+    );
+}
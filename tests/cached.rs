@@ -1,9 +1,25 @@
-use dataloader::cached::Loader;
-use dataloader::BatchFn;
+#![allow(
+    dead_code,
+    clippy::clone_on_copy,
+    clippy::let_unit_value,
+    clippy::await_holding_lock
+)]
+
+use dataloader::cached::{ArcLoader, Loader, Provenance, Source, Update};
+use dataloader::{
+    ArcBatchFn, Backpressure, BatchFn, BatchPlanner, ChunkPolicy, ConsistencyMode, ErrorCaching,
+    LoadError, MissingKeyAction, MissingKeyPolicy, ResultPolicy, Runtime, RuntimeFuture,
+    SendBatchFn, Sendable,
+};
 use futures::executor::block_on;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
-use std::future::ready;
+use std::future::{ready, Future};
+use std::hash::BuildHasher;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{panic, thread};
 
 struct MyLoadFn;
@@ -85,6 +101,20 @@ impl BatchFn<usize, usize> for LoadFnForEmptyTest {
     }
 }
 
+#[derive(Clone)]
+struct LoadFnWithExtraKey {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithExtraKey {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        *self.calls.lock().unwrap() += 1;
+        let mut ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
+        ret.insert(0, 0);
+        ready(ret).await
+    }
+}
+
 #[test]
 fn test_load() {
     let mut i = 0;
@@ -163,7 +193,9 @@ fn test_load() {
 #[should_panic(expected = "could not lookup result for given key: 1337")]
 fn test_load_unresolved_key() {
     let load_fn = LoadFnForEmptyTest;
-    let loader = Loader::new(load_fn.clone()).with_max_batch_size(4);
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(4)
+        .with_debug_keys();
 
     let h1 = thread::spawn(move || {
         let r1 = loader.load(1337);
@@ -250,3 +282,1296 @@ fn test_load_many() {
         );
     }
 }
+
+#[test]
+fn test_result_policy_accept_caches_unrequested_keys() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load(0)), 0);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_result_policy_ignore_drops_unrequested_keys() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_result_policy(ResultPolicy::Ignore);
+
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load(0)), 0);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_result_policy_reject_fails_batch() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn).with_result_policy(ResultPolicy::Reject);
+
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(2),
+    ));
+    assert_eq!(r1, Err(LoadError::UnrequestedKeys { count: 1 }));
+    assert_eq!(r2, Err(LoadError::UnrequestedKeys { count: 1 }));
+}
+
+#[test]
+fn test_result_policy_exact_detects_missing_values() {
+    let loader = Loader::new(LoadFnForEmptyTest).with_result_policy(ResultPolicy::Exact);
+
+    let r = block_on(loader.try_load_many(vec![1, 2]));
+    assert_eq!(
+        r,
+        Err(LoadError::UnequalKeyValueSize {
+            key_count: 2,
+            value_count: 0
+        })
+    );
+}
+
+#[derive(Clone)]
+struct FlakyLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, Result<usize, String>> for FlakyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Result<usize, String>> {
+        let ret = {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            let ok = *calls > 1;
+            keys.iter()
+                .map(|k| (*k, if ok { Ok(*k) } else { Err("down".to_owned()) }))
+                .collect::<HashMap<_, _>>()
+        };
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_error_refresh_on_dispatch() {
+    let load_fn = FlakyLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+
+    let load_fn = FlakyLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_error_refresh_on_dispatch(true);
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_error_caching() {
+    let load_fn = FlakyLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_error_caching(ErrorCaching::Never);
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+
+    let load_fn = FlakyLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let ttl = Duration::from_millis(20);
+    let loader = Loader::new(load_fn.clone()).with_error_caching(ErrorCaching::Ttl(ttl));
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(block_on(loader.load(1)), Err("down".to_owned()));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+    thread::sleep(ttl);
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(block_on(loader.load(1)), Ok(1));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+static KEY_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct CountedKey(usize);
+
+impl Clone for CountedKey {
+    fn clone(&self) -> Self {
+        KEY_CLONES.fetch_add(1, Ordering::SeqCst);
+        CountedKey(self.0)
+    }
+}
+
+struct CountedKeyLoadFn;
+
+impl BatchFn<CountedKey, usize> for CountedKeyLoadFn {
+    async fn load(&mut self, keys: &[CountedKey]) -> HashMap<CountedKey, usize> {
+        let ret = keys.iter().map(|k| (CountedKey(k.0), k.0)).collect();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_key_cloned_once_queued_and_once_per_batch() {
+    let loader = Loader::new(CountedKeyLoadFn);
+
+    let keys = vec![CountedKey(7), CountedKey(7), CountedKey(7)];
+    let ret = block_on(loader.load_many(keys));
+    assert_eq!(ret.len(), 1);
+    assert_eq!(KEY_CLONES.load(Ordering::SeqCst), 2);
+
+    // cached keys are not cloned at all
+    assert_eq!(block_on(loader.load(CountedKey(7))), 7);
+    assert_eq!(KEY_CLONES.load(Ordering::SeqCst), 2);
+}
+
+type Probe = Arc<Mutex<Option<Box<dyn Fn() -> bool + Send>>>>;
+
+#[derive(Clone)]
+struct ProbeLoadFn {
+    probe: Probe,
+    observed: Arc<Mutex<Vec<bool>>>,
+}
+
+impl BatchFn<usize, usize> for ProbeLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let observed = self.probe.lock().unwrap().as_ref().map(|probe| probe());
+        self.observed.lock().unwrap().extend(observed);
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_pending_len_and_is_loading() {
+    let load_fn = ProbeLoadFn {
+        probe: Arc::new(Mutex::new(None)),
+        observed: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    let l = loader.clone();
+    *load_fn.probe.lock().unwrap() = Some(Box::new(move || l.is_loading()));
+
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        let mut f2 = Box::pin(loader.load(2));
+        assert!(futures::poll!(f1.as_mut()).is_pending());
+        assert!(futures::poll!(f2.as_mut()).is_pending());
+        assert_eq!(loader.pending_len().await, 2);
+        assert!(!loader.is_loading());
+        assert_eq!(futures::future::join(f1, f2).await, (1, 2));
+        assert_eq!(loader.pending_len().await, 0);
+    });
+    assert!(!loader.is_loading());
+    assert_eq!(*load_fn.observed.lock().unwrap(), vec![true]);
+}
+
+#[derive(Clone)]
+struct OddOnlyLoadFn;
+
+impl BatchFn<usize, usize> for OddOnlyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(
+            keys.iter()
+                .filter(|k| *k % 2 == 1)
+                .map(|k| (*k, *k))
+                .collect(),
+        )
+        .await
+    }
+}
+
+#[test]
+fn test_load_results_returns_partial_results() {
+    let loader = Loader::new(OddOnlyLoadFn)
+        .with_max_batch_size(2)
+        .with_debug_keys();
+
+    let ret = block_on(loader.load_results(vec![1, 2, 3]));
+    assert_eq!(ret.len(), 3);
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Err(LoadError::NotFound("2".to_owned())));
+    assert_eq!(ret[&3], Ok(3));
+    assert!(block_on(loader.try_load_many(vec![1, 2, 3])).is_err());
+}
+
+#[test]
+fn test_max_wait_rounds_under_contention() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(50)
+        .with_max_wait_rounds(4)
+        .with_yield_count(1);
+    let handles = (0..8)
+        .map(|_| {
+            let loader = loader.clone();
+            thread::spawn(move || {
+                // every thread requests the same keys, which must be loaded only once
+                let keys = (0..200).collect::<Vec<usize>>();
+                let loads = keys.iter().map(|k| loader.load(*k));
+                assert_eq!(block_on(futures::future::join_all(loads)), keys);
+            })
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert_eq!(load_fn.loaded_keys.lock().unwrap().len(), 200);
+    assert!(*load_fn.max_batch_loaded.lock().unwrap() <= 50);
+}
+
+#[cfg(feature = "runtime-async-std")]
+#[derive(Clone)]
+struct HangingOnceLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+#[cfg(feature = "runtime-async-std")]
+impl BatchFn<usize, usize> for HangingOnceLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let calls = {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            *calls
+        };
+        if calls == 1 {
+            futures::future::pending::<()>().await;
+        }
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+// the timer of the tokio runtime is only available within a tokio runtime
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_load_timeout() {
+    let load_fn = HangingOnceLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_load_timeout(std::time::Duration::from_millis(20))
+        .with_runtime(dataloader::AsyncStdRuntime);
+
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(2),
+    ));
+    assert_eq!(r1, Err(LoadError::Timeout));
+    assert_eq!(r2, Err(LoadError::Timeout));
+
+    // the keys were not cached and are loaded again
+    assert_eq!(block_on(loader.try_load(1)), Ok(1));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_dropped_load_withdraws_key() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        let mut f2 = Box::pin(loader.load(2));
+        let mut f3 = Box::pin(loader.load(2));
+        assert!(futures::poll!(f1.as_mut()).is_pending());
+        assert!(futures::poll!(f2.as_mut()).is_pending());
+        assert!(futures::poll!(f3.as_mut()).is_pending());
+        drop(f1);
+        // 2 is still awaited by f3
+        drop(f2);
+        assert_eq!(loader.pending_len().await, 1);
+        assert_eq!(f3.await, 2);
+    });
+    let loaded_keys = load_fn.loaded_keys.lock().unwrap();
+    assert_eq!(*loaded_keys, HashSet::from([2]));
+}
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        self.batches.lock().unwrap().push(keys.clone());
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_cache_partitioned_by_principal() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    let alice = loader.for_principal("alice");
+    let bob = loader.for_principal("bob");
+
+    let loads = futures::future::join3(alice.load(1), bob.load(1), bob.load(2));
+    assert_eq!(block_on(loads), (1, 1, 2));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+
+    // cached for the principals which requested the keys only
+    assert_eq!(block_on(bob.load(1)), 1);
+    assert_eq!(block_on(alice.load(2)), 2);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![2], vec![1]]
+    );
+
+    block_on(alice.prime(3, 30));
+    assert_eq!(block_on(alice.load(3)), 30);
+    assert_eq!(block_on(bob.load(3)), 3);
+
+    // clearing without a principal clears the caches of all principals
+    block_on(loader.clear(1));
+    block_on(futures::future::join(alice.load(1), bob.load(1)));
+    assert_eq!(load_fn.batches.lock().unwrap().last(), Some(&vec![1]));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 5);
+}
+
+#[test]
+fn test_load_fresh() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime(1, 10));
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert!(load_fn.batches.lock().unwrap().is_empty());
+
+    let loads = futures::future::join3(loader.load_fresh(1), loader.load(1), loader.load(2));
+    assert_eq!(block_on(loads), (1, 1, 2));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_apply_update() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    let alice = loader.for_principal("alice");
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(block_on(alice.load(1)), 1);
+
+    block_on(loader.apply_update(1, Update::Upsert(10)));
+    block_on(loader.apply_update(2, Update::Delete));
+    block_on(loader.apply_update(3, Update::Upsert(30)));
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(block_on(alice.load(1)), 10);
+    assert_eq!(block_on(loader.load(3)), 30);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+
+    // deleted keys are loaded again, updates of keys a principal never loaded are not shared
+    assert_eq!(block_on(loader.load(2)), 2);
+    assert_eq!(block_on(alice.load(3)), 3);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![1], vec![2], vec![3]]
+    );
+}
+
+#[test]
+fn test_max_batches_per_window() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4, 5, 6]), loader.load(7));
+    let (values, value) = block_on(loads);
+    assert_eq!(values.len(), 6);
+    assert_eq!(value, 7);
+    // keys rolled over to later windows are batched before keys queued after them
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7]]
+    );
+}
+
+impl BatchPlanner<usize> for BatchesLoadFn {
+    fn plan(&mut self, keys: Vec<usize>) -> Vec<Vec<usize>> {
+        // odd keys, then even keys but 0, and a key which was not requested
+        let (odd, even): (Vec<_>, Vec<_>) = keys.into_iter().partition(|k| k % 2 == 1);
+        vec![
+            odd,
+            even.into_iter().filter(|k| *k != 0).collect(),
+            vec![100],
+        ]
+    }
+}
+
+#[test]
+fn test_batch_planner() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_batch_planner();
+
+    let values = block_on(loader.load_many(vec![0, 1, 2, 3, 4]));
+    assert_eq!(values.len(), 5);
+    // the key left out of the plan is loaded last, the unrequested key is ignored
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 3], vec![2, 4], vec![0]]
+    );
+}
+
+#[test]
+fn test_key_cost() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(4)
+        .with_key_cost(|k: &usize| *k);
+
+    let values = block_on(loader.load_many(vec![1, 2, 3, 5, 1]));
+    assert_eq!(values.len(), 4);
+    // batches cost up to 4, and the key costing 5 is loaded on its own
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3], vec![5]]
+    );
+}
+
+#[test]
+fn test_chunk_policy_round_robin() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1)
+        .with_chunk_policy(ChunkPolicy::RoundRobin);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4, 5, 6]), loader.load(7));
+    let (values, value) = block_on(loads);
+    assert_eq!(values.len(), 6);
+    assert_eq!(value, 7);
+    // the single load joins the next batch rather than waiting for the rest of the load_many
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3, 7], vec![4, 5], vec![6]]
+    );
+}
+
+#[test]
+fn test_refresh_many() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime_many(vec![(1, 10), (2, 20), (3, 30)]));
+
+    let refreshed = block_on(loader.refresh_many(vec![1, 2]));
+    assert_eq!(refreshed, HashMap::from([(1, 1), (2, 2)]));
+    assert_eq!(block_on(loader.refresh(3)), 3);
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3])).len(), 3);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+}
+
+#[test]
+fn test_weak_loader() {
+    let loader = Loader::new(MyLoadFn);
+    let weak = loader.downgrade();
+    block_on(loader.prime(1, 10));
+    let upgraded = weak.upgrade().expect("loader alive");
+    assert_eq!(block_on(upgraded.load(1)), 10);
+
+    drop(upgraded);
+    drop(loader);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn test_group_by() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_group_by(|k| k % 2);
+
+    let values = block_on(loader.load_many(vec![1, 2, 3, 4, 5]));
+    assert_eq!(values.len(), 5);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(batches, vec![vec![1, 3, 5], vec![2, 4]]);
+}
+
+#[test]
+fn test_chunk_size() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_group_by(|k| k % 2)
+        .with_chunk_size(2);
+
+    let values = block_on(loader.load_many(vec![1, 2, 3, 4, 5]));
+    assert_eq!(values.len(), 5);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(batches, vec![vec![1, 3], vec![2, 4], vec![5]]);
+}
+
+/// Records the most batches running at once, each yielding once while running.
+#[derive(Clone, Default)]
+struct ConcurrentLoadFn {
+    running: Arc<Mutex<(usize, usize)>>,
+}
+
+impl BatchFn<usize, usize> for ConcurrentLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        {
+            let mut running = self.running.lock().unwrap();
+            running.0 += 1;
+            running.1 = running.1.max(running.0);
+        }
+        let mut yielded = false;
+        std::future::poll_fn(|cx| {
+            if yielded {
+                return std::task::Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        })
+        .await;
+        self.running.lock().unwrap().0 -= 1;
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_max_concurrent_batches() {
+    let load_fn = ConcurrentLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3, 4])).len(), 4);
+    assert_eq!(load_fn.running.lock().unwrap().1, 1);
+
+    let load_fn = ConcurrentLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_concurrent_batches(2);
+    let values = block_on(loader.load_many(vec![1, 2, 3, 4, 5, 6]));
+    assert_eq!(values, (1..=6).map(|k| (k, k)).collect::<HashMap<_, _>>());
+    assert_eq!(load_fn.running.lock().unwrap().1, 2);
+
+    fn assert_send<T: Send>(_: T) {}
+    assert_send(loader.load(1));
+
+    // configuring a clone leaves the original loader as it is
+    let load_fn = ConcurrentLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+    let concurrent = loader.clone().with_max_concurrent_batches(2);
+    assert_eq!(block_on(concurrent.load_many(vec![1, 2, 3, 4])).len(), 4);
+    assert_eq!(load_fn.running.lock().unwrap().1, 2);
+    *load_fn.running.lock().unwrap() = (0, 0);
+    assert_eq!(block_on(loader.load_many(vec![5, 6, 7, 8])).len(), 4);
+    assert_eq!(load_fn.running.lock().unwrap().1, 1);
+}
+
+#[test]
+fn test_consistency_modes() {
+    for mode in [ConsistencyMode::ReadYourWrites, ConsistencyMode::Eventual] {
+        let loader = Loader::new(MyLoadFn).with_consistency(mode);
+        assert_eq!(block_on(loader.load(1)), 1);
+        block_on(loader.prime(1, 10));
+        assert_eq!(block_on(loader.load(1)), 10);
+    }
+
+    // cached values only change once cleared or reloaded
+    let loader = Loader::new(MyLoadFn).with_consistency(ConsistencyMode::Snapshot);
+    assert_eq!(block_on(loader.load(1)), 1);
+    block_on(loader.prime(1, 10));
+    block_on(loader.apply_update(1, Update::Upsert(10)));
+    assert_eq!(block_on(loader.load(1)), 1);
+    block_on(loader.clear(1));
+    block_on(loader.prime(1, 10));
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(block_on(loader.load_fresh(1)), 1);
+    block_on(loader.apply_update(1, Update::Delete));
+    block_on(loader.prime(1, 20));
+    assert_eq!(block_on(loader.load(1)), 20);
+}
+
+/// Counts single and bulk accesses, like a remote cache where each one is a round trip.
+#[derive(Default)]
+struct RoundTripCache {
+    values: HashMap<usize, usize>,
+    round_trips: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl dataloader::cached::Cache for RoundTripCache {
+    type Key = usize;
+    type Val = usize;
+
+    fn get(&mut self, key: &usize) -> Option<&usize> {
+        self.round_trips.lock().unwrap().push("get");
+        self.values.get(key)
+    }
+
+    fn insert(&mut self, key: usize, val: usize) {
+        self.round_trips.lock().unwrap().push("insert");
+        self.values.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &usize) -> Option<usize> {
+        self.values.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    fn get_many(&mut self, keys: &[usize]) -> Vec<Option<usize>> {
+        self.round_trips.lock().unwrap().push("get_many");
+        keys.iter().map(|k| self.values.get(k).copied()).collect()
+    }
+
+    fn insert_many(&mut self, entries: Vec<(usize, usize)>) {
+        self.round_trips.lock().unwrap().push("insert_many");
+        self.values.extend(entries);
+    }
+}
+
+#[test]
+fn test_cache_get_many() {
+    let cache = RoundTripCache::default();
+    let round_trips = cache.round_trips.clone();
+    let loader = Loader::with_cache(MyLoadFn, cache);
+
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3])).len(), 3);
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3, 4])).len(), 4);
+    // one round trip each to look up the keys, to write the batch and to read its results
+    let load_many = ["get_many", "insert_many", "get_many"];
+    assert_eq!(*round_trips.lock().unwrap(), load_many.repeat(2));
+}
+
+#[test]
+fn test_prefetch() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(3);
+    block_on(loader.prime(1, 10));
+
+    // prefetched keys join the batch of the next load
+    block_on(loader.prefetch(vec![1, 2, 3]));
+    assert!(load_fn.batches.lock().unwrap().is_empty());
+    assert_eq!(block_on(loader.load(4)), 4);
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3])).len(), 3);
+    let batches = load_fn.batches.lock().unwrap().clone();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(
+        batches[0].iter().copied().collect::<HashSet<_>>(),
+        HashSet::from([2, 3, 4])
+    );
+
+    // a full batch is dispatched right away
+    block_on(loader.prefetch_one(5));
+    block_on(loader.prefetch(vec![6, 7]));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+    assert_eq!(block_on(loader.load_many(vec![5, 6, 7])).len(), 3);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_batches_keys_in_request_order() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(3);
+
+    block_on(loader.prefetch_one(9));
+    block_on(loader.prefetch_one(2));
+    assert_eq!(block_on(loader.load_many(vec![5, 1, 7, 4])).len(), 4);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![2, 5, 9], vec![1, 4, 7]]
+    );
+}
+
+#[derive(Clone, Default)]
+struct EmailLoadFn {
+    batches: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl BatchFn<String, usize> for EmailLoadFn {
+    async fn load(&mut self, keys: &[String]) -> HashMap<String, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (k.clone(), k.len())).collect()
+    }
+}
+
+#[test]
+fn test_key_normalizer() {
+    let load_fn = EmailLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_key_normalizer(|k: String| k.to_lowercase());
+
+    let keys = vec!["Foo@x.com".to_owned(), "foo@x.com".to_owned()];
+    let values = block_on(loader.load_many(keys));
+    assert_eq!(values, HashMap::from([("foo@x.com".to_owned(), 9)]));
+    // served from the cache entry of the normalized key
+    assert_eq!(block_on(loader.load("FOO@X.COM".to_owned())), 9);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec!["foo@x.com".to_owned()]]
+    );
+}
+
+#[test]
+fn test_load_many_ref() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime(1, 10));
+
+    let keys = vec![1, 2, 3];
+    let values = block_on(loader.load_many_ref(&keys));
+    assert_eq!(values, HashMap::from([(&1, 10), (&2, 2), (&3, 3)]));
+    // only the keys missing from the cache are loaded
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2, 3]]);
+
+    let values = block_on(loader.load_many_ref(keys.iter().take(2)));
+    assert_eq!(values, HashMap::from([(&1, 10), (&2, 2)]));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+/// Counts the hashers it builds.
+#[derive(Clone, Default)]
+struct CountingHasher(Arc<AtomicUsize>);
+
+impl BuildHasher for CountingHasher {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        DefaultHasher::new()
+    }
+}
+
+#[test]
+fn test_with_hasher() {
+    let hasher = CountingHasher::default();
+    let loader = Loader::with_hasher(MyLoadFn, hasher.clone());
+
+    let values = block_on(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(values, HashMap::from([(1, 1), (2, 2), (3, 3)]));
+    assert_eq!(block_on(loader.load(2)), 2);
+    assert!(hasher.0.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn test_arc_loader() {
+    let loader = ArcLoader::new(ArcBatchFn(MyLoadFn));
+    let v1: Arc<usize> = block_on(loader.load(1));
+    let v2 = block_on(loader.load(1));
+    assert_eq!(*v1, 1);
+    // hits share the cached value
+    assert!(Arc::ptr_eq(&v1, &v2));
+}
+
+#[test]
+fn test_get_cached() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.get_cached(1)), None);
+    assert!(!block_on(loader.contains(1)));
+    assert!(block_on(loader.cached_keys()).is_empty());
+    // peeking doesn't load
+    assert_eq!(*load_fn.calls.lock().unwrap(), 0);
+
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.get_cached(1)), Some(1));
+    assert!(block_on(loader.contains(0)));
+    let mut keys = block_on(loader.cached_keys());
+    keys.sort();
+    assert_eq!(keys, vec![0, 1]);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_entry() {
+    let loader = Loader::new(MyLoadFn);
+    let inserted = Arc::new(Mutex::new(Vec::new()));
+
+    let record = inserted.clone();
+    let entry = loader
+        .entry(1)
+        .on_insert(move |k, v| record.lock().unwrap().push((*k, *v)));
+    assert_eq!(block_on(entry.get()), None);
+    assert_eq!(block_on(entry.or_load()), (1, Source::Batch));
+    assert_eq!(*inserted.lock().unwrap(), vec![(1, 1)]);
+
+    let record = inserted.clone();
+    let entry = loader
+        .entry(1)
+        .on_insert(move |k, v| record.lock().unwrap().push((*k, *v)));
+    assert_eq!(block_on(entry.or_load()), (1, Source::Cache));
+    // hits don't run the closure
+    assert_eq!(inserted.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_prime_with_ttl() {
+    let loader = Loader::new(MyLoadFn);
+    block_on(loader.prime_with_ttl(1, 10, Duration::from_millis(20)));
+    block_on(loader.prime(2, 20));
+    assert_eq!(block_on(loader.load(1)), 10);
+
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(block_on(loader.get_cached(1)), None);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load(2)), 20);
+}
+
+impl SendBatchFn<usize, usize> for MyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().map(|v| (*v, *v)).collect()
+    }
+}
+
+/// Runs each spawned task to completion on a thread of its own.
+struct ThreadRuntime;
+
+impl Runtime for ThreadRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(async {})
+    }
+
+    fn sleep(&self, _: Duration) -> Option<RuntimeFuture> {
+        None
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        thread::spawn(move || block_on(future));
+    }
+}
+
+#[test]
+fn test_stale_while_revalidate() {
+    let loader = Loader::new(Sendable(MyLoadFn))
+        .with_runtime(ThreadRuntime)
+        .with_stale_while_revalidate(Duration::from_secs(60));
+    block_on(loader.prime_with_ttl(1, 10, Duration::from_millis(20)));
+
+    thread::sleep(Duration::from_millis(30));
+    // the stale value is served while a spawned task refreshes it
+    assert_eq!(block_on(loader.load(1)), 10);
+    for _ in 0..500 {
+        if block_on(loader.get_cached(1)).is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(block_on(loader.get_cached(1)), Some(1));
+    assert_eq!(block_on(loader.pending_len()), 0);
+}
+
+#[test]
+fn test_cache_policy() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    // odd values expire right away, even ones are cached for good
+    let loader = Loader::new(load_fn.clone())
+        .with_cache_policy(|v: &usize| (v % 2 == 1).then_some(Duration::ZERO));
+
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+    assert_eq!(block_on(loader.load(2)), 2);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[derive(Clone, Default)]
+struct LoadFnWithCounts {
+    counts: Arc<Mutex<Vec<(usize, usize)>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithCounts {
+    const COUNTS: bool = true;
+
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+
+    async fn load_with_counts(&mut self, keys: &[(usize, usize)]) -> HashMap<usize, usize> {
+        let mut counts = self.counts.lock().unwrap();
+        counts.extend_from_slice(keys);
+        counts.sort();
+        keys.iter().map(|(k, _)| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_load_with_counts() {
+    let load_fn = LoadFnWithCounts::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(async {
+        let (a, b, c) = futures::join!(loader.load(1), loader.load(1), loader.load(2));
+        assert_eq!((a, b, c), (1, 1, 2));
+    });
+    assert_eq!(*load_fn.counts.lock().unwrap(), vec![(1, 2), (2, 1)]);
+}
+
+#[test]
+fn test_max_pending() {
+    let loader = Loader::new(MyLoadFn).with_max_pending(1, Backpressure::Fail);
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(2),
+    ));
+    assert_eq!((r1, r2), (Ok(1), Err(LoadError::QueueFull)));
+    // pending keys can still be joined
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(3),
+        loader.try_load(3),
+    ));
+    assert_eq!((r1, r2), (Ok(3), Ok(3)));
+
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_pending(1, Backpressure::Wait);
+    let ret = block_on(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(ret.len(), 3);
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_debug() {
+    let loader = Loader::new(MyLoadFn).with_name("user_loader");
+    block_on(loader.prime(1, 1));
+    let debug = format!("{:?}", loader);
+    assert!(debug.starts_with("Loader { name: Some(\"user_loader\"), max_batch_size: 200"));
+    assert!(debug.ends_with("cached: Some(1), pending: 0, .. }"));
+}
+
+#[test]
+fn test_existence_filter() {
+    // e.g. a bloom filter of the key space
+    let known: HashSet<usize> = vec![2, 4].into_iter().collect();
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader =
+        Loader::new(load_fn.clone()).with_existence_filter(move |k: &usize| known.contains(k));
+
+    let ret = block_on(loader.load_results(vec![1, 2]));
+    assert_eq!(ret[&1], Err(LoadError::NotFound("<key>".to_owned())));
+    assert_eq!(ret[&2], Ok(2));
+    assert!(block_on(loader.try_load(3)).is_err());
+    assert_eq!(
+        *load_fn.loaded_keys.lock().unwrap(),
+        vec![2].into_iter().collect()
+    );
+
+    let loader = loader.with_missing_key_policy(MissingKeyPolicy::Default(0));
+    assert_eq!(
+        block_on(loader.entry(5).try_or_load()),
+        Ok((0, Source::Filtered))
+    );
+}
+
+/// A replica which only returns key 1 from its second call on, and never returns keys 2 and 3.
+#[derive(Clone, Default)]
+struct LaggingLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for LaggingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut calls = self.calls.lock().unwrap();
+        *calls += 1;
+        let caught_up = *calls > 1;
+        keys.iter()
+            .filter(|k| **k > 3 || (**k == 1 && caught_up))
+            .map(|k| (*k, *k))
+            .collect()
+    }
+}
+
+#[test]
+fn test_missing_key_handler() {
+    let load_fn = LaggingLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_missing_key_handler(|k: &usize| match k {
+        1 => MissingKeyAction::Retry,
+        2 => MissingKeyAction::Default(0),
+        _ => MissingKeyAction::Error,
+    });
+
+    let ret = block_on(loader.load_results(vec![1, 2, 3, 4]));
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Ok(0));
+    assert_eq!(ret[&3], Err(LoadError::NotFound("<key>".to_owned())));
+    assert_eq!(ret[&4], Ok(4));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+    // the default is cached
+    assert_eq!(block_on(loader.load(2)), 0);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_load_many_detailed() {
+    let load_fn = LaggingLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime(4, 4));
+
+    let ret = block_on(loader.load_many_detailed(vec![1, 4, 5]));
+    assert_eq!(ret.values, vec![(4, 4), (5, 5)].into_iter().collect());
+    assert_eq!(ret.provenance[&1], Provenance::Missing);
+    assert_eq!(ret.provenance[&4], Provenance::Hit);
+    assert_eq!(ret.provenance[&5], Provenance::Loaded);
+    assert_eq!((ret.hits(), ret.misses()), (1, 2));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+
+    let ret = block_on(loader.load_many_detailed(vec![4, 5]));
+    assert_eq!((ret.hits(), ret.misses()), (2, 0));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_child() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime(1, 10));
+    let event1 = block_on(loader.child());
+    let event2 = block_on(loader.child());
+
+    // the children batch on their own, serving the values cached by the parent
+    let loads = futures::future::join3(event1.load(1), event1.load(2), event2.load(3));
+    assert_eq!(block_on(loads), (10, 2, 3));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2], vec![3]]);
+
+    // and cache what they load in the parent
+    assert_eq!(block_on(loader.get_cached(2)), Some(2));
+    assert_eq!(block_on(block_on(loader.child()).load(3)), 3);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+
+    block_on(event1.clear(2));
+    assert_eq!(block_on(loader.get_cached(2)), None);
+}
+
+#[test]
+fn test_load2_load3() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load2(1, 2)), (1, 2));
+    assert_eq!(block_on(loader.load3(3, 2, 3)), (3, 2, 3));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+
+    let loader =
+        Loader::new(LaggingLoadFn::default()).with_missing_key_policy(MissingKeyPolicy::Skip);
+    assert_eq!(
+        block_on(loader.try_load2(4, 2)),
+        Err(LoadError::NotFound("<key>".to_owned()))
+    );
+}
+
+#[test]
+fn test_load_group() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_chunk_size(1);
+    block_on(loader.prime(1, 10));
+
+    let group = block_on(loader.load_group(vec![1, 2, 3, 2]));
+    assert_eq!(group, HashMap::from([(1, 1), (2, 2), (3, 3)]));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+    // the values of the group are cached
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_barrier_dispatches_full_batches() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+    let barrier = loader.barrier();
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    drop(barrier);
+    assert_eq!(block_on(loader.load(3)), 3);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+}
+
+/// Returns `key * 10`, signalling when its first batch starts and holding that batch until it
+/// is opened.
+struct GatedFn {
+    started: Option<futures::channel::oneshot::Sender<()>>,
+    gate: Option<futures::channel::oneshot::Receiver<()>>,
+}
+
+impl GatedFn {
+    fn new() -> (
+        Self,
+        futures::channel::oneshot::Receiver<()>,
+        futures::channel::oneshot::Sender<()>,
+    ) {
+        let (started, on_start) = futures::channel::oneshot::channel();
+        let (open, gate) = futures::channel::oneshot::channel();
+        let load_fn = GatedFn {
+            started: Some(started),
+            gate: Some(gate),
+        };
+        (load_fn, on_start, open)
+    }
+}
+
+impl BatchFn<usize, usize> for GatedFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        if let Some(started) = self.started.take() {
+            started.send(()).unwrap();
+        }
+        if let Some(gate) = self.gate.take() {
+            gate.await.unwrap();
+        }
+        keys.iter().map(|k| (*k, k * 10)).collect()
+    }
+}
+
+#[test]
+fn test_writes_during_batch() {
+    let (load_fn, started, open) = GatedFn::new();
+    let loader = Loader::new(load_fn);
+    block_on(async {
+        let load = loader.load_many(vec![1, 2, 3]);
+        let write = async {
+            started.await.unwrap();
+            // the lock is released while the batch function runs
+            loader.prime(1, 100).await;
+            loader.clear(2).await;
+            open.send(()).unwrap();
+        };
+        let (loaded, ()) = futures::join!(load, write);
+        assert_eq!(loaded.get(&1), Some(&100));
+        assert_eq!(loaded.get(&3), Some(&30));
+    });
+    // the results of the batch don't overwrite the prime nor undo the clear
+    assert_eq!(block_on(loader.get_cached(1)), Some(100));
+    assert_eq!(block_on(loader.get_cached(2)), None);
+    assert_eq!(block_on(loader.get_cached(3)), Some(30));
+}
+
+#[test]
+fn test_subscribe() {
+    let (load_fn, started, open) = GatedFn::new();
+    let loader = Loader::new(load_fn);
+    let mut one = block_on(loader.subscribe(1));
+    let mut two = block_on(loader.subscribe(2));
+    block_on(async {
+        let load = loader.load_many(vec![1, 2]);
+        let write = async {
+            started.await.unwrap();
+            loader.prime(1, 100).await;
+            open.send(()).unwrap();
+        };
+        futures::join!(load, write);
+    });
+    // the stale result of the batch is not published after the prime
+    let (primed, update) = one.try_next().unwrap();
+    assert_eq!(update, Update::Upsert(100));
+    assert_eq!(one.try_next(), None);
+    let (loaded, update) = two.try_next().unwrap();
+    assert_eq!(update, Update::Upsert(20));
+    assert!(loaded > primed);
+
+    block_on(loader.apply_update(1, Update::Upsert(101)));
+    block_on(loader.clear(1));
+    block_on(loader.prime(2, 200));
+    let (updated, update) = block_on(one.next()).unwrap();
+    assert_eq!(update, Update::Upsert(101));
+    let (cleared, update) = block_on(one.next()).unwrap();
+    assert_eq!(update, Update::Delete);
+    assert!(primed < updated && updated < cleared);
+    assert_eq!(two.try_next().map(|(_, u)| u), Some(Update::Upsert(200)));
+
+    // subscriptions end once the loader is dropped
+    drop(loader);
+    assert_eq!(block_on(one.next()), None);
+}
+
+#[test]
+fn test_apply_update_during_batch() {
+    let (load_fn, started, open) = GatedFn::new();
+    let loader = Loader::new(load_fn);
+    block_on(async {
+        let deleted = loader.try_load(1);
+        let upserted = loader.try_load(2);
+        let loaded = loader.try_load(3);
+        let update = async {
+            started.await.unwrap();
+            loader.apply_update(1, Update::Delete).await;
+            loader.apply_update(2, Update::Upsert(200)).await;
+            open.send(()).unwrap();
+        };
+        let (deleted, upserted, loaded, ()) = futures::join!(deleted, upserted, loaded, update);
+        // the callers waiting for the batch don't get the values it fetched before the updates
+        assert!(matches!(deleted, Err(LoadError::NotFound(_))));
+        assert_eq!(upserted, Ok(200));
+        assert_eq!(loaded, Ok(30));
+    });
+    assert_eq!(block_on(loader.get_cached(1)), None);
+    assert_eq!(block_on(loader.get_cached(2)), Some(200));
+}
+
+#[test]
+fn test_consistency_modes_during_batch() {
+    // primes 1 while the batch loading it is in flight, returning what the load and the cache
+    // hold afterwards
+    let prime_during_batch = |mode| {
+        let (load_fn, started, open) = GatedFn::new();
+        let loader = Loader::new(load_fn).with_consistency(mode);
+        let loaded = block_on(async {
+            let load = loader.load(1);
+            let prime = async {
+                started.await.unwrap();
+                loader.prime(1, 100).await;
+                open.send(()).unwrap();
+            };
+            futures::join!(load, prime).0
+        });
+        (loaded, block_on(loader.get_cached(1)), loader)
+    };
+
+    let (loaded, cached, _) = prime_during_batch(ConsistencyMode::ReadYourWrites);
+    assert_eq!((loaded, cached), (100, Some(100)));
+
+    // the last write wins, which is the result of the batch
+    let (loaded, cached, _) = prime_during_batch(ConsistencyMode::Eventual);
+    assert_eq!((loaded, cached), (10, Some(10)));
+
+    // the cached value doesn't change until it is cleared
+    let (loaded, cached, loader) = prime_during_batch(ConsistencyMode::Snapshot);
+    assert_eq!((loaded, cached), (100, Some(100)));
+    block_on(loader.prime(1, 101));
+    assert_eq!(block_on(loader.load(1)), 100);
+    block_on(loader.clear(1));
+    block_on(loader.prime(1, 101));
+    assert_eq!(block_on(loader.load(1)), 101);
+}
+
+#[test]
+fn test_parent_write_during_child_batch() {
+    let (load_fn, started, open) = GatedFn::new();
+    let loader = Loader::new(load_fn);
+    let child = block_on(loader.child());
+    block_on(async {
+        let load = child.load_many(vec![1, 2]);
+        let write = async {
+            started.await.unwrap();
+            loader.prime(1, 100).await;
+            open.send(()).unwrap();
+        };
+        let (loaded, ()) = futures::join!(load, write);
+        assert_eq!(loaded.get(&1), Some(&10));
+    });
+    // the value the child fetched before the prime doesn't overwrite it in the parent
+    assert_eq!(block_on(loader.get_cached(1)), Some(100));
+    assert_eq!(block_on(loader.get_cached(2)), Some(20));
+}
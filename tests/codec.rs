@@ -0,0 +1,80 @@
+use dataloader::cached::{Cache, CacheEntries, CodecCache, Loader, ValueCodec};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+/// Run-length encodes the bytes of a string, failing to decode an empty encoding.
+struct RunLength;
+
+impl ValueCodec<String> for RunLength {
+    type Encoded = Vec<(u8, u8)>;
+
+    fn encode(&self, val: &String) -> Vec<(u8, u8)> {
+        let mut ret: Vec<(u8, u8)> = Vec::new();
+        for b in val.bytes() {
+            match ret.last_mut() {
+                Some((last, n)) if *last == b && *n < u8::MAX => *n += 1,
+                _ => ret.push((b, 1)),
+            }
+        }
+        ret
+    }
+
+    fn decode(&self, encoded: &Vec<(u8, u8)>) -> Option<String> {
+        if encoded.is_empty() {
+            return None;
+        }
+        let bytes = encoded
+            .iter()
+            .flat_map(|(b, n)| std::iter::repeat_n(*b, *n as usize))
+            .collect();
+        String::from_utf8(bytes).ok()
+    }
+}
+
+#[test]
+fn test_codec_cache() {
+    let mut cache = CodecCache::new(HashMap::new(), RunLength);
+    cache.insert(1, "a".repeat(100));
+    cache.insert(2, String::new());
+    assert_eq!(cache.inner()[&1], vec![(b'a', 100)]);
+    assert_eq!(cache.get(&1), Some(&"a".repeat(100)));
+    // an entry which cannot be decoded is a miss
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get_many(&[1, 3]), vec![Some("a".repeat(100)), None]);
+    assert_eq!(cache.entries(), vec![(1, "a".repeat(100))]);
+    assert_eq!(cache.remove(&1), Some("a".repeat(100)));
+    assert_eq!(cache.len_hint(), Some(1));
+}
+
+#[derive(Clone)]
+struct BlobLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, String> for BlobLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, String> {
+        *self.calls.lock().unwrap() += 1;
+        ready(keys.iter().map(|k| (*k, "x".repeat(*k))).collect()).await
+    }
+}
+
+#[test]
+fn test_loader_with_codec_cache() {
+    let load_fn = BlobLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let encode = |v: &String| RunLength.encode(v);
+    let decode = |encoded: &Vec<(u8, u8)>| RunLength.decode(encoded);
+    let cache = CodecCache::new(HashMap::new(), (encode, decode));
+    let loader = Loader::with_cache(load_fn.clone(), cache);
+
+    assert_eq!(
+        block_on(loader.load_many(vec![10, 20]))[&20],
+        "x".repeat(20)
+    );
+    assert_eq!(block_on(loader.load(10)), "x".repeat(10));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+}
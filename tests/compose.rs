@@ -0,0 +1,101 @@
+use dataloader::cached::Loader;
+use dataloader::compose::Load;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq)]
+struct User {
+    id: usize,
+    name: String,
+}
+
+#[derive(Clone, Default)]
+struct UserLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, User> for UserLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, User> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter()
+            .map(|k| {
+                let name = format!("user {}", k);
+                (*k, User { id: *k, name })
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_map_values_shares_cache() {
+    let load_fn = UserLoadFn::default();
+    let users = Loader::new(load_fn.clone());
+    let names = users.clone().map_values(|user: User| user.name);
+
+    assert_eq!(block_on(users.load(1)).id, 1);
+    let (name, many) = block_on(futures::future::join(
+        names.load(1),
+        names.load_many(vec![2, 3]),
+    ));
+    assert_eq!(name, "user 1");
+    assert_eq!(many[&3], "user 3");
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1], vec![2, 3]]);
+
+    let lengths = names.map_values(|name: String| name.len());
+    assert_eq!(block_on(lengths.try_load(2)), Ok(6));
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Post {
+    id: usize,
+    author: usize,
+}
+
+#[derive(Clone, Default)]
+struct PostLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, Post> for PostLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Post> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter()
+            .map(|k| {
+                (
+                    *k,
+                    Post {
+                        id: *k,
+                        author: k % 2,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_then_load_batches_each_step() {
+    let post_fn = PostLoadFn::default();
+    let user_fn = UserLoadFn::default();
+    let posts = Loader::new(post_fn.clone())
+        .then_load(|post: &Post| post.author, Loader::new(user_fn.clone()));
+
+    let (many, one) = block_on(futures::future::join(
+        posts.load_many(vec![1, 2, 3]),
+        posts.load(4),
+    ));
+    assert_eq!(many[&3].1.id, 1);
+    assert_eq!(
+        one,
+        (Post { id: 4, author: 0 }, block_on(posts.next().load(0)))
+    );
+
+    let mut post_batches = post_fn.batches.lock().unwrap().clone();
+    post_batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(post_batches, vec![vec![1, 2, 3, 4]]);
+    let mut user_batches = user_fn.batches.lock().unwrap().clone();
+    user_batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(user_batches, vec![vec![0, 1]]);
+}
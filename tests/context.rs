@@ -0,0 +1,59 @@
+use dataloader::context::{ContextBatchFn, ContextLoader};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Batch = (String, Vec<usize>);
+
+#[derive(Clone, Default)]
+struct LocaleLoadFn {
+    batches: Arc<Mutex<Vec<Batch>>>,
+}
+
+impl ContextBatchFn<usize, String, String> for LocaleLoadFn {
+    async fn load(&mut self, keys: &[usize], locale: &String) -> HashMap<usize, String> {
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        self.batches.lock().unwrap().push((locale.clone(), sorted));
+        keys.iter()
+            .map(|k| (*k, format!("{} {}", locale, k)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_keys_grouped_by_context() {
+    let load_fn = LocaleLoadFn::default();
+    let loader = ContextLoader::new(load_fn.clone());
+
+    let (en, fr, one) = block_on(futures::future::join3(
+        loader.load_many_with_ctx(vec![1, 2], "en".to_owned()),
+        loader.load_many_with_ctx(vec![1], "fr".to_owned()),
+        loader.load_with_ctx(3, "en".to_owned()),
+    ));
+    assert_eq!(en[&1], "en 1");
+    assert_eq!(fr[&1], "fr 1");
+    assert_eq!(one, "en 3");
+
+    // cached per context
+    assert_eq!(block_on(loader.load_with_ctx(1, "fr".to_owned())), "fr 1");
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(
+        batches,
+        vec![("en".to_owned(), vec![1, 2, 3]), ("fr".to_owned(), vec![1])]
+    );
+}
+
+#[test]
+fn test_loader_config() {
+    let load_fn = LocaleLoadFn::default();
+    let loader =
+        ContextLoader::new(load_fn.clone()).with_loader_config(|l| l.with_max_batch_size(1));
+
+    let values = block_on(loader.try_load_many_with_ctx(vec![1, 2], "en".to_owned()));
+    assert_eq!(values.unwrap().len(), 2);
+    block_on(loader.loader().clear(("en".to_owned(), 1)));
+    block_on(loader.load_with_ctx(1, "en".to_owned()));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 3);
+}
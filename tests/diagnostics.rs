@@ -0,0 +1,75 @@
+#[cfg(feature = "debug-diagnostics")]
+mod diagnostics_tests {
+    use dataloader::cached::Loader;
+    use dataloader::BatchFn;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct CapturingLogger;
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            WARNINGS.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    struct SlowFn;
+
+    impl BatchFn<usize, usize> for SlowFn {
+        async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+            thread::sleep(Duration::from_millis(20));
+            keys.iter().map(|k| (*k, *k)).collect()
+        }
+    }
+
+    #[test]
+    fn test_lock_hold_warning() {
+        log::set_logger(&CapturingLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+
+        // the batch function runs without the lock
+        let loader = Loader::new(SlowFn)
+            .with_name("slow_loader")
+            .with_lock_hold_threshold(Duration::from_millis(10));
+        block_on(loader.load_many(vec![1, 2, 3]));
+        assert!(loader.longest_lock_hold() < Duration::from_millis(10));
+        assert!(WARNINGS.lock().unwrap().is_empty());
+
+        // grouping the keys runs under the lock
+        let slow_group = |k: &usize| {
+            thread::sleep(Duration::from_millis(20));
+            *k
+        };
+        let loader = Loader::new(SlowFn)
+            .with_name("slow_loader")
+            .with_lock_hold_threshold(Duration::from_millis(10))
+            .with_group_by(slow_group);
+        block_on(loader.load_many(vec![1, 2, 3]));
+        assert!(loader.longest_lock_hold() >= Duration::from_millis(60));
+        {
+            let warnings = WARNINGS.lock().unwrap();
+            assert_eq!(warnings.len(), 1);
+            assert!(warnings[0].starts_with("dataloader slow_loader held its state lock for "));
+            assert!(warnings[0].ends_with(" while dispatching 3 keys"));
+        }
+
+        // the threshold is set per loader
+        let loader = Loader::new(SlowFn)
+            .with_name("patient_loader")
+            .with_group_by(slow_group);
+        block_on(loader.load(1));
+        assert!(loader.longest_lock_hold() >= Duration::from_millis(20));
+        assert_eq!(WARNINGS.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,38 @@
+use dataloader::{eager, BatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        self.batches.lock().unwrap().push(keys.clone());
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_eager_dispatch() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = eager::cached(load_fn.clone());
+    let loads = futures::future::join(loader.load(1), loader.load(2));
+    assert_eq!(block_on(loads), (1, 2));
+    assert_eq!(block_on(loader.load_many(vec![3, 4, 1])).len(), 3);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1], vec![2], vec![3, 4]]
+    );
+
+    let load_fn = BatchesLoadFn::default();
+    let loader = eager::non_cached(load_fn.clone());
+    let loads = futures::future::join(loader.load(1), loader.load(1));
+    assert_eq!(block_on(loads), (1, 1));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1], vec![1]]);
+}
@@ -0,0 +1,90 @@
+use dataloader::non_cached::Loader;
+use dataloader::{cached, BatchFn, LoadError, TryBatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct ConnectionReset(io::Error);
+
+impl fmt::Display for ConnectionReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "connection reset")
+    }
+}
+
+impl Error for ConnectionReset {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+struct ResetLoadFn;
+
+impl TryBatchFn<usize, usize> for ResetLoadFn {
+    type Error = ConnectionReset;
+
+    async fn try_load(&mut self, _keys: &[usize]) -> Result<HashMap<usize, usize>, Self::Error> {
+        Err(ConnectionReset(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "peer hung up",
+        )))
+    }
+}
+
+#[test]
+fn test_batch_error_chain() {
+    let loader = Loader::new(ResetLoadFn);
+    let err = block_on(loader.try_load(1)).unwrap_err();
+    assert_eq!(err.to_string(), "batch function failed");
+
+    let mut chain = Vec::new();
+    let mut source = err.source();
+    while let Some(e) = source {
+        chain.push(e.to_string());
+        source = e.source();
+    }
+    assert_eq!(chain, vec!["connection reset", "peer hung up"]);
+
+    match &err {
+        LoadError::Batch(e) => assert!(e.get_ref().downcast_ref::<ConnectionReset>().is_some()),
+        _ => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Other);
+}
+
+/// Panics on the first call.
+#[derive(Clone, Default)]
+struct PanicOnceLoadFn {
+    panicked: Arc<AtomicBool>,
+}
+
+impl BatchFn<usize, usize> for PanicOnceLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        if !self.panicked.swap(true, Ordering::SeqCst) {
+            panic!("db is down");
+        }
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_batch_panic() {
+    let loader = Loader::new(PanicOnceLoadFn::default());
+    let loads = futures::future::join(loader.try_load(1), loader.try_load(2));
+    let (r1, r2) = block_on(loads);
+    let panicked = LoadError::Panicked("db is down".to_owned());
+    assert_eq!(r1, Err(panicked.clone()));
+    assert_eq!(r2, Err(panicked.clone()));
+    assert_eq!(panicked.to_string(), "batch function panicked: db is down");
+    // the loader is still usable
+    assert_eq!(block_on(loader.load(1)), 1);
+
+    let loader = cached::Loader::new(PanicOnceLoadFn::default());
+    assert_eq!(block_on(loader.try_load(1)), Err(panicked));
+    assert_eq!(block_on(loader.load(1)), 1);
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrow)]
+
 use dataloader::cached::Loader;
 use dataloader::BatchFn;
 use futures::executor::block_on;
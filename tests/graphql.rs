@@ -0,0 +1,282 @@
+use dataloader::graphql::FieldKey;
+
+#[test]
+fn test_field_key_normalizes_fields() {
+    let a = FieldKey::new(1, ["name", "id", "name"], None);
+    let b = FieldKey::new(1, vec!["id".to_owned(), "name".to_owned()], None);
+    assert_eq!(a, b);
+    assert_eq!(a.fields, vec!["id", "name"]);
+}
+
+#[cfg(feature = "async-graphql")]
+mod async_graphql_tests {
+    use async_graphql::dataloader::DataLoader;
+    use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema};
+    use async_graphql::{Request, Value, Variables};
+    use dataloader::cached::Loader;
+    use dataloader::graphql::{
+        lookahead_ids, prefetch_lookahead, AsyncGraphqlBatchFn, AsyncGraphqlLoader, FieldKey,
+        LoaderExt, Locale, LookaheadId,
+    };
+    use dataloader::{BatchFn, SendBatchFn};
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[derive(Clone)]
+    struct UserBatcher {
+        keys: Arc<Mutex<Vec<FieldKey<i32>>>>,
+    }
+
+    impl BatchFn<FieldKey<i32>, User> for UserBatcher {
+        async fn load(&mut self, keys: &[FieldKey<i32>]) -> HashMap<FieldKey<i32>, User> {
+            self.keys.lock().unwrap().extend_from_slice(keys);
+            keys.iter()
+                .map(|k| (k.clone(), User { id: k.id }))
+                .collect()
+        }
+    }
+
+    #[derive(Clone)]
+    struct User {
+        id: i32,
+    }
+
+    #[Object]
+    impl User {
+        async fn id(&self) -> i32 {
+            self.id
+        }
+
+        async fn name(&self) -> String {
+            format!("user {}", self.id)
+        }
+    }
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn user(&self, ctx: &Context<'_>, id: i32) -> User {
+            let loader = ctx.data_unchecked::<Loader<FieldKey<i32>, User, UserBatcher>>();
+            loader.load_from_info(id, ctx).await.unwrap()
+        }
+    }
+
+    #[test]
+    fn test_load_from_info() {
+        let batcher = UserBatcher {
+            keys: Arc::new(Mutex::new(Vec::new())),
+        };
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(Loader::new(batcher.clone()))
+            .data(Locale("en".to_owned()))
+            .finish();
+        let r = block_on(schema.execute("{ a: user(id: 1) { name id } b: user(id: 2) { id } }"));
+        assert!(r.errors.is_empty(), "{:?}", r.errors);
+
+        let mut keys = batcher.keys.lock().unwrap().clone();
+        keys.sort();
+        let en = Some("en".to_owned());
+        assert_eq!(
+            keys,
+            vec![
+                FieldKey::new(1, ["id", "name"], en.clone()),
+                FieldKey::new(2, ["id"], en),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookahead_ids() {
+        let query = "query Users($b: Int, $c: Int = 3) { a: user(id: 1) { name id } ...More }
+            fragment More on Query { b: user(id: $b) { id } c: user(id: $c) { ... on User { id } } }";
+        let request =
+            Request::new(query).variables(Variables::from_json(serde_json::json!({ "b": 2 })));
+        let ids = lookahead_ids(&request, "user", "id");
+        let id = |id: i32, fields: &[&str]| LookaheadId {
+            id: Value::from(id),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        };
+        assert_eq!(
+            ids,
+            vec![id(1, &["name", "id"]), id(2, &["id"]), id(3, &["id"])]
+        );
+        assert!(lookahead_ids(&Request::new("{ user("), "user", "id").is_empty());
+    }
+
+    #[test]
+    fn test_prefetch_lookahead() {
+        let batcher = UserBatcher {
+            keys: Arc::new(Mutex::new(Vec::new())),
+        };
+        let loader = Loader::new(batcher.clone());
+        let request = Request::new("{ a: user(id: 1) { name id } b: user(id: 2) { id } }")
+            .data(Locale("en".to_owned()));
+        block_on(prefetch_lookahead(&loader, &request, "user", "id", |l| {
+            let id = match l.id {
+                Value::Number(n) => n.as_i64()? as i32,
+                _ => return None,
+            };
+            Some(FieldKey::new(id, l.fields, Some("en".to_owned())))
+        }));
+        // queued into the batch the first resolver dispatches
+        assert_eq!(block_on(loader.pending_len()), 2);
+        assert!(batcher.keys.lock().unwrap().is_empty());
+
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(loader)
+            .finish();
+        let r = block_on(schema.execute(request));
+        assert!(r.errors.is_empty(), "{:?}", r.errors);
+        assert_eq!(batcher.keys.lock().unwrap().len(), 2);
+    }
+
+    struct Doubler;
+
+    impl SendBatchFn<i32, i32> for Doubler {
+        async fn load(&mut self, keys: &[i32]) -> HashMap<i32, i32> {
+            keys.iter().map(|k| (*k, k * 2)).collect()
+        }
+    }
+
+    #[test]
+    fn test_batch_fn_as_async_graphql_loader() {
+        let loader = DataLoader::new(AsyncGraphqlLoader::new(Doubler), |f| {
+            thread::spawn(move || block_on(f))
+        });
+        let values = block_on(loader.load_many(vec![1, 2])).unwrap();
+        assert_eq!(values, HashMap::from([(1, 2), (2, 4)]));
+    }
+
+    struct NoZeroLoader;
+
+    impl async_graphql::dataloader::Loader<i32> for NoZeroLoader {
+        type Value = i32;
+        type Error = String;
+
+        async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, i32>, String> {
+            if keys.contains(&0) {
+                return Err("zero".to_owned());
+            }
+            Ok(keys.iter().map(|k| (*k, k * 2)).collect())
+        }
+    }
+
+    #[test]
+    fn test_async_graphql_loader_as_batch_fn() {
+        let loader = Loader::new(AsyncGraphqlBatchFn(NoZeroLoader));
+        assert_eq!(block_on(loader.load(2)), Ok(4));
+        assert_eq!(block_on(loader.load(0)), Err("zero".to_owned()));
+    }
+}
+
+#[cfg(feature = "juniper")]
+mod juniper_tests {
+    use dataloader::cached::Loader;
+    use dataloader::graphql::{FieldKey, LoaderContext, LoaderContextFactory, LoaderExt};
+    use dataloader::BatchFn;
+    use futures::executor::block_on;
+    use juniper::{EmptyMutation, EmptySubscription, Executor, RootNode, Variables};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct UserBatcher {
+        keys: Arc<Mutex<Vec<FieldKey<i32>>>>,
+    }
+
+    impl BatchFn<FieldKey<i32>, User> for UserBatcher {
+        async fn load(&mut self, keys: &[FieldKey<i32>]) -> HashMap<FieldKey<i32>, User> {
+            self.keys.lock().unwrap().extend_from_slice(keys);
+            keys.iter()
+                .map(|k| (k.clone(), User { id: k.id }))
+                .collect()
+        }
+    }
+
+    struct Loaders {
+        users: Loader<FieldKey<i32>, User, UserBatcher>,
+    }
+
+    type Context = LoaderContext<Loaders>;
+
+    #[derive(Clone)]
+    struct User {
+        id: i32,
+    }
+
+    #[juniper::graphql_object(Context = Context, Scalar = juniper::DefaultScalarValue)]
+    impl User {
+        fn id(&self) -> i32 {
+            self.id
+        }
+
+        fn name(&self) -> String {
+            format!("user {}", self.id)
+        }
+    }
+
+    struct Query;
+
+    #[juniper::graphql_object(Context = Context, Scalar = juniper::DefaultScalarValue)]
+    impl Query {
+        async fn user(id: i32, executor: &Executor<'_, '_, Context>) -> User {
+            let loaders = executor.context();
+            loaders
+                .users
+                .load_from_executor(id, executor)
+                .await
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn test_load_from_executor_per_request() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let factory = {
+            let keys = keys.clone();
+            LoaderContextFactory::new(move || Loaders {
+                users: Loader::new(UserBatcher { keys: keys.clone() }),
+            })
+        };
+        let schema = RootNode::new(Query, EmptyMutation::new(), EmptySubscription::new());
+        let query = "{ a: user(id: 1) { name id } b: user(id: 2) { id } }";
+
+        let ctx = factory.context(Some("en".to_owned()));
+        let (_, errors) = block_on(juniper::execute(
+            query,
+            None,
+            &schema,
+            &Variables::new(),
+            &ctx,
+        ))
+        .unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // a new request doesn't see the values cached by the previous one
+        let ctx = factory.context(None);
+        block_on(juniper::execute(
+            query,
+            None,
+            &schema,
+            &Variables::new(),
+            &ctx,
+        ))
+        .unwrap();
+
+        let mut keys = keys.lock().unwrap().clone();
+        keys.sort();
+        let en = Some("en".to_owned());
+        assert_eq!(
+            keys,
+            vec![
+                FieldKey::new(1, ["id", "name"], None),
+                FieldKey::new(1, ["id", "name"], en.clone()),
+                FieldKey::new(2, ["id"], None),
+                FieldKey::new(2, ["id"], en),
+            ]
+        );
+    }
+}
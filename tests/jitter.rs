@@ -0,0 +1,138 @@
+use dataloader::{cached, BatchFn, Jitter, JitterRng, XorShiftRng};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn test_jitter_bounds() {
+    let mut rng = XorShiftRng::seeded(42);
+    let base = Duration::from_millis(100);
+    for _ in 0..1000 {
+        let d = Jitter::Percent(20).apply(base, &mut rng);
+        assert!(d >= Duration::from_millis(80) && d <= Duration::from_millis(120));
+
+        let d = Jitter::Range(Duration::from_millis(5), Duration::from_millis(10))
+            .apply(base, &mut rng);
+        assert!(d >= Duration::from_millis(105) && d <= Duration::from_millis(110));
+    }
+    assert_eq!(Jitter::None.apply(base, &mut rng), base);
+}
+
+#[test]
+fn test_jitter_is_deterministic_with_seeded_rng() {
+    let base = Duration::from_millis(100);
+    let mut a = XorShiftRng::seeded(7);
+    let mut b = XorShiftRng::seeded(7);
+    for _ in 0..10 {
+        assert_eq!(
+            Jitter::Percent(50).apply(base, &mut a),
+            Jitter::Percent(50).apply(base, &mut b)
+        );
+    }
+}
+
+struct IdentityFn;
+
+impl BatchFn<usize, usize> for IdentityFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+/// Counts up from 0, so that every jitter it draws is known in advance.
+struct CountingRng(u64);
+
+impl JitterRng for CountingRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 += 1;
+        self.0 - 1
+    }
+}
+
+#[test]
+fn test_custom_wait_with_injected_jitter() {
+    // records the jittered delays instead of sleeping, as the default runtime has no timer
+    let delays = Arc::new(Mutex::new(Vec::new()));
+    let rng = Mutex::new(CountingRng(0));
+    let wait = {
+        let delays = delays.clone();
+        move || {
+            let jitter = Jitter::Range(Duration::ZERO, Duration::from_nanos(2));
+            let delay = jitter.apply(Duration::from_millis(20), &mut *rng.lock().unwrap());
+            delays.lock().unwrap().push(delay);
+            Box::pin(ready(())) as dataloader::RuntimeFuture
+        }
+    };
+    let loader = cached::Loader::new(IdentityFn).with_custom_wait_for_work(wait);
+
+    let loads = futures::future::join(loader.load(3), loader.load(4));
+    assert_eq!(block_on(loads), (3, 4));
+    assert_eq!(block_on(loader.load(5)), 5);
+    let waits = delays.lock().unwrap().len();
+    assert!(waits >= 2);
+    // the cached key is returned without waiting
+    assert_eq!(block_on(loader.load(3)), 3);
+
+    // every wait drew the next jitter of the injected source
+    let base = Duration::from_millis(20);
+    let expected = (0..waits as u64)
+        .map(|n| base + Duration::from_nanos(n % 3))
+        .collect::<Vec<_>>();
+    assert_eq!(*delays.lock().unwrap(), expected);
+}
+
+// `delay_fn` sleeps on the `DefaultRuntime`, and the timer of the tokio runtime is only available
+// within a tokio runtime
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+mod delay {
+    use dataloader::non_cached::Loader;
+    use dataloader::{cached, delay_fn_with_rng, BatchFn, Jitter, XorShiftRng};
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::future::ready;
+    use std::time::{Duration, Instant};
+
+    struct IdentityFn;
+
+    impl BatchFn<usize, usize> for IdentityFn {
+        async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+            ready(keys.iter().map(|k| (*k, *k)).collect()).await
+        }
+    }
+
+    #[test]
+    fn test_delay_fn() {
+        let wait = delay_fn_with_rng(
+            Duration::from_millis(20),
+            Jitter::Range(Duration::ZERO, Duration::from_millis(5)),
+            XorShiftRng::seeded(1),
+        );
+        let loader = Loader::new(IdentityFn).with_custom_wait_for_work(wait);
+
+        let start = Instant::now();
+        assert_eq!(block_on(loader.load(3)), 3);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_delay_fn_cached() {
+        let wait = delay_fn_with_rng(
+            Duration::from_millis(20),
+            Jitter::None,
+            XorShiftRng::seeded(1),
+        );
+        let loader = cached::Loader::new(IdentityFn).with_custom_wait_for_work(wait);
+
+        let start = Instant::now();
+        let loads = futures::future::join(loader.load(3), loader.load(4));
+        assert_eq!(block_on(loads), (3, 4));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // cached values are returned without waiting
+        let start = Instant::now();
+        assert_eq!(block_on(loader.load(3)), 3);
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+}
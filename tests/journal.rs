@@ -0,0 +1,79 @@
+use dataloader::journal::{Journal, JournalEntry, JournalSink};
+use dataloader::{cached, non_cached, BatchFn, LoadError, TryBatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+struct IdentityFn;
+
+impl BatchFn<usize, usize> for IdentityFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[derive(Clone, Default)]
+struct VecSink {
+    writes: Arc<Mutex<Vec<Vec<JournalEntry>>>>,
+}
+
+impl JournalSink for VecSink {
+    async fn write(&mut self, entries: Vec<JournalEntry>) {
+        self.writes.lock().unwrap().push(entries);
+    }
+}
+
+#[test]
+fn test_journal() {
+    let sink = VecSink::default();
+    let journal = Journal::new("users", sink.clone(), 2)
+        .with_redaction(|k: &usize| format!("user-{}", k))
+        .with_correlation(|| Some("req-1".to_string()));
+    let loader = cached::Loader::new(IdentityFn).with_journal(&journal);
+
+    assert_eq!(block_on(loader.load(1)), 1);
+    // buffered until the capacity is reached
+    assert!(sink.writes.lock().unwrap().is_empty());
+    assert_eq!(block_on(loader.load(2)), 2);
+    // cached, no batch is dispatched
+    assert_eq!(block_on(loader.load(1)), 1);
+
+    let writes = sink.writes.lock().unwrap().clone();
+    assert_eq!(writes.len(), 1);
+    let keys = writes[0].iter().map(|e| e.keys.clone()).collect::<Vec<_>>();
+    assert_eq!(keys, vec![vec!["user-1"], vec!["user-2"]]);
+    assert!(writes[0].iter().all(|e| e.loader == "users"
+        && e.correlation_id.as_deref() == Some("req-1")
+        && e.outcome.is_ok()));
+}
+
+struct FailingFn;
+
+impl TryBatchFn<usize, usize> for FailingFn {
+    type Error = &'static str;
+
+    async fn try_load(&mut self, _keys: &[usize]) -> Result<HashMap<usize, usize>, &'static str> {
+        ready(Err("unavailable")).await
+    }
+}
+
+#[test]
+fn test_journal_records_failed_batches() {
+    let sink = VecSink::default();
+    let journal = Journal::new("failing", sink.clone(), 10);
+    let loader = non_cached::Loader::new(FailingFn).with_journal(&journal);
+
+    assert!(block_on(loader.try_load(1)).is_err());
+    assert!(sink.writes.lock().unwrap().is_empty());
+    block_on(journal.flush());
+
+    let writes = sink.writes.lock().unwrap().clone();
+    assert_eq!(writes.len(), 1);
+    let entry = &writes[0][0];
+    // keys are hashed by default
+    assert_eq!(entry.keys.len(), 1);
+    assert_ne!(entry.keys[0], "1");
+    assert_eq!(entry.correlation_id, None);
+    assert_eq!(entry.outcome, Err(LoadError::Batch("unavailable".into())));
+}
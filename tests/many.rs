@@ -0,0 +1,43 @@
+use dataloader::{cached, non_cached, BatchFn, BatchFnMany, Many};
+use futures::executor::block_on;
+use std::collections::HashMap;
+
+/// Returns the ids of the posts of every author, authors with an odd id having none.
+struct PostsByAuthor;
+
+impl BatchFnMany<usize, usize> for PostsByAuthor {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Vec<usize>> {
+        keys.iter()
+            .filter(|k| *k % 2 == 0)
+            .map(|k| (*k, vec![*k * 10, *k * 10 + 1]))
+            .collect()
+    }
+}
+
+/// Like `PostsByAuthor`, but leaving out the authors without posts as a plain batch function.
+struct PostsByAuthorFn;
+
+impl BatchFn<usize, Vec<usize>> for PostsByAuthorFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Vec<usize>> {
+        PostsByAuthor.load(keys).await
+    }
+}
+
+#[test]
+fn test_many() {
+    let loader = cached::Loader::new(Many(PostsByAuthor));
+    let posts = block_on(loader.load_many(vec![1, 2]));
+    assert_eq!(posts, HashMap::from([(1, vec![]), (2, vec![20, 21])]));
+    assert_eq!(block_on(loader.load_values(2)), vec![20, 21]);
+}
+
+#[test]
+fn test_load_values() {
+    let loader = cached::Loader::new(PostsByAuthorFn);
+    assert_eq!(block_on(loader.load_values(1)), Vec::<usize>::new());
+    assert!(block_on(loader.try_load(1)).is_err());
+
+    let loader = non_cached::Loader::new(PostsByAuthorFn);
+    assert_eq!(block_on(loader.load_values(1)), Vec::<usize>::new());
+    assert_eq!(block_on(loader.try_load_values(4)), Ok(vec![40, 41]));
+}
@@ -0,0 +1,106 @@
+use dataloader::{cached, non_cached, BatchFn, MissingKeyPolicy};
+use futures::executor::block_on;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::future::ready;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Returns values for even keys only.
+struct EvenLoadFn;
+
+impl BatchFn<usize, usize> for EvenLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let ret = keys.iter().filter(|k| *k % 2 == 0).map(|k| (*k, *k));
+        ready(ret.collect()).await
+    }
+}
+
+/// The formatted outcome of a call, `None` if it panicked.
+fn outcome<T: Debug>(call: impl FnOnce() -> T) -> Option<String> {
+    panic::catch_unwind(AssertUnwindSafe(call))
+        .ok()
+        .map(|v| format!("{:?}", v))
+}
+
+fn sorted<V>(values: HashMap<usize, V>) -> BTreeMap<usize, V> {
+    values.into_iter().collect()
+}
+
+/// Outcomes of `load(1)`, `try_load(1)`, `load_many([1, 2])`, `try_load_many([1, 2])` and
+/// `load_results([1, 2])`, where key 1 is missing.
+macro_rules! outcomes {
+    ($loader:expr) => {{
+        let loader = $loader;
+        vec![
+            outcome(|| block_on(loader.load(1))),
+            outcome(|| block_on(loader.try_load(1))),
+            outcome(|| sorted(block_on(loader.load_many(vec![1, 2])))),
+            outcome(|| block_on(loader.try_load_many(vec![1, 2])).map(sorted)),
+            outcome(|| sorted(block_on(loader.load_results(vec![1, 2])))),
+        ]
+    }};
+}
+
+fn expected(policy: &MissingKeyPolicy<usize>) -> Vec<Option<&'static str>> {
+    match policy {
+        MissingKeyPolicy::Error => vec![
+            None,
+            Some(r#"Err(NotFound("1"))"#),
+            None,
+            Some(r#"Err(NotFound("1"))"#),
+            Some(r#"{1: Err(NotFound("1")), 2: Ok(2)}"#),
+        ],
+        MissingKeyPolicy::Default(_) => vec![
+            Some("0"),
+            Some("Ok(0)"),
+            Some("{1: 0, 2: 2}"),
+            Some("Ok({1: 0, 2: 2})"),
+            Some("{1: Ok(0), 2: Ok(2)}"),
+        ],
+        MissingKeyPolicy::Skip => vec![
+            None,
+            Some(r#"Err(NotFound("1"))"#),
+            Some("{2: 2}"),
+            Some("Ok({2: 2})"),
+            Some("{2: Ok(2)}"),
+        ],
+        MissingKeyPolicy::Panic => vec![None; 5],
+    }
+}
+
+fn policies() -> Vec<MissingKeyPolicy<usize>> {
+    vec![
+        MissingKeyPolicy::Error,
+        MissingKeyPolicy::Default(0),
+        MissingKeyPolicy::Skip,
+        MissingKeyPolicy::Panic,
+    ]
+}
+
+#[test]
+fn test_cached_missing_key_policies() {
+    for policy in policies() {
+        let loader = cached::Loader::new(EvenLoadFn)
+            .with_missing_key_policy(policy.clone())
+            .with_debug_keys();
+        let expected = expected(&policy)
+            .into_iter()
+            .map(|o| o.map(String::from))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes!(loader), expected, "{:?}", policy);
+    }
+}
+
+#[test]
+fn test_non_cached_missing_key_policies() {
+    for policy in policies() {
+        let loader = non_cached::Loader::new(EvenLoadFn)
+            .with_missing_key_policy(policy.clone())
+            .with_debug_keys();
+        let expected = expected(&policy)
+            .into_iter()
+            .map(|o| o.map(String::from))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes!(loader), expected, "{:?}", policy);
+    }
+}
@@ -0,0 +1,72 @@
+use dataloader::multi::{MultiBatchFn, MultiLoader};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(u32),
+    Post(u32),
+    Comment(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    User(String),
+    Post(String),
+}
+
+#[test]
+fn test_keys_are_routed() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let (user_calls, post_calls) = (calls.clone(), calls.clone());
+    let load_fn = MultiBatchFn::new()
+        .route(
+            |key| matches!(key, Key::User(_)),
+            move |keys: Vec<Key>| {
+                user_calls.lock().unwrap().push(keys.len());
+                async move {
+                    keys.into_iter()
+                        .filter_map(|key| match key {
+                            Key::User(id) => Some((key, Value::User(format!("user {}", id)))),
+                            _ => None,
+                        })
+                        .collect::<HashMap<_, _>>()
+                }
+            },
+        )
+        .route(
+            |key| matches!(key, Key::Post(_)),
+            move |keys: Vec<Key>| {
+                post_calls.lock().unwrap().push(keys.len());
+                async move {
+                    keys.into_iter()
+                        .filter_map(|key| match key {
+                            Key::Post(id) => Some((key, Value::Post(format!("post {}", id)))),
+                            _ => None,
+                        })
+                        .collect::<HashMap<_, _>>()
+                }
+            },
+        );
+    let loader = MultiLoader::new(load_fn);
+
+    let loads = futures::future::join3(
+        loader.load(Key::User(1)),
+        loader.load(Key::Post(1)),
+        loader.try_load_many(vec![Key::User(2), Key::Comment(1)]),
+    );
+    let (user, post, rest) = block_on(loads);
+    assert_eq!(user, Value::User("user 1".to_string()));
+    assert_eq!(post, Value::Post("post 1".to_string()));
+    assert!(rest.is_err());
+
+    // one batch, one call per route
+    let mut calls = calls.lock().unwrap().clone();
+    calls.sort();
+    assert_eq!(calls, vec![1, 2]);
+    assert_eq!(
+        block_on(loader.load(Key::User(2))),
+        Value::User("user 2".to_string())
+    );
+}
@@ -1,9 +1,21 @@
+#![allow(
+    clippy::clone_on_copy,
+    clippy::let_unit_value,
+    clippy::await_holding_lock
+)]
+
 use dataloader::non_cached::Loader;
-use dataloader::BatchFn;
+use dataloader::{
+    Backpressure, BatchFn, BatchPlanner, ChunkPolicy, LoadError, MissingKeyAction, ResultPolicy,
+};
 use futures::executor::block_on;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::future::ready;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{panic, thread};
 
 struct MyLoadFn;
@@ -75,6 +87,20 @@ impl BatchFn<usize, usize> for LoadFnForEmptyTest {
     }
 }
 
+#[derive(Clone)]
+struct LoadFnWithExtraKey {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithExtraKey {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        *self.calls.lock().unwrap() += 1;
+        let mut ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
+        ret.insert(0, 0);
+        ready(ret).await
+    }
+}
+
 #[test]
 fn test_load() {
     let mut i = 0;
@@ -151,7 +177,9 @@ fn test_load() {
 #[should_panic(expected = "could not lookup result for given key: 1337")]
 fn test_load_unresolved_key() {
     let load_fn = LoadFnForEmptyTest;
-    let loader = Loader::new(load_fn.clone()).with_max_batch_size(4);
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(4)
+        .with_debug_keys();
 
     let h1 = thread::spawn(move || {
         let r1 = loader.load(1337);
@@ -237,3 +265,834 @@ fn test_load_many() {
         );
     }
 }
+
+#[test]
+fn test_result_policy_reject_fails_batch() {
+    let load_fn = LoadFnWithExtraKey {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_result_policy(ResultPolicy::Reject);
+
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(1),
+    ));
+    assert_eq!(r1, Err(LoadError::UnrequestedKeys { count: 1 }));
+    assert_eq!(r2, Err(LoadError::UnrequestedKeys { count: 1 }));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+
+    let loader = loader.with_result_policy(ResultPolicy::Ignore);
+    assert_eq!(block_on(loader.try_load(1)), Ok(1));
+}
+
+#[test]
+fn test_result_policy_exact_detects_missing_values() {
+    let loader = Loader::new(LoadFnForEmptyTest).with_result_policy(ResultPolicy::Exact);
+
+    let r = block_on(loader.try_load_many(vec![1, 2, 2]));
+    assert_eq!(
+        r,
+        Err(LoadError::UnequalKeyValueSize {
+            key_count: 2,
+            value_count: 0
+        })
+    );
+}
+
+static KEY_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct CountedKey(usize);
+
+impl Clone for CountedKey {
+    fn clone(&self) -> Self {
+        KEY_CLONES.fetch_add(1, Ordering::SeqCst);
+        CountedKey(self.0)
+    }
+}
+
+struct CountedKeyLoadFn;
+
+impl BatchFn<CountedKey, usize> for CountedKeyLoadFn {
+    async fn load(&mut self, keys: &[CountedKey]) -> HashMap<CountedKey, usize> {
+        let ret = keys.iter().map(|k| (CountedKey(k.0), k.0)).collect();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_key_cloned_once_per_batch() {
+    let loader = Loader::new(CountedKeyLoadFn);
+
+    let keys = vec![CountedKey(7), CountedKey(7), CountedKey(7)];
+    let ret = block_on(loader.load_many(keys));
+    assert_eq!(ret.len(), 1);
+    assert_eq!(KEY_CLONES.load(Ordering::SeqCst), 1);
+}
+
+type Probe = Arc<Mutex<Option<Box<dyn Fn() -> bool + Send>>>>;
+
+#[derive(Clone)]
+struct ProbeLoadFn {
+    probe: Probe,
+    observed: Arc<Mutex<Vec<bool>>>,
+}
+
+impl BatchFn<usize, usize> for ProbeLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let observed = self.probe.lock().unwrap().as_ref().map(|probe| probe());
+        self.observed.lock().unwrap().extend(observed);
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_pending_len_and_is_loading() {
+    let load_fn = ProbeLoadFn {
+        probe: Arc::new(Mutex::new(None)),
+        observed: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    let l = loader.clone();
+    *load_fn.probe.lock().unwrap() = Some(Box::new(move || l.is_loading()));
+
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        let mut f2 = Box::pin(loader.load(2));
+        assert!(futures::poll!(f1.as_mut()).is_pending());
+        assert!(futures::poll!(f2.as_mut()).is_pending());
+        assert_eq!(loader.pending_len().await, 2);
+        assert!(!loader.is_loading());
+        assert_eq!(futures::future::join(f1, f2).await, (1, 2));
+        assert_eq!(loader.pending_len().await, 0);
+    });
+    assert!(!loader.is_loading());
+    assert_eq!(*load_fn.observed.lock().unwrap(), vec![true]);
+}
+
+#[derive(Clone)]
+struct CountingLoadFn {
+    loaded: Arc<Mutex<Vec<usize>>>,
+}
+
+impl BatchFn<usize, usize> for CountingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.loaded.lock().unwrap().extend(keys);
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_hot_key_cache() {
+    let load_fn = CountingLoadFn {
+        loaded: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_hot_key_cache(2, Duration::from_secs(60));
+
+    for _ in 0..5 {
+        assert_eq!(block_on(loader.load(1)), 1);
+    }
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(block_on(loader.load(2)), 2);
+    // 1 becomes hot on its second request, 2 on its second request
+    assert_eq!(*load_fn.loaded.lock().unwrap(), vec![1, 1, 2, 2]);
+
+    let loader = Loader::new(load_fn.clone());
+    load_fn.loaded.lock().unwrap().clear();
+    for _ in 0..3 {
+        block_on(loader.load(1));
+    }
+    assert_eq!(*load_fn.loaded.lock().unwrap(), vec![1, 1, 1]);
+}
+
+#[derive(Clone)]
+struct OddOnlyLoadFn;
+
+impl BatchFn<usize, usize> for OddOnlyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(
+            keys.iter()
+                .filter(|k| *k % 2 == 1)
+                .map(|k| (*k, *k))
+                .collect(),
+        )
+        .await
+    }
+}
+
+#[test]
+fn test_load_results_returns_partial_results() {
+    let loader = Loader::new(OddOnlyLoadFn)
+        .with_max_batch_size(2)
+        .with_debug_keys();
+
+    let ret = block_on(loader.load_results(vec![1, 2, 3]));
+    assert_eq!(ret.len(), 3);
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Err(LoadError::NotFound("2".to_owned())));
+    assert_eq!(ret[&3], Ok(3));
+    assert!(block_on(loader.try_load_many(vec![1, 2, 3])).is_err());
+}
+
+#[derive(Clone)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+/// Returns `Pending` once, like a yield on a single threaded executor.
+struct YieldOnce(bool);
+
+impl std::future::Future for YieldOnce {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        if self.0 {
+            return std::task::Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
+
+/// Loads keys `0..n`, with each caller arriving one poll after the previous one, so that the
+/// wait of every caller ends before the next one had a chance to queue its key.
+fn load_trickling(loader: &Loader<usize, usize, BatchesLoadFn>, n: usize) -> Vec<usize> {
+    let loads = (0..n).map(|i| async move {
+        for _ in i..n {
+            YieldOnce(false).await;
+        }
+        loader.load(i).await
+    });
+    block_on(futures::future::join_all(loads))
+}
+
+#[test]
+fn test_max_wait_rounds_merges_trickling_callers() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader =
+        Loader::new(load_fn.clone()).with_custom_wait_for_work(|| Box::pin(YieldOnce(false)));
+    assert_eq!(load_trickling(&loader, 8), (0..8).collect::<Vec<_>>());
+    assert!(load_fn.batches.lock().unwrap().len() > 1);
+
+    load_fn.batches.lock().unwrap().clear();
+    let loader = loader.with_max_wait_rounds(16);
+    assert_eq!(load_trickling(&loader, 8), (0..8).collect::<Vec<_>>());
+    let batches = load_fn.batches.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].len(), 8);
+}
+
+#[test]
+fn test_adaptive_wait() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_adaptive_wait(2, 64);
+    assert_eq!(block_on(loader.effective_batch_size()), 0.0);
+    assert_eq!(load_trickling(&loader, 8), (0..8).collect::<Vec<_>>());
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+    assert_eq!(block_on(loader.effective_batch_size()), 8.0);
+
+    // dispatches after `max_yields` even while keys keep arriving
+    load_fn.batches.lock().unwrap().clear();
+    let loader = Loader::new(load_fn.clone()).with_adaptive_wait(2, 2);
+    assert_eq!(load_trickling(&loader, 8), (0..8).collect::<Vec<_>>());
+    assert!(load_fn.batches.lock().unwrap().len() > 1);
+    assert!(block_on(loader.effective_batch_size()) < 8.0);
+}
+
+#[test]
+fn test_min_batch_size() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_custom_wait_for_work(|| Box::pin(YieldOnce(false)))
+        .with_min_batch_size(8, Duration::from_secs(10));
+    assert_eq!(load_trickling(&loader, 8), (0..8).collect::<Vec<_>>());
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+    // small batches are dispatched at the deadline
+    load_fn.batches.lock().unwrap().clear();
+    let loader = loader.with_min_batch_size(100, Duration::from_millis(20));
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_max_wait_rounds_under_contention() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(50)
+        .with_max_wait_rounds(4)
+        .with_yield_count(1);
+    let handles = (0..8)
+        .map(|t| {
+            let loader = loader.clone();
+            thread::spawn(move || {
+                let keys = (0..100).map(|i| t * 100 + i).collect::<Vec<_>>();
+                let loads = keys.iter().map(|k| loader.load(*k));
+                assert_eq!(block_on(futures::future::join_all(loads)), keys);
+            })
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        h.join().unwrap();
+    }
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|b| b.len() <= 50));
+    assert_eq!(batches.iter().map(|b| b.len()).sum::<usize>(), 800);
+}
+
+#[cfg(feature = "runtime-async-std")]
+#[derive(Clone)]
+struct HangingOnceLoadFn {
+    calls: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "runtime-async-std")]
+impl BatchFn<usize, usize> for HangingOnceLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            futures::future::pending::<()>().await;
+        }
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+// the timer of the tokio runtime is only available within a tokio runtime
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_load_timeout() {
+    let load_fn = HangingOnceLoadFn {
+        calls: Arc::new(AtomicUsize::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_load_timeout(Duration::from_millis(20))
+        .with_runtime(dataloader::AsyncStdRuntime);
+
+    let results = block_on(loader.load_results(vec![1, 2]));
+    assert_eq!(results[&1], Err(LoadError::Timeout));
+    assert_eq!(results[&2], Err(LoadError::Timeout));
+
+    assert_eq!(block_on(loader.try_load(1)), Ok(1));
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_idle_dispatch() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_idle_dispatch(Duration::from_millis(50), Duration::from_secs(5))
+        .with_runtime(dataloader::AsyncStdRuntime);
+
+    // the second key is queued while the queue is not idle yet
+    let late = async {
+        async_std::task::sleep(Duration::from_millis(10)).await;
+        loader.load(2).await
+    };
+    let values = block_on(futures::future::join(loader.load(1), late));
+    assert_eq!(values, (1, 2));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+}
+
+#[test]
+fn test_dropped_load_withdraws_request() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        let mut f2 = Box::pin(loader.load_many(vec![2, 3]));
+        assert!(futures::poll!(f1.as_mut()).is_pending());
+        assert!(futures::poll!(f2.as_mut()).is_pending());
+        drop(f2);
+        assert_eq!(loader.pending_len().await, 1);
+        assert_eq!(f1.await, 1);
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+static LIVE_VALUES: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts its live instances in `LIVE_VALUES`.
+struct Tracked;
+
+impl Tracked {
+    fn new() -> Self {
+        LIVE_VALUES.fetch_add(1, Ordering::SeqCst);
+        Tracked
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        Tracked::new()
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        LIVE_VALUES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct TrackedLoadFn;
+
+impl BatchFn<usize, Tracked> for TrackedLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Tracked> {
+        ready(keys.iter().map(|k| (*k, Tracked::new())).collect()).await
+    }
+}
+
+#[test]
+fn test_unclaimed_results_are_dropped() {
+    let loader = Loader::new(TrackedLoadFn).with_max_batch_size(2);
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        assert!(futures::poll!(f1.as_mut()).is_pending());
+        // dispatches the batch of 1 and 2, completing f1 without it being polled again
+        let v2 = loader.load(2).await;
+        assert_eq!(LIVE_VALUES.load(Ordering::SeqCst), 2);
+        drop(f1);
+        drop(v2);
+    });
+    assert_eq!(LIVE_VALUES.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_max_batches_per_window() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4, 5, 6]), loader.load(7));
+    let (values, value) = block_on(loads);
+    assert_eq!(values.len(), 6);
+    assert_eq!(value, 7);
+    // requests rolled over to later windows are batched before requests queued after them
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7]]);
+}
+
+impl BatchPlanner<usize> for BatchesLoadFn {
+    fn plan(&mut self, keys: Vec<usize>) -> Vec<Vec<usize>> {
+        // odd keys, then even keys but 0, and a key which was not requested
+        let (odd, even): (Vec<_>, Vec<_>) = keys.into_iter().partition(|k| k % 2 == 1);
+        vec![
+            odd,
+            even.into_iter().filter(|k| *k != 0).collect(),
+            vec![100],
+        ]
+    }
+}
+
+#[test]
+fn test_batch_planner() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_batch_planner();
+
+    let values = block_on(loader.load_many(vec![0, 1, 2, 3, 4]));
+    assert_eq!(values.len(), 5);
+    // the key left out of the plan is loaded last, the unrequested key is ignored
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 3], vec![2, 4], vec![0]]
+    );
+}
+
+#[test]
+fn test_key_cost() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(4)
+        .with_key_cost(|k: &usize| *k);
+
+    let values = block_on(loader.load_many(vec![1, 2, 3, 5]));
+    assert_eq!(values.len(), 4);
+    // batches cost up to 4, and the key costing 5 is loaded on its own
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3], vec![5]]
+    );
+}
+
+#[test]
+fn test_chunk_policy_round_robin() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1)
+        .with_chunk_policy(ChunkPolicy::RoundRobin);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4, 5, 6]), loader.load(7));
+    let (values, value) = block_on(loads);
+    assert_eq!(values.len(), 6);
+    assert_eq!(value, 7);
+    // the single load joins the next batch rather than waiting for the rest of the load_many
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(batches, vec![vec![1, 2], vec![3, 7], vec![4, 5], vec![6]]);
+}
+
+#[test]
+fn test_weak_loader() {
+    let loader: Loader<usize, usize, _> = Loader::new(MyLoadFn);
+    let weak = loader.downgrade();
+    assert_eq!(block_on(weak.upgrade().expect("loader alive").load(1)), 1);
+    drop(loader);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn test_group_by() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_group_by(|k| k % 2);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4]), loader.load(1));
+    let (values, value) = block_on(loads);
+    assert_eq!(values, HashMap::from([(1, 1), (2, 2), (3, 3), (4, 4)]));
+    assert_eq!(value, 1);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|batch| batch.sort());
+    batches.sort();
+    assert_eq!(batches, vec![vec![1, 3], vec![2, 4]]);
+}
+
+#[test]
+fn test_chunk_size() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_chunk_size(2);
+
+    let loads = futures::future::join(loader.load_many(vec![1, 2, 3, 4, 5]), loader.load(1));
+    let (values, value) = block_on(loads);
+    assert_eq!(values.len(), 5);
+    assert_eq!(value, 1);
+    // a single flush of all keys, loaded in chunks
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3, 4], vec![5]]
+    );
+}
+
+#[test]
+fn test_max_concurrent_batches() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_concurrent_batches(3)
+        .with_group_by(|k| k % 2);
+    let values = block_on(loader.load_many(vec![1, 2, 3, 4]));
+    assert_eq!(values.len(), 4);
+    // two chunks of two requests, each split into a group of odd and even keys
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(batches.len(), 4);
+    assert!(batches.iter().all(|batch| batch.len() == 1));
+
+    // the batch function may be cloned after the loader
+    let loader = Loader::new(load_fn.clone());
+    let values = block_on(
+        loader
+            .clone()
+            .with_max_concurrent_batches(2)
+            .load_many(vec![5, 6]),
+    );
+    assert_eq!(values.len(), 2);
+}
+
+#[test]
+fn test_single_flight() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1);
+    let values = block_on(loader.load_many(vec![1, 2, 1, 3, 1, 2]));
+    assert_eq!(values.len(), 3);
+    // 1 and 2 are loaded again by the third window
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(batches, vec![vec![1, 2], vec![1, 3], vec![1, 2]]);
+
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_max_batches_per_window(1)
+        .with_single_flight(true);
+    let values = block_on(loader.load_many(vec![1, 2, 1, 3, 1, 2]));
+    assert_eq!(values.len(), 3);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|batch| batch.sort());
+    assert_eq!(batches, vec![vec![1, 2], vec![1, 3], vec![2]]);
+}
+
+#[derive(Clone, Default)]
+struct EmailLoadFn {
+    batches: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl BatchFn<String, usize> for EmailLoadFn {
+    async fn load(&mut self, keys: &[String]) -> HashMap<String, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (k.clone(), k.len())).collect()
+    }
+}
+
+#[test]
+fn test_key_normalizer() {
+    let load_fn = EmailLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_key_normalizer(|k: String| k.to_lowercase());
+
+    let loads = futures::future::join(
+        loader.load_many(vec!["Foo@x.com".to_owned()]),
+        loader.load("foo@x.com".to_owned()),
+    );
+    let (values, value) = block_on(loads);
+    assert_eq!(values, HashMap::from([("foo@x.com".to_owned(), 9)]));
+    assert_eq!(value, 9);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec!["foo@x.com".to_owned()]]
+    );
+}
+
+#[test]
+fn test_load_many_into_iter() {
+    let loader = Loader::new(MyLoadFn);
+    let values = block_on(loader.load_many(1..=3));
+    assert_eq!(values, HashMap::from([(1, 1), (2, 2), (3, 3)]));
+    let values = block_on(loader.load_many([4]));
+    assert_eq!(values, HashMap::from([(4, 4)]));
+}
+
+/// Counts the hashers it builds.
+#[derive(Clone, Default)]
+struct CountingHasher(Arc<AtomicUsize>);
+
+impl BuildHasher for CountingHasher {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        DefaultHasher::new()
+    }
+}
+
+#[test]
+fn test_with_hasher() {
+    let hasher = CountingHasher::default();
+    let loader = Loader::with_hasher(MyLoadFn, hasher.clone());
+
+    let values = block_on(loader.load_many(vec![1, 2, 2]));
+    assert_eq!(values, HashMap::from([(1, 1), (2, 2)]));
+    // used to deduplicate the keys of the batch
+    assert!(hasher.0.load(Ordering::SeqCst) >= 3);
+}
+
+#[derive(Clone, Default)]
+struct LoadFnWithCounts {
+    counts: Arc<Mutex<Vec<(usize, usize)>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithCounts {
+    const COUNTS: bool = true;
+
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+
+    async fn load_with_counts(&mut self, keys: &[(usize, usize)]) -> HashMap<usize, usize> {
+        let mut counts = self.counts.lock().unwrap();
+        counts.extend_from_slice(keys);
+        counts.sort();
+        keys.iter().map(|(k, _)| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_load_with_counts() {
+    let load_fn = LoadFnWithCounts::default();
+    let loader = Loader::new(load_fn.clone());
+    block_on(async {
+        let (a, b) = futures::join!(loader.load_many(vec![1, 2]), loader.load(1));
+        assert_eq!((a.len(), b), (2, 1));
+    });
+    assert_eq!(*load_fn.counts.lock().unwrap(), vec![(1, 2), (2, 1)]);
+}
+
+#[test]
+fn test_max_pending() {
+    let loader = Loader::new(MyLoadFn).with_max_pending(1, Backpressure::Fail);
+    let ret = block_on(loader.load_results(vec![1, 2]));
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Err(LoadError::QueueFull));
+
+    let loader = Loader::new(MyLoadFn).with_max_pending(2, Backpressure::Wait);
+    let (r1, r2) = block_on(futures::future::join(
+        loader.load_many(vec![1, 2]),
+        loader.load(3),
+    ));
+    assert_eq!((r1.len(), r2), (2, 3));
+    assert_eq!(block_on(loader.effective_batch_size()), 1.5);
+}
+
+#[test]
+fn test_debug() {
+    let loader: Loader<usize, usize, _> = Loader::new(MyLoadFn).with_name("user_loader");
+    let debug = format!("{:?}", loader);
+    assert!(debug.starts_with("Loader { name: Some(\"user_loader\"), max_batch_size: 200"));
+    assert!(debug.ends_with("pending: 0, .. }"));
+}
+
+#[test]
+fn test_existence_filter() {
+    // e.g. a bloom filter of the key space
+    let known: HashSet<usize> = vec![2, 4].into_iter().collect();
+    let loader = Loader::new(MyLoadFn).with_existence_filter(move |k: &usize| known.contains(k));
+    let ret = block_on(loader.load_results(vec![1, 2]));
+    assert_eq!(ret[&1], Err(LoadError::NotFound("<key>".to_owned())));
+    assert_eq!(ret[&2], Ok(2));
+    assert_eq!(block_on(loader.try_load(4)), Ok(4));
+    assert_eq!(block_on(loader.effective_batch_size()), 1.0);
+}
+
+/// A replica which only returns key 1 from its second call on, and never returns keys 2 and 3.
+#[derive(Clone, Default)]
+struct LaggingLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for LaggingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut calls = self.calls.lock().unwrap();
+        *calls += 1;
+        let caught_up = *calls > 1;
+        keys.iter()
+            .filter(|k| **k > 3 || (**k == 1 && caught_up))
+            .map(|k| (*k, *k))
+            .collect()
+    }
+}
+
+#[test]
+fn test_missing_key_handler() {
+    let load_fn = LaggingLoadFn::default();
+    let loader = Loader::new(load_fn.clone()).with_missing_key_handler(|k: &usize| match k {
+        1 | 3 => MissingKeyAction::Retry,
+        2 => MissingKeyAction::Default(0),
+        _ => MissingKeyAction::Error,
+    });
+
+    let ret = block_on(loader.load_results(vec![1, 1, 2, 3, 4]));
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Ok(0));
+    // retried once
+    assert_eq!(ret[&3], Err(LoadError::NotFound("<key>".to_owned())));
+    assert_eq!(ret[&4], Ok(4));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_load2_load3() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load2(1, 2)), (1, 2));
+    assert_eq!(block_on(loader.load3(3, 4, 5)), (3, 4, 5));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+
+    let loader = Loader::new(OddOnlyLoadFn).with_debug_keys();
+    assert_eq!(
+        block_on(loader.try_load3(1, 3, 4)),
+        Err(LoadError::NotFound("4".to_owned()))
+    );
+}
+
+#[test]
+fn test_load_group() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+
+    let group = block_on(loader.load_group(vec![1, 2, 3, 2]));
+    assert_eq!(group, HashMap::from([(1, 1), (2, 2), (3, 3)]));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+
+    let loader =
+        Loader::new(OddOnlyLoadFn).with_missing_key_handler(|_: &usize| MissingKeyAction::Retry);
+    let ret = block_on(loader.try_load_group(vec![1, 2]));
+    assert_eq!(ret, Err(LoadError::NotFound("<key>".to_owned())));
+    assert_eq!(block_on(loader.load(3)), 3);
+}
+
+#[test]
+fn test_barrier() {
+    let load_fn = BatchesLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader =
+        Loader::new(load_fn.clone()).with_custom_wait_for_work(|| Box::pin(YieldOnce(false)));
+    let barrier = loader.barrier();
+    let release = async move {
+        for _ in 0..16 {
+            YieldOnce(false).await;
+        }
+        drop(barrier);
+    };
+    let loads = futures::future::join_all((0..8).map(|i| {
+        let loader = &loader;
+        async move {
+            for _ in i..8 {
+                YieldOnce(false).await;
+            }
+            loader.load(i).await
+        }
+    }));
+    let (_, values) = block_on(futures::future::join(release, loads));
+    assert_eq!(values, (0..8).collect::<Vec<_>>());
+    let batches = load_fn.batches.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].len(), 8);
+}
@@ -0,0 +1,77 @@
+use dataloader::{cached, non_cached};
+use dataloader::{BatchFn, LoadError, NoopObserver, Observer};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct IdentityFn;
+
+impl BatchFn<usize, usize> for IdentityFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[derive(Clone, Default)]
+struct RecordingObserver {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl Observer for RecordingObserver {
+    fn batch_dispatched(&self, keys: usize) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("dispatched {}", keys));
+    }
+
+    fn batch_completed(&self, keys: usize, _elapsed: Duration, error: Option<&LoadError>) {
+        let event = format!("completed {} {:?}", keys, error);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn cache_hit(&self) {
+        self.events.lock().unwrap().push("hit".to_string());
+    }
+
+    fn cache_miss(&self) {
+        self.events.lock().unwrap().push("miss".to_string());
+    }
+}
+
+// the call sites of a disabled observer are compiled out
+const _: () = assert!(!NoopObserver::ENABLED);
+
+#[test]
+fn test_noop_observer_is_free() {
+    assert_eq!(std::mem::size_of::<NoopObserver>(), 0);
+    assert_eq!(
+        std::mem::size_of::<cached::Loader<usize, usize, IdentityFn>>(),
+        std::mem::size_of::<cached::Loader<usize, usize, IdentityFn, HashMap<usize, usize>, ()>>(),
+    );
+}
+
+#[test]
+fn test_cached_observer() {
+    let observer = RecordingObserver::default();
+    let loader = cached::Loader::new(IdentityFn).with_observer(observer.clone());
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(
+        *observer.events.lock().unwrap(),
+        vec!["miss", "miss", "dispatched 2", "completed 2 None", "hit"]
+    );
+}
+
+#[test]
+fn test_non_cached_observer() {
+    let observer = RecordingObserver::default();
+    let loader = non_cached::Loader::new(IdentityFn).with_observer(observer.clone());
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 1])).len(), 2);
+    assert_eq!(
+        *observer.events.lock().unwrap(),
+        vec!["dispatched 2", "completed 2 None"]
+    );
+}
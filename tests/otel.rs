@@ -0,0 +1,75 @@
+#[cfg(feature = "otel")]
+mod otel_tests {
+    use dataloader::cached::Loader;
+    use dataloader::BatchFn;
+    use futures::executor::block_on;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use std::collections::HashMap;
+
+    struct IdentityFn;
+
+    impl BatchFn<usize, usize> for IdentityFn {
+        async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+            keys.iter().map(|k| (*k, *k)).collect()
+        }
+    }
+
+    #[test]
+    fn test_otel_metrics() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let loader = Loader::new(IdentityFn)
+            .with_name("user_loader")
+            .with_otel_metrics(&provider.meter("test"));
+        assert_eq!(loader.name(), Some("user_loader"));
+
+        block_on(loader.load_many(vec![1, 2, 3]));
+        block_on(loader.load(1));
+        provider.force_flush().unwrap();
+
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let metrics = metrics
+            .iter()
+            .flat_map(|m| m.scope_metrics())
+            .flat_map(|s| s.metrics())
+            .collect::<Vec<_>>();
+        let metric = |name: &str| {
+            metrics
+                .iter()
+                .find(|m| m.name() == name)
+                .map(|m| m.data())
+                .unwrap()
+        };
+        let loader_label = KeyValue::new("loader", "user_loader");
+
+        match metric("dataloader.batch.size") {
+            AggregatedMetrics::U64(MetricData::Histogram(h)) => {
+                let point = h.data_points().next().unwrap();
+                assert_eq!((point.count(), point.sum()), (1, 3));
+                assert!(point.attributes().any(|kv| *kv == loader_label));
+            }
+            _ => panic!("batch size is not a histogram"),
+        }
+        match metric("dataloader.cache.lookups") {
+            AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+                let mut lookups = sum
+                    .data_points()
+                    .map(|point| {
+                        let hit = point
+                            .attributes()
+                            .any(|kv| *kv == KeyValue::new("cache.hit", true));
+                        (hit, point.value())
+                    })
+                    .collect::<Vec<_>>();
+                lookups.sort();
+                assert_eq!(lookups, vec![(false, 3), (true, 1)]);
+            }
+            _ => panic!("cache lookups are not a sum"),
+        }
+    }
+}
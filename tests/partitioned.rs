@@ -0,0 +1,60 @@
+use dataloader::partitioned::{PartitionedBatchFn, PartitionedLoader};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+type Batch = (u32, Vec<usize>);
+
+#[derive(Clone)]
+struct TenantLoadFn {
+    batches: Arc<Mutex<Vec<Batch>>>,
+}
+
+impl PartitionedBatchFn<u32, usize, String> for TenantLoadFn {
+    async fn load(&mut self, tenant: &u32, keys: &[usize]) -> HashMap<usize, String> {
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        self.batches.lock().unwrap().push((*tenant, sorted));
+        let ret = keys
+            .iter()
+            .map(|k| (*k, format!("tenant {} row {}", tenant, k)))
+            .collect();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_partitions_are_segregated() {
+    let load_fn = TenantLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = PartitionedLoader::new(load_fn.clone());
+
+    let (a, b) = block_on(futures::future::join(
+        loader.load_many(1, vec![1, 2]),
+        loader.load_many(2, vec![1]),
+    ));
+    assert_eq!(a[&1], "tenant 1 row 1");
+    assert_eq!(b[&1], "tenant 2 row 1");
+
+    // cached per partition
+    assert_eq!(block_on(loader.load(2, 1)), "tenant 2 row 1");
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(batches, vec![(1, vec![1, 2]), (2, vec![1])]);
+}
+
+#[test]
+fn test_clear_partition() {
+    let load_fn = TenantLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader =
+        PartitionedLoader::new(load_fn.clone()).with_loader_config(|l| l.with_max_batch_size(1));
+
+    block_on(loader.load(1, 1));
+    block_on(loader.clear_partition(&1));
+    block_on(loader.load(1, 1));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
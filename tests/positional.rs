@@ -0,0 +1,47 @@
+use dataloader::cached::Loader;
+use dataloader::{LoadError, Positional, PositionalBatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+
+/// Returns the squares of even keys, and nothing for odd keys.
+struct EvenSquares;
+
+impl PositionalBatchFn<usize, usize> for EvenSquares {
+    async fn load(&mut self, keys: &[usize]) -> Vec<Option<usize>> {
+        keys.iter()
+            .map(|k| if k % 2 == 0 { Some(k * k) } else { None })
+            .collect()
+    }
+}
+
+/// Drops the value of the last key.
+struct Truncated;
+
+impl PositionalBatchFn<usize, usize> for Truncated {
+    async fn load(&mut self, keys: &[usize]) -> Vec<Option<usize>> {
+        keys.iter().skip(1).map(|k| Some(*k)).collect()
+    }
+}
+
+#[test]
+fn test_positional() {
+    let loader = Loader::new(Positional(EvenSquares));
+    let values = block_on(loader.load_results(vec![2, 3, 4]));
+    assert_eq!(values[&2], Ok(Ok(4)));
+    assert_eq!(values[&4], Ok(Ok(16)));
+    assert!(matches!(values[&3], Err(LoadError::NotFound(_))));
+}
+
+#[test]
+fn test_positional_length_mismatch() {
+    let loader = Loader::new(Positional(Truncated));
+    let values = block_on(loader.load_many(vec![1, 2]));
+    let mismatch = Err(LoadError::UnequalKeyValueSize {
+        key_count: 2,
+        value_count: 1,
+    });
+    assert_eq!(
+        values,
+        HashMap::from([(1, mismatch.clone()), (2, mismatch)])
+    );
+}
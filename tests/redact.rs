@@ -0,0 +1,72 @@
+use dataloader::{cached, non_cached, BatchFn, KeyRedactor, LoadError, SaltedHash};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+struct EmptyLoadFn;
+
+impl BatchFn<String, usize> for EmptyLoadFn {
+    async fn load(&mut self, _keys: &[String]) -> HashMap<String, usize> {
+        ready(HashMap::new()).await
+    }
+}
+
+#[test]
+fn test_salted_hash() {
+    let key = "jane@example.com".to_string();
+    let hash = SaltedHash::new("pepper").redact(&key);
+    assert_eq!(hash.len(), 16);
+    assert_eq!(hash, SaltedHash::new("pepper").redact(&key));
+    assert_ne!(hash, SaltedHash::new("salt").redact(&key));
+}
+
+#[test]
+fn test_redacted_errors() {
+    let key = "jane@example.com".to_string();
+    let loader = cached::Loader::new(EmptyLoadFn).with_key_redactor(SaltedHash::new("pepper"));
+    let err = block_on(loader.try_load(key.clone())).unwrap_err();
+    assert_eq!(
+        err,
+        LoadError::NotFound(SaltedHash::new("pepper").redact(&key))
+    );
+    assert!(!err.to_string().contains("jane"));
+
+    let loader = non_cached::Loader::new(EmptyLoadFn)
+        .with_key_redactor(|key: &String| key.replace(|c| c != '@', "*"));
+    assert_eq!(
+        block_on(loader.try_load(key)),
+        Err(LoadError::NotFound("****@***********".to_string()))
+    );
+}
+
+/// A key without a `Debug` implementation.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Secret(String);
+
+impl BatchFn<Secret, usize> for EmptyLoadFn {
+    async fn load(&mut self, _keys: &[Secret]) -> HashMap<Secret, usize> {
+        ready(HashMap::new()).await
+    }
+}
+
+#[test]
+fn test_keys_without_debug() {
+    let key = Secret("hunter2".to_string());
+    let loader = cached::Loader::new(EmptyLoadFn);
+    assert_eq!(
+        block_on(loader.try_load(key.clone())),
+        Err(LoadError::NotFound("<key>".to_string()))
+    );
+
+    let loader = non_cached::Loader::new(EmptyLoadFn);
+    assert_eq!(
+        block_on(loader.try_load(key)),
+        Err(LoadError::NotFound("<key>".to_string()))
+    );
+
+    let loader = cached::Loader::new(EmptyLoadFn).with_debug_keys();
+    assert_eq!(
+        block_on(loader.try_load("jane".to_string())),
+        Err(LoadError::NotFound(r#""jane""#.to_string()))
+    );
+}
@@ -0,0 +1,115 @@
+use dataloader::cached;
+use dataloader::{LoadError, Retry, RetryPolicy, TryBatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Fails the first `failures` calls.
+#[derive(Clone)]
+struct FlakyLoadFn {
+    failures: usize,
+    calls: Arc<AtomicUsize>,
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl FlakyLoadFn {
+    fn new(failures: usize) -> Self {
+        FlakyLoadFn {
+            failures,
+            calls: Arc::new(AtomicUsize::new(0)),
+            batches: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl TryBatchFn<usize, usize> for FlakyLoadFn {
+    type Error = String;
+
+    async fn try_load(&mut self, keys: &[usize]) -> Result<HashMap<usize, usize>, String> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err("connection reset".to_string());
+        }
+        ready(Ok(keys.iter().map(|k| (*k, *k)).collect())).await
+    }
+}
+
+#[test]
+fn test_retry_delays() {
+    let retry = Retry::exponential(3, Duration::from_millis(50));
+    let e = LoadError::Timeout;
+    assert_eq!(retry.retry_after(1, &e), Some(Duration::from_millis(50)));
+    assert_eq!(retry.retry_after(2, &e), Some(Duration::from_millis(100)));
+    assert_eq!(retry.retry_after(3, &e), Some(Duration::from_millis(200)));
+    assert_eq!(retry.retry_after(4, &e), None);
+
+    let retry = Retry::fixed(2, Duration::from_millis(10));
+    assert_eq!(retry.retry_after(2, &e), Some(Duration::from_millis(10)));
+    assert_eq!(retry.retry_after(3, &e), None);
+}
+
+#[test]
+fn test_batch_error_without_retry() {
+    let load_fn = FlakyLoadFn::new(1);
+    let loader = cached::Loader::new(load_fn.clone());
+    assert_eq!(
+        block_on(loader.try_load(1)),
+        Err(LoadError::Batch("connection reset".into()))
+    );
+    // failed keys are not cached
+    assert_eq!(block_on(loader.try_load(1)), Ok(1));
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 2);
+}
+
+// the timer of the tokio runtime is only available within a tokio runtime
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_retry() {
+    let load_fn = FlakyLoadFn::new(2);
+    let loader = cached::Loader::new(load_fn.clone())
+        .with_retry(Retry::fixed(2, Duration::from_millis(1)))
+        .with_runtime(dataloader::AsyncStdRuntime);
+    let (r1, r2) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(2),
+    ));
+    assert_eq!((r1, r2), (Ok(1), Ok(2)));
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 3);
+
+    let load_fn = FlakyLoadFn::new(3);
+    let loader = dataloader::non_cached::Loader::new(load_fn.clone())
+        .with_retry(Retry::exponential(2, Duration::from_millis(1)))
+        .with_runtime(dataloader::AsyncStdRuntime);
+    assert_eq!(
+        block_on(loader.try_load(1)),
+        Err(LoadError::Batch("connection reset".into()))
+    );
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 3);
+}
+
+// the timer of the tokio runtime is only available within a tokio runtime
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn test_retry_skips_dropped_keys() {
+    let load_fn = FlakyLoadFn::new(1);
+    let loader = cached::Loader::new(load_fn.clone())
+        .with_retry(Retry::fixed(1, Duration::from_millis(10)))
+        .with_runtime(dataloader::AsyncStdRuntime);
+    block_on(async {
+        let mut f1 = Box::pin(loader.load(1));
+        let mut f2 = Box::pin(loader.load(2));
+        assert!(futures::poll!(f2.as_mut()).is_pending());
+        // until the first call failed and the batch waits to be retried
+        while load_fn.calls.load(Ordering::SeqCst) == 0 {
+            assert!(futures::poll!(f1.as_mut()).is_pending());
+        }
+        drop(f2);
+        assert_eq!(f1.await, 1);
+    });
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![1, 2], vec![1]]);
+}
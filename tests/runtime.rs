@@ -0,0 +1,136 @@
+use dataloader::cached::Loader;
+use dataloader::{BatchFn, Runtime, RuntimeFuture};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+struct IdentityLoadFn;
+
+impl BatchFn<usize, usize> for IdentityLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[derive(Clone, Default)]
+struct CountingRuntime {
+    yields: Arc<AtomicUsize>,
+}
+
+impl Runtime for CountingRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        self.yields.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async {})
+    }
+
+    fn sleep(&self, _duration: Duration) -> Option<RuntimeFuture> {
+        None
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        std::thread::spawn(move || block_on(future));
+    }
+}
+
+#[test]
+fn test_with_runtime() {
+    let runtime = CountingRuntime::default();
+    let loader = Loader::new(IdentityLoadFn)
+        .with_yield_count(3)
+        .with_runtime(runtime.clone());
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(runtime.yields.load(Ordering::SeqCst), 3);
+
+    let loader = dataloader::non_cached::Loader::new(IdentityLoadFn)
+        .with_yield_count(2)
+        .with_runtime(runtime.clone());
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(runtime.yields.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn test_smol_executor() {
+    let loader = Loader::new(IdentityLoadFn);
+    let ret = smol::block_on(async {
+        let ex = smol::Executor::new();
+        let loads = (0..10)
+            .map(|k| {
+                let loader = loader.clone();
+                ex.spawn(async move { loader.load(k).await })
+            })
+            .collect::<Vec<_>>();
+        ex.run(futures::future::join_all(loads)).await
+    });
+    assert_eq!(ret, (0..10).collect::<Vec<_>>());
+}
+
+/// Sleeps and spawns on threads of their own, counting the spawned tasks.
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+#[derive(Clone, Default)]
+struct ThreadTimerRuntime {
+    spawned: Arc<AtomicUsize>,
+}
+
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+impl Runtime for ThreadTimerRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(async {})
+    }
+
+    fn sleep(&self, duration: Duration) -> Option<RuntimeFuture> {
+        let (done, slept) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            let _ = done.send(());
+        });
+        Some(Box::pin(async move {
+            let _ = slept.await;
+        }))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.spawned.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || block_on(future));
+    }
+}
+
+#[cfg(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-wasm"
+))]
+#[test]
+fn test_idle_dispatch_ticks_on_a_spawned_task() {
+    use dataloader::testing::{Identity, RecordingBatchFn};
+
+    let runtime = ThreadTimerRuntime::default();
+    let load_fn = RecordingBatchFn::new(Identity);
+    let loader = Loader::new(load_fn.clone())
+        .with_idle_dispatch(Duration::from_millis(50), Duration::from_secs(5))
+        .with_runtime(runtime.clone());
+
+    // the second key is queued while the queue is not idle yet
+    let late = async {
+        runtime.sleep(Duration::from_millis(10)).unwrap().await;
+        loader.load(2).await
+    };
+    assert_eq!(
+        block_on(futures::future::join(loader.load(1), late)),
+        (1, 2)
+    );
+    load_fn.assert_batches(&[&[1, 2]]);
+    // one ticking task served both callers
+    assert_eq!(runtime.spawned.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,74 @@
+use dataloader::shadow::Shadow;
+use dataloader::{cached, non_cached, BatchFn, Runtime, RuntimeFuture};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::pin::Pin;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Runs every spawned task on a thread of its own, so the tests don't depend on the runtime
+/// features.
+struct ThreadRuntime;
+
+impl Runtime for ThreadRuntime {
+    fn yield_now(&self) -> RuntimeFuture {
+        Box::pin(async {})
+    }
+
+    fn sleep(&self, _duration: Duration) -> Option<RuntimeFuture> {
+        None
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        thread::spawn(move || block_on(future));
+    }
+}
+
+struct IdentityFn;
+
+impl BatchFn<usize, usize> for IdentityFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+fn wait_for_shadow_keys(shadow: &Shadow<usize>, keys: u64) {
+    let start = Instant::now();
+    while shadow.report().shadow.keys < keys && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn test_shadow_records_alternative_batching() {
+    let shadow = Shadow::new(ThreadRuntime, |loader| loader.with_max_batch_size(10));
+    let loader = non_cached::Loader::new(IdentityFn)
+        .with_max_batch_size(2)
+        .with_shadow(&shadow);
+
+    let ret = block_on(loader.load_many(vec![1, 2, 3, 4, 5, 6]));
+    assert_eq!(ret.len(), 6);
+    wait_for_shadow_keys(&shadow, 6);
+
+    let report = shadow.report();
+    assert_eq!(report.primary.batches, 3);
+    assert_eq!(report.primary.largest_batch, 2);
+    assert_eq!(report.shadow.keys, 6);
+    assert_eq!(report.shadow.batches, 1);
+    assert_eq!(report.shadow.mean_batch_size(), 6.0);
+}
+
+#[test]
+fn test_shadow_of_cached_loader_sees_misses_only() {
+    let shadow = Shadow::new(ThreadRuntime, |loader| loader);
+    let loader = cached::Loader::new(IdentityFn).with_shadow(&shadow);
+
+    block_on(loader.load_many(vec![1, 2]));
+    block_on(loader.load_many(vec![1, 2, 3]));
+    wait_for_shadow_keys(&shadow, 3);
+
+    let report = shadow.report();
+    assert_eq!(report.primary.keys, 3);
+    assert_eq!(report.shadow.keys, 3);
+}
@@ -0,0 +1,57 @@
+use dataloader::cached::{Loader, WeightedCache};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct CountingLoadFn {
+    loaded: Arc<Mutex<Vec<usize>>>,
+}
+
+impl BatchFn<usize, String> for CountingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, String> {
+        self.loaded.lock().unwrap().extend(keys);
+        keys.iter().map(|k| (*k, k.to_string())).collect()
+    }
+}
+
+#[test]
+fn test_export_import_cache() {
+    let load_fn = CountingLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    let mut entries = block_on(loader.export_cache());
+    entries.sort();
+    assert_eq!(entries, vec![(1, "1".to_owned()), (2, "2".to_owned())]);
+
+    let cache = WeightedCache::new(100, |v: &String| v.len());
+    let restored = Loader::with_cache(load_fn.clone(), cache);
+    block_on(restored.import_cache(entries));
+    assert_eq!(block_on(restored.load(2)), "2");
+    assert_eq!(block_on(restored.export_cache()).len(), 2);
+    assert_eq!(load_fn.loaded.lock().unwrap().len(), 2);
+
+    // principals export their own cache only
+    assert!(block_on(loader.for_principal("alice").export_cache()).is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_file() {
+    let path =
+        std::env::temp_dir().join(format!("dataloader-snapshot-{}.json", std::process::id()));
+    let load_fn = CountingLoadFn::default();
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load_many(vec![1, 2, 3])).len(), 3);
+    block_on(loader.save_snapshot(&path)).unwrap();
+
+    let restored = Loader::new(load_fn.clone());
+    block_on(restored.load_snapshot(&path)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(block_on(restored.load_many(vec![1, 2, 3])).len(), 3);
+    assert_eq!(load_fn.loaded.lock().unwrap().len(), 3);
+
+    let missing = std::env::temp_dir().join("dataloader-snapshot-missing.json");
+    assert!(block_on(restored.load_snapshot(missing)).is_err());
+}
@@ -0,0 +1,182 @@
+use dataloader::non_cached::Loader;
+use dataloader::{Backpressure, LoadError, SendBatchFn, Sendable};
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::future::join_all;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+/// Lets the `DefaultRuntime` spawn the dispatcher task while the guard is alive, as spawning on
+/// the tokio runtime is only available within a tokio runtime.
+#[cfg(feature = "runtime-tokio")]
+fn enter_runtime() -> tokio::runtime::EnterGuard<'static> {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    RUNTIME
+        .get_or_init(|| tokio::runtime::Runtime::new().unwrap())
+        .enter()
+}
+
+#[cfg(not(feature = "runtime-tokio"))]
+struct Entered;
+
+#[cfg(not(feature = "runtime-tokio"))]
+fn enter_runtime() -> Entered {
+    Entered
+}
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+    threads: Arc<Mutex<HashSet<ThreadId>>>,
+}
+
+impl SendBatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        self.threads.lock().unwrap().insert(thread::current().id());
+        // odd keys are missing
+        keys.iter()
+            .filter(|k| *k % 2 == 0)
+            .map(|k| (*k, *k * 10))
+            .collect()
+    }
+}
+
+#[test]
+fn test_spawned() {
+    let _runtime = enter_runtime();
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::spawned(load_fn.clone());
+
+    let values = block_on(join_all((0..6).map(|k| loader.try_load(k % 4))));
+    assert_eq!(values[0], Ok(0));
+    assert_eq!(values[2], Ok(20));
+    assert_eq!(values[5], Err(LoadError::NotFound("<key>".to_owned())));
+    let batches = load_fn.batches.lock().unwrap();
+    // duplicates are batched once
+    assert!(batches.iter().all(|batch| batch.len() <= 4));
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 4);
+    // the batches run on the dispatcher task rather than in the callers
+    assert!(!load_fn
+        .threads
+        .lock()
+        .unwrap()
+        .contains(&thread::current().id()));
+}
+
+#[test]
+fn test_spawned_across_threads() {
+    let _runtime = enter_runtime();
+    let load_fn = BatchesLoadFn::default();
+    let loader = Loader::new(Sendable(load_fn.clone()))
+        .with_max_batch_size(8)
+        .with_debug_keys()
+        .spawn();
+
+    let handles = (0..4)
+        .map(|i| {
+            let loader = loader.clone();
+            thread::spawn(move || block_on(loader.load_results(vec![i * 2, i * 2 + 1])))
+        })
+        .collect::<Vec<_>>();
+    for (i, handle) in handles.into_iter().enumerate() {
+        let results = handle.join().unwrap();
+        assert_eq!(results[&(i * 2)], Ok(i * 20));
+        assert_eq!(
+            results[&(i * 2 + 1)],
+            Err(LoadError::NotFound((i * 2 + 1).to_string()))
+        );
+    }
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|batch| batch.len() <= 8));
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 8);
+    assert_eq!(loader.pending_len(), 0);
+}
+
+/// Holds its first batch until the gate is opened.
+#[derive(Clone)]
+struct GatedLoadFn {
+    gate: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl GatedLoadFn {
+    fn new() -> (Self, oneshot::Sender<()>) {
+        let (open, gate) = oneshot::channel();
+        let load_fn = GatedLoadFn {
+            gate: Arc::new(Mutex::new(Some(gate))),
+            batches: Arc::default(),
+        };
+        (load_fn, open)
+    }
+
+    fn wait_for_batches(&self, n: usize) {
+        let start = Instant::now();
+        while self.batches.lock().unwrap().len() < n && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl SendBatchFn<usize, usize> for GatedLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        let gate = self.gate.lock().unwrap().take();
+        if let Some(gate) = gate {
+            let _ = gate.await;
+        }
+        keys.iter().map(|k| (*k, *k * 10)).collect()
+    }
+}
+
+#[test]
+fn test_spawned_full_queue_fails() {
+    let _runtime = enter_runtime();
+    let (load_fn, open) = GatedLoadFn::new();
+    let loader = Loader::new(Sendable(load_fn.clone()))
+        .with_max_pending(2, Backpressure::Fail)
+        .spawn();
+
+    let first = {
+        let loader = loader.clone();
+        thread::spawn(move || block_on(loader.load_many(vec![1, 2])))
+    };
+    // the dispatcher holds both keys until their batch completes
+    load_fn.wait_for_batches(1);
+    assert_eq!(block_on(loader.try_load(3)), Err(LoadError::QueueFull));
+    assert_eq!(loader.pending_len(), 0);
+
+    open.send(()).unwrap();
+    assert_eq!(first.join().unwrap().len(), 2);
+    assert_eq!(block_on(loader.try_load(3)), Ok(30));
+}
+
+#[test]
+fn test_spawned_full_queue_waits() {
+    let _runtime = enter_runtime();
+    let (load_fn, open) = GatedLoadFn::new();
+    let loader = Loader::new(Sendable(load_fn.clone()))
+        .with_max_pending(2, Backpressure::Wait)
+        .spawn();
+
+    let first = {
+        let loader = loader.clone();
+        thread::spawn(move || block_on(loader.load_many(vec![1, 2])))
+    };
+    load_fn.wait_for_batches(1);
+    let third = {
+        let loader = loader.clone();
+        thread::spawn(move || block_on(loader.load(3)))
+    };
+    // the third key is not queued while the queue is full
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(loader.pending_len(), 0);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+    open.send(()).unwrap();
+    assert_eq!(first.join().unwrap().len(), 2);
+    assert_eq!(third.join().unwrap(), 30);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+}
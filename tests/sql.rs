@@ -0,0 +1,135 @@
+#[cfg(feature = "sqlx")]
+mod sqlx_tests {
+    use dataloader::cached::Loader;
+    use dataloader::sql::SqlxBatchFn;
+    use futures::executor::block_on;
+    use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+
+    #[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+    struct User {
+        id: i64,
+        name: String,
+    }
+
+    async fn pool() -> SqlitePool {
+        // every connection opens a database of its own
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO users (id, name) VALUES (1, 'ann'), (2, 'bob')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn test_sqlx_batch_fn() {
+        let pool = block_on(pool());
+        let loader = Loader::new(SqlxBatchFn::new(
+            pool,
+            |ids: &[i64]| {
+                sqlx::query_as(
+                    "SELECT id, name FROM users WHERE id IN (SELECT value FROM json_each(?))",
+                )
+                .bind(format!("{:?}", ids))
+            },
+            |user: &User| user.id,
+        ));
+
+        let users = block_on(loader.try_load_many(vec![1, 2])).unwrap();
+        assert_eq!(users[&1].as_ref().unwrap().name, "ann");
+        assert_eq!(users[&2].as_ref().unwrap().name, "bob");
+        assert!(block_on(loader.try_load(3)).is_err());
+    }
+
+    #[test]
+    fn test_sqlx_batch_fn_query_error() {
+        let pool = block_on(pool());
+        let loader = Loader::new(SqlxBatchFn::new(
+            pool,
+            |ids: &[i64]| {
+                sqlx::query_as(
+                    "SELECT id, name FROM missing WHERE id IN (SELECT value FROM json_each(?))",
+                )
+                .bind(format!("{:?}", ids))
+            },
+            |user: &User| user.id,
+        ));
+
+        let user = block_on(loader.load(1));
+        let e = user.unwrap_err();
+        assert!(e.get_ref().downcast_ref::<sqlx::Error>().is_some());
+    }
+}
+
+#[cfg(feature = "diesel-async")]
+mod diesel_tests {
+    use dataloader::cached::Loader;
+    use dataloader::sql::DieselBatchFn;
+    use diesel::prelude::*;
+    use diesel::sqlite::SqliteConnection;
+    use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+    use diesel_async::{AsyncConnection, RunQueryDsl};
+    use std::sync::{Arc, Mutex};
+
+    diesel::table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Queryable)]
+    struct User {
+        id: i32,
+        name: String,
+    }
+
+    async fn connection() -> SyncConnectionWrapper<SqliteConnection> {
+        let mut conn = SyncConnectionWrapper::<SqliteConnection>::establish(":memory:")
+            .await
+            .unwrap();
+        diesel::sql_query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        diesel::sql_query("INSERT INTO users (id, name) VALUES (1, 'ann'), (2, 'bob'), (3, 'cy')")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_diesel_batch_fn_chunks_keys() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let chunks = Arc::new(Mutex::new(Vec::new()));
+            let recorded = chunks.clone();
+            let load_fn = DieselBatchFn::new(
+                connection().await,
+                move |ids: &[i32]| {
+                    recorded.lock().unwrap().push(ids.len());
+                    users::table.filter(users::id.eq_any(ids.to_vec()))
+                },
+                |user: &User| user.id,
+            )
+            .with_max_params(2);
+            let loader = Loader::new(load_fn);
+
+            let users = loader.load_many(vec![1, 2, 3]).await;
+            assert_eq!(users[&3].as_ref().unwrap().name, "cy");
+            assert_eq!(users.len(), 3);
+            assert_eq!(*chunks.lock().unwrap(), vec![2, 1]);
+        });
+    }
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "stream-ext")]
+
+use dataloader::stream::BatchLoadExt;
+use dataloader::{cached, non_cached, BatchFn, LoadError};
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct BatchesLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for BatchesLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        self.batches.lock().unwrap().push(keys.clone());
+        // odd keys are missing
+        keys.iter()
+            .filter(|k| *k % 2 == 0)
+            .map(|k| (*k, *k * 10))
+            .collect()
+    }
+}
+
+#[test]
+fn test_batch_load_preserves_order() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = cached::Loader::new(load_fn.clone())
+        .with_max_batch_size(4)
+        .with_debug_keys();
+    let keys = vec![4, 2, 8, 6, 3, 0, 10, 12, 14];
+    let loaded = block_on(
+        stream::iter(keys.clone())
+            .batch_load(&loader)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(loaded.iter().map(|(k, _)| *k).collect::<Vec<_>>(), keys);
+    assert_eq!(loaded[0].1, Ok(40));
+    assert_eq!(loaded[4].1, Err(LoadError::NotFound("3".to_owned())));
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|batch| batch.len() <= 4));
+    assert!(batches.len() < keys.len());
+}
+
+#[test]
+fn test_batch_load_windowed() {
+    let load_fn = BatchesLoadFn::default();
+    let loader = non_cached::Loader::new(load_fn.clone());
+    let loaded = block_on(
+        stream::iter(0..10)
+            .batch_load_windowed(&loader, 3)
+            .map(|(k, v)| (k, v.ok()))
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(
+        loaded,
+        (0..10)
+            .map(|k| (k, (k % 2 == 0).then_some(k * 10)))
+            .collect::<Vec<_>>()
+    );
+    // no more than the window of keys is in flight at once
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|batch| batch.len() <= 3));
+}
@@ -0,0 +1,64 @@
+// the timer of the tokio runtime is only available within a tokio runtime
+#![cfg(feature = "runtime-async-std")]
+
+use dataloader::{
+    cached, non_cached, Arrived, AsyncStdRuntime, LoadError, Observer, Streaming, StreamingBatchFn,
+};
+use futures::executor::block_on;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Delivers the values of the even keys right away, then hangs until the batch times out.
+#[derive(Clone)]
+struct HangingLoadFn;
+
+impl StreamingBatchFn<usize, usize> for HangingLoadFn {
+    async fn load(&mut self, keys: &[usize], mut values: Arrived<'_, usize, usize>) {
+        for k in keys.iter().filter(|k| *k % 2 == 0) {
+            values.push(*k, *k * 10);
+        }
+        futures::future::pending::<()>().await;
+    }
+}
+
+#[derive(Clone, Default)]
+struct PartialBatches(Arc<Mutex<Vec<(usize, usize)>>>);
+
+impl Observer for PartialBatches {
+    fn partial_batch(&self, keys: usize, salvaged: usize) {
+        self.0.lock().unwrap().push((keys, salvaged));
+    }
+}
+
+#[test]
+fn test_non_cached_timeout_salvages_arrived_values() {
+    let observer = PartialBatches::default();
+    let loader = non_cached::Loader::new(Streaming::new(HangingLoadFn))
+        .with_load_timeout(Duration::from_millis(20))
+        .with_runtime(AsyncStdRuntime)
+        .with_observer(observer.clone());
+
+    let results = block_on(loader.load_results(vec![1, 2, 3, 4]));
+    assert_eq!(results[&2], Ok(20));
+    assert_eq!(results[&4], Ok(40));
+    assert_eq!(results[&1], Err(LoadError::Timeout));
+    assert_eq!(results[&3], Err(LoadError::Timeout));
+    assert_eq!(*observer.0.lock().unwrap(), vec![(4, 2)]);
+}
+
+#[test]
+fn test_cached_timeout_salvages_arrived_values() {
+    let observer = PartialBatches::default();
+    let loader = cached::Loader::new(Streaming::new(HangingLoadFn))
+        .with_load_timeout(Duration::from_millis(20))
+        .with_runtime(AsyncStdRuntime)
+        .with_observer(observer.clone());
+
+    let results = block_on(loader.load_results(vec![1, 2, 3, 4]));
+    assert_eq!(results[&2], Ok(20));
+    assert_eq!(results[&1], Err(LoadError::Timeout));
+    assert_eq!(*observer.0.lock().unwrap(), vec![(4, 2)]);
+    // the salvaged values are cached like any other
+    assert_eq!(block_on(loader.try_load(4)), Ok(40));
+    assert_eq!(observer.0.lock().unwrap().len(), 1);
+}
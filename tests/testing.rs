@@ -0,0 +1,279 @@
+//! Tests of the testing utilities, and seeded rounds of random loads checking invariants of
+//! the loaders. The loads of a round run concurrently on a single thread, in the order its
+//! executor happens to poll them, so the rounds only cover the interleavings that order produces
+//! for the seeds. They are not a concurrency model check: unlike a model checker such as loom,
+//! they don't explore the interleavings of threads nor of the locks of the loaders.
+
+use dataloader::cached::Loader;
+use dataloader::non_cached;
+use dataloader::testing::{Identity, ManualRuntime, RecordingBatchFn};
+use futures::executor::{block_on, LocalPool};
+use futures::future::{join_all, LocalBoxFuture};
+use futures::task::LocalSpawnExt;
+use futures::FutureExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+#[test]
+fn test_recording_batch_fn() {
+    let load_fn = RecordingBatchFn::new(Identity);
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+
+    let values = block_on(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(values, vec![(1, 1), (2, 2), (3, 3)].into_iter().collect());
+    load_fn.assert_calls(2);
+    assert_eq!(load_fn.batches().concat().len(), 3);
+
+    load_fn.clear();
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load(4)), 4);
+    load_fn.assert_batches(&[&[4]]);
+}
+
+#[test]
+#[should_panic(expected = "expected 2 batch function calls, got [[1]]")]
+fn test_recording_batch_fn_assert_calls() {
+    let load_fn = RecordingBatchFn::new(Identity);
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.load(1));
+    load_fn.assert_calls(2);
+}
+
+#[test]
+fn test_manual_runtime() {
+    let runtime = ManualRuntime::new();
+    let load_fn = RecordingBatchFn::new(Identity);
+    let loader = Loader::new(load_fn.clone())
+        .with_yield_count(1)
+        .with_runtime(runtime.clone());
+    let mut pool = LocalPool::new();
+
+    let first = pool
+        .spawner()
+        .spawn_local_with_handle({
+            let loader = loader.clone();
+            async move { loader.load(1).await }
+        })
+        .unwrap();
+    pool.run_until_stalled();
+    // the load waits for work until the runtime advances
+    assert!(runtime.yielding() > 0);
+    load_fn.assert_calls(0);
+
+    let second = pool
+        .spawner()
+        .spawn_local_with_handle({
+            let loader = loader.clone();
+            async move { loader.load(2).await }
+        })
+        .unwrap();
+    pool.run_until_stalled();
+    while runtime.advance() > 0 {
+        pool.run_until_stalled();
+    }
+    assert_eq!(pool.run_until(first), 1);
+    assert_eq!(pool.run_until(second), 2);
+    load_fn.assert_batches(&[&[1, 2]]);
+    assert_eq!(runtime.yielding(), 0);
+}
+
+/// Runs random rounds of concurrent loads, primes and clears on a cached loader, checking that
+/// every load sees its key, that batches never exceed the maximum batch size or repeat a key, and
+/// that a key is only loaded again after it was cleared.
+#[test]
+fn test_cached_properties() {
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let max_batch_size = rng.gen_range(1..=4);
+        let load_fn = RecordingBatchFn::new(Identity);
+        let loader = Loader::new(load_fn.clone()).with_max_batch_size(max_batch_size);
+        let mut cached = HashSet::new();
+
+        for _ in 0..8 {
+            let mut requested = HashSet::new();
+            let mut loads: Vec<LocalBoxFuture<'_, HashMap<usize, usize>>> = Vec::new();
+            for _ in 0..rng.gen_range(1..6) {
+                if rng.gen_bool(0.5) {
+                    let key = rng.gen_range(0..12);
+                    requested.insert(key);
+                    let loader = &loader;
+                    loads.push(
+                        async move { Some((key, loader.load(key).await)).into_iter().collect() }
+                            .boxed_local(),
+                    );
+                } else {
+                    let keys = (0..rng.gen_range(0..4))
+                        .map(|_| rng.gen_range(0..12))
+                        .collect::<Vec<_>>();
+                    requested.extend(keys.iter().copied());
+                    loads.push(loader.load_many(keys).boxed_local());
+                }
+            }
+            for values in block_on(join_all(loads)) {
+                assert!(values.iter().all(|(k, v)| k == v), "seed {}", seed);
+            }
+
+            let batches = load_fn.batches();
+            load_fn.clear();
+            let mut loaded = HashSet::new();
+            for batch in &batches {
+                assert!(
+                    batch.len() <= max_batch_size,
+                    "seed {}: {:?}",
+                    seed,
+                    batches
+                );
+                for key in batch {
+                    assert!(!cached.contains(key), "seed {}: {} reloaded", seed, key);
+                    assert!(loaded.insert(*key), "seed {}: {} repeated", seed, key);
+                }
+            }
+            assert!(loaded.is_subset(&requested), "seed {}", seed);
+            cached.extend(requested);
+
+            for _ in 0..rng.gen_range(0..3) {
+                let key = rng.gen_range(0..12);
+                if rng.gen_bool(0.5) {
+                    block_on(loader.clear(key));
+                    cached.remove(&key);
+                } else {
+                    block_on(loader.prime(key, key));
+                    cached.insert(key);
+                }
+            }
+        }
+    }
+}
+
+/// Runs random rounds of concurrent loads on a non-cached loader, checking that every load sees
+/// its key, that batches never exceed the maximum batch size or repeat a key, and that every
+/// requested key is loaded in every round, as nothing is cached.
+#[test]
+fn test_non_cached_properties() {
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let max_batch_size = rng.gen_range(1..=4);
+        let load_fn = RecordingBatchFn::new(Identity);
+        let loader = non_cached::Loader::new(load_fn.clone()).with_max_batch_size(max_batch_size);
+
+        for _ in 0..8 {
+            let mut requested = HashSet::new();
+            let mut loads: Vec<LocalBoxFuture<'_, HashMap<usize, usize>>> = Vec::new();
+            for _ in 0..rng.gen_range(1..6) {
+                if rng.gen_bool(0.5) {
+                    let key = rng.gen_range(0..12);
+                    requested.insert(key);
+                    let loader = &loader;
+                    loads.push(
+                        async move { Some((key, loader.load(key).await)).into_iter().collect() }
+                            .boxed_local(),
+                    );
+                } else {
+                    let keys = (0..rng.gen_range(0..4))
+                        .map(|_| rng.gen_range(0..12))
+                        .collect::<Vec<_>>();
+                    requested.extend(keys.iter().copied());
+                    loads.push(loader.load_many(keys).boxed_local());
+                }
+            }
+            for values in block_on(join_all(loads)) {
+                assert!(values.iter().all(|(k, v)| k == v), "seed {}", seed);
+            }
+
+            let batches = load_fn.batches();
+            load_fn.clear();
+            let mut loaded = HashSet::new();
+            for batch in &batches {
+                assert!(
+                    batch.len() <= max_batch_size,
+                    "seed {}: {:?}",
+                    seed,
+                    batches
+                );
+                let unique = batch.iter().collect::<HashSet<_>>();
+                assert_eq!(unique.len(), batch.len(), "seed {}: {:?}", seed, batch);
+                loaded.extend(batch.iter().copied());
+            }
+            assert_eq!(loaded, requested, "seed {}", seed);
+        }
+    }
+}
+
+/// Runs random rounds of loads on the [`ManualRuntime`], queuing all of them before the runtime
+/// advances, and checks the batch count the runtime promises: as no caller stops waiting for
+/// work before the advance, only full batches go out meanwhile, so the keys of a round load in
+/// as few batches as the maximum batch size allows.
+#[test]
+fn test_manual_runtime_batch_counts() {
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let max_batch_size = rng.gen_range(1..=8);
+        let runtime = ManualRuntime::new();
+        let load_fn = RecordingBatchFn::new(Identity);
+        let cached = rng.gen_bool(0.5);
+        let cached_loader = Loader::new(load_fn.clone())
+            .with_max_batch_size(max_batch_size)
+            .with_yield_count(1)
+            .with_runtime(runtime.clone());
+        let loader = non_cached::Loader::new(load_fn.clone())
+            .with_max_batch_size(max_batch_size)
+            .with_yield_count(1)
+            .with_runtime(runtime.clone());
+        let mut pool = LocalPool::new();
+        let mut loaded = HashSet::new();
+
+        for _ in 0..4 {
+            let mut requested = HashSet::new();
+            let mut handles = Vec::new();
+            // every key is requested once per round, as the non-cached loader fills its batches
+            // with requests rather than with distinct keys
+            let mut unused = (0..24).collect::<Vec<_>>();
+            for _ in 0..rng.gen_range(1..8) {
+                let keys = (0..rng.gen_range(1..4))
+                    .map(|_| unused.swap_remove(rng.gen_range(0..unused.len())))
+                    .collect::<Vec<_>>();
+                requested.extend(keys.iter().copied());
+                let load = if cached {
+                    let loader = cached_loader.clone();
+                    async move { loader.load_many(keys).await }.boxed_local()
+                } else {
+                    let loader = loader.clone();
+                    async move { loader.load_many(keys).await }.boxed_local()
+                };
+                handles.push(pool.spawner().spawn_local_with_handle(load).unwrap());
+                pool.run_until_stalled();
+            }
+            let batches = load_fn.batches();
+            assert!(
+                batches.iter().all(|batch| batch.len() == max_batch_size),
+                "seed {}: {:?}",
+                seed,
+                batches
+            );
+            while runtime.advance() > 0 {
+                pool.run_until_stalled();
+            }
+            for handle in handles {
+                let values = pool.run_until(handle);
+                assert!(values.iter().all(|(k, v)| k == v), "seed {}", seed);
+            }
+
+            let batches = load_fn.batches();
+            load_fn.clear();
+            let keys = batches.concat();
+            assert_eq!(
+                batches.len(),
+                keys.len().div_ceil(max_batch_size),
+                "seed {}: {:?}",
+                seed,
+                batches
+            );
+            if !cached {
+                loaded.clear();
+            }
+            loaded.extend(keys);
+            assert!(requested.is_subset(&loaded), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use dataloader::cached::{Cache, Loader, TieredCache, WeightedCache};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_tiered_cache_promotes_hits() {
+    let l1 = WeightedCache::new(2, |_: &usize| 1);
+    let mut cache = TieredCache::new(l1, HashMap::new());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    // evicts 1 from the first tier only
+    cache.insert(3, 30);
+    assert_eq!(cache.l1().len(), 2);
+    assert_eq!(cache.l2().len(), 3);
+
+    assert_eq!(cache.get(&1), Some(&10));
+    // promoted, evicting 2
+    assert_eq!(cache.l1().len(), 2);
+    assert_eq!(cache.get_many(&[1, 2, 4]), vec![Some(10), Some(20), None]);
+
+    assert_eq!(cache.remove(&2), Some(20));
+    assert_eq!(cache.get(&2), None);
+    cache.clear();
+    assert_eq!(cache.len_hint(), Some(0));
+}
+
+#[derive(Clone)]
+struct CountingLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, usize> for CountingLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        *self.calls.lock().unwrap() += 1;
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_loader_with_tiered_cache() {
+    let load_fn = CountingLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let cache = TieredCache::new(WeightedCache::new(1, |_: &usize| 1), HashMap::new());
+    let loader = Loader::with_cache(load_fn.clone(), cache);
+
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    // served from the second tier
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load(2)), 2);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+}
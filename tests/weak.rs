@@ -0,0 +1,106 @@
+use dataloader::cached::{Cache, Loader, WeakCache};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_weak_cache_does_not_keep_values_alive() {
+    let mut cache = WeakCache::new(1);
+    let a = Arc::new("a".to_owned());
+    cache.insert(1, a.clone());
+    cache.insert(2, Arc::new("b".to_owned()));
+
+    // 2 is pinned as the most recent insert, 1 is alive as long as `a` is
+    assert_eq!(cache.get(&1), Some(&a));
+    assert_eq!(cache.get(&2).map(|v| v.as_str()), Some("b"));
+    assert_eq!(cache.len(), 2);
+
+    drop(a);
+    assert_eq!(cache.get(&1), None);
+    cache.insert(3, Arc::new("c".to_owned()));
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.len(), 1);
+
+    assert_eq!(cache.remove(&3).map(|v| v.len()), None);
+    assert!(cache.is_empty());
+}
+
+#[derive(Clone, Default)]
+struct BlobLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, Arc<Vec<u8>>> for BlobLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Arc<Vec<u8>>> {
+        *self.calls.lock().unwrap() += 1;
+        keys.iter().map(|k| (*k, Arc::new(vec![0; *k]))).collect()
+    }
+}
+
+#[test]
+fn test_loader_with_weak_cache() {
+    let load_fn = BlobLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::with_cache(load_fn.clone(), WeakCache::new(2));
+
+    let blob = block_on(loader.load(10));
+    assert_eq!(block_on(loader.load_many(vec![10, 20, 30])).len(), 3);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+
+    // 40 and 50 push 20 and 30 out of the pinned values, 10 is held by `blob`
+    assert_eq!(block_on(loader.load_many(vec![40, 50])).len(), 2);
+    assert!(Arc::ptr_eq(&block_on(loader.load(10)), &blob));
+    block_on(loader.load(50));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 3);
+    block_on(loader.load(20));
+    assert_eq!(*load_fn.calls.lock().unwrap(), 4);
+}
+
+/// Returns the documents held by `store`, loading missing ones into it.
+#[derive(Clone, Default)]
+struct StoreLoadFn {
+    store: Arc<Mutex<HashMap<usize, Arc<String>>>>,
+    loaded: Arc<Mutex<Vec<usize>>>,
+}
+
+impl BatchFn<usize, Arc<String>> for StoreLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Arc<String>> {
+        let mut store = self.store.lock().unwrap();
+        self.loaded.lock().unwrap().extend(keys);
+        keys.iter()
+            .map(|k| {
+                let doc = store.entry(*k).or_insert_with(|| Arc::new(k.to_string()));
+                (*k, doc.clone())
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_values_cached_elsewhere() {
+    let load_fn = StoreLoadFn::default();
+    let loader = Loader::with_cache(load_fn.clone(), WeakCache::new(0));
+
+    let doc = block_on(loader.load(1));
+    assert!(Arc::ptr_eq(&doc, &load_fn.store.lock().unwrap()[&1]));
+    drop(doc);
+    assert_eq!(block_on(loader.load(1)).as_str(), "1");
+    assert_eq!(*load_fn.loaded.lock().unwrap(), vec![1]);
+
+    // once the store drops a document, it is a miss
+    load_fn.store.lock().unwrap().remove(&1);
+    assert_eq!(block_on(loader.load(1)).as_str(), "1");
+    assert_eq!(*load_fn.loaded.lock().unwrap(), vec![1, 1]);
+}
+
+#[test]
+fn test_values_nobody_holds() {
+    let load_fn = BlobLoadFn::default();
+    let loader = Loader::with_cache(load_fn.clone(), WeakCache::new(0));
+    // callers get the values of their batch, which are dropped right after
+    assert_eq!(block_on(loader.load(1)).len(), 1);
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 2);
+}
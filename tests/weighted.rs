@@ -0,0 +1,66 @@
+use dataloader::cached::{Cache, Loader, WeightedCache};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_weighted_cache_evicts_least_recently_used() {
+    let mut cache = WeightedCache::new(10, |v: &String| v.len());
+    cache.insert(1, "aaaa".to_owned());
+    cache.insert(2, "bbbb".to_owned());
+    assert_eq!(cache.total_weight(), 8);
+
+    // touch 1 so that 2 is evicted first
+    assert!(cache.get(&1).is_some());
+    cache.insert(3, "cccc".to_owned());
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get(&1).map(String::as_str), Some("aaaa"));
+    assert_eq!(cache.total_weight(), 8);
+
+    // a value heavier than the capacity evicts everything else
+    cache.insert(4, "d".repeat(20));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.remove(&4).map(|v| v.len()), Some(20));
+    assert_eq!(cache.total_weight(), 0);
+}
+
+#[test]
+fn test_cache_weight() {
+    let cache = WeightedCache::new(10, |v: &String| v.len());
+    assert_eq!(cache.weight(&1, &"abc".to_owned()), 3);
+    // other caches count their values
+    let cache = HashMap::<usize, String>::new();
+    assert_eq!(cache.weight(&1, &"abc".to_owned()), 1);
+}
+
+#[derive(Clone)]
+struct BlobLoadFn {
+    calls: Arc<Mutex<usize>>,
+}
+
+impl BatchFn<usize, Vec<u8>> for BlobLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Vec<u8>> {
+        *self.calls.lock().unwrap() += 1;
+        ready(keys.iter().map(|k| (*k, vec![0; *k])).collect()).await
+    }
+}
+
+#[test]
+fn test_loader_with_weighted_cache() {
+    let load_fn = BlobLoadFn {
+        calls: Arc::new(Mutex::new(0)),
+    };
+    let cache = WeightedCache::new(100, |v: &Vec<u8>| v.len());
+    let loader = Loader::with_cache(load_fn.clone(), cache);
+
+    assert_eq!(block_on(loader.load(60)).len(), 60);
+    assert_eq!(block_on(loader.load(60)).len(), 60);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 1);
+
+    // evicts 60
+    assert_eq!(block_on(loader.load(50)).len(), 50);
+    assert_eq!(block_on(loader.load(60)).len(), 60);
+    assert_eq!(*load_fn.calls.lock().unwrap(), 3);
+}
@@ -0,0 +1,72 @@
+use dataloader::cached::Loader;
+use dataloader::writer::Writer;
+use dataloader::{BatchFn, BatchStoreFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::{Arc, Mutex};
+
+type Batch = Vec<(usize, usize)>;
+
+#[derive(Clone, Default)]
+struct RecordingStoreFn {
+    batches: Arc<Mutex<Vec<Batch>>>,
+}
+
+impl BatchStoreFn<usize, usize> for RecordingStoreFn {
+    async fn store(&mut self, values: Vec<(usize, usize)>) {
+        self.batches.lock().unwrap().push(values);
+        ready(()).await
+    }
+}
+
+struct IdentityLoadFn;
+
+impl BatchFn<usize, usize> for IdentityLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        ready(keys.iter().map(|k| (*k, *k)).collect()).await
+    }
+}
+
+#[test]
+fn test_writes_are_batched() {
+    let store_fn = RecordingStoreFn::default();
+    let writer = Writer::new(store_fn.clone()).with_max_batch_size(3);
+
+    block_on(futures::future::join3(
+        writer.store(1, 10),
+        writer.store(1, 11),
+        writer.store_many(vec![(2, 20), (3, 30)]),
+    ));
+    assert_eq!(
+        *store_fn.batches.lock().unwrap(),
+        vec![vec![(1, 10), (1, 11), (2, 20)], vec![(3, 30)]]
+    );
+    assert_eq!(block_on(writer.pending_len()), 0);
+}
+
+#[test]
+fn test_writes_prime_cache() {
+    let store_fn = RecordingStoreFn::default();
+    let loader = Loader::new(IdentityLoadFn);
+    let writer = Writer::new(store_fn.clone()).with_cache(&loader);
+
+    block_on(writer.store(1, 10));
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(block_on(loader.load(2)), 2);
+}
+
+#[test]
+fn test_flush_dropped_write() {
+    let store_fn = RecordingStoreFn::default();
+    let writer = Writer::new(store_fn.clone());
+
+    block_on(async {
+        let mut store = Box::pin(writer.store(1, 10));
+        assert!(futures::poll!(store.as_mut()).is_pending());
+        drop(store);
+        assert_eq!(writer.pending_len().await, 1);
+        writer.flush().await;
+    });
+    assert_eq!(*store_fn.batches.lock().unwrap(), vec![vec![(1, 10)]]);
+}